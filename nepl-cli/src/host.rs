@@ -0,0 +1,123 @@
+//! The host side of compiled programs: the `nepl` imports linked into
+//! every module the CLI runs.
+
+use anyhow::{Context, Result};
+use nepl_core::codegen_wasm::Assertion;
+use nepl_core::diagnostic::Diagnostic;
+use wasmi::{Caller, Engine, Instance, Linker, Module, Store, Val};
+
+/// State of the host functions.
+#[derive(Debug, Default)]
+pub struct Host {
+    /// Whether `print_i32` collects its output in `printed` instead of
+    /// writing to stdout.
+    capture: bool,
+    pub printed: String,
+    /// Index of the assertion that failed, set by `assert_failed`.
+    pub failed_assertion: Option<u32>,
+}
+
+impl Host {
+    /// A host that prints to stdout.
+    pub fn new() -> Host {
+        Host::default()
+    }
+
+    /// A host that collects what the program prints in `printed`.
+    pub fn capturing() -> Host {
+        Host {
+            capture: true,
+            ..Host::default()
+        }
+    }
+
+    /// The diagnostic of the `assert` the program stopped at, if any.
+    pub fn assertion_failure(&self, assertions: &[Assertion]) -> Option<Diagnostic> {
+        let assertion = assertions.get(self.failed_assertion? as usize)?;
+        Some(
+            Diagnostic::error_key("E1000.assertion-failed", assertion.span)
+                .arg("message", &assertion.message),
+        )
+    }
+}
+
+/// An instantiated module.
+pub struct Program {
+    store: Store<Host>,
+    instance: Instance,
+}
+
+impl Program {
+    /// Instantiate `wasm` with the host functions.
+    pub fn new(wasm: &[u8], host: Host) -> Result<Program> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm).context("failed to compile wasm artifact")?;
+        let linker = linker(&engine)?;
+        let mut store = Store::new(&engine, host);
+        let instance = linker
+            .instantiate_and_start(&mut store, &module)
+            .context("failed to instantiate module")?;
+        Ok(Program { store, instance })
+    }
+
+    /// Call the exported function `name` without arguments and return
+    /// its result.
+    pub fn call(&mut self, name: &str) -> Result<Option<Val>> {
+        let func = self
+            .instance
+            .get_func(&self.store, name)
+            .with_context(|| format!("exported function {name} missing"))?;
+        let mut results: Vec<Val> = func
+            .ty(&self.store)
+            .results()
+            .iter()
+            .map(|ty| Val::default(*ty))
+            .collect();
+        func.call(&mut self.store, &[], &mut results)?;
+        Ok(results.pop())
+    }
+
+    pub fn host(&self) -> &Host {
+        self.store.data()
+    }
+
+    pub fn host_mut(&mut self) -> &mut Host {
+        self.store.data_mut()
+    }
+}
+
+fn linker(engine: &Engine) -> Result<Linker<Host>> {
+    let mut linker = Linker::new(engine);
+    linker
+        .func_wrap(
+            "nepl",
+            "print_i32",
+            |mut caller: Caller<'_, Host>, value: i32| {
+                let host = caller.data_mut();
+                if host.capture {
+                    host.printed.push_str(&format!("{value}\n"));
+                } else {
+                    println!("{value}");
+                }
+            },
+        )
+        .context("failed to define host function print_i32")?;
+    linker
+        .func_wrap("nepl", "random_i32", || {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.subsec_nanos() as i32)
+                .unwrap_or(4)
+        })
+        .context("failed to define host function random_i32")?;
+    linker
+        .func_wrap(
+            "nepl",
+            "assert_failed",
+            |mut caller: Caller<'_, Host>, index: i32| {
+                caller.data_mut().failed_assertion = Some(index as u32);
+            },
+        )
+        .context("failed to define host function assert_failed")?;
+    Ok(linker)
+}
@@ -1,22 +1,45 @@
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, IsTerminal, Read};
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use nepl_core::span::FileId;
-use nepl_core::{CompilationArtifact, compile_wasm, emit_llvm_ir};
-use wasmi::{Engine, Linker, Module, Store};
+use clap::{Parser, Subcommand, ValueEnum};
+use nepl_core::call_tree::{self, ResolvedSeq};
+use nepl_core::diagnostic::{Diagnostic, Severity};
+use nepl_core::error_codes;
+use nepl_core::fix::apply_suggestions;
+use nepl_core::format::{FormatOptions, format_source};
+use nepl_core::lint::{Level, LintConfig};
+use nepl_core::messages::Locale;
+use nepl_core::render::Renderer;
+use nepl_core::report::Reporter;
+use nepl_core::span::{FileId, LineIndex};
+use nepl_core::{CompilationArtifact, CoreError, compile_wasm_with, emit_llvm_ir_with};
+use wasmi::Val;
+
+mod host;
+mod repl;
+mod testing;
+
+use host::{Host, Program};
 
 /// コマンドライン引数を定義するための構造体
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short, long)]
     input: Option<String>,
 
-    #[arg(short, long)]
-    output: String,
+    #[arg(short, long, required = true)]
+    output: Option<String>,
 
     #[arg(
         long,
@@ -33,16 +56,221 @@ struct Cli {
         help = "Compile as library (do not wrap top-level in an implicit main)"
     )]
     lib: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        default_value_t = ColorChoice::Auto,
+        help = "Use colors in diagnostics"
+    )]
+    color: ColorChoice,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        default_value_t = ErrorFormat::Human,
+        help = "Format of diagnostics printed to stderr"
+    )]
+    error_format: ErrorFormat,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "LANG",
+        value_parser = parse_locale,
+        help = "Language of diagnostics: en, ja [default: $NEPL_LANG, then $LANG]"
+    )]
+    lang: Option<Locale>,
+
+    #[arg(
+        short = 'W',
+        long = "warn",
+        value_name = "LINT",
+        global = true,
+        help = "Report the lint as a warning"
+    )]
+    warn: Vec<String>,
+
+    #[arg(
+        short = 'D',
+        long = "deny",
+        value_name = "LINT",
+        global = true,
+        help = "Report the lint as an error"
+    )]
+    deny: Vec<String>,
+
+    #[arg(
+        short = 'A',
+        long = "allow",
+        value_name = "LINT",
+        global = true,
+        help = "Do not report the lint"
+    )]
+    allow: Vec<String>,
+
+    #[arg(long, global = true, help = "Treat all warnings as errors")]
+    deny_warnings: bool,
+}
+
+fn parse_locale(tag: &str) -> Result<Locale, String> {
+    Locale::parse(tag).ok_or_else(|| format!("unsupported language `{tag}` (expected en or ja)"))
+}
+
+/// The language of diagnostics: `--lang`, then `NEPL_LANG`, then the
+/// POSIX locale variables. Unsupported locales fall back to English.
+fn locale(cli: &Cli) -> Locale {
+    if let Some(locale) = cli.lang {
+        return locale;
+    }
+    let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+    var("NEPL_LANG")
+        .or_else(|| var("LC_ALL"))
+        .or_else(|| var("LC_MESSAGES"))
+        .or_else(|| var("LANG"))
+        .and_then(|tag| Locale::parse(&tag))
+        .unwrap_or_default()
+}
+
+/// The lint levels given with `-A`, `-W` and `-D`. When a lint is named
+/// more than once, `-D` wins over `-W`, which wins over `-A`.
+fn lint_config(cli: &Cli) -> Result<LintConfig> {
+    let mut config = LintConfig::new();
+    let flags = [
+        (&cli.allow, Level::Allow),
+        (&cli.warn, Level::Warn),
+        (&cli.deny, Level::Deny),
+    ];
+    for (names, level) in flags {
+        for name in names {
+            config
+                .set(name, level)
+                .map_err(|e| anyhow::anyhow!("{e}"))?;
+        }
+    }
+    config.deny_warnings = cli.deny_warnings;
+    Ok(config)
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the detailed explanation of a diagnostic code, e.g. `E0300`
+    Explain { code: String },
+    /// Apply the suggested fixes of all diagnostics to a source file
+    Fix {
+        file: PathBuf,
+        /// Print the fixed source to stdout instead of rewriting the file
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Format source files in place
+    Fmt {
+        /// Files to format; formats stdin to stdout when none are given
+        files: Vec<PathBuf>,
+        /// Do not write anything; fail if a file is not formatted
+        #[arg(long)]
+        check: bool,
+    },
+    /// Show how the P-style sequences starting on a line are grouped,
+    /// with the implied parentheses written out
+    ExplainParse {
+        /// Location as `FILE:LINE` (lines count from 1)
+        location: String,
+    },
+    /// Start an interactive session that evaluates expressions and keeps
+    /// definitions in scope
+    Repl,
+    /// Compile the `test` declarations of source files and run them
+    Test {
+        /// Files whose tests to run
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+/// Fixing one diagnostic can reveal another; give up after this many
+/// rounds of recompiling.
+const MAX_FIX_PASSES: usize = 8;
+
+/// How diagnostics are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ErrorFormat {
+    /// Source excerpts with labels, for people.
+    Human,
+    /// One JSON object per diagnostic and line.
+    Json,
+    /// A SARIF 2.1.0 log.
+    Sarif,
+}
+
+/// When to color diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// Only when stderr is a terminal and `NO_COLOR` is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    execute(cli)
+    match execute(cli) {
+        Err(e) if e.is::<Reported>() => std::process::exit(1),
+        result => result,
+    }
+}
+
+/// The error of a command whose failure was reported as diagnostics in
+/// a machine-readable format: stderr must hold nothing else, so `main`
+/// exits without printing it.
+#[derive(Debug)]
+struct Reported;
+
+impl std::fmt::Display for Reported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("diagnostics were reported")
+    }
+}
+
+impl std::error::Error for Reported {}
+
+/// The error ending a command that failed after printing diagnostics:
+/// `message` in the human format, `Reported` otherwise.
+fn failure(cli: &Cli, message: String) -> anyhow::Error {
+    match cli.error_format {
+        ErrorFormat::Human => anyhow::anyhow!(message),
+        ErrorFormat::Json | ErrorFormat::Sarif => Reported.into(),
+    }
 }
 
 fn execute(cli: Cli) -> Result<()> {
-    let source = match cli.input {
-        Some(path) => fs::read_to_string(&path)
+    match &cli.command {
+        Some(Command::Explain { code }) => return explain(code),
+        Some(Command::Fix { file, dry_run }) => return fix(file, *dry_run, &cli),
+        Some(Command::Fmt { files, check }) => return fmt(files, *check, &cli),
+        Some(Command::ExplainParse { location }) => return explain_parse(location, &cli),
+        Some(Command::Repl) => return start_repl(&cli),
+        Some(Command::Test { files }) => return testing::run(files, &cli),
+        None => {}
+    }
+    let output = cli.output.as_deref().context("--output is required")?;
+    let name = cli.input.clone().unwrap_or_else(|| "<stdin>".to_string());
+    let source = match &cli.input {
+        Some(path) => fs::read_to_string(path)
             .with_context(|| format!("failed to read input file {path}"))?,
         None => {
             let mut buffer = String::new();
@@ -52,21 +280,23 @@ fn execute(cli: Cli) -> Result<()> {
     };
 
     let file_id = FileId(0);
+    let lints = lint_config(&cli)?;
 
     match cli.emit.as_str() {
         "wasm" => {
-            let artifact = compile_wasm(file_id, &source)
-                .map_err(|e| anyhow::anyhow!("{e:?}"))?;
-            write_output(&cli.output, &artifact.wasm)?;
+            let artifact = compile_wasm_with(file_id, &source, &lints)
+                .map_err(|e| report(e, file_id, &name, &source, &cli))?;
+            print_diagnostics(&artifact.warnings, file_id, &name, &source, &cli);
+            write_output(output, &artifact.wasm)?;
             if cli.run {
-                let result = run_wasm(&artifact)?;
+                let result = run_wasm(&artifact, file_id, &name, &source, &cli)?;
                 println!("Program exited with {result}");
             }
         }
         "llvm" => {
-            let ir = emit_llvm_ir(file_id, &source)
-                .map_err(|e| anyhow::anyhow!("{e:?}"))?;
-            write_output(&cli.output, ir.as_bytes())?;
+            let ir = emit_llvm_ir_with(file_id, &source, &lints)
+                .map_err(|e| report(e, file_id, &name, &source, &cli))?;
+            write_output(output, ir.as_bytes())?;
             if cli.run {
                 eprintln!("--run is ignored for non-wasm outputs");
             }
@@ -81,34 +311,272 @@ fn execute(cli: Cli) -> Result<()> {
     Ok(())
 }
 
-fn write_output(path: &str, bytes: &[u8]) -> Result<()> {
-    if let Some(parent) = PathBuf::from(path).parent() {
-        if !parent.as_os_str().is_empty() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("failed to create directory {parent:?}"))?;
+/// Print the explanation of a diagnostic code.
+fn explain(code: &str) -> Result<()> {
+    let entry = error_codes::lookup(code)
+        .with_context(|| format!("`{code}` is not a valid diagnostic code"))?;
+    println!("{}: {}\n", entry.code, entry.title);
+    print!("{}", entry.explanation);
+    Ok(())
+}
+
+/// Apply suggestions until the file compiles or no fix applies anymore.
+fn fix(path: &PathBuf, dry_run: bool, cli: &Cli) -> Result<()> {
+    let name = path.display().to_string();
+    let original = fs::read_to_string(path)
+        .with_context(|| format!("failed to read input file {name}"))?;
+    let file_id = FileId(0);
+    let lints = lint_config(cli)?;
+
+    let mut source = original.clone();
+    let mut applied = 0;
+    let mut result = compile_wasm_with(file_id, &source, &lints);
+    for _ in 0..MAX_FIX_PASSES {
+        let diagnostics = match &result {
+            Ok(artifact) => &artifact.warnings,
+            Err(CoreError::Diagnostics(diagnostics)) => diagnostics,
+            Err(_) => break,
+        };
+        let fixed = apply_suggestions(file_id, &source, diagnostics);
+        if fixed.applied == 0 {
+            break;
+        }
+        applied += fixed.applied;
+        source = fixed.source;
+        result = compile_wasm_with(file_id, &source, &lints);
+    }
+
+    if dry_run {
+        print!("{source}");
+    } else if source != original {
+        fs::write(path, &source).with_context(|| format!("failed to write {name}"))?;
+    }
+    match applied {
+        1 => eprintln!("applied 1 fix to {name}"),
+        n => eprintln!("applied {n} fixes to {name}"),
+    }
+    match result {
+        Ok(artifact) => {
+            print_diagnostics(&artifact.warnings, file_id, &name, &source, cli);
+            Ok(())
         }
+        Err(e) => Err(report(e, file_id, &name, &source, cli)),
+    }
+}
+
+/// Format files in place, or stdin to stdout. With `check`, only
+/// report the files that are not formatted.
+fn fmt(files: &[PathBuf], check: bool, cli: &Cli) -> Result<()> {
+    let file_id = FileId(0);
+    let options = FormatOptions::default();
+    let format = |name: &str, source: &str| {
+        format_source(file_id, source, &options).map_err(|diagnostics| {
+            print_diagnostics(&diagnostics, file_id, name, source, cli);
+            failure(cli, format!("could not format {name}"))
+        })
+    };
+
+    if files.is_empty() {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        let formatted = format("<stdin>", &source)?;
+        if check {
+            anyhow::ensure!(formatted == source, "<stdin> is not formatted");
+        } else {
+            print!("{formatted}");
+        }
+        return Ok(());
+    }
+
+    let mut unformatted = 0;
+    for path in files {
+        let name = path.display().to_string();
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read input file {name}"))?;
+        let formatted = format(&name, &source)?;
+        if formatted == source {
+            continue;
+        }
+        if check {
+            eprintln!("{name} is not formatted");
+            unformatted += 1;
+        } else {
+            fs::write(path, &formatted).with_context(|| format!("failed to write {name}"))?;
+        }
+    }
+    match unformatted {
+        0 => Ok(()),
+        1 => Err(anyhow::anyhow!("1 file is not formatted")),
+        n => Err(anyhow::anyhow!("{n} files are not formatted")),
+    }
+}
+
+/// `nepl explain-parse FILE:LINE`.
+fn explain_parse(location: &str, cli: &Cli) -> Result<()> {
+    let (path, line) = location
+        .rsplit_once(':')
+        .and_then(|(path, line)| Some((path, line.parse::<u32>().ok()?)))
+        .filter(|(_, line)| *line > 0)
+        .with_context(|| format!("expected FILE:LINE, found `{location}`"))?;
+    let source =
+        fs::read_to_string(path).with_context(|| format!("failed to read input file {path}"))?;
+
+    let file_id = FileId(0);
+    let result = call_tree::resolve_calls(file_id, &source);
+    if result.diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        // The grouping is still shown as far as resolution got.
+        print_diagnostics(&result.diagnostics, file_id, path, &source, cli);
+    }
+    print!("{}", explain_line(&source, &result.seqs, line - 1)?);
+    Ok(())
+}
+
+/// The sequences that start on the 0-based `line`, outermost only, each
+/// as its source text followed by `= ` and the explained text.
+fn explain_line(source: &str, seqs: &[ResolvedSeq], line: u32) -> Result<String> {
+    let index = LineIndex::new(source);
+    let on_line: Vec<&ResolvedSeq> = seqs
+        .iter()
+        .filter(|s| index.line_col(s.span.start).0 == line)
+        .collect();
+    let outermost = on_line.iter().filter(|s| {
+        !on_line
+            .iter()
+            .any(|o| o.span != s.span && o.span.start <= s.span.start && s.span.end <= o.span.end)
+    });
+
+    let mut out = String::new();
+    for seq in outermost {
+        let text = &source[seq.span.start as usize..seq.span.end as usize];
+        out.push_str(&format!("  {text}\n= {}\n", call_tree::explain(source, seqs, seq.span)));
+    }
+    anyhow::ensure!(!out.is_empty(), "no P-style sequence starts on line {}", line + 1);
+    Ok(out)
+}
+
+/// Run `nepl repl` on stdin and stdout.
+fn start_repl(cli: &Cli) -> Result<()> {
+    let mut session = repl::Repl::new(lint_config(cli)?, cli.color.enabled(), locale(cli));
+    let interactive = io::stdin().is_terminal();
+    repl::run(
+        &mut session,
+        &mut io::stdin().lock(),
+        &mut io::stdout(),
+        interactive,
+    )
+}
+
+/// Print the diagnostics of a failed compilation to stderr.
+fn report(
+    error: CoreError,
+    file_id: FileId,
+    name: &str,
+    source: &str,
+    cli: &Cli,
+) -> anyhow::Error {
+    let CoreError::Diagnostics(diagnostics) = error else {
+        return anyhow::anyhow!("{error}");
+    };
+    print_diagnostics(&diagnostics, file_id, name, source, cli);
+    failure(cli, format!("could not compile {name}"))
+}
+
+/// Print diagnostics (errors or warnings) to stderr in the chosen format.
+fn print_diagnostics(
+    diagnostics: &[Diagnostic],
+    file_id: FileId,
+    name: &str,
+    source: &str,
+    cli: &Cli,
+) {
+    if diagnostics.is_empty() {
+        return;
+    }
+    match cli.error_format {
+        ErrorFormat::Human => {
+            let mut renderer = Renderer::new()
+                .with_color(cli.color.enabled())
+                .with_locale(locale(cli));
+            renderer.add_file(file_id, name, source);
+            eprint!("{}", renderer.render_all(diagnostics));
+            explain_hint(diagnostics);
+        }
+        ErrorFormat::Json | ErrorFormat::Sarif => {
+            let mut reporter = Reporter::new().with_locale(locale(cli));
+            reporter.add_file(file_id, name, source);
+            if cli.error_format == ErrorFormat::Json {
+                eprint!("{}", reporter.json_lines(diagnostics));
+            } else {
+                eprintln!("{}", reporter.sarif(diagnostics));
+            }
+        }
+    }
+}
+
+/// Point at `nepl explain` for the codes of the errors that occurred.
+fn explain_hint(diagnostics: &[Diagnostic]) {
+    let mut codes: Vec<&str> = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .filter_map(|d| d.code)
+        .collect();
+    codes.sort_unstable();
+    codes.dedup();
+    match codes.as_slice() {
+        [] => {}
+        [code] => eprintln!("For more information about this error, try `nepl explain {code}`."),
+        [first, ..] => {
+            eprintln!("Some errors have detailed explanations: {}.", codes.join(", "));
+            eprintln!("For more information about an error, try `nepl explain {first}`.");
+        }
+    }
+}
+
+fn write_output(path: &str, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = PathBuf::from(path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {parent:?}"))?;
     }
     fs::write(path, bytes).with_context(|| format!("failed to write output file {path}"))?;
     Ok(())
 }
 
-fn run_wasm(artifact: &CompilationArtifact) -> Result<i32> {
-    let engine = Engine::default();
-    let module = Module::new(&engine, &artifact.wasm).context("failed to compile wasm artifact")?;
-    let linker = Linker::new(&engine);
-    let mut store = Store::new(&engine, ());
-    let instance = linker
-        .instantiate_and_start(&mut store, &module)
-        .context("failed to instantiate module")?;
-    let main = instance
-        .get_typed_func::<(), i32>(&store, "main")
-        .context("exported main function missing or has wrong type")?;
-    main.call(&mut store, ()).context("failed to execute main")
+/// Run `main` and return its result as text. A failed `assert` is
+/// reported as a diagnostic.
+fn run_wasm(
+    artifact: &CompilationArtifact,
+    file_id: FileId,
+    name: &str,
+    source: &str,
+    cli: &Cli,
+) -> Result<String> {
+    let mut program = Program::new(&artifact.wasm, Host::new())?;
+    let result = match program.call("main") {
+        Ok(result) => result,
+        Err(e) => {
+            let Some(diagnostic) = program.host().assertion_failure(&artifact.assertions) else {
+                return Err(e.context("failed to execute main"));
+            };
+            print_diagnostics(&[diagnostic], file_id, name, source, cli);
+            return Err(failure(cli, "failed to execute main".into()));
+        }
+    };
+    Ok(match result {
+        Some(Val::I32(v)) => v.to_string(),
+        Some(Val::I64(v)) => v.to_string(),
+        Some(Val::F32(v)) => format!("{:?}", f32::from(v)),
+        Some(Val::F64(v)) => format!("{:?}", f64::from(v)),
+        Some(other) => format!("{other:?}"),
+        None => "()".to_string(),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use nepl_core::lint;
 
     #[test]
     fn cli_parses_defaults() {
@@ -116,4 +584,134 @@ mod tests {
         assert_eq!(cli.emit, "wasm");
         assert!(!cli.run);
     }
+
+    #[test]
+    fn explain_does_not_need_an_output() {
+        let cli = Cli::parse_from(["nepl-cli", "explain", "E0300"]);
+        assert!(matches!(cli.command, Some(Command::Explain { code }) if code == "E0300"));
+        assert!(Cli::try_parse_from(["nepl-cli"]).is_err());
+    }
+
+    #[test]
+    fn lang_flag_selects_the_locale() {
+        let cli = Cli::parse_from(["nepl-cli", "--lang", "ja", "explain", "E0300"]);
+        assert_eq!(locale(&cli), Locale::Ja);
+        let cli = Cli::parse_from(["nepl-cli", "--output", "out.wasm", "--lang", "en_US.UTF-8"]);
+        assert_eq!(locale(&cli), Locale::En);
+        assert!(Cli::try_parse_from(["nepl-cli", "--output", "out.wasm", "--lang", "fr"]).is_err());
+    }
+
+    #[test]
+    fn lint_flags_set_levels() {
+        let args = "nepl-cli -o out.wasm -A unused-variables -W shadowing -D shadowing";
+        let cli = Cli::parse_from(args.split(' ').chain(["--deny-warnings"]));
+        let config = lint_config(&cli).unwrap();
+        assert_eq!(config.level(&lint::UNUSED_VARIABLES), Level::Allow);
+        assert_eq!(config.level(&lint::SHADOWING), Level::Deny);
+        assert!(config.deny_warnings);
+
+        let cli = Cli::parse_from(["nepl-cli", "-o", "out.wasm", "-W", "no_such_lint"]);
+        let err = lint_config(&cli).unwrap_err();
+        assert_eq!(err.to_string(), "unknown lint `no_such_lint`");
+    }
+
+    #[test]
+    fn deny_warnings_fails_the_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("main.nepl");
+        let output = dir.path().join("main.wasm");
+        fs::write(&input, "{ let unused = 1; 0 }").unwrap();
+        let args = [
+            "nepl-cli",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ];
+
+        execute(Cli::parse_from(args)).unwrap();
+        assert!(output.exists());
+
+        let denied = Cli::parse_from(args.iter().copied().chain(["--deny-warnings"]));
+        assert!(execute(denied).is_err());
+    }
+
+    #[test]
+    fn fmt_rewrites_or_checks_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.nepl");
+        let path_arg = path.to_str().unwrap();
+        fs::write(&path, "{ let x=1 ;x }").unwrap();
+
+        let check = Cli::parse_from(["nepl-cli", "fmt", "--check", path_arg]);
+        assert_eq!(
+            execute(check).unwrap_err().to_string(),
+            "1 file is not formatted"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ let x=1 ;x }");
+
+        execute(Cli::parse_from(["nepl-cli", "fmt", path_arg])).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ let x = 1; x }\n");
+        execute(Cli::parse_from(["nepl-cli", "fmt", "--check", path_arg])).unwrap();
+
+        fs::write(&path, "{ let x = ; x }").unwrap();
+        let cli = Cli::parse_from(["nepl-cli", "--color", "never", "fmt", path_arg]);
+        assert!(execute(cli).is_err());
+    }
+
+    #[test]
+    fn explain_parse_shows_implied_parentheses() {
+        let source = "{\n    let x = add 1 mul 2 3;\n    neg add x neg 1\n}";
+        let result = call_tree::resolve_calls(FileId(0), source);
+        assert_eq!(
+            explain_line(source, &result.seqs, 1).unwrap(),
+            "  add 1 mul 2 3\n= add 1 (mul 2 3)\n"
+        );
+        assert_eq!(
+            explain_line(source, &result.seqs, 2).unwrap(),
+            "  neg add x neg 1\n= neg (add x (neg 1))\n"
+        );
+        assert_eq!(
+            explain_line(source, &result.seqs, 0).unwrap_err().to_string(),
+            "no P-style sequence starts on line 1"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.nepl");
+        fs::write(&path, source).unwrap();
+        let location = format!("{}:2", path.display());
+        execute(Cli::parse_from(["nepl-cli", "explain-parse", &location])).unwrap();
+        let cli = Cli::parse_from(["nepl-cli", "explain-parse", "main.nepl"]);
+        assert_eq!(
+            execute(cli).unwrap_err().to_string(),
+            "expected FILE:LINE, found `main.nepl`"
+        );
+    }
+
+    #[test]
+    fn fix_rewrites_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.nepl");
+        fs::write(&path, "{ let count = 1; set count add coutn 1; count }").unwrap();
+
+        let cli = Cli::parse_from(["nepl-cli", "--color", "never", "fix", path.to_str().unwrap()]);
+        execute(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{ let mut count = 1; set count add count 1; count }"
+        );
+    }
+
+    #[test]
+    fn fix_leaves_assigned_variables_compiling() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.nepl");
+        fs::write(&path, "{ let unused = 1; let mut x = 1; set x 2; 0 }").unwrap();
+
+        let cli = Cli::parse_from(["nepl-cli", "--color", "never", "fix", path.to_str().unwrap()]);
+        execute(cli).unwrap();
+        let fixed = fs::read_to_string(&path).unwrap();
+        assert_eq!(fixed, "{ let _unused = 1; let mut x = 1; set x 2; 0 }");
+        nepl_core::compile_wasm(FileId(0), &fixed).unwrap();
+    }
 }
@@ -0,0 +1,867 @@
+//! `nepl repl`: an interactive session.
+//!
+//! The session keeps the source of every definition accepted so far
+//! (`let`, `fn`, `struct`, `enum`, `namespace`, `use`). Each input is
+//! compiled together with them as one block, `{ defs; input }`, and the
+//! module is run in wasmi. Scalar `let` bindings are stored with the
+//! value they evaluated to, so that side effects of their initialisers
+//! are not repeated by later inputs. Other `let`s are replayed from
+//! source, so they are only accepted when their initialiser is pure.
+//!
+//! Nothing carries values from one run to the next, so an input that
+//! assigns a session binding with `set` is kept and replayed too, and
+//! is likewise only accepted when it is pure.
+
+use std::fs;
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use nepl_core::ast::{Expr, ExprKind, LetExpr};
+use nepl_core::builtins::builtins;
+use nepl_core::diagnostic::{Diagnostic, Severity};
+use nepl_core::hir::{HirExpr, HirExprKind, HirFunction};
+use nepl_core::lexer::{self, TokenKind};
+use nepl_core::lint::{Level, LintConfig};
+use nepl_core::messages::{self, Locale};
+use nepl_core::parser;
+use nepl_core::render::Renderer;
+use nepl_core::span::{FileId, Span};
+use nepl_core::typecheck;
+use nepl_core::types::{ArrowKind, Type};
+use nepl_core::{CompilationArtifact, CoreError, compile_wasm_with};
+use wasmi::Val;
+
+use crate::host::{self, Host};
+
+const PROMPT: &str = "nepl> ";
+const CONTINUATION: &str = "....> ";
+const FILE_ID: FileId = FileId(0);
+
+const HELP: &str = "\
+Enter an expression to evaluate it, or a definition to keep it in scope.
+  :type EXPR    show the type of an expression
+  :hir EXPR     show the typed HIR of an expression
+  :load FILE    add the definitions of a file to the session
+  :help         show this message
+  :quit         leave the session
+";
+
+/// The state of an interactive session.
+pub struct Repl {
+    /// Source of the definitions accepted so far, in order.
+    definitions: Vec<String>,
+    lints: LintConfig,
+    color: bool,
+    locale: Locale,
+}
+
+/// A program built from the session's definitions and one input.
+struct Program {
+    source: String,
+    /// Byte offset of the input in `source`.
+    offset: u32,
+    input: String,
+}
+
+impl Repl {
+    /// Start an empty session. Unused bindings and shadowing are normal
+    /// in a session, so those lints are always allowed.
+    pub fn new(mut lints: LintConfig, color: bool, locale: Locale) -> Repl {
+        for lint in ["unused_variables", "unused_uses", "shadowing"] {
+            lints.set(lint, Level::Allow).expect("known lint names");
+        }
+        Repl {
+            definitions: Vec::new(),
+            lints,
+            color,
+            locale,
+        }
+    }
+
+    /// Handle one complete input and return what to print, or `None`
+    /// when the session should end.
+    pub fn eval(&mut self, input: &str) -> Option<String> {
+        let input = input.trim();
+        let (command, argument) = match input.strip_prefix(':') {
+            Some(rest) => {
+                let (command, argument) =
+                    rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                (Some(command), argument.trim())
+            }
+            None => (None, input),
+        };
+        let reply = match command {
+            None if input.is_empty() => Ok(String::new()),
+            None => self.input(input),
+            Some("type" | "t") => self.type_of(argument),
+            Some("hir") => self.hir(argument),
+            Some("load" | "l") => self.load(argument),
+            Some("help" | "h" | "?") => Ok(HELP.to_string()),
+            Some("quit" | "q") => return None,
+            Some(other) => {
+                let args = [("command", other.to_string())];
+                Ok(self.message("repl.unknown-command", &args))
+            }
+        };
+        Some(reply.unwrap_or_else(|error| error))
+    }
+
+    /// Evaluate an expression or accept definitions.
+    fn input(&mut self, input: &str) -> Result<String, String> {
+        let program = self.program(input);
+        let items = program.items()?;
+        let Some(last) = items.last() else {
+            return Ok(String::new());
+        };
+        let assigning = self.assignments(&items)?;
+        let assigns = |item: &Expr| assigning.iter().any(|(span, _)| *span == item.span);
+
+        // Items that are replayed from source must be pure. The last
+        // `let` is stored as a literal instead when its value is a
+        // scalar and it assigns no session binding.
+        let lets: Vec<&Expr> = items
+            .iter()
+            .filter(|item| matches!(item.kind, ExprKind::Let(_)) && is_definition(item))
+            .collect();
+        let stored = |item: &Expr| std::ptr::eq(item, last) && !assigns(item);
+        if !lets.is_empty() || !assigning.is_empty() {
+            let typed = self.typecheck(&program, &program)?;
+            for item in lets {
+                self.check_replayable(item, &typed, stored(item))?;
+            }
+            for (span, name) in &assigning {
+                let pure = hir_of(&typed, *span).is_none_or(|e| is_pure(&e, &typed.functions));
+                if !pure {
+                    return Err(self.error("repl.impure-set", &[("name", name.clone())]));
+                }
+            }
+        }
+
+        let mut out = String::new();
+        let mut definitions: Vec<String> = items
+            .iter()
+            .filter(|item| is_definition(item) || assigns(item))
+            .map(|item| program.text(item.span).to_string())
+            .collect();
+        match &last.kind {
+            ExprKind::Let(LetExpr { name, is_mut, .. }) if name.name != "_" => {
+                // Evaluate the binding and show its value.
+                let shown = self.program(&format!("{input};\n{}", name.name));
+                let (ty, value, warnings) = self.run(&shown, &program)?;
+                out.push_str(&warnings);
+                out.push_str(&value.printed);
+                let value_text = value.display(&ty);
+                out.push_str(&format!("{}: {ty} = {value_text}\n", name.name));
+                if stored(last)
+                    && let Some(literal) = value.literal(&ty)
+                {
+                    let mutability = if *is_mut { "mut " } else { "" };
+                    *definitions.last_mut().unwrap() =
+                        format!("let {mutability}{}: {ty} = {literal}", name.name);
+                }
+            }
+            ExprKind::LetFunction(f) => {
+                let shown = self.program(&format!("{input};\n{}", f.name.name));
+                let (ty, warnings) = self.check(&shown, &program)?;
+                out.push_str(&warnings);
+                out.push_str(&format!("fn {}: {ty}\n", f.name.name));
+            }
+            ExprKind::EnumDef(def) => {
+                out.push_str(&self.check(&program, &program)?.1);
+                out.push_str(&format!("enum {}\n", def.name.name));
+            }
+            ExprKind::StructDef(def) => {
+                out.push_str(&self.check(&program, &program)?.1);
+                out.push_str(&format!("struct {}\n", def.name.name));
+            }
+            _ if is_definition(last) => {
+                out.push_str(&self.check(&program, &program)?.1);
+            }
+            _ => {
+                let (ty, value, warnings) = self.run(&program, &program)?;
+                out.push_str(&warnings);
+                out.push_str(&value.printed);
+                if !matches!(ty, Type::Unit | Type::Never) {
+                    out.push_str(&format!("{}: {ty}\n", value.display(&ty)));
+                }
+            }
+        }
+        self.definitions.append(&mut definitions);
+        Ok(out)
+    }
+
+    /// `:type EXPR`
+    fn type_of(&self, input: &str) -> Result<String, String> {
+        let program = self.program(input);
+        let (ty, warnings) = self.check(&program, &program)?;
+        Ok(format!("{warnings}{ty}\n"))
+    }
+
+    /// `:hir EXPR`
+    fn hir(&self, input: &str) -> Result<String, String> {
+        let program = self.program(input);
+        let items = program.items()?;
+        let Some(last) = items.last() else {
+            return Ok(String::new());
+        };
+        let typed = self.typecheck(&program, &program)?;
+        match hir_of(&typed, last.span) {
+            Some(mut expr) => {
+                // Show spans into the input, as diagnostics do.
+                expr.map_spans(&mut |span| program.shift_span(span));
+                Ok(format!("{expr:#?}\n"))
+            }
+            None => Ok(self.message("repl.no-hir", &[("input", input.to_string())])),
+        }
+    }
+
+    /// `:load FILE`
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        if path.is_empty() {
+            return Err(self.message("repl.load-usage", &[]));
+        }
+        let source = fs::read_to_string(path).map_err(|e| {
+            self.error("repl.cannot-read", &[("path", path.to_string()), ("error", e.to_string())])
+        })?;
+        let parsed = parser::parse_file(FILE_ID, &source);
+        if let Err(CoreError::Diagnostics(diagnostics)) =
+            compile_wasm_with(FILE_ID, &source, &self.lints)
+        {
+            return Err(self.render(path, &source, &diagnostics));
+        }
+        let items = match &parsed.expr {
+            Some(Expr {
+                kind: ExprKind::Block(block),
+                ..
+            }) => block.expressions.iter().collect(),
+            Some(expr) => vec![expr],
+            None => Vec::new(),
+        };
+        let definitions: Vec<String> = items
+            .into_iter()
+            .filter(|item| is_definition(item))
+            .map(|item| source[item.span.start as usize..item.span.end as usize].to_string())
+            .collect();
+
+        // The definitions must also fit with the ones of the session.
+        let joined = definitions.join(";\n");
+        let program = self.program(&joined);
+        let typed = self.typecheck(&program, &program)?;
+        for item in program.items()? {
+            if matches!(item.kind, ExprKind::Let(_)) {
+                self.check_replayable(&item, &typed, false)?;
+            }
+        }
+        let count = definitions.len();
+        self.definitions.extend(definitions);
+        let args = [("count", count.to_string()), ("path", path.to_string())];
+        Ok(self.message("repl.loaded", &args))
+    }
+
+    /// Check that the `let` `item` can be kept in the session: its
+    /// initialiser is replayed by every later input, so it must be pure,
+    /// unless the value is `stored` as a literal.
+    fn check_replayable(
+        &self,
+        item: &Expr,
+        typed: &typecheck::TypeCheckResult,
+        stored: bool,
+    ) -> Result<(), String> {
+        let ExprKind::Let(binding) = &item.kind else {
+            return Ok(());
+        };
+        let mut init = None;
+        if let Some(root) = &typed.expr {
+            root.walk(&mut |e: &HirExpr| {
+                if let HirExprKind::Let {
+                    name, init: value, ..
+                } = &e.kind
+                    && name.span == binding.name.span
+                {
+                    init = Some(value.as_ref().clone());
+                }
+            });
+        }
+        let Some(init) = init else {
+            return Ok(());
+        };
+        if (stored && is_scalar(&init.ty)) || is_pure(&init, &typed.functions) {
+            return Ok(());
+        }
+        let key = match is_scalar(&init.ty) {
+            true => "repl.impure-let.scalar",
+            false => "repl.impure-let",
+        };
+        Err(self.error(key, &[("name", binding.name.name.clone())]))
+    }
+
+    /// The catalog message `key` in the session's language, as a line.
+    fn message(&self, key: &str, args: &[(&str, String)]) -> String {
+        format!("{}\n", messages::format(self.locale, key, args))
+    }
+
+    /// An error line with the catalog message `key`.
+    fn error(&self, key: &str, args: &[(&str, String)]) -> String {
+        format!("error: {}", self.message(key, args))
+    }
+
+    /// The items of `items` that assign a binding kept in the session,
+    /// one of earlier inputs or a top-level `let` of this one, with the
+    /// name of the first such binding. Bindings declared in nested
+    /// scopes, as `let`s or parameters, are not kept.
+    fn assignments(&self, items: &[Expr]) -> Result<Vec<(Span, String)>, String> {
+        let session = self.program("").items_before()?;
+        let kept: Vec<&str> = session
+            .iter()
+            .chain(items)
+            .filter_map(|item| match &item.kind {
+                ExprKind::Let(binding) => Some(binding.name.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        let mut assigning = Vec::new();
+        for item in items {
+            let mut nested: Vec<&str> = Vec::new();
+            let mut targets: Vec<&str> = Vec::new();
+            item.walk(&mut |e| match &e.kind {
+                ExprKind::Let(binding) if !std::ptr::eq(e, item) => nested.push(&binding.name.name),
+                ExprKind::FuncLiteral(f) => {
+                    nested.extend(f.params.iter().map(|p| p.name.name.as_str()))
+                }
+                ExprKind::Set(set) => {
+                    if let ExprKind::Ident(target) = &set.target.base.kind {
+                        targets.push(&target.name);
+                    }
+                }
+                _ => {}
+            });
+            if let Some(name) = targets
+                .into_iter()
+                .find(|name| kept.contains(name) && !nested.contains(name))
+            {
+                assigning.push((item.span, name.to_string()));
+            }
+        }
+        Ok(assigning)
+    }
+
+    /// The session's definitions followed by `input`, as one block.
+    fn program(&self, input: &str) -> Program {
+        let mut source = String::from("{\n");
+        for definition in &self.definitions {
+            source.push_str(definition);
+            source.push_str(";\n");
+        }
+        let offset = source.len() as u32;
+        let input = input.trim_end().trim_end_matches(';').to_string();
+        source.push_str(&input);
+        source.push_str("\n}");
+        Program {
+            source,
+            offset,
+            input,
+        }
+    }
+
+    /// Type-check `program` and return its type and rendered warnings.
+    /// Diagnostics are shown against `shown`, the program the user typed.
+    fn check(&self, program: &Program, shown: &Program) -> Result<(Type, String), String> {
+        let typed = self.typecheck(program, shown)?;
+        let ty = typed.expr.map_or(Type::Unit, |e| e.ty);
+        let warnings = nepl_core::check(FILE_ID, &program.source, &self.lints);
+        Ok((ty, shown.render(self, &warnings)))
+    }
+
+    fn typecheck(
+        &self,
+        program: &Program,
+        shown: &Program,
+    ) -> Result<typecheck::TypeCheckResult, String> {
+        let diagnostics = nepl_core::check(FILE_ID, &program.source, &self.lints);
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            return Err(shown.render(self, &diagnostics));
+        }
+        let parsed = parser::parse_file(FILE_ID, &program.source);
+        let expr = parsed.expr.expect("checked programs parse");
+        Ok(typecheck::typecheck_expr(&expr))
+    }
+
+    /// Compile and run `program`; return the type and value of its
+    /// result and rendered warnings.
+    fn run(&self, program: &Program, shown: &Program) -> Result<(Type, Value, String), String> {
+        let artifact =
+            compile_wasm_with(FILE_ID, &program.source, &self.lints).map_err(|e| match e {
+                CoreError::Diagnostics(diagnostics) => shown.render(self, &diagnostics),
+                other => format!("{other}\n"),
+            })?;
+        let warnings = shown.render(self, &artifact.warnings);
+        let (ty, _) = self.check(program, shown)?;
+        let value = execute(&artifact).map_err(|failure| match failure {
+            Failure::Assertion(printed, diagnostic) => {
+                format!("{printed}{}", shown.render(self, &[*diagnostic]))
+            }
+            Failure::Error(e) => format!("error: {e:#}\n"),
+        })?;
+        Ok((ty, value, warnings))
+    }
+
+    fn render(&self, name: &str, source: &str, diagnostics: &[Diagnostic]) -> String {
+        if diagnostics.is_empty() {
+            return String::new();
+        }
+        let mut renderer = Renderer::new()
+            .with_color(self.color)
+            .with_locale(self.locale);
+        renderer.add_file(FILE_ID, name, source);
+        renderer.render_all(diagnostics)
+    }
+}
+
+impl Program {
+    /// The top-level items that came from the input.
+    fn items(&self) -> Result<Vec<Expr>, String> {
+        Ok(self
+            .all_items()?
+            .into_iter()
+            .filter(|item| item.span.start >= self.offset)
+            .collect())
+    }
+
+    /// The top-level items of the session's definitions.
+    fn items_before(&self) -> Result<Vec<Expr>, String> {
+        Ok(self
+            .all_items()?
+            .into_iter()
+            .filter(|item| item.span.start < self.offset)
+            .collect())
+    }
+
+    fn all_items(&self) -> Result<Vec<Expr>, String> {
+        let parsed = parser::parse_file(FILE_ID, &self.source);
+        let errors: Vec<Diagnostic> = parsed
+            .diagnostics
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .collect();
+        if !errors.is_empty() {
+            return Err(self.render_plain(&errors));
+        }
+        let Some(Expr {
+            kind: ExprKind::Block(block),
+            ..
+        }) = parsed.expr
+        else {
+            return Ok(Vec::new());
+        };
+        Ok(block.expressions)
+    }
+
+    fn text(&self, span: Span) -> &str {
+        &self.source[span.start as usize..span.end as usize]
+    }
+
+    /// Render `diagnostics` of the program against the input alone.
+    fn render(&self, repl: &Repl, diagnostics: &[Diagnostic]) -> String {
+        let shifted: Vec<Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.primary.span.end >= self.offset)
+            .map(|d| self.shift(d.clone()))
+            .collect();
+        repl.render("<repl>", &self.input, &shifted)
+    }
+
+    fn render_plain(&self, diagnostics: &[Diagnostic]) -> String {
+        let shifted: Vec<Diagnostic> = diagnostics.iter().map(|d| self.shift(d.clone())).collect();
+        let mut renderer = Renderer::new();
+        renderer.add_file(FILE_ID, "<repl>", &self.input);
+        renderer.render_all(&shifted)
+    }
+
+    /// Move `span` from the program into the input.
+    fn shift_span(&self, span: &mut Span) {
+        let (offset, len) = (self.offset, self.input.len() as u32);
+        span.start = span.start.saturating_sub(offset).min(len);
+        span.end = span.end.saturating_sub(offset).min(len);
+    }
+
+    /// Move the spans of `diagnostic` from the program into the input.
+    fn shift(&self, mut diagnostic: Diagnostic) -> Diagnostic {
+        self.shift_span(&mut diagnostic.primary.span);
+        for label in &mut diagnostic.secondary {
+            self.shift_span(&mut label.span);
+        }
+        for suggestion in &mut diagnostic.suggestions {
+            for edit in &mut suggestion.edits {
+                self.shift_span(&mut edit.span);
+            }
+        }
+        diagnostic
+    }
+}
+
+/// Items kept in scope for later inputs. `let _` binds nothing.
+fn is_definition(item: &Expr) -> bool {
+    match &item.kind {
+        ExprKind::Let(binding) => binding.name.name != "_",
+        kind => matches!(
+            kind,
+            ExprKind::LetFunction(_)
+                | ExprKind::EnumDef(_)
+                | ExprKind::StructDef(_)
+                | ExprKind::Namespace(_)
+                | ExprKind::Use(_)
+        ),
+    }
+}
+
+/// The HIR of the top-level item at `span` of a checked program.
+fn hir_of(typed: &typecheck::TypeCheckResult, span: Span) -> Option<HirExpr> {
+    let mut found = None;
+    if let Some(root) = &typed.expr {
+        root.walk(&mut |e: &HirExpr| {
+            if found.is_none() && e.span == span {
+                found = Some(e.clone());
+            }
+        });
+    }
+    found
+}
+
+/// Types whose values are stored as literals.
+fn is_scalar(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::I32 | Type::I64 | Type::F32 | Type::F64 | Type::Bool
+    )
+}
+
+/// Whether evaluating `e` calls no impure function or builtin, so that
+/// evaluating it again has no effects and gives the same value.
+fn is_pure(e: &HirExpr, functions: &[HirFunction]) -> bool {
+    let mut pure = true;
+    e.walk(&mut |e: &HirExpr| match &e.kind {
+        HirExprKind::Call { callee, .. } => {
+            pure &= functions
+                .iter()
+                .any(|f| f.name.name == callee.name && f.arrow == ArrowKind::Pure);
+        }
+        HirExprKind::CallIndirect { callee, .. } => {
+            pure &= matches!(
+                callee.ty,
+                Type::Function {
+                    arrow: ArrowKind::Pure,
+                    ..
+                }
+            );
+        }
+        HirExprKind::BuiltinCall { kind, .. } => {
+            pure &= builtins().iter().any(|b| {
+                b.kind == *kind
+                    && matches!(
+                        b.ty,
+                        Type::Function {
+                            arrow: ArrowKind::Pure,
+                            ..
+                        }
+                    )
+            });
+        }
+        _ => {}
+    });
+    pure
+}
+
+/// The result of running a program.
+struct Value {
+    result: Option<Val>,
+    /// What the program printed with `print_i32`.
+    printed: String,
+}
+
+impl Value {
+    fn display(&self, ty: &Type) -> String {
+        match (&self.result, ty) {
+            (Some(Val::I32(v)), Type::I32) => v.to_string(),
+            (Some(Val::I32(v)), Type::Bool) => (*v != 0).to_string(),
+            (Some(Val::I64(v)), _) => v.to_string(),
+            (Some(Val::F32(v)), _) => format!("{:?}", f32::from(*v)),
+            (Some(Val::F64(v)), _) => format!("{:?}", f64::from(*v)),
+            (None, _) => "()".to_string(),
+            _ => format!("<{ty}>"),
+        }
+    }
+
+    /// Source text that evaluates to the value, for scalar types.
+    /// Values without a literal of their own (the minimum integers,
+    /// infinities and NaN) are written as operations on literals.
+    fn literal(&self, ty: &Type) -> Option<String> {
+        let signed = |negative: bool, digits: String| {
+            if negative {
+                format!("neg {digits}")
+            } else {
+                digits
+            }
+        };
+        let float = |v: f64, suffix: &str| {
+            if v.is_nan() {
+                format!("div 0.0{suffix} 0.0{suffix}")
+            } else if v.is_infinite() {
+                signed(v < 0.0, format!("div 1.0{suffix} 0.0{suffix}"))
+            } else {
+                signed(v < 0.0, format!("{:?}{suffix}", v.abs()))
+            }
+        };
+        match (&self.result, ty) {
+            (Some(Val::I32(i32::MIN)), Type::I32) => Some("sub neg 2147483647 1".to_string()),
+            (Some(Val::I32(v)), Type::I32) => Some(signed(*v < 0, v.unsigned_abs().to_string())),
+            (Some(Val::I32(v)), Type::Bool) => Some((*v != 0).to_string()),
+            (Some(Val::I64(i64::MIN)), Type::I64) => {
+                Some("sub neg 9223372036854775807i64 1i64".to_string())
+            }
+            (Some(Val::I64(v)), Type::I64) => {
+                Some(signed(*v < 0, format!("{}i64", v.unsigned_abs())))
+            }
+            (Some(Val::F32(v)), Type::F32) => {
+                let v = f32::from(*v);
+                // Through `f64`, `{:?}` would print digits that do not
+                // round-trip as an `f32` literal.
+                Some(match v.is_finite() {
+                    true => signed(v < 0.0, format!("{:?}f32", v.abs())),
+                    false => float(v as f64, "f32"),
+                })
+            }
+            (Some(Val::F64(v)), Type::F64) => Some(float(f64::from(*v), "")),
+            _ => None,
+        }
+    }
+}
+
+/// Why running a program failed.
+enum Failure {
+    /// An `assert` failed, after the program printed the text.
+    Assertion(String, Box<Diagnostic>),
+    Error(anyhow::Error),
+}
+
+/// Instantiate the module and call its `main`, capturing what it prints.
+fn execute(artifact: &CompilationArtifact) -> Result<Value, Failure> {
+    let mut program = host::Program::new(&artifact.wasm, Host::capturing()).map_err(Failure::Error)?;
+    let called = program.call("main");
+    let printed = std::mem::take(&mut program.host_mut().printed);
+    match called {
+        Ok(result) => Ok(Value { result, printed }),
+        Err(e) => Err(match program.host().assertion_failure(&artifact.assertions) {
+            Some(diagnostic) => Failure::Assertion(printed, Box::new(diagnostic)),
+            None => Failure::Error(anyhow::anyhow!("{printed}{e}")),
+        }),
+    }
+}
+
+/// Whether `buffer` is a whole input: brackets are balanced, and an
+/// offside block (a line ending in `:`) has been closed by a blank line.
+pub fn is_complete(buffer: &str) -> bool {
+    if buffer.trim_start().starts_with(':') {
+        return true;
+    }
+    let mut depth = 0i32;
+    for token in lexer::lex(FILE_ID, buffer).tokens {
+        match token.kind {
+            TokenKind::LParen | TokenKind::LBrace | TokenKind::LBracket => depth += 1,
+            TokenKind::RParen | TokenKind::RBrace | TokenKind::RBracket => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return false;
+    }
+    let offside = buffer.lines().any(|line| line.trim_end().ends_with(':'));
+    !offside || buffer.ends_with("\n\n")
+}
+
+/// Run a session reading from `input` until it ends or `:quit`.
+/// Prompts are written only when `prompt` is set.
+pub fn run(
+    repl: &mut Repl,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    prompt: bool,
+) -> Result<()> {
+    let mut buffer = String::new();
+    loop {
+        if prompt {
+            let text = if buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION
+            };
+            write!(output, "{text}")?;
+            output.flush()?;
+        }
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            if let Some(reply) = repl.eval(&buffer) {
+                write!(output, "{reply}")?;
+            }
+            return Ok(());
+        }
+        if buffer.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        buffer.push_str(&line);
+        if !buffer.ends_with('\n') {
+            buffer.push('\n');
+        }
+        if !is_complete(&buffer) {
+            continue;
+        }
+        let Some(reply) = repl.eval(&buffer) else {
+            return Ok(());
+        };
+        write!(output, "{reply}")?;
+        buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(lines: &str) -> String {
+        let mut repl = Repl::new(LintConfig::new(), false, Locale::En);
+        let mut output = Vec::new();
+        run(&mut repl, &mut lines.as_bytes(), &mut output, false).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn bindings_stay_in_scope() {
+        let out = session(
+            "let x = add 1 2\n\
+             fn double = |i32 n|->i32 add n n\n\
+             double x\n\
+             let mut y = neg 2.5\n\
+             add x 10 > double\n\
+             y\n",
+        );
+        assert_eq!(
+            out,
+            "x: i32 = 3\n\
+             fn double: (i32) -> i32\n\
+             6: i32\n\
+             y: f64 = -2.5\n\
+             26: i32\n\
+             -2.5: f64\n"
+        );
+    }
+
+    #[test]
+    fn initialisers_run_once() {
+        let out = session("let x = { print_i32 7; 5 }\nadd x 1\n");
+        assert_eq!(out, "7\nx: i32 = 5\n6: i32\n");
+    }
+
+    #[test]
+    fn assignments_to_kept_bindings_are_kept() {
+        let out = session(
+            "let mut m = 5\n\
+             set m add m 1\n\
+             m\n\
+             let mut d = 1; set d mul d 3; d\n\
+             let x = { set m 10; m }\n\
+             add x add m d\n",
+        );
+        assert_eq!(out, "m: i32 = 5\n6: i32\n3: i32\nx: i32 = 10\n23: i32\n");
+
+        // An assignment with side effects cannot be replayed.
+        let out = session("let mut c = 0\nset c random_i32\nc\n");
+        assert_eq!(
+            out,
+            "c: i32 = 0\n\
+             error: this input assigns `c`, which is kept in the session, so it is replayed \
+             by every later input; it cannot be, because it has side effects that would run \
+             again\n\
+             0: i32\n"
+        );
+        // Bindings of nested scopes can be assigned.
+        let out = session("{ let mut c = 0; set c 10; c }\n");
+        assert_eq!(out, "10: i32\n");
+    }
+
+    #[test]
+    fn only_pure_initialisers_are_replayed() {
+        let out = session(
+            "let f = { print_i32 9; |i32 x|->i32 x }\n\
+             let g = |i32 x|->i32 add x 1\n\
+             g 1\n\
+             let n = random_i32; n\n\
+             let m = sub neg 2147483647 1\n\
+             m\n",
+        );
+        assert_eq!(
+            out,
+            "error: `let f` cannot be kept in the session: its initialiser has side effects \
+             that would run again on every later input\n\
+             g: (i32) -> i32 = <(i32) -> i32>\n\
+             2: i32\n\
+             error: `let n` cannot be kept in the session: its initialiser has side effects \
+             that would run again on every later input; enter it as an input of its own to \
+             keep its value\n\
+             m: i32 = -2147483648\n\
+             -2147483648: i32\n"
+        );
+    }
+
+    #[test]
+    fn types_and_multi_line_input() {
+        let out = session(
+            "enum Shape {\n  Circle(i32);\n  Square(i32)\n}\n\
+             :type Circle 3\n\
+             fn inc = |i32 n|->i32:\n    add n 1\n\n\
+             inc 4\n\
+             :type lt 1 2\n",
+        );
+        assert_eq!(
+            out,
+            "enum Shape\nShape\nfn inc: (i32) -> i32\n5: i32\nBool\n"
+        );
+    }
+
+    #[test]
+    fn errors_point_into_the_input() {
+        let out = session("let x = 1\nadd x true\nx\n");
+        assert!(out.contains("<repl>:1:7"), "{out}");
+        assert!(out.contains("add x true"), "{out}");
+        // The failed input does not disturb the session.
+        assert!(out.ends_with("1: i32\n"), "{out}");
+    }
+
+    #[test]
+    fn commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("defs.nepl");
+        fs::write(&path, "{\n    fn sq = |i32 n|->i32 mul n n;\n    sq 3\n}").unwrap();
+        let out = session(&format!(
+            ":load {}\nsq 4\n:hir sq 12\n:quit\n2\n",
+            path.display()
+        ));
+        assert!(out.starts_with("loaded 1 definitions from "), "{out}");
+        assert!(out.contains("16: i32\n"), "{out}");
+        assert!(out.contains("I32(\n"), "{out}");
+        // Spans of the HIR are offsets into the input.
+        assert!(out.contains("start: 0,\n"), "{out}");
+        assert!(out.contains("start: 3,\n"), "{out}");
+        assert!(out.contains("end: 5,\n"), "{out}");
+        // Nothing is evaluated after `:quit`.
+        assert!(!out.contains("2: i32"), "{out}");
+        assert!(session(":help\n").contains(":type EXPR"));
+    }
+
+    #[test]
+    fn completeness() {
+        assert!(is_complete("add 1 2\n"));
+        assert!(!is_complete("fn f = |i32 n|->i32 {\n"));
+        assert!(!is_complete("while b:\n  f 1\n"));
+        assert!(is_complete("while b:\n  f 1\n\n"));
+        assert!(is_complete(":type x\n"));
+    }
+}
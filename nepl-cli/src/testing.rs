@@ -0,0 +1,188 @@
+//! `nepl test`: compile the `test` declarations of source files and
+//! run each in a fresh instance of the module.
+//!
+//! Progress and a summary are printed to stdout in the style of
+//! `cargo test`; the diagnostics of failed tests go to stderr in the
+//! chosen error format.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use nepl_core::CompilationArtifact;
+use nepl_core::codegen_wasm::TestExport;
+use nepl_core::compile_tests;
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::span::FileId;
+
+use crate::host::{Host, Program};
+use crate::{Cli, Reported, failure, lint_config, print_diagnostics, report};
+
+/// A test that did not pass.
+struct Failure {
+    name: String,
+    /// What the test printed before it failed.
+    printed: String,
+    diagnostic: Diagnostic,
+}
+
+/// Run the tests of `files`. Fails if a file does not compile or a test
+/// fails.
+pub fn run(files: &[PathBuf], cli: &Cli) -> Result<()> {
+    let file_id = FileId(0);
+    let lints = lint_config(cli)?;
+    let (mut passed, mut failed, mut broken) = (0, 0, 0);
+    for path in files {
+        let name = path.display().to_string();
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read input file {name}"))?;
+        let artifact = match compile_tests(file_id, &source, &lints) {
+            Ok(artifact) => artifact,
+            Err(e) => {
+                let e = report(e, file_id, &name, &source, cli);
+                if !e.is::<Reported>() {
+                    eprintln!("{e:#}");
+                }
+                broken += 1;
+                continue;
+            }
+        };
+        print_diagnostics(&artifact.warnings, file_id, &name, &source, cli);
+
+        match artifact.tests.len() {
+            1 => println!("running 1 test in {name}"),
+            n => println!("running {n} tests in {name}"),
+        }
+        let mut failures = Vec::new();
+        for test in &artifact.tests {
+            match run_test(&artifact, test)? {
+                None => {
+                    println!("test {} ... ok", test.name);
+                    passed += 1;
+                }
+                Some(failure) => {
+                    println!("test {} ... FAILED", test.name);
+                    failures.push(failure);
+                    failed += 1;
+                }
+            }
+        }
+        if !failures.is_empty() {
+            for failure in failures.iter().filter(|f| !f.printed.is_empty()) {
+                println!("\n---- {} stdout ----", failure.name);
+                print!("{}", failure.printed);
+            }
+            println!("\nfailures:");
+            for failure in &failures {
+                println!("    {}", failure.name);
+            }
+            println!();
+            let diagnostics: Vec<Diagnostic> = failures.into_iter().map(|f| f.diagnostic).collect();
+            print_diagnostics(&diagnostics, file_id, &name, &source, cli);
+        }
+        println!();
+    }
+
+    let result = if failed == 0 && broken == 0 {
+        "ok"
+    } else {
+        "FAILED"
+    };
+    println!("test result: {result}. {passed} passed; {failed} failed");
+    match (broken, failed) {
+        (0, 0) => Ok(()),
+        (0, 1) => Err(failure(cli, "1 test failed".into())),
+        (0, n) => Err(failure(cli, format!("{n} tests failed"))),
+        (1, _) => Err(failure(cli, "1 file could not be compiled".into())),
+        (n, _) => Err(failure(cli, format!("{n} files could not be compiled"))),
+    }
+}
+
+/// Run one test in a fresh instance; `None` if it passed.
+fn run_test(artifact: &CompilationArtifact, test: &TestExport) -> Result<Option<Failure>> {
+    let mut program = Program::new(&artifact.wasm, Host::capturing())?;
+    let Err(e) = program.call(&test.export) else {
+        return Ok(None);
+    };
+    let diagnostic = program
+        .host()
+        .assertion_failure(&artifact.assertions)
+        .unwrap_or_else(|| {
+            Diagnostic::error_key("E1000.trap", test.span).arg("detail", e.root_cause())
+        });
+    Ok(Some(Failure {
+        name: test.name.clone(),
+        printed: std::mem::take(&mut program.host_mut().printed),
+        diagnostic,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use nepl_core::lint::LintConfig;
+
+    fn outcomes(source: &str) -> Vec<(String, Option<(String, String)>)> {
+        let artifact = compile_tests(FileId(0), source, &LintConfig::default()).unwrap();
+        artifact
+            .tests
+            .iter()
+            .map(|test| {
+                let failure = run_test(&artifact, test).unwrap().map(|f| {
+                    let text = &source[f.diagnostic.primary.span.start as usize
+                        ..f.diagnostic.primary.span.end as usize];
+                    (f.printed, text.to_string())
+                });
+                (test.name.clone(), failure)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tests_run_in_fresh_instances() {
+        let source = r#"{
+            fn double = |i32 n|*>i32 add n n;
+            test "ok": assert eq double 2 4 "double 2";
+            test "assertion": { print_i32 1; assert eq double 3 7 "double 3" };
+            test "trap": { let zero = 0; print_i32 div 1 zero };
+            0
+        }"#;
+        assert_eq!(
+            outcomes(source),
+            [
+                ("ok".to_string(), None),
+                (
+                    "assertion".to_string(),
+                    Some((
+                        "1\n".to_string(),
+                        r#"assert eq double 3 7 "double 3""#.to_string()
+                    ))
+                ),
+                (
+                    "trap".to_string(),
+                    Some((
+                        String::new(),
+                        r#"test "trap": { let zero = 0; print_i32 div 1 zero }"#.to_string()
+                    ))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn failing_tests_fail_the_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.nepl");
+        let path_arg = path.to_str().unwrap();
+        fs::write(&path, r#"{ test "fails": assert false "no"; 0 }"#).unwrap();
+        let cli = Cli::parse_from(["nepl-cli", "--color", "never", "test", path_arg]);
+        assert_eq!(
+            crate::execute(cli).unwrap_err().to_string(),
+            "1 test failed"
+        );
+
+        fs::write(&path, r#"{ test "passes": assert true "yes"; 0 }"#).unwrap();
+        crate::execute(Cli::parse_from(["nepl-cli", "test", path_arg])).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+//! With `--error-format json` or `sarif`, stderr holds only the
+//! diagnostics, so that tools can parse all of it.
+
+use std::process::{Command, Output};
+
+use serde_json::Value;
+use tempfile::tempdir;
+
+/// Compile `source` with the given error format.
+fn compile(source: &str, format: &str) -> Output {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("input.nepl");
+    std::fs::write(&input, source).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_nepl-cli"))
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(dir.path().join("out.wasm"))
+        .args(["--error-format", format, "--lang", "en"])
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+const BROKEN: &str = "{ let unused = 1; add 1 y }";
+
+#[test]
+fn json_errors_are_json_lines_only() {
+    let output = compile(BROKEN, "json");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let diagnostics: Vec<Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{e}: {line}")))
+        .collect();
+    let codes: Vec<&str> = diagnostics
+        .iter()
+        .map(|d| d["code"].as_str().unwrap())
+        .collect();
+    assert_eq!(codes, ["E0200", "W0001"]);
+}
+
+#[test]
+fn sarif_errors_are_one_document() {
+    let output = compile(BROKEN, "sarif");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let sarif: Value = serde_json::from_str(&stderr).unwrap_or_else(|e| panic!("{e}: {stderr}"));
+    let results = sarif["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn human_errors_end_with_the_error_line() {
+    let output = compile(BROKEN, "human");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error: could not compile"), "{stderr}");
+}
@@ -0,0 +1,137 @@
+//! UI tests: every `.nepl` file under `tests/ui` and the examples
+//! directory is compiled and run with the CLI, and what it prints is
+//! compared with the expectations stored next to it:
+//!
+//! - `NAME.stdout`: the values the program printed and its exit value;
+//! - `NAME.stderr`: diagnostics and errors.
+//!
+//! A missing file means the stream is expected to be empty. Run with
+//! `NEPL_UPDATE_UI=1` to rewrite the expectations from the actual output.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tempfile::tempdir;
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+fn nepl_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "nepl"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Compile and run `path` (relative to the workspace root, so that
+/// diagnostics name it the same way everywhere) and return its stdout
+/// and stderr.
+fn run(path: &Path) -> (String, String) {
+    let out = tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_nepl-cli"))
+        .arg("--input")
+        .arg(path)
+        .arg("--output")
+        .arg(out.path().join("out.wasm"))
+        .args(["--run", "--color", "never", "--lang", "en"])
+        .current_dir(workspace_root())
+        // Errors end with a backtrace when these are set.
+        .env("RUST_BACKTRACE", "0")
+        .env("RUST_LIB_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+/// A line diff of `expected` and `actual`, `-` marking expected lines
+/// that are missing and `+` lines that are new.
+fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+    // lcs[i][j]: length of the longest common subsequence of a[i..], b[j..].
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = String::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out += &format!("  {}\n", a[i]);
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out += &format!("- {}\n", a[i]);
+            i += 1;
+        } else {
+            out += &format!("+ {}\n", b[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Compare `actual` with the expectation in `path`, or rewrite it when
+/// updating. Returns a description of the difference.
+fn check(path: &Path, actual: &str, update: bool) -> Option<String> {
+    let expected = fs::read_to_string(path).unwrap_or_default();
+    if expected == actual {
+        return None;
+    }
+    if update {
+        if actual.is_empty() {
+            fs::remove_file(path).unwrap();
+        } else {
+            fs::write(path, actual).unwrap();
+        }
+        return None;
+    }
+    Some(format!("{}:\n{}", path.display(), diff(&expected, actual)))
+}
+
+#[test]
+fn ui() {
+    let root = workspace_root();
+    let update = std::env::var_os("NEPL_UPDATE_UI").is_some();
+    let mut files = nepl_files(&root.join("nepl-cli/tests/ui"));
+    files.extend(nepl_files(&root.join("examples")));
+    assert!(files.len() >= 10);
+
+    let mut failures = Vec::new();
+    for file in &files {
+        let relative = file.strip_prefix(&root).unwrap();
+        let (stdout, stderr) = run(relative);
+        for (extension, actual) in [("stdout", &stdout), ("stderr", &stderr)] {
+            let expected = file.with_extension(extension);
+            failures.extend(check(&expected, actual, update));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\nrun with NEPL_UPDATE_UI=1 to accept the new output",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn diff_marks_changed_lines() {
+    assert_eq!(diff("a\nb\nc\n", "a\nx\nc\n"), "  a\n- b\n+ x\n  c\n");
+    assert_eq!(diff("", "new\n"), "+ new\n");
+}
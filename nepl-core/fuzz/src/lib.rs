@@ -0,0 +1,100 @@
+//! Properties checked by the fuzz targets in `fuzz_targets/`.
+//!
+//! Every check panics when the property does not hold, which libFuzzer
+//! reports as a crash. `nepl-core/tests/fuzz_corpus.rs` runs the same
+//! checks over the seed corpus on stable Rust.
+
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::lexer::lex;
+use nepl_core::parser::parse_file;
+use nepl_core::span::{FileId, Span};
+use nepl_core::{CoreError, compile_wasm};
+
+const FILE: FileId = FileId(0);
+
+/// Panic unless `span` lies within `source` and on character
+/// boundaries, so that it can be used to slice the source.
+pub fn check_span(source: &str, span: Span, what: &str) {
+    let (start, end) = (span.start as usize, span.end as usize);
+    assert!(
+        start <= end && end <= source.len(),
+        "{what}: span {start}..{end} outside of a source of {} bytes",
+        source.len()
+    );
+    assert!(
+        source.is_char_boundary(start) && source.is_char_boundary(end),
+        "{what}: span {start}..{end} splits a character"
+    );
+}
+
+/// Check the spans of a diagnostic's labels and suggested edits.
+pub fn check_diagnostic(source: &str, diagnostic: &Diagnostic) {
+    let what = format!("diagnostic {:?} {}", diagnostic.code, diagnostic.message);
+    check_span(source, diagnostic.primary.span, &what);
+    for label in &diagnostic.secondary {
+        check_span(source, label.span, &what);
+    }
+    for suggestion in &diagnostic.suggestions {
+        for edit in &suggestion.edits {
+            check_span(source, edit.span, &what);
+        }
+    }
+    assert_ne!(
+        diagnostic.code,
+        Some("E0900"),
+        "internal compiler error: {what}"
+    );
+}
+
+/// The lexer does not panic, and tokens, trivia and diagnostics stay
+/// within the source.
+pub fn check_lex(source: &str) {
+    let result = lex(FILE, source);
+    for token in &result.tokens {
+        check_span(source, token.span, "token");
+        let text = Span::new(FILE, token.text_start, token.text_end);
+        check_span(source, text, "token text");
+        for trivia in token.leading_trivia.iter().chain(&token.trailing_trivia) {
+            check_span(source, trivia.span, "trivia");
+        }
+    }
+    for diagnostic in &result.diagnostics {
+        check_diagnostic(source, diagnostic);
+    }
+}
+
+/// The parser does not panic, and the spans of the AST and of the
+/// diagnostics stay within the source.
+pub fn check_parse(source: &str) {
+    let result = parse_file(FILE, source);
+    if let Some(expr) = &result.expr {
+        expr.walk(&mut |e| check_span(source, e.span, "expression"));
+    }
+    for diagnostic in &result.diagnostics {
+        check_diagnostic(source, diagnostic);
+    }
+}
+
+/// The whole pipeline does not panic, diagnostics stay within the
+/// source, and every module it produces is valid wasm.
+pub fn check_compile(source: &str) {
+    match compile_wasm(FILE, source) {
+        Ok(artifact) => {
+            if let Err(e) = wasmparser::Validator::new().validate_all(&artifact.wasm) {
+                panic!("invalid wasm: {e}");
+            }
+            for diagnostic in &artifact.warnings {
+                check_diagnostic(source, diagnostic);
+            }
+            for assertion in &artifact.assertions {
+                check_span(source, assertion.span, "assertion");
+            }
+        }
+        Err(CoreError::Diagnostics(diagnostics)) => {
+            for diagnostic in &diagnostics {
+                check_diagnostic(source, diagnostic);
+            }
+        }
+        Err(CoreError::Internal(message)) => panic!("internal error: {message}"),
+    }
+}
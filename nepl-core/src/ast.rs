@@ -5,6 +5,7 @@ use alloc::string::String;
 use alloc::vec::Vec;
 
 use crate::span::Span;
+use crate::types::{ArrowKind, Type};
 
 /// Identifier in the surface AST.
 ///
@@ -19,17 +20,48 @@ pub struct Ident {
 
 /// Literal values as they appear in source code.
 ///
-/// Numeric literals are kept in textual form so that later phases
-/// can decide how to interpret them (`i32` vs `i64`, `f32` vs `f64`)
-/// according to the type system rules.
+/// Unless a suffix fixes it, the type of a numeric literal is left to
+/// later phases (`i32` vs `i64`, `f32` vs `f64`) according to the type
+/// system rules.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LiteralKind {
-    Int(String),
-    Float(String),
+    Int(IntLiteral),
+    Float(FloatLiteral),
     Bool(bool),
     String(String),
 }
 
+impl LiteralKind {
+    /// The type written as a suffix of a numeric literal (`10i64`).
+    pub fn suffix(&self) -> Option<&Type> {
+        match self {
+            LiteralKind::Int(lit) => lit.suffix.as_ref(),
+            LiteralKind::Float(lit) => lit.suffix.as_ref(),
+            LiteralKind::Bool(_) | LiteralKind::String(_) => None,
+        }
+    }
+}
+
+/// An integer literal such as `42`, `0xff`, `0b1010` or `1_000i64`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntLiteral {
+    /// The value, or `None` when it does not fit in 64 bits. Whether it
+    /// fits its type is checked by the type checker.
+    pub value: Option<u64>,
+    /// `i32` or `i64`.
+    pub suffix: Option<Type>,
+}
+
+/// A floating-point literal such as `1.5`, `2e10` or `1.5f32`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FloatLiteral {
+    /// The decimal text without `_` separators and suffix, ready for
+    /// `str::parse` at the literal's final type.
+    pub text: String,
+    /// `f32` or `f64`.
+    pub suffix: Option<Type>,
+}
+
 /// Expression node in the surface AST.
 ///
 /// Each expression carries its own span. The `kind` describes the
@@ -41,6 +73,65 @@ pub struct Expr {
     pub span: Span,
 }
 
+impl Expr {
+    /// Call `f` on this expression and every expression nested in it,
+    /// parents before children and in source order.
+    pub fn walk<'a>(&'a self, f: &mut dyn FnMut(&'a Expr)) {
+        f(self);
+        let mut visit = |e: &'a Expr| e.walk(f);
+        match &self.kind {
+            ExprKind::Seq(items) => items.iter().for_each(visit),
+            ExprKind::Paren(e) | ExprKind::Loop(LoopExpr { body: e }) => visit(e),
+            ExprKind::Pipe(chain) => {
+                visit(&chain.first);
+                chain.rest.iter().for_each(visit);
+            }
+            ExprKind::If(e) => {
+                for branch in core::iter::once(&e.if_branch).chain(&e.elseif_branches) {
+                    visit(&branch.condition);
+                    visit(&branch.body);
+                }
+                visit(&e.else_branch);
+            }
+            ExprKind::While(e) => {
+                visit(&e.condition);
+                visit(&e.body);
+            }
+            ExprKind::Match(e) => {
+                visit(&e.scrutinee);
+                for case in &e.cases.items {
+                    case.pattern.walk_literals(&mut visit);
+                    visit(&case.body);
+                }
+            }
+            ExprKind::Block(b) => b.expressions.iter().for_each(visit),
+            ExprKind::Let(e) => visit(&e.value),
+            ExprKind::LetFunction(e) => visit(&e.value),
+            ExprKind::FuncLiteral(e) => visit(&e.body),
+            ExprKind::Namespace(e) => visit(&e.body),
+            ExprKind::When(e) => {
+                visit(&e.condition);
+                visit(&e.body);
+            }
+            ExprKind::Test(e) => visit(&e.body),
+            ExprKind::Return(ReturnExpr { value, .. })
+            | ExprKind::Break(BreakExpr { value, .. }) => value.iter().for_each(|e| visit(e)),
+            ExprKind::Set(e) => {
+                visit(&e.target.base);
+                visit(&e.value);
+            }
+            ExprKind::Literal(_)
+            | ExprKind::Ident(_)
+            | ExprKind::Include(_)
+            | ExprKind::Import(_)
+            | ExprKind::Use(_)
+            | ExprKind::Continue(_)
+            | ExprKind::EnumDef(_)
+            | ExprKind::StructDef(_) => {}
+        }
+    }
+}
+
 /// Surface expression variants.
 ///
 /// Note that there is deliberately **no** dedicated `Call` variant.
@@ -92,6 +183,9 @@ pub enum ExprKind {
     /// Function binding: `fn name = expr`.
     LetFunction(LetFunctionExpr),
 
+    /// Function literal: `|T1 a, T2 b|->R body`.
+    FuncLiteral(FuncLiteralExpr),
+
     /// Include expression: `include "path"`.
     Include(IncludeExpr),
 
@@ -107,6 +201,9 @@ pub enum ExprKind {
     /// When expression: compile-time conditional.
     When(WhenExpr),
 
+    /// Test declaration: `test "name": body`.
+    Test(TestExpr),
+
     /// Return expression: `return` or `return expr`.
     Return(ReturnExpr),
 
@@ -204,12 +301,15 @@ pub struct ScopedList<T> {
 }
 
 /// Let expression: `let` / `let mut` / `let hoist` with optional `pub`.
+///
+/// `ty` holds the optional annotation in `let x: T = expr`.
 #[derive(Debug, Clone, PartialEq)]
 pub struct LetExpr {
     pub is_pub: bool,
     pub is_mut: bool,
     pub is_hoist: bool,
     pub name: Ident,
+    pub ty: Option<TypeExpr>,
     pub value: Box<Expr>,
     pub span: Span,
 }
@@ -223,6 +323,30 @@ pub struct LetFunctionExpr {
     pub span: Span,
 }
 
+/// Function literal: `|T1 a, T2 mut b|->R body` or `|...|*>R body`.
+///
+/// A generic literal lists its type parameters first: `<T>|T x|->T x`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncLiteralExpr {
+    pub type_params: Vec<Ident>,
+    pub params: Vec<FuncParam>,
+    pub arrow: ArrowKind,
+    /// Span of the `->` / `*>` token.
+    pub arrow_span: Span,
+    pub result: TypeExpr,
+    pub body: Box<Expr>,
+    pub span: Span,
+}
+
+/// A single function literal parameter: `T name` or `T mut name`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncParam {
+    pub ty: TypeExpr,
+    pub is_mut: bool,
+    pub name: Ident,
+    pub span: Span,
+}
+
 /// Include expression: `include "path"`.
 #[derive(Debug, Clone, PartialEq)]
 pub struct IncludeExpr {
@@ -270,6 +394,16 @@ pub struct WhenExpr {
     pub span: Span,
 }
 
+/// Test declaration: `test "name": body`. Tests are only compiled by
+/// test builds, where each becomes a function of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestExpr {
+    pub name: String,
+    pub name_span: Span,
+    pub body: Box<Expr>, // scoped expression
+    pub span: Span,
+}
+
 /// Return expression.
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReturnExpr {
@@ -310,6 +444,7 @@ pub struct SetExpr {
 pub struct EnumDefExpr {
     pub is_pub: bool,
     pub name: Ident,
+    pub type_params: Vec<Ident>,
     pub variants: ScopedList<EnumVariant>,
     pub span: Span,
 }
@@ -327,6 +462,7 @@ pub struct EnumVariant {
 pub struct StructDefExpr {
     pub is_pub: bool,
     pub name: Ident,
+    pub type_params: Vec<Ident>,
     pub fields: ScopedList<StructField>,
     pub span: Span,
 }
@@ -339,7 +475,8 @@ pub struct StructField {
     pub span: Span,
 }
 
-/// Parsed type expression used by enum payloads and struct fields.
+/// Parsed type expression used by enum payloads, struct fields,
+/// `let` annotations and function literal signatures.
 #[derive(Debug, Clone, PartialEq)]
 pub struct TypeExpr {
     pub span: Span,
@@ -350,7 +487,14 @@ pub struct TypeExpr {
 #[derive(Debug, Clone, PartialEq)]
 pub enum TypeExprKind {
     Named(String),
-    Fn(Vec<TypeExpr>, Box<TypeExpr>, Box<TypeExpr>),
+    /// Application of a generic type: `Option<i32>`.
+    Apply { name: String, args: Vec<TypeExpr> },
+    /// Function type: `(T1, ..., Tn) -> R` or `(T1, ..., Tn) *> R`.
+    Fn {
+        params: Vec<TypeExpr>,
+        result: Box<TypeExpr>,
+        arrow: ArrowKind,
+    },
     Paren(Box<TypeExpr>),
 }
 
@@ -364,6 +508,18 @@ pub enum Pattern {
     Struct(StructPattern),
 }
 
+impl Pattern {
+    /// Call `f` on the literal expressions inside this pattern.
+    fn walk_literals<'a>(&'a self, f: &mut dyn FnMut(&'a Expr)) {
+        match self {
+            Pattern::Literal(e) => f(e),
+            Pattern::Enum(p) => p.args.iter().for_each(|a| a.walk_literals(f)),
+            Pattern::Struct(p) => p.fields.iter().for_each(|x| x.pattern.walk_literals(f)),
+            Pattern::Ident(..) | Pattern::Wildcard(_) => {}
+        }
+    }
+}
+
 /// Single field pattern in a struct pattern.
 #[derive(Debug, Clone, PartialEq)]
 pub struct StructPatternField {
@@ -23,6 +23,84 @@ pub enum BuiltinKind {
 
     /// Prints a 32-bit integer to the host's console or log.
     WasiPrintI32,
+
+    /// Reserves a number of bytes of linear memory and returns the
+    /// address of the first one.
+    MemAlloc,
+
+    /// Reads the value of type `T` stored in the 8-byte slot at an address.
+    MemLoad,
+
+    /// Writes a value of type `T` into the 8-byte slot at an address.
+    MemStore,
+
+    /// Stops the program with the given message when the condition is
+    /// false. The message must be a string literal.
+    Assert,
+
+    /// Primitive operator on numbers or booleans. Backends select the
+    /// concrete instruction from the operand type.
+    Operator(Operator),
+}
+
+/// Primitive operators provided by the compiler itself.
+///
+/// The stdlib wrappers (`math.nepl`, `logic.nepl`, `bit.nepl`) are
+/// thin functions over these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Neg,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    Xor,
+    Not,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    BitShl,
+    BitShr,
+}
+
+impl Operator {
+    /// The NEPL-level name of this operator.
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::Add => "add",
+            Operator::Sub => "sub",
+            Operator::Mul => "mul",
+            Operator::Div => "div",
+            Operator::Mod => "mod",
+            Operator::Neg => "neg",
+            Operator::Eq => "eq",
+            Operator::Ne => "ne",
+            Operator::Lt => "lt",
+            Operator::Le => "le",
+            Operator::Gt => "gt",
+            Operator::Ge => "ge",
+            Operator::And => "and",
+            Operator::Or => "or",
+            Operator::Xor => "xor",
+            Operator::Not => "not",
+            Operator::BitAnd => "bit_and",
+            Operator::BitOr => "bit_or",
+            Operator::BitXor => "bit_xor",
+            Operator::BitNot => "bit_not",
+            Operator::BitShl => "bit_shl",
+            Operator::BitShr => "bit_shr",
+        }
+    }
 }
 
 /// Metadata about a single builtin symbol.
@@ -37,6 +115,9 @@ pub struct BuiltinDescriptor {
     /// choose whether and how to use it.
     pub logical_module: &'static str,
 
+    /// Type parameters of a generic builtin (e.g. `T` of `load`).
+    pub type_params: &'static [&'static str],
+
     /// The type of the builtin in the NEPL type system.
     pub ty: Type,
 
@@ -49,26 +130,103 @@ pub struct BuiltinDescriptor {
 /// New backends and stdlib code should prefer referring to this
 /// table instead of hard-coding builtin names.
 pub fn builtins() -> Vec<BuiltinDescriptor> {
-    vec![
+    let mut list = vec![
         BuiltinDescriptor {
             name: "page_size",
             logical_module: "platform.wasm_core",
+            type_params: &[],
             ty: Type::impure_function(Vec::new(), Type::I32),
             kind: BuiltinKind::WasmPageSize,
         },
         BuiltinDescriptor {
             name: "random_i32",
             logical_module: "platform.wasi",
+            type_params: &[],
             ty: Type::impure_function(Vec::new(), Type::I32),
             kind: BuiltinKind::WasiRandomI32,
         },
         BuiltinDescriptor {
             name: "print_i32",
             logical_module: "platform.wasi",
+            type_params: &[],
             ty: Type::impure_function(vec![Type::I32], Type::Unit),
             kind: BuiltinKind::WasiPrintI32,
         },
-    ]
+        BuiltinDescriptor {
+            name: "alloc",
+            logical_module: "core.mem",
+            type_params: &[],
+            ty: Type::impure_function(vec![Type::I32], Type::I32),
+            kind: BuiltinKind::MemAlloc,
+        },
+        BuiltinDescriptor {
+            name: "load",
+            logical_module: "core.mem",
+            type_params: &["T"],
+            ty: Type::impure_function(vec![Type::I32], Type::Var("T".into())),
+            kind: BuiltinKind::MemLoad,
+        },
+        BuiltinDescriptor {
+            name: "store",
+            logical_module: "core.mem",
+            type_params: &["T"],
+            ty: Type::impure_function(vec![Type::I32, Type::Var("T".into())], Type::Unit),
+            kind: BuiltinKind::MemStore,
+        },
+        BuiltinDescriptor {
+            name: "assert",
+            logical_module: "core",
+            type_params: &[],
+            ty: Type::pure_function(vec![Type::Bool, Type::Named("String".into())], Type::Unit),
+            kind: BuiltinKind::Assert,
+        },
+    ];
+    list.extend(operator_builtins());
+    list
+}
+
+/// Overloads of the primitive operators.
+///
+/// All operators are pure (`*>`). Arithmetic and comparisons are
+/// defined for every numeric type, `mod` and the bitwise operators
+/// only for integers, and the logical operators for `Bool`.
+fn operator_builtins() -> Vec<BuiltinDescriptor> {
+    use Operator::*;
+
+    let mut list = Vec::new();
+    let mut push = |op: Operator, params: Vec<Type>, result: Type| {
+        list.push(BuiltinDescriptor {
+            name: op.name(),
+            logical_module: "core",
+            type_params: &[],
+            ty: Type::pure_function(params, result),
+            kind: BuiltinKind::Operator(op),
+        });
+    };
+
+    for t in [Type::I32, Type::I64, Type::F32, Type::F64] {
+        for op in [Add, Sub, Mul, Div] {
+            push(op, vec![t.clone(), t.clone()], t.clone());
+        }
+        push(Neg, vec![t.clone()], t.clone());
+        for op in [Eq, Ne, Lt, Le, Gt, Ge] {
+            push(op, vec![t.clone(), t.clone()], Type::Bool);
+        }
+    }
+
+    for t in [Type::I32, Type::I64] {
+        for op in [Mod, BitAnd, BitOr, BitXor, BitShl, BitShr] {
+            push(op, vec![t.clone(), t.clone()], t.clone());
+        }
+        push(BitNot, vec![t.clone()], t);
+    }
+
+    for op in [And, Or, Xor, Eq, Ne] {
+        push(op, vec![Type::Bool, Type::Bool], Type::Bool);
+    }
+    push(Not, vec![Type::Bool], Type::Bool);
+
+    list
 }
 
 /// Look up a builtin by its NEPL-level name.
@@ -79,3 +237,8 @@ pub fn builtins() -> Vec<BuiltinDescriptor> {
 pub fn find_builtin(name: &str) -> Option<BuiltinDescriptor> {
     builtins().into_iter().find(|b| b.name == name)
 }
+
+/// Look up every overload of a builtin by its NEPL-level name.
+pub fn find_builtins(name: &str) -> Vec<BuiltinDescriptor> {
+    builtins().into_iter().filter(|b| b.name == name).collect()
+}
@@ -0,0 +1,126 @@
+//! Resolved structure of P-style sequences (no_std).
+//!
+//! The parser keeps `f a g b c` as a flat `ExprKind::Seq`; only the type
+//! checker decides, from the arity and types of the names involved,
+//! that it means `f a (g b c)`. While it does so it records the
+//! grouping of every sequence as a [`CallTree`], returned in
+//! `TypeCheckResult::seqs`. Tools use it to show the parentheses the
+//! resolution implied: editors as inlay hints, the CLI as
+//! `nepl explain-parse`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::diagnostic::Diagnostic;
+use crate::parser;
+use crate::span::{FileId, Span};
+use crate::typecheck;
+
+/// How some terms of a P-style sequence were grouped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallTree {
+    /// A term of the sequence used as a value.
+    Term(Span),
+    /// A function, constructor or type annotation `head` applied to the
+    /// trees after it.
+    Apply {
+        head: Span,
+        args: Vec<CallTree>,
+        span: Span,
+    },
+}
+
+impl CallTree {
+    pub fn span(&self) -> Span {
+        match self {
+            CallTree::Term(span) | CallTree::Apply { span, .. } => *span,
+        }
+    }
+}
+
+/// The resolved grouping of one `ExprKind::Seq`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedSeq {
+    /// Span of the whole sequence.
+    pub span: Span,
+    /// The values the sequence reduced to; more than one only when the
+    /// sequence is ill-formed (`E0302`).
+    pub groups: Vec<CallTree>,
+}
+
+impl ResolvedSeq {
+    /// Spans that resolution grouped without parentheses in the source:
+    /// every application with arguments except one spanning the whole
+    /// sequence. Ordered by start, outer spans first.
+    pub fn implicit_parens(&self) -> Vec<Span> {
+        let mut spans = Vec::new();
+        for group in &self.groups {
+            collect_parens(group, self.span, &mut spans);
+        }
+        spans.sort_by_key(|s| (s.start, core::cmp::Reverse(s.end)));
+        spans
+    }
+}
+
+fn collect_parens(tree: &CallTree, whole: Span, out: &mut Vec<Span>) {
+    if let CallTree::Apply { args, span, .. } = tree {
+        if !args.is_empty() && *span != whole {
+            out.push(*span);
+        }
+        for arg in args {
+            collect_parens(arg, whole, out);
+        }
+    }
+}
+
+/// Result of resolving the sequences of a file.
+#[derive(Debug)]
+pub struct CallTreeResult {
+    pub seqs: Vec<ResolvedSeq>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parse and type-check `source` and return how each of its sequences
+/// was resolved, in the order the type checker resolved them.
+pub fn resolve_calls(file_id: FileId, source: &str) -> CallTreeResult {
+    let parsed = parser::parse_file(file_id, source);
+    let mut diagnostics = parsed.diagnostics;
+    let Some(expr) = parsed.expr else {
+        return CallTreeResult {
+            seqs: Vec::new(),
+            diagnostics,
+        };
+    };
+    let mut typed = typecheck::typecheck_expr(&expr);
+    diagnostics.append(&mut typed.diagnostics);
+    CallTreeResult {
+        seqs: typed.seqs,
+        diagnostics,
+    }
+}
+
+/// The text of `span` with the implicit parentheses of every sequence
+/// inside it written out: `add 1 mul 2 3` becomes `add 1 (mul 2 3)`.
+pub fn explain(source: &str, seqs: &[ResolvedSeq], span: Span) -> String {
+    let mut inserts: Vec<(u32, bool)> = Vec::new();
+    for seq in seqs {
+        if span.start <= seq.span.start && seq.span.end <= span.end {
+            for paren in seq.implicit_parens() {
+                inserts.push((paren.start, true));
+                inserts.push((paren.end, false));
+            }
+        }
+    }
+    // At one offset, closing parentheses come before opening ones.
+    inserts.sort_by_key(|(offset, open)| (*offset, *open));
+
+    let mut out = String::new();
+    let mut at = span.start as usize;
+    for (offset, open) in inserts {
+        out.push_str(&source[at..offset as usize]);
+        out.push(if open { '(' } else { ')' });
+        at = offset as usize;
+    }
+    out.push_str(&source[at..span.end as usize]);
+    out
+}
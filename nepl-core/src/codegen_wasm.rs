@@ -1,95 +1,1318 @@
 //! WASM backend for NEPL core (no_std).
 //!
-//! This module translates typed HIR into a minimal wasm module using
-//! the `wasm-encoder` crate. For now, it supports only a very small
-//! subset of HIR (e.g. a single i32 literal as the entry expression)
-//! and returns diagnostics for unsupported constructs.
+//! This module translates typed HIR into a wasm module using the
+//! `wasm-encoder` crate.
+//!
+//! - The entry expression becomes the exported function `main`.
+//! - Lifted functions are emitted on demand, starting from `main`.
+//!   Generic functions are monomorphized: every distinct list of type
+//!   arguments that reaches a function produces its own wasm function.
+//!   Polymorphic recursion, where instantiating a function leads to
+//!   ever new instances of itself, is reported as unsupported.
+//! - Struct and enum values live in linear memory and are passed around
+//!   as `i32` addresses. Every field / payload occupies one 8-byte slot;
+//!   an enum value starts with its variant tag in slot 0.
+//! - Host builtins are imported from the `nepl` module only when used.
+//!   A failed `assert` calls the import `assert_failed` with the index
+//!   of the assertion in `WasmModule::assertions` and then traps.
+//! - In test builds, every test is exported as a function `test:NAME`
+//!   without parameters or results.
+//! - Function values are addresses of closure records: slot 0 holds
+//!   the function's index in the table, the following slots the
+//!   captured values. Every lifted function takes the address of its
+//!   record as a trailing `i32` parameter (0 for direct calls), so that
+//!   function values can be called with `call_indirect`.
+//! - `mut` parameters are passed by copy-in/copy-out: a function
+//!   returns the final values of its `mut` parameters after its result,
+//!   and the caller stores them back into the variables it passed.
 
 #![allow(dead_code)]
 
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 
+use crate::builtins::{BuiltinKind, Operator};
 use crate::diagnostic::Diagnostic;
-use crate::hir::{HirExpr, HirExprKind};
+use crate::hir::{HirExpr, HirExprKind, HirFunction, HirModule, HirPattern, HirTest};
 use crate::span::Span;
 use crate::types::Type;
 
-// NOTE: this assumes the `wasm-encoder` crate is available.
-// The exact version and API may need adjustment in the real project.
+use alloc::borrow::Cow;
+
 use wasm_encoder::{
-    CodeSection, ExportKind, ExportSection, Function, FunctionSection, Instruction, Module,
+    BlockType, CodeSection, ConstExpr, ElementSection, Elements, EntityType, ExportKind,
+    ExportSection, Function, FunctionSection, GlobalSection, GlobalType, ImportSection,
+    Instruction, MemArg, MemorySection, MemoryType, Module, RefType, TableSection, TableType,
     TypeSection, ValType,
 };
 
+/// Size in bytes of one struct field / enum payload / `load` slot.
+pub const SLOT_SIZE: u32 = 8;
+
+/// How many instances of one function may lead to each other before
+/// the instantiation is taken for polymorphic recursion.
+const MAX_NESTED_INSTANCES: usize = 32;
+
+/// Name of the wasm import module providing host builtins.
+pub const HOST_MODULE: &str = "nepl";
+
+/// A generated wasm module with what the host needs to report on it.
+#[derive(Debug, Clone)]
+pub struct WasmModule {
+    pub bytes: Vec<u8>,
+    /// The `assert` calls of the program, indexed by the argument of
+    /// `assert_failed`.
+    pub assertions: Vec<Assertion>,
+    /// The exported tests, in source order.
+    pub tests: Vec<TestExport>,
+}
+
+/// An `assert` call: its message and where it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    pub message: String,
+    pub span: Span,
+}
+
+/// A test exported from a test build.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestExport {
+    pub name: String,
+    /// Name of the exported `() -> ()` function running the test.
+    pub export: String,
+    pub span: Span,
+}
+
 /// Generate a wasm module for the given *entry expression*.
 ///
-/// 現段階では、エントリ式が `Type::I32` かつ `HirExprKind::I32` の場合だけ
-/// 対応し、それ以外は "codegen not implemented" の Diagnostic を返す。
-pub fn generate_wasm(entry: &HirExpr) -> Result<Vec<u8>, Vec<Diagnostic>> {
-    let mut diagnostics = Vec::new();
-
-    // 現時点では、エントリは i32 型の式とする。
-    if entry.ty != Type::I32 {
-        diagnostics.push(Diagnostic::error(
-            "entry expression must have type i32 for wasm backend",
-            entry.span,
-        ));
-        return Err(diagnostics);
-    }
-
-    match &entry.kind {
-        HirExprKind::I32(value) => Ok(emit_const_i32_module(*value)),
-        _ => {
-            diagnostics.push(Diagnostic::error(
-                "code generation for this expression kind is not implemented yet",
-                entry.span,
+/// `module` holds the lifted functions the entry expression (and those
+/// functions) may call.
+pub fn generate_wasm(module: &HirModule, entry: &HirExpr) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    generate_wasm_module(module, entry, &[]).map(|m| m.bytes)
+}
+
+/// Generate a wasm module for the given *entry expression* that also
+/// exports `tests`, whose functions must be in `module`.
+pub fn generate_wasm_module(
+    module: &HirModule,
+    entry: &HirExpr,
+    tests: &[HirTest],
+) -> Result<WasmModule, Vec<Diagnostic>> {
+    let mut module_gen = ModuleGen::new(module, entry);
+    module_gen.run(entry, tests);
+    if !module_gen.diagnostics.is_empty() {
+        return Err(module_gen.diagnostics);
+    }
+    let wasm = module_gen.finish();
+    #[cfg(feature = "validate")]
+    if let Some(error) = module_gen.validate(&wasm.bytes) {
+        return Err(vec![error]);
+    }
+    Ok(wasm)
+}
+
+/// A function instantiated with concrete type arguments.
+#[derive(Debug, Clone, PartialEq)]
+struct Instance {
+    symbol: String,
+    type_args: Vec<Type>,
+}
+
+/// The code of a defined function and where it came from.
+struct Body<'m> {
+    function: Function,
+    /// The function as named in messages, e.g. "`main`".
+    name: String,
+    /// The HIR expression each instruction was emitted for, if any.
+    origins: Vec<Option<&'m HirExpr>>,
+}
+
+impl<'m> Body<'m> {
+    /// A function not generated from HIR.
+    fn synthetic(function: Function, name: String) -> Self {
+        Body {
+            function,
+            name,
+            origins: Vec::new(),
+        }
+    }
+}
+
+/// Module-level state: function indices, types and emitted code.
+struct ModuleGen<'m> {
+    module: &'m HirModule,
+    types: TypeSection,
+    signatures: Vec<(Vec<ValType>, Vec<ValType>)>,
+    /// Host builtins used by the program, in import order.
+    imports: Vec<BuiltinKind>,
+    /// Function instances in index order (after imports, `alloc` and `main`).
+    instances: Vec<Instance>,
+    /// For each instance, the instance whose code first referred to it
+    /// (`None` for `main` and tests).
+    parents: Vec<Option<usize>>,
+    /// The instance being emitted.
+    current: Option<usize>,
+    /// Type indices of every defined function, in index order.
+    func_types: Vec<u32>,
+    /// Functions used as values, in table order.
+    table: Vec<u32>,
+    /// Defined functions in index order.
+    bodies: Vec<Body<'m>>,
+    assertions: Vec<Assertion>,
+    /// Exported tests with their function indices.
+    tests: Vec<(TestExport, u32)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'m> ModuleGen<'m> {
+    fn new(module: &'m HirModule, entry: &HirExpr) -> Self {
+        // Host imports must come first in the function index space, so
+        // find every host builtin the program can reach up front.
+        let mut imports = Vec::new();
+        let mut collect = |e: &HirExpr| {
+            if let HirExprKind::BuiltinCall { kind, .. } = &e.kind
+                && matches!(
+                    kind,
+                    BuiltinKind::WasiPrintI32 | BuiltinKind::WasiRandomI32 | BuiltinKind::Assert
+                )
+                && !imports.contains(kind)
+            {
+                imports.push(*kind);
+            }
+        };
+        visit(entry, &mut collect);
+        for f in &module.functions {
+            visit(&f.body, &mut collect);
+        }
+
+        ModuleGen {
+            module,
+            types: TypeSection::new(),
+            signatures: Vec::new(),
+            imports,
+            instances: Vec::new(),
+            parents: Vec::new(),
+            current: None,
+            func_types: Vec::new(),
+            table: Vec::new(),
+            bodies: Vec::new(),
+            assertions: Vec::new(),
+            tests: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn signature(&mut self, params: Vec<ValType>, results: Vec<ValType>) -> u32 {
+        if let Some(i) = self
+            .signatures
+            .iter()
+            .position(|(p, r)| *p == params && *r == results)
+        {
+            return i as u32;
+        }
+        self.types.ty().function(params.clone(), results.clone());
+        self.signatures.push((params, results));
+        (self.signatures.len() - 1) as u32
+    }
+
+    fn alloc_index(&self) -> u32 {
+        self.imports.len() as u32
+    }
+
+    fn import_index(&self, kind: BuiltinKind) -> u32 {
+        self.imports.iter().position(|k| *k == kind).unwrap_or(0) as u32
+    }
+
+    /// Index of a function instance, scheduling it for emission when it
+    /// is seen for the first time. `span` is the reference to it.
+    fn instance_index(&mut self, symbol: &str, type_args: Vec<Type>, span: Span) -> u32 {
+        let inst = Instance {
+            symbol: symbol.into(),
+            type_args,
+        };
+        let pos = match self.instances.iter().position(|i| *i == inst) {
+            Some(pos) => pos,
+            None if self.nested_instances(symbol) >= MAX_NESTED_INSTANCES => {
+                // The module is not emitted, so the index does not matter.
+                let diag =
+                    Diagnostic::error_key("E0800.polymorphic-recursion", span).arg("name", symbol);
+                self.diagnostics.push(diag);
+                0
+            }
+            None => {
+                self.instances.push(inst);
+                self.parents.push(self.current);
+                self.instances.len() - 1
+            }
+        };
+        self.imports.len() as u32 + 2 + pos as u32
+    }
+
+    /// How many instances of `symbol` led to the instance being emitted,
+    /// counting it.
+    fn nested_instances(&self, symbol: &str) -> usize {
+        let mut count = 0;
+        let mut at = self.current;
+        while let Some(i) = at {
+            count += (self.instances[i].symbol == symbol) as usize;
+            at = self.parents[i];
+        }
+        count
+    }
+
+    /// Table slot of a function used as a value.
+    fn table_slot(&mut self, func_index: u32) -> u32 {
+        match self.table.iter().position(|f| *f == func_index) {
+            Some(slot) => slot as u32,
+            None => {
+                self.table.push(func_index);
+                (self.table.len() - 1) as u32
+            }
+        }
+    }
+
+    fn lookup_function(&self, symbol: &str) -> Option<&'m HirFunction> {
+        self.module.functions.iter().find(|f| f.name.name == symbol)
+    }
+
+    fn run(&mut self, entry: &'m HirExpr, tests: &[HirTest]) {
+        self.emit_alloc();
+
+        // main
+        let results: Vec<ValType> = valtype(&entry.ty).into_iter().collect();
+        let ty = self.signature(Vec::new(), results);
+        self.func_types.push(ty);
+        let mut fg = FnGen::new(Vec::new(), 0);
+        fg.expr(self, entry);
+        let (function, origins) = fg.finish(entry);
+        self.bodies.push(Body {
+            function,
+            name: "`main`".into(),
+            origins,
+        });
+
+        let test_indices: Vec<u32> = tests
+            .iter()
+            .map(|t| self.instance_index(&t.function, Vec::new(), t.span))
+            .collect();
+
+        // Instances discovered while emitting code are queued in
+        // `instances`; emit them until no new one shows up.
+        let mut next = 0;
+        while next < self.instances.len() {
+            let inst = self.instances[next].clone();
+            self.current = Some(next);
+            next += 1;
+            self.emit_instance(&inst);
+        }
+        self.current = None;
+
+        // Test entry points come last so that instance indices stay
+        // contiguous.
+        let ty = self.signature(Vec::new(), Vec::new());
+        for (test, callee) in tests.iter().zip(test_indices) {
+            let index = self.imports.len() as u32 + self.func_types.len() as u32;
+            self.func_types.push(ty);
+            let mut f = Function::new(Vec::new());
+            f.instruction(&Instruction::I32Const(0));
+            f.instruction(&Instruction::Call(callee));
+            f.instruction(&Instruction::End);
+            let export = TestExport {
+                name: test.name.clone(),
+                export: format!("test:{}", test.name),
+                span: test.span,
+            };
+            self.bodies
+                .push(Body::synthetic(f, format!("`{}`", export.export)));
+            self.tests.push((export, index));
+        }
+    }
+
+    fn emit_instance(&mut self, inst: &Instance) {
+        let Some(func) = self.lookup_function(&inst.symbol) else {
+            self.diagnostics.push(internal_error(
+                Span::dummy(),
+                &format!("unknown function `{}`", inst.symbol),
             ));
-            Err(diagnostics)
+            self.func_types.push(0);
+            self.bodies.push(Body::synthetic(
+                Function::new(Vec::new()),
+                format!("`{}`", inst.symbol),
+            ));
+            return;
+        };
+        let subst: Vec<(String, Type)> = func
+            .type_params
+            .iter()
+            .cloned()
+            .zip(inst.type_args.iter().cloned())
+            .collect();
+
+        let mut params: Vec<ValType> = func
+            .params
+            .iter()
+            .filter_map(|p| valtype(&p.ty.substitute(&subst)))
+            .collect();
+        // closure record
+        params.push(ValType::I32);
+        let mut results: Vec<ValType> =
+            valtype(&func.result.substitute(&subst)).into_iter().collect();
+        results.extend(
+            func.params
+                .iter()
+                .filter(|p| p.mutable)
+                .filter_map(|p| valtype(&p.ty.substitute(&subst))),
+        );
+        let ty = self.signature(params.clone(), results);
+        self.func_types.push(ty);
+
+        let mut fg = FnGen::new(subst, params.len() as u32);
+        let mut next_param = 0;
+        for p in &func.params {
+            let local = valtype(&fg.ty(&p.ty)).map(|_| {
+                next_param += 1;
+                next_param - 1
+            });
+            if p.mutable && let Some(l) = local {
+                fg.out_params.push(l);
+            }
+            fg.scope.push((p.name.name.clone(), local));
+        }
+        let env = next_param;
+        for (i, c) in func.captures.iter().enumerate() {
+            let local = valtype(&fg.ty(&c.ty)).map(|vt| {
+                fg.emit(Instruction::LocalGet(env));
+                fg.emit(load(vt, (i as u32 + 1) * SLOT_SIZE));
+                let l = fg.new_local(vt);
+                fg.emit(Instruction::LocalSet(l));
+                l
+            });
+            fg.scope.push((c.name.name.clone(), local));
+        }
+        fg.expr(self, &func.body);
+        if !func.body.ty.is_never() {
+            fg.push_out_params();
+        }
+        let (function, origins) = fg.finish(&func.body);
+        self.bodies.push(Body {
+            function,
+            name: format!("`{}`", inst.symbol),
+            origins,
+        });
+    }
+
+    /// `alloc(size) -> addr`: bump allocator over linear memory that
+    /// grows the memory when needed. Global 0 holds the next free
+    /// address.
+    fn emit_alloc(&mut self) {
+        let ty = self.signature(vec![ValType::I32], vec![ValType::I32]);
+        self.func_types.push(ty);
+
+        let mut f = Function::new(vec![(2, ValType::I32)]);
+        let (size, ptr, end) = (0, 1, 2);
+        for ins in [
+            Instruction::GlobalGet(0),
+            Instruction::LocalSet(ptr),
+            // end = (ptr + size + 7) & ~7
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(size),
+            Instruction::I32Add,
+            Instruction::I32Const(SLOT_SIZE as i32 - 1),
+            Instruction::I32Add,
+            Instruction::I32Const(!(SLOT_SIZE as i32 - 1)),
+            Instruction::I32And,
+            Instruction::LocalTee(end),
+            Instruction::GlobalSet(0),
+            // if end > memory.size * 64KiB: grow by the missing pages
+            Instruction::LocalGet(end),
+            Instruction::MemorySize(0),
+            Instruction::I32Const(16),
+            Instruction::I32Shl,
+            Instruction::I32GtU,
+            Instruction::If(BlockType::Empty),
+            Instruction::LocalGet(end),
+            Instruction::MemorySize(0),
+            Instruction::I32Const(16),
+            Instruction::I32Shl,
+            Instruction::I32Sub,
+            Instruction::I32Const(0xffff),
+            Instruction::I32Add,
+            Instruction::I32Const(16),
+            Instruction::I32ShrU,
+            Instruction::MemoryGrow(0),
+            Instruction::I32Const(-1),
+            Instruction::I32Eq,
+            Instruction::If(BlockType::Empty),
+            Instruction::Unreachable,
+            Instruction::End,
+            Instruction::End,
+            Instruction::LocalGet(ptr),
+            Instruction::End,
+        ] {
+            f.instruction(&ins);
+        }
+        self.bodies.push(Body::synthetic(f, "`alloc`".into()));
+    }
+
+    fn finish(&mut self) -> WasmModule {
+        let mut imports = ImportSection::new();
+        for kind in self.imports.clone() {
+            let (name, params, results) = match kind {
+                BuiltinKind::WasiPrintI32 => ("print_i32", vec![ValType::I32], vec![]),
+                BuiltinKind::Assert => ("assert_failed", vec![ValType::I32], vec![]),
+                _ => ("random_i32", vec![], vec![ValType::I32]),
+            };
+            let ty = self.signature(params, results);
+            imports.import(HOST_MODULE, name, EntityType::Function(ty));
+        }
+
+        let mut functions = FunctionSection::new();
+        for ty in &self.func_types {
+            functions.function(*ty);
+        }
+
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType {
+            minimum: 1,
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+
+        let mut tables = TableSection::new();
+        tables.table(TableType {
+            element_type: RefType::FUNCREF,
+            table64: false,
+            minimum: self.table.len() as u64,
+            maximum: Some(self.table.len() as u64),
+            shared: false,
+        });
+
+        let mut elements = ElementSection::new();
+        if !self.table.is_empty() {
+            elements.active(
+                None,
+                &ConstExpr::i32_const(0),
+                Elements::Functions(Cow::Borrowed(&self.table)),
+            );
+        }
+
+        let mut globals = GlobalSection::new();
+        // Address 0 is never handed out so that it can serve as null.
+        globals.global(
+            GlobalType {
+                val_type: ValType::I32,
+                mutable: true,
+                shared: false,
+            },
+            &ConstExpr::i32_const(SLOT_SIZE as i32),
+        );
+
+        let mut exports = ExportSection::new();
+        exports.export("main", ExportKind::Func, self.alloc_index() + 1);
+        exports.export("memory", ExportKind::Memory, 0);
+        for (test, index) in &self.tests {
+            exports.export(&test.export, ExportKind::Func, *index);
+        }
+
+        let mut code = CodeSection::new();
+        for body in &self.bodies {
+            code.function(&body.function);
+        }
+
+        let mut module = Module::new();
+        module.section(&self.types);
+        module.section(&imports);
+        module.section(&functions);
+        module.section(&tables);
+        module.section(&memories);
+        module.section(&globals);
+        module.section(&exports);
+        module.section(&elements);
+        module.section(&code);
+        WasmModule {
+            bytes: module.finish(),
+            assertions: core::mem::take(&mut self.assertions),
+            tests: self.tests.drain(..).map(|(test, _)| test).collect(),
         }
     }
 }
 
-/// Build a simple wasm module with:
-///
-/// - a single function `main : () -> i32`
-/// - body: `i32.const value`
-fn emit_const_i32_module(value: i32) -> Vec<u8> {
-    let mut module = Module::new();
-
-    // Type section: (-> i32)
-    let mut types = TypeSection::new();
-    let type_index = types.len();
-    types.ty().function(Vec::<ValType>::new(), [ValType::I32]);
-    module.section(&types);
-
-    // Function section: one function of the given type
-    let mut functions = FunctionSection::new();
-    functions.function(type_index);
-    module.section(&functions);
-
-    // Export section: export the function as "main"
-    let mut exports = ExportSection::new();
-    exports.export("main", ExportKind::Func, 0);
-    module.section(&exports);
-
-    // Code section: body of the function
-    let mut code = CodeSection::new();
-    let mut func = Function::new(Vec::new());
-    func.instruction(&Instruction::I32Const(value));
-    func.instruction(&Instruction::End);
-    code.function(&func);
-    module.section(&code);
-
-    module.finish()
+#[cfg(feature = "validate")]
+impl<'m> ModuleGen<'m> {
+    /// Validate the module `finish` produced. A failure is a bug in this
+    /// backend; it is reported as an internal error naming the HIR
+    /// expression whose code the validator rejected.
+    fn validate(&self, bytes: &[u8]) -> Option<Diagnostic> {
+        let error = wasmparser::Validator::new().validate_all(bytes).err()?;
+        let detail = error.message();
+        let Some((body, origin)) = self.locate(bytes, error.offset()) else {
+            return Some(
+                Diagnostic::error_key("E0900.invalid-wasm-module", Span::dummy())
+                    .arg("detail", detail),
+            );
+        };
+        Some(match origin {
+            Some(e) => Diagnostic::error_key("E0900.invalid-wasm", e.span)
+                .arg("expr", e.describe())
+                .arg("function", &body.name)
+                .arg("detail", detail),
+            None => Diagnostic::error_key("E0900.invalid-wasm-function", Span::dummy())
+                .arg("function", &body.name)
+                .arg("detail", detail),
+        })
+    }
+
+    /// The function whose code contains the module offset `offset`, and
+    /// the origin of the instruction at or before it.
+    fn locate(&self, bytes: &[u8], offset: usize) -> Option<(&Body<'m>, Option<&'m HirExpr>)> {
+        use wasmparser::{Parser, Payload};
+
+        let entries = Parser::new(0)
+            .parse_all(bytes)
+            .filter_map(|payload| match payload {
+                Ok(Payload::CodeSectionEntry(entry)) => Some(entry),
+                _ => None,
+            });
+        // Code section entries are the defined functions in index order,
+        // like `bodies`.
+        let (entry, body) = entries
+            .zip(&self.bodies)
+            .find(|(entry, _)| entry.range().contains(&offset))?;
+        let mut reader = entry.get_operators_reader().ok()?;
+        let mut origin = None;
+        // Every `Instruction` encodes as exactly one operator.
+        for i in 0.. {
+            match reader.read_with_offset() {
+                Ok((_, at)) if at <= offset => origin = body.origins.get(i).copied().flatten(),
+                _ => break,
+            }
+        }
+        Some((body, origin))
+    }
+}
+
+/// Kind of an open wasm block, used to compute branch depths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Label {
+    Other,
+    /// Target of `break` (the block around a loop).
+    Break,
+    /// Target of `continue` (the loop itself).
+    Continue,
+}
+
+/// Per-function code generation state.
+struct FnGen<'h> {
+    /// Type arguments of the instance being emitted.
+    subst: Vec<(String, Type)>,
+    num_params: u32,
+    locals: Vec<ValType>,
+    /// Visible variables; `None` for variables of a zero-sized type.
+    scope: Vec<(String, Option<u32>)>,
+    labels: Vec<Label>,
+    /// Locals of the `mut` parameters, returned after the result.
+    out_params: Vec<u32>,
+    code: Vec<Instruction<'static>>,
+    /// The HIR expression being lowered when each instruction of `code`
+    /// was emitted, if any.
+    origins: Vec<Option<&'h HirExpr>>,
+    /// The innermost expression being lowered.
+    lowering: Option<&'h HirExpr>,
+}
+
+impl<'h> FnGen<'h> {
+    fn new(subst: Vec<(String, Type)>, num_params: u32) -> Self {
+        FnGen {
+            subst,
+            num_params,
+            locals: Vec::new(),
+            scope: Vec::new(),
+            labels: Vec::new(),
+            out_params: Vec::new(),
+            code: Vec::new(),
+            origins: Vec::new(),
+            lowering: None,
+        }
+    }
+
+    /// The encoded function and the origin of each of its
+    /// instructions. The final `end`, where the validator reports a
+    /// result of the wrong type, is attributed to the whole body.
+    fn finish(mut self, body: &'h HirExpr) -> (Function, Vec<Option<&'h HirExpr>>) {
+        self.lowering = Some(body);
+        self.emit(Instruction::End);
+        let mut f = Function::new(self.locals.iter().map(|t| (1, *t)));
+        for ins in &self.code {
+            f.instruction(ins);
+        }
+        (f, self.origins)
+    }
+
+    fn push_out_params(&mut self) {
+        for l in self.out_params.clone() {
+            self.emit(Instruction::LocalGet(l));
+        }
+    }
+
+    fn ty(&self, ty: &Type) -> Type {
+        ty.substitute(&self.subst)
+    }
+
+    fn emit(&mut self, ins: Instruction<'static>) {
+        self.code.push(ins);
+        self.origins.push(self.lowering);
+    }
+
+    fn new_local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        self.num_params + self.locals.len() as u32 - 1
+    }
+
+    fn lookup(&self, name: &str) -> Option<u32> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, l)| *l)
+    }
+
+    /// Relative branch depth of the innermost label of `kind`.
+    fn depth_of(&self, kind: Label) -> u32 {
+        let pos = self.labels.iter().rposition(|l| *l == kind).unwrap_or(0);
+        (self.labels.len() - 1 - pos) as u32
+    }
+
+    fn block(&mut self, ins: Instruction<'static>, label: Label) {
+        self.emit(ins);
+        self.labels.push(label);
+    }
+
+    fn end(&mut self) {
+        self.emit(Instruction::End);
+        self.labels.pop();
+    }
+
+    fn block_type(&self, ty: &Type) -> BlockType {
+        match valtype(&self.ty(ty)) {
+            Some(v) => BlockType::Result(v),
+            None => BlockType::Empty,
+        }
+    }
+
+    /// Emit `e` and drop its value, if any.
+    fn discard(&mut self, m: &mut ModuleGen<'_>, e: &'h HirExpr) {
+        self.expr(m, e);
+        if valtype(&self.ty(&e.ty)).is_some() {
+            self.emit(Instruction::Drop);
+        }
+    }
+
+    /// Emit `e`, leaving its value (if it has one) on the stack.
+    fn expr(&mut self, m: &mut ModuleGen<'_>, e: &'h HirExpr) {
+        let outer = self.lowering.replace(e);
+        self.expr_inner(m, e);
+        if e.ty.is_never() {
+            // Keep the operand stack polymorphic after diverging code.
+            self.emit(Instruction::Unreachable);
+        }
+        self.lowering = outer;
+    }
+
+    fn expr_inner(&mut self, m: &mut ModuleGen<'_>, e: &'h HirExpr) {
+        match &e.kind {
+            HirExprKind::I32(v) => self.emit(Instruction::I32Const(*v)),
+            HirExprKind::I64(v) => self.emit(Instruction::I64Const(*v)),
+            HirExprKind::F32(v) => self.emit(Instruction::F32Const((*v).into())),
+            HirExprKind::F64(v) => self.emit(Instruction::F64Const((*v).into())),
+            HirExprKind::Bool(b) => self.emit(Instruction::I32Const(*b as i32)),
+            HirExprKind::Unit => {}
+            HirExprKind::String(_) => self.unsupported(m, e.span, "E0800.string-values"),
+            HirExprKind::FuncRef {
+                name,
+                type_args,
+                captures,
+            } => {
+                let type_args = type_args.iter().map(|t| self.ty(t)).collect();
+                let idx = m.instance_index(&name.name, type_args, e.span);
+                let slot = m.table_slot(idx);
+                self.record(m, Some(slot), captures);
+            }
+
+            HirExprKind::Var(name) => {
+                if let Some(l) = self.lookup(&name.name) {
+                    self.emit(Instruction::LocalGet(l));
+                }
+            }
+
+            HirExprKind::Call {
+                callee,
+                type_args,
+                args,
+            } => {
+                for a in args {
+                    self.expr(m, a);
+                }
+                let type_args = type_args.iter().map(|t| self.ty(t)).collect();
+                let idx = m.instance_index(&callee.name, type_args, e.span);
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::Call(idx));
+
+                // The final values of `mut` arguments sit above the
+                // result; store them back, last one first.
+                let Some(func) = m.lookup_function(&callee.name) else {
+                    return;
+                };
+                let written: Vec<&HirExpr> = func
+                    .params
+                    .iter()
+                    .zip(args)
+                    .filter(|(p, _)| p.mutable)
+                    .map(|(_, a)| a)
+                    .collect();
+                for a in written.iter().rev() {
+                    if valtype(&self.ty(&a.ty)).is_none() {
+                        continue;
+                    }
+                    let local = match &a.kind {
+                        HirExprKind::Var(name) => self.lookup(&name.name),
+                        _ => None,
+                    };
+                    match local {
+                        Some(l) => self.emit(Instruction::LocalSet(l)),
+                        None => self.emit(Instruction::Drop),
+                    }
+                }
+            }
+
+            HirExprKind::CallIndirect { callee, args } => {
+                self.expr(m, callee);
+                let closure = self.new_local(ValType::I32);
+                self.emit(Instruction::LocalSet(closure));
+                for a in args {
+                    self.expr(m, a);
+                }
+                self.emit(Instruction::LocalGet(closure));
+                self.emit(Instruction::LocalGet(closure));
+                self.emit(Instruction::I32Load(mem_arg(0, ValType::I32)));
+
+                let Type::Function { params, result, .. } = self.ty(&callee.ty) else {
+                    self.unsupported(m, e.span, "E0800.indirect-call");
+                    return;
+                };
+                let mut params: Vec<ValType> = params.iter().filter_map(valtype).collect();
+                params.push(ValType::I32);
+                let results = valtype(&result).into_iter().collect();
+                let type_index = m.signature(params, results);
+                self.emit(Instruction::CallIndirect {
+                    type_index,
+                    table_index: 0,
+                });
+            }
+
+            HirExprKind::BuiltinCall { kind, args } => self.builtin(m, e, *kind, args),
+
+            HirExprKind::StructInit { fields, .. } => self.record(m, None, fields),
+            HirExprKind::EnumInit { tag, args, .. } => self.record(m, Some(*tag), args),
+
+            HirExprKind::Let { name, init, .. } => {
+                self.expr(m, init);
+                let local = valtype(&self.ty(&init.ty)).map(|v| {
+                    let l = self.new_local(v);
+                    self.emit(Instruction::LocalSet(l));
+                    l
+                });
+                // The enclosing block drops the binding.
+                self.scope.push((name.name.clone(), local));
+            }
+
+            HirExprKind::Set { target, value } => {
+                self.expr(m, value);
+                let local = match &target.base.kind {
+                    HirExprKind::Var(name) => self.lookup(&name.name),
+                    _ => None,
+                };
+                match local {
+                    Some(l) => self.emit(Instruction::LocalSet(l)),
+                    None if valtype(&self.ty(&value.ty)).is_some() => {
+                        self.emit(Instruction::Drop)
+                    }
+                    None => {}
+                }
+            }
+
+            HirExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(m, cond);
+                let bt = self.block_type(&e.ty);
+                self.block(Instruction::If(bt), Label::Other);
+                self.branch(m, then_branch, &e.ty);
+                self.emit(Instruction::Else);
+                self.branch(m, else_branch, &e.ty);
+                self.end();
+            }
+
+            HirExprKind::While { cond, body } => {
+                self.block(Instruction::Block(BlockType::Empty), Label::Break);
+                self.block(Instruction::Loop(BlockType::Empty), Label::Continue);
+                self.expr(m, cond);
+                self.emit(Instruction::I32Eqz);
+                self.emit(Instruction::BrIf(1));
+                self.discard(m, body);
+                self.emit(Instruction::Br(0));
+                self.end();
+                self.end();
+            }
+
+            HirExprKind::Loop { body } => {
+                let bt = self.block_type(&e.ty);
+                self.block(Instruction::Block(bt), Label::Break);
+                self.block(Instruction::Loop(BlockType::Empty), Label::Continue);
+                self.discard(m, body);
+                self.emit(Instruction::Br(0));
+                self.end();
+                // The loop only exits through `break`; the end of the
+                // block must not expect a value from it.
+                self.emit(Instruction::Unreachable);
+                self.end();
+            }
+
+            HirExprKind::Break { value } => {
+                if let Some(v) = value {
+                    self.expr(m, v);
+                }
+                let depth = self.depth_of(Label::Break);
+                self.emit(Instruction::Br(depth));
+            }
+            HirExprKind::Continue => {
+                let depth = self.depth_of(Label::Continue);
+                self.emit(Instruction::Br(depth));
+            }
+            HirExprKind::Return { value } => {
+                if let Some(v) = value {
+                    self.expr(m, v);
+                }
+                self.push_out_params();
+                self.emit(Instruction::Return);
+            }
+
+            HirExprKind::Match { scrutinee, arms } => {
+                self.expr(m, scrutinee);
+                let value = valtype(&self.ty(&scrutinee.ty)).map(|v| {
+                    let l = self.new_local(v);
+                    self.emit(Instruction::LocalSet(l));
+                    l
+                });
+
+                let bt = self.block_type(&e.ty);
+                self.block(Instruction::Block(bt), Label::Other);
+                for arm in arms {
+                    self.block(Instruction::Block(BlockType::Empty), Label::Other);
+                    let saved = self.scope.len();
+                    self.pattern(m, &arm.pattern, value, &scrutinee.ty);
+                    self.branch(m, &arm.body, &e.ty);
+                    self.emit(Instruction::Br(1));
+                    self.scope.truncate(saved);
+                    self.end();
+                }
+                // No arm matched.
+                self.emit(Instruction::Unreachable);
+                self.end();
+            }
+
+            HirExprKind::Block { expressions } => {
+                let saved = self.scope.len();
+                if let Some((last, init)) = expressions.split_last() {
+                    for x in init {
+                        self.discard(m, x);
+                    }
+                    self.expr(m, last);
+                }
+                self.scope.truncate(saved);
+            }
+        }
+    }
+
+    /// Emit a branch of an `if` / `match` whose result has type `ty`.
+    /// A branch of a zero-sized type in a valued context cannot occur
+    /// (the typechecker requires equal branch types), but a branch may
+    /// produce a value the context drops.
+    fn branch(&mut self, m: &mut ModuleGen<'_>, e: &'h HirExpr, ty: &Type) {
+        if valtype(&self.ty(ty)).is_none() {
+            self.discard(m, e);
+        } else {
+            self.expr(m, e);
+        }
+    }
+
+    /// Allocate a record (struct or enum value) and store its slots.
+    fn record(&mut self, m: &mut ModuleGen<'_>, tag: Option<u32>, values: &'h [HirExpr]) {
+        let first = tag.is_some() as u32;
+        let slots = (first + values.len() as u32).max(1);
+        self.emit(Instruction::I32Const((slots * SLOT_SIZE) as i32));
+        self.emit(Instruction::Call(m.alloc_index()));
+        let addr = self.new_local(ValType::I32);
+        self.emit(Instruction::LocalSet(addr));
+
+        if let Some(tag) = tag {
+            self.emit(Instruction::LocalGet(addr));
+            self.emit(Instruction::I32Const(tag as i32));
+            self.emit(Instruction::I32Store(mem_arg(0, ValType::I32)));
+        }
+        for (i, v) in values.iter().enumerate() {
+            let offset = (first + i as u32) * SLOT_SIZE;
+            match valtype(&self.ty(&v.ty)) {
+                Some(vt) => {
+                    self.emit(Instruction::LocalGet(addr));
+                    self.expr(m, v);
+                    self.emit(store(vt, offset));
+                }
+                None => self.discard(m, v),
+            }
+        }
+        self.emit(Instruction::LocalGet(addr));
+    }
+
+    /// Emit the test of `pat` against the value in `local` (of type
+    /// `ty`), branching out of the enclosing block when it fails, and
+    /// bind the pattern's variables.
+    fn pattern(&mut self, m: &mut ModuleGen<'_>, pat: &HirPattern, local: Option<u32>, ty: &Type) {
+        let get = |l: Option<u32>| Instruction::LocalGet(l.unwrap_or(0));
+        match pat {
+            HirPattern::Wildcard(_) => {}
+            HirPattern::Ident { name, .. } => self.scope.push((name.name.clone(), local)),
+            HirPattern::LitI32(v) => {
+                self.emit(get(local));
+                self.emit(Instruction::I32Const(*v));
+                self.emit(Instruction::I32Ne);
+                self.emit(Instruction::BrIf(0));
+            }
+            HirPattern::LitBool(v) => {
+                self.emit(get(local));
+                self.emit(Instruction::I32Const(*v as i32));
+                self.emit(Instruction::I32Ne);
+                self.emit(Instruction::BrIf(0));
+            }
+            HirPattern::LitI64(v) => {
+                self.emit(get(local));
+                self.emit(Instruction::I64Const(*v));
+                self.emit(Instruction::I64Ne);
+                self.emit(Instruction::BrIf(0));
+            }
+            HirPattern::LitF32(v) => {
+                self.emit(get(local));
+                self.emit(Instruction::F32Const((*v).into()));
+                self.emit(Instruction::F32Ne);
+                self.emit(Instruction::BrIf(0));
+            }
+            HirPattern::LitF64(v) => {
+                self.emit(get(local));
+                self.emit(Instruction::F64Const((*v).into()));
+                self.emit(Instruction::F64Ne);
+                self.emit(Instruction::BrIf(0));
+            }
+            HirPattern::LitString(_) => self.unsupported(m, Span::dummy(), "E0800.string-patterns"),
+            HirPattern::EnumVariant { tag, args, .. } => {
+                self.emit(get(local));
+                self.emit(Instruction::I32Load(mem_arg(0, ValType::I32)));
+                self.emit(Instruction::I32Const(*tag as i32));
+                self.emit(Instruction::I32Ne);
+                self.emit(Instruction::BrIf(0));
+                for (i, a) in args.iter().enumerate() {
+                    self.sub_pattern(m, a, local, (i as u32 + 1) * SLOT_SIZE, ty);
+                }
+            }
+            HirPattern::Struct { fields, .. } => {
+                for f in fields {
+                    self.sub_pattern(m, &f.pattern, local, f.index as u32 * SLOT_SIZE, ty);
+                }
+            }
+        }
+    }
+
+    /// Load the slot at `offset` of the record in `record` and match it
+    /// against `pat`.
+    fn sub_pattern(
+        &mut self,
+        m: &mut ModuleGen<'_>,
+        pat: &HirPattern,
+        record: Option<u32>,
+        offset: u32,
+        ty: &Type,
+    ) {
+        let slot_ty = match pat {
+            HirPattern::Wildcard(_) => return,
+            HirPattern::Ident { ty, .. } => self.ty(ty),
+            HirPattern::LitI32(_) => Type::I32,
+            HirPattern::LitI64(_) => Type::I64,
+            HirPattern::LitF32(_) => Type::F32,
+            HirPattern::LitF64(_) => Type::F64,
+            HirPattern::LitBool(_) => Type::Bool,
+            // Nested records are addresses.
+            _ => ty.clone(),
+        };
+        let local = valtype(&slot_ty).map(|vt| {
+            self.emit(Instruction::LocalGet(record.unwrap_or(0)));
+            self.emit(load(vt, offset));
+            let l = self.new_local(vt);
+            self.emit(Instruction::LocalSet(l));
+            l
+        });
+        self.pattern(m, pat, local, &slot_ty);
+    }
+
+    fn builtin(
+        &mut self,
+        m: &mut ModuleGen<'_>,
+        e: &'h HirExpr,
+        kind: BuiltinKind,
+        args: &'h [HirExpr],
+    ) {
+        match kind {
+            BuiltinKind::WasmPageSize => self.emit(Instruction::I32Const(65536)),
+            BuiltinKind::WasiRandomI32 | BuiltinKind::WasiPrintI32 => {
+                for a in args {
+                    self.expr(m, a);
+                }
+                self.emit(Instruction::Call(m.import_index(kind)));
+            }
+            BuiltinKind::MemAlloc => {
+                self.expr(m, &args[0]);
+                self.emit(Instruction::Call(m.alloc_index()));
+            }
+            BuiltinKind::Assert => {
+                let HirExprKind::String(message) = &args[1].kind else {
+                    self.unsupported(m, args[1].span, "E0800.string-values");
+                    return;
+                };
+                m.assertions.push(Assertion {
+                    message: message.clone(),
+                    span: e.span,
+                });
+                self.expr(m, &args[0]);
+                self.emit(Instruction::I32Eqz);
+                self.emit(Instruction::If(BlockType::Empty));
+                self.emit(Instruction::I32Const(m.assertions.len() as i32 - 1));
+                self.emit(Instruction::Call(m.import_index(kind)));
+                self.emit(Instruction::Unreachable);
+                self.emit(Instruction::End);
+            }
+            BuiltinKind::MemLoad => {
+                self.expr(m, &args[0]);
+                match valtype(&self.ty(&e.ty)) {
+                    Some(vt) => self.emit(load(vt, 0)),
+                    None => self.emit(Instruction::Drop),
+                }
+            }
+            BuiltinKind::MemStore => {
+                self.expr(m, &args[0]);
+                self.expr(m, &args[1]);
+                match valtype(&self.ty(&args[1].ty)) {
+                    Some(vt) => self.emit(store(vt, 0)),
+                    None => self.emit(Instruction::Drop),
+                }
+            }
+            BuiltinKind::Operator(op) => {
+                let operand = self.ty(&args[0].ty);
+                if matches!(op, Operator::Neg)
+                    && matches!(operand, Type::I32 | Type::I64)
+                {
+                    // 0 - x
+                    self.emit(if operand == Type::I32 {
+                        Instruction::I32Const(0)
+                    } else {
+                        Instruction::I64Const(0)
+                    });
+                }
+                for a in args {
+                    self.expr(m, a);
+                }
+                match operator_instructions(op, &operand) {
+                    Some(code) => {
+                        for ins in code {
+                            self.emit(ins);
+                        }
+                    }
+                    None => self.unsupported(m, e.span, "E0800.operator"),
+                }
+            }
+        }
+    }
+
+    /// Report a construct the backend cannot lower; `key` is an `E0800`
+    /// catalog message.
+    fn unsupported(&mut self, m: &mut ModuleGen<'_>, span: Span, key: &'static str) {
+        m.diagnostics.push(Diagnostic::error_key(key, span));
+        self.emit(Instruction::Unreachable);
+    }
+}
+
+/// The wasm value type of a NEPL type, or `None` for zero-sized types.
+fn valtype(ty: &Type) -> Option<ValType> {
+    match ty {
+        Type::I64 => Some(ValType::I64),
+        Type::F32 => Some(ValType::F32),
+        Type::F64 => Some(ValType::F64),
+        Type::Unit | Type::Never => None,
+        // Bool, addresses of records and strings, function table indices.
+        _ => Some(ValType::I32),
+    }
+}
+
+fn mem_arg(offset: u32, vt: ValType) -> MemArg {
+    MemArg {
+        offset: offset as u64,
+        align: if matches!(vt, ValType::I64 | ValType::F64) { 3 } else { 2 },
+        memory_index: 0,
+    }
+}
+
+fn load(vt: ValType, offset: u32) -> Instruction<'static> {
+    let arg = mem_arg(offset, vt);
+    match vt {
+        ValType::I64 => Instruction::I64Load(arg),
+        ValType::F32 => Instruction::F32Load(arg),
+        ValType::F64 => Instruction::F64Load(arg),
+        _ => Instruction::I32Load(arg),
+    }
+}
+
+fn store(vt: ValType, offset: u32) -> Instruction<'static> {
+    let arg = mem_arg(offset, vt);
+    match vt {
+        ValType::I64 => Instruction::I64Store(arg),
+        ValType::F32 => Instruction::F32Store(arg),
+        ValType::F64 => Instruction::F64Store(arg),
+        _ => Instruction::I32Store(arg),
+    }
+}
+
+/// Instructions implementing `op` on operands of type `ty` (already on
+/// the stack).
+fn operator_instructions(op: Operator, ty: &Type) -> Option<Vec<Instruction<'static>>> {
+    use Instruction as I;
+    use Operator::*;
+
+    let ins = match (ty, op) {
+        (Type::I32, Add) => I::I32Add,
+        (Type::I32, Sub) | (Type::I32, Neg) => I::I32Sub,
+        (Type::I32, Mul) => I::I32Mul,
+        (Type::I32, Div) => I::I32DivS,
+        (Type::I32, Mod) => I::I32RemS,
+        (Type::I32, Eq) | (Type::Bool, Eq) => I::I32Eq,
+        (Type::I32, Ne) | (Type::Bool, Ne) | (Type::Bool, Xor) => I::I32Ne,
+        (Type::I32, Lt) => I::I32LtS,
+        (Type::I32, Le) => I::I32LeS,
+        (Type::I32, Gt) => I::I32GtS,
+        (Type::I32, Ge) => I::I32GeS,
+        (Type::I32, BitAnd) | (Type::Bool, And) => I::I32And,
+        (Type::I32, BitOr) | (Type::Bool, Or) => I::I32Or,
+        (Type::I32, BitXor) => I::I32Xor,
+        (Type::I32, BitShl) => I::I32Shl,
+        (Type::I32, BitShr) => I::I32ShrS,
+        (Type::I32, BitNot) => return Some(vec![I::I32Const(-1), I::I32Xor]),
+        (Type::Bool, Not) => I::I32Eqz,
+
+        (Type::I64, Add) => I::I64Add,
+        (Type::I64, Sub) | (Type::I64, Neg) => I::I64Sub,
+        (Type::I64, Mul) => I::I64Mul,
+        (Type::I64, Div) => I::I64DivS,
+        (Type::I64, Mod) => I::I64RemS,
+        (Type::I64, Eq) => I::I64Eq,
+        (Type::I64, Ne) => I::I64Ne,
+        (Type::I64, Lt) => I::I64LtS,
+        (Type::I64, Le) => I::I64LeS,
+        (Type::I64, Gt) => I::I64GtS,
+        (Type::I64, Ge) => I::I64GeS,
+        (Type::I64, BitAnd) => I::I64And,
+        (Type::I64, BitOr) => I::I64Or,
+        (Type::I64, BitXor) => I::I64Xor,
+        (Type::I64, BitShl) => I::I64Shl,
+        (Type::I64, BitShr) => I::I64ShrS,
+        (Type::I64, BitNot) => return Some(vec![I::I64Const(-1), I::I64Xor]),
+
+        (Type::F32, Add) => I::F32Add,
+        (Type::F32, Sub) => I::F32Sub,
+        (Type::F32, Mul) => I::F32Mul,
+        (Type::F32, Div) => I::F32Div,
+        (Type::F32, Neg) => I::F32Neg,
+        (Type::F32, Eq) => I::F32Eq,
+        (Type::F32, Ne) => I::F32Ne,
+        (Type::F32, Lt) => I::F32Lt,
+        (Type::F32, Le) => I::F32Le,
+        (Type::F32, Gt) => I::F32Gt,
+        (Type::F32, Ge) => I::F32Ge,
+
+        (Type::F64, Add) => I::F64Add,
+        (Type::F64, Sub) => I::F64Sub,
+        (Type::F64, Mul) => I::F64Mul,
+        (Type::F64, Div) => I::F64Div,
+        (Type::F64, Neg) => I::F64Neg,
+        (Type::F64, Eq) => I::F64Eq,
+        (Type::F64, Ne) => I::F64Ne,
+        (Type::F64, Lt) => I::F64Lt,
+        (Type::F64, Le) => I::F64Le,
+        (Type::F64, Gt) => I::F64Gt,
+        (Type::F64, Ge) => I::F64Ge,
+
+        _ => return None,
+    };
+    Some(vec![ins])
+}
+
+/// Call `f` on `e` and every expression nested in it.
+fn visit(e: &HirExpr, f: &mut impl FnMut(&HirExpr)) {
+    f(e);
+    match &e.kind {
+        HirExprKind::Call { args, .. }
+        | HirExprKind::BuiltinCall { args, .. }
+        | HirExprKind::EnumInit { args, .. } => args.iter().for_each(|a| visit(a, f)),
+        HirExprKind::StructInit { fields, .. } => fields.iter().for_each(|a| visit(a, f)),
+        HirExprKind::FuncRef { captures, .. } => captures.iter().for_each(|a| visit(a, f)),
+        HirExprKind::CallIndirect { callee, args } => {
+            visit(callee, f);
+            args.iter().for_each(|a| visit(a, f));
+        }
+        HirExprKind::Let { init, .. } => visit(init, f),
+        HirExprKind::Set { target, value } => {
+            visit(&target.base, f);
+            visit(value, f);
+        }
+        HirExprKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            visit(cond, f);
+            visit(then_branch, f);
+            visit(else_branch, f);
+        }
+        HirExprKind::While { cond, body } => {
+            visit(cond, f);
+            visit(body, f);
+        }
+        HirExprKind::Loop { body } => visit(body, f),
+        HirExprKind::Break { value } | HirExprKind::Return { value } => {
+            if let Some(v) = value {
+                visit(v, f);
+            }
+        }
+        HirExprKind::Match { scrutinee, arms } => {
+            visit(scrutinee, f);
+            arms.iter().for_each(|a| visit(&a.body, f));
+        }
+        HirExprKind::Block { expressions } => expressions.iter().for_each(|x| visit(x, f)),
+        _ => {}
+    }
 }
 
 /// Utility for creating a simple internal error diagnostic.
-///
-/// 今は使っていないが、将来 HIR lowering 中の internal error を
-/// 記録するのに使うかもしれない。
 fn internal_error(span: Span, msg: &str) -> Diagnostic {
-    Diagnostic::error(
-        alloc::format!("internal codegen error: {}", msg),
-        span,
-    )
+    Diagnostic::error_key("E0900.codegen", span).arg("detail", msg)
 }
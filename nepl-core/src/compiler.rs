@@ -9,13 +9,19 @@
 #![allow(dead_code)]
 
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 
+use crate::ast::Expr;
 use crate::codegen_llvm;
-use crate::codegen_wasm;
+use crate::codegen_wasm::{self, Assertion, TestExport};
 use crate::diagnostic::{Diagnostic, Severity};
 use crate::error::CoreError;
+use crate::hir::HirModule;
+use crate::interp::{Host, Interpreter, Value};
 use crate::lexer;
+use crate::lint::{self, LintConfig, LintLevels};
+use crate::name_resolve::{self, NameResolveResult};
 use crate::parser;
 use crate::span::FileId;
 use crate::typecheck;
@@ -28,6 +34,33 @@ use crate::typecheck;
 pub struct CompilationArtifact {
     /// The compiled wasm module bytes.
     pub wasm: Vec<u8>,
+    /// Lint warnings reported while compiling.
+    pub warnings: Vec<Diagnostic>,
+    /// The `assert` calls, indexed by the argument of the host import
+    /// `assert_failed`.
+    pub assertions: Vec<Assertion>,
+    /// The exported tests (test builds only).
+    pub tests: Vec<TestExport>,
+}
+
+/// The results of the front end for one source file.
+#[derive(Debug)]
+pub struct FrontEnd {
+    /// The AST, unless parsing failed badly.
+    pub expr: Option<Expr>,
+    /// The names of `expr`; empty without it.
+    pub names: NameResolveResult,
+    /// The type check result; `None` exactly when `expr` is.
+    pub typed: Option<typecheck::TypeCheckResult>,
+    /// Every diagnostic of the front end. Those of the type checker
+    /// are moved here out of `typed`.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Compile a NEPL source file into a wasm module, with lints at their
+/// default levels.
+pub fn compile_wasm(file_id: FileId, source: &str) -> Result<CompilationArtifact, CoreError> {
+    compile_wasm_with(file_id, source, &LintConfig::default())
 }
 
 /// Compile a NEPL source file into a wasm module.
@@ -37,40 +70,59 @@ pub struct CompilationArtifact {
 ///
 /// どこかの段階でエラーが1つでも発生した場合、
 /// それまでに収集した `Diagnostic` を `CoreError::Diagnostics` として返す。
-pub fn compile_wasm(file_id: FileId, source: &str) -> Result<CompilationArtifact, CoreError> {
-    // 1. Lexing
-    let lex_result = lexer::lex(file_id, source);
-
-    // 2. Parsing (from existing lex result to avoid re-lexing)
-    let parse_result = parser::parse_tokens(source, &lex_result);
-
-    // AST が取れないレベルでの致命的エラー
-    let ast = match parse_result.expr {
-        Some(ref e) => e,
-        None => {
-            return Err(CoreError::from_diagnostics(parse_result.diagnostics));
-        }
-    };
+/// Lints denied by `lints` count as errors.
+pub fn compile_wasm_with(
+    file_id: FileId,
+    source: &str,
+    lints: &LintConfig,
+) -> Result<CompilationArtifact, CoreError> {
+    build(file_id, source, lints, false)
+}
 
-    // 3. Type checking → HIR
-    let typecheck_result = typecheck::typecheck_expr(ast);
+/// Compile a NEPL source file as a test build: the bodies of its `test`
+/// declarations are type-checked and each is exported as a function
+/// `test:NAME` (listed in `CompilationArtifact::tests`).
+pub fn compile_tests(
+    file_id: FileId,
+    source: &str,
+    lints: &LintConfig,
+) -> Result<CompilationArtifact, CoreError> {
+    build(file_id, source, lints, true)
+}
 
-    // ここまでの diagnostics を全部まとめる
-    let mut diagnostics: Vec<Diagnostic> = Vec::new();
-    diagnostics.extend(lex_result.diagnostics.into_iter());
-    diagnostics.extend(parse_result.diagnostics.into_iter());
-    diagnostics.extend(typecheck_result.diagnostics.into_iter());
+fn build(
+    file_id: FileId,
+    source: &str,
+    lints: &LintConfig,
+    test_mode: bool,
+) -> Result<CompilationArtifact, CoreError> {
+    let FrontEnd {
+        typed: typecheck_result,
+        mut diagnostics,
+        ..
+    } = front_end(file_id, source, lints, test_mode);
 
     // HIR が得られない、またはエラーが存在するならここで終了
+    let Some(typecheck_result) = typecheck_result else {
+        return Err(CoreError::from_diagnostics(diagnostics));
+    };
     if has_error(&diagnostics) || typecheck_result.expr.is_none() {
         return Err(CoreError::from_diagnostics(diagnostics));
     }
 
     let hir_entry = typecheck_result.expr.as_ref().unwrap();
+    let hir_module = HirModule {
+        functions: typecheck_result.functions,
+    };
 
     // 4. WASM codegen
-    match codegen_wasm::generate_wasm(hir_entry) {
-        Ok(bytes) => Ok(CompilationArtifact { wasm: bytes }),
+    match codegen_wasm::generate_wasm_module(&hir_module, hir_entry, &typecheck_result.tests) {
+        Ok(module) => Ok(CompilationArtifact {
+            wasm: module.bytes,
+            warnings: diagnostics,
+            assertions: module.assertions,
+            tests: module.tests,
+        }),
         Err(mut cg_diags) => {
             diagnostics.append(&mut cg_diags);
             Err(CoreError::from_diagnostics(diagnostics))
@@ -78,28 +130,31 @@ pub fn compile_wasm(file_id: FileId, source: &str) -> Result<CompilationArtifact
     }
 }
 
+/// Compile a NEPL source file into LLVM IR (stub), with lints at their
+/// default levels.
+pub fn emit_llvm_ir(file_id: FileId, source: &str) -> Result<String, CoreError> {
+    emit_llvm_ir_with(file_id, source, &LintConfig::default())
+}
+
 /// Compile a NEPL source file into LLVM IR (stub).
 ///
 /// パイプラインは `compile_wasm` と同じ lex → parse → typecheck までを共有し、
 /// 最後に `codegen_llvm::generate_llvm_ir` を呼び出す。
-pub fn emit_llvm_ir(file_id: FileId, source: &str) -> Result<String, CoreError> {
-    let lex_result = lexer::lex(file_id, source);
-    let parse_result = parser::parse_tokens(source, &lex_result);
+/// Warnings are not returned.
+pub fn emit_llvm_ir_with(
+    file_id: FileId,
+    source: &str,
+    lints: &LintConfig,
+) -> Result<String, CoreError> {
+    let FrontEnd {
+        typed: typecheck_result,
+        diagnostics,
+        ..
+    } = front_end(file_id, source, lints, false);
 
-    let ast = match parse_result.expr {
-        Some(ref e) => e,
-        None => {
-            return Err(CoreError::from_diagnostics(parse_result.diagnostics));
-        }
+    let Some(typecheck_result) = typecheck_result else {
+        return Err(CoreError::from_diagnostics(diagnostics));
     };
-
-    let typecheck_result = typecheck::typecheck_expr(ast);
-
-    let mut diagnostics: Vec<Diagnostic> = Vec::new();
-    diagnostics.extend(lex_result.diagnostics.into_iter());
-    diagnostics.extend(parse_result.diagnostics.into_iter());
-    diagnostics.extend(typecheck_result.diagnostics.into_iter());
-
     if has_error(&diagnostics) || typecheck_result.expr.is_none() {
         return Err(CoreError::from_diagnostics(diagnostics));
     }
@@ -109,6 +164,105 @@ pub fn emit_llvm_ir(file_id: FileId, source: &str) -> Result<String, CoreError>
     Ok(ir)
 }
 
+/// Type-check a NEPL source file and evaluate it with the HIR
+/// interpreter instead of generating code, with lints at their default
+/// levels.
+///
+/// Host builtins are delegated to `host`. With `fuel`, evaluation stops
+/// with `E1001` after that many steps. A runtime error is returned as
+/// the only diagnostic of the error. Deep recursion needs a thread of
+/// [`interp::STACK_SIZE`](crate::interp::STACK_SIZE).
+pub fn interpret<H: Host>(
+    file_id: FileId,
+    source: &str,
+    host: H,
+    fuel: Option<u64>,
+) -> Result<Value, CoreError> {
+    let FrontEnd {
+        typed: typecheck_result,
+        diagnostics,
+        ..
+    } = front_end(file_id, source, &LintConfig::default(), false);
+
+    let Some(typecheck_result) = typecheck_result else {
+        return Err(CoreError::from_diagnostics(diagnostics));
+    };
+    let Some(entry) = typecheck_result.expr.as_ref() else {
+        return Err(CoreError::from_diagnostics(diagnostics));
+    };
+    if has_error(&diagnostics) {
+        return Err(CoreError::from_diagnostics(diagnostics));
+    }
+
+    let module = HirModule {
+        functions: typecheck_result.functions,
+    };
+    let mut interpreter = Interpreter::new(&module, host);
+    if let Some(fuel) = fuel {
+        interpreter = interpreter.with_fuel(fuel);
+    }
+    interpreter
+        .eval(entry)
+        .map_err(|d| CoreError::from_diagnostics(vec![*d]))
+}
+
+/// Run the front end (lexer, parser, type checker and lints) and
+/// return every diagnostic, without generating code. The bodies of
+/// `test` declarations are checked too.
+pub fn check(file_id: FileId, source: &str, lints: &LintConfig) -> Vec<Diagnostic> {
+    analyze(file_id, source, lints).diagnostics
+}
+
+/// Like [`check`], but keep the AST, its names and the type check
+/// result as well, for tools that answer queries about the file.
+pub fn analyze(file_id: FileId, source: &str, lints: &LintConfig) -> FrontEnd {
+    front_end(file_id, source, lints, true)
+}
+
+/// lex -> parse -> name_resolve + typecheck. `test_mode` selects
+/// `typecheck_tests`.
+fn front_end(file_id: FileId, source: &str, lints: &LintConfig, test_mode: bool) -> FrontEnd {
+    // 1. Lexing
+    let lex_result = lexer::lex(file_id, source);
+
+    // 2. Parsing (from existing lex result to avoid re-lexing)
+    let parse_result = parser::parse_tokens(source, &lex_result);
+
+    // AST が取れないレベルでの致命的エラー
+    let Some(ast) = &parse_result.expr else {
+        return FrontEnd {
+            expr: None,
+            names: NameResolveResult::default(),
+            typed: None,
+            diagnostics: parse_result.diagnostics,
+        };
+    };
+
+    // 3. Type checking → HIR, with the lint levels of this file
+    let (levels, pragma_diags) =
+        LintLevels::from_source(lints.clone(), source, &lex_result.tokens);
+    let mut typecheck_result = if test_mode {
+        typecheck::typecheck_tests(ast, &levels)
+    } else {
+        typecheck::typecheck_expr_with(ast, &levels)
+    };
+
+    // ここまでの diagnostics を全部まとめる
+    // (`parse_result` already includes those of the lexer)
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    diagnostics.extend(parse_result.diagnostics);
+    diagnostics.extend(pragma_diags);
+    diagnostics.append(&mut typecheck_result.diagnostics);
+    let names = name_resolve::resolve_names(ast);
+    diagnostics.extend(lint::unused_uses(&names, &levels));
+    FrontEnd {
+        expr: parse_result.expr,
+        names,
+        typed: Some(typecheck_result),
+        diagnostics,
+    }
+}
+
 /// Returns true if any of the diagnostics is an error.
 fn has_error(diags: &[Diagnostic]) -> bool {
     diags
@@ -0,0 +1,664 @@
+//! Lossless concrete syntax tree (no_std).
+//!
+//! The AST in `ast.rs` drops whitespace, comments and most punctuation.
+//! The tree in this module keeps every byte of the source, so tools such
+//! as a formatter, refactorings or a language server can inspect and
+//! rewrite code without destroying its layout. Printing a tree gives
+//! back the exact text it was parsed from.
+//!
+//! As in Roslyn and rust-analyzer, the tree has two layers:
+//!
+//! * The *green* tree ([`GreenNode`], [`GreenToken`]) is immutable and
+//!   position independent: nodes only know their kind, their children
+//!   and their text length, so subtrees can be shared between versions
+//!   of a file.
+//! * The *red* tree ([`SyntaxNode`], [`SyntaxToken`]) is a thin cursor
+//!   over the green tree, built on demand while walking it, that knows
+//!   absolute offsets and parents.
+//!
+//! Trivia (whitespace and comments) and any text the lexer skipped are
+//! tokens of their own. Trivia between two nodes belongs to their
+//! common parent, so every node starts and ends with a real token.
+//!
+//! [`crate::parser::parse_cst`] builds a tree, and
+//! [`crate::parser::lower_cst`] derives the AST from one.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::ast::ExprKind;
+use crate::lexer::{Token, TokenKind, Trivia, TriviaKind};
+use crate::span::{FileId, Span};
+
+/// Kind of a node or token in the concrete syntax tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    // Tokens
+    /// A token produced by the lexer.
+    Token(TokenKind),
+    /// Whitespace or a comment.
+    Trivia(TriviaKind),
+    /// Source text the lexer skipped, such as an unexpected character
+    /// or an unterminated string literal.
+    Garbage,
+
+    // Nodes
+    /// The whole file.
+    Root,
+    /// Tokens that did not parse.
+    Error,
+    Seq,
+    Pipe,
+    Paren,
+    Literal,
+    Ident,
+    If,
+    Loop,
+    While,
+    Match,
+    MatchCase,
+    Block,
+    Let,
+    LetFunction,
+    FuncLiteral,
+    Param,
+    TypeParams,
+    Include,
+    Import,
+    Namespace,
+    Use,
+    When,
+    Test,
+    Return,
+    Break,
+    Continue,
+    Set,
+    EnumDef,
+    EnumVariant,
+    StructDef,
+    StructField,
+    Pattern,
+    TypeExpr,
+}
+
+impl SyntaxKind {
+    /// Returns true for token kinds, including trivia and garbage.
+    pub fn is_token(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Token(_) | SyntaxKind::Trivia(_) | SyntaxKind::Garbage
+        )
+    }
+
+    /// Returns true for whitespace and comments.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, SyntaxKind::Trivia(_))
+    }
+
+    /// The node kind of an expression.
+    pub fn of_expr(kind: &ExprKind) -> SyntaxKind {
+        match kind {
+            ExprKind::Seq(_) => SyntaxKind::Seq,
+            ExprKind::Paren(_) => SyntaxKind::Paren,
+            ExprKind::Pipe(_) => SyntaxKind::Pipe,
+            ExprKind::Literal(_) => SyntaxKind::Literal,
+            ExprKind::Ident(_) => SyntaxKind::Ident,
+            ExprKind::If(_) => SyntaxKind::If,
+            ExprKind::Loop(_) => SyntaxKind::Loop,
+            ExprKind::While(_) => SyntaxKind::While,
+            ExprKind::Match(_) => SyntaxKind::Match,
+            ExprKind::Block(_) => SyntaxKind::Block,
+            ExprKind::Let(_) => SyntaxKind::Let,
+            ExprKind::LetFunction(_) => SyntaxKind::LetFunction,
+            ExprKind::FuncLiteral(_) => SyntaxKind::FuncLiteral,
+            ExprKind::Include(_) => SyntaxKind::Include,
+            ExprKind::Import(_) => SyntaxKind::Import,
+            ExprKind::Namespace(_) => SyntaxKind::Namespace,
+            ExprKind::Use(_) => SyntaxKind::Use,
+            ExprKind::When(_) => SyntaxKind::When,
+            ExprKind::Test(_) => SyntaxKind::Test,
+            ExprKind::Return(_) => SyntaxKind::Return,
+            ExprKind::Break(_) => SyntaxKind::Break,
+            ExprKind::Continue(_) => SyntaxKind::Continue,
+            ExprKind::Set(_) => SyntaxKind::Set,
+            ExprKind::EnumDef(_) => SyntaxKind::EnumDef,
+            ExprKind::StructDef(_) => SyntaxKind::StructDef,
+        }
+    }
+}
+
+impl fmt::Display for SyntaxKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxKind::Token(kind) => write!(f, "{kind:?}"),
+            SyntaxKind::Trivia(kind) => write!(f, "{kind:?}"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+// === green tree ===============================================================
+
+/// An immutable token without a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreenToken {
+    kind: SyntaxKind,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: SyntaxKind, text: impl Into<String>) -> Self {
+        GreenToken {
+            kind,
+            text: text.into(),
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn text_len(&self) -> u32 {
+        self.text.len() as u32
+    }
+}
+
+/// An immutable node without a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    text_len: u32,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        let text_len = children.iter().map(GreenElement::text_len).sum();
+        GreenNode {
+            kind,
+            text_len,
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn text_len(&self) -> u32 {
+        self.text_len
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    fn write_text(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => node.write_text(out)?,
+                GreenElement::Token(token) => out.write_str(&token.text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A child of a [`GreenNode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GreenElement {
+    Node(Arc<GreenNode>),
+    Token(Arc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            GreenElement::Node(node) => node.kind,
+            GreenElement::Token(token) => token.kind,
+        }
+    }
+
+    pub fn text_len(&self) -> u32 {
+        match self {
+            GreenElement::Node(node) => node.text_len,
+            GreenElement::Token(token) => token.text_len(),
+        }
+    }
+}
+
+// === red tree =================================================================
+
+/// A node of the tree with its position and parent.
+///
+/// Cloning is cheap. Two `SyntaxNode`s are equal when they are the same
+/// node of the same tree.
+#[derive(Clone)]
+pub struct SyntaxNode(Arc<NodeData>);
+
+struct NodeData {
+    green: Arc<GreenNode>,
+    file_id: FileId,
+    offset: u32,
+    parent: Option<SyntaxNode>,
+}
+
+/// A token of the tree with its position and parent.
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Arc<GreenToken>,
+    offset: u32,
+    parent: SyntaxNode,
+}
+
+/// Either a node or a token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    /// Make `green` the root of a tree for the file `file_id`.
+    pub fn new_root(file_id: FileId, green: Arc<GreenNode>) -> Self {
+        SyntaxNode(Arc::new(NodeData {
+            green,
+            file_id,
+            offset: 0,
+            parent: None,
+        }))
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Arc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn span(&self) -> Span {
+        let start = self.0.offset;
+        Span::new(self.0.file_id, start, start + self.0.green.text_len)
+    }
+
+    /// The source text of this node, trivia included.
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.0.green.text_len as usize);
+        let _ = self.0.green.write_text(&mut text);
+        text
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    /// This node and its parents, innermost first.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode> {
+        core::iter::successors(Some(self.clone()), SyntaxNode::parent)
+    }
+
+    /// Direct children, nodes and tokens, in source order.
+    pub fn children_with_tokens(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        let mut offset = self.0.offset;
+        self.0.green.children.iter().map(move |child| {
+            let start = offset;
+            offset += child.text_len();
+            match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Arc::new(NodeData {
+                    green: green.clone(),
+                    file_id: self.0.file_id,
+                    offset: start,
+                    parent: Some(self.clone()),
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: green.clone(),
+                    offset: start,
+                    parent: self.clone(),
+                }),
+            }
+        })
+    }
+
+    /// Direct child nodes in source order.
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        self.children_with_tokens().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// This node and every node below it, parents before children.
+    pub fn descendants(&self) -> impl Iterator<Item = SyntaxNode> {
+        let mut stack = alloc::vec![self.clone()];
+        core::iter::from_fn(move || {
+            let node = stack.pop()?;
+            let len = stack.len();
+            stack.extend(node.children());
+            stack[len..].reverse();
+            Some(node)
+        })
+    }
+
+    /// Every token below this node, trivia included, in source order.
+    pub fn tokens(&self) -> impl Iterator<Item = SyntaxToken> {
+        let mut stack = alloc::vec![SyntaxElement::Node(self.clone())];
+        core::iter::from_fn(move || {
+            loop {
+                match stack.pop()? {
+                    SyntaxElement::Token(token) => return Some(token),
+                    SyntaxElement::Node(node) => {
+                        let len = stack.len();
+                        stack.extend(node.children_with_tokens());
+                        stack[len..].reverse();
+                    }
+                }
+            }
+        })
+    }
+
+    /// The token that contains the byte `offset`. An offset at the end
+    /// of the node gives its last token.
+    pub fn token_at_offset(&self, offset: u32) -> Option<SyntaxToken> {
+        let span = self.span();
+        if offset < span.start || offset > span.end {
+            return None;
+        }
+        let mut last = None;
+        for child in self.children_with_tokens() {
+            let child_span = match &child {
+                SyntaxElement::Node(node) => node.span(),
+                SyntaxElement::Token(token) => token.span(),
+            };
+            if offset < child_span.end {
+                return match child {
+                    SyntaxElement::Node(node) => node.token_at_offset(offset),
+                    SyntaxElement::Token(token) => Some(token),
+                };
+            }
+            last = Some(child);
+        }
+        match last? {
+            SyntaxElement::Node(node) => node.token_at_offset(offset),
+            SyntaxElement::Token(token) => Some(token),
+        }
+    }
+
+    /// An indented dump of the tree, one node or token per line, such
+    /// as `Ident@0..3` followed by `  Token(Ident)@0..3 "add"`.
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        let _ = self.write_tree(&mut out, 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) -> fmt::Result {
+        use core::fmt::Write;
+
+        let span = self.span();
+        writeln!(
+            out,
+            "{:indent$}{}@{}..{}",
+            "",
+            self.kind(),
+            span.start,
+            span.end,
+            indent = depth * 2
+        )?;
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.write_tree(out, depth + 1)?,
+                SyntaxElement::Token(token) => {
+                    writeln!(out, "{:indent$}{:?}", "", token, indent = (depth + 1) * 2)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl Eq for SyntaxNode {}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(f, "{}@{}..{}", self.kind(), span.start, span.end)
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.green.write_text(f)
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(
+            self.parent.0.file_id,
+            self.offset,
+            self.offset + self.green.text_len(),
+        )
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        self.kind().is_trivia()
+    }
+}
+
+impl PartialEq for SyntaxToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.green, &other.green) && self.offset == other.offset
+    }
+}
+
+impl Eq for SyntaxToken {}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "{}@{}..{} {:?}",
+            self.kind(),
+            span.start,
+            span.end,
+            self.text()
+        )
+    }
+}
+
+impl fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.text())
+    }
+}
+
+// === building =================================================================
+
+/// What the parser did, recorded while it runs in CST mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Event {
+    Start(SyntaxKind),
+    /// The parser consumed the next token.
+    Token,
+    Finish,
+}
+
+/// Build a green tree for `source` from the lexer's tokens and the
+/// parser's events. Tokens the parser never consumed end up in an
+/// `Error` node at the end of the root.
+pub(crate) fn build(source: &str, tokens: &[Token], events: &[Event]) -> GreenNode {
+    let mut builder = Builder {
+        source,
+        tokens,
+        next: 0,
+        leading_done: false,
+        cursor: 0,
+        pending: Vec::new(),
+        stack: alloc::vec![(SyntaxKind::Root, Vec::new())],
+    };
+    for event in events {
+        match *event {
+            Event::Start(kind) => builder.start(kind),
+            Event::Token => builder.token(),
+            Event::Finish => builder.finish(),
+        }
+    }
+
+    let eof = tokens.len().saturating_sub(1);
+    if builder.next < eof {
+        builder.start(SyntaxKind::Error);
+        while builder.next < eof {
+            builder.token();
+        }
+        builder.finish();
+    }
+    builder.leading_trivia();
+    builder.gap(source.len() as u32);
+
+    let (kind, children) = builder.stack.pop().expect("root node");
+    debug_assert!(builder.stack.is_empty(), "unbalanced parser events");
+    GreenNode::new(kind, children)
+}
+
+struct Builder<'a> {
+    source: &'a str,
+    tokens: &'a [Token],
+    /// Index of the next token to emit.
+    next: usize,
+    /// Whether the leading trivia of `tokens[next]` was emitted already.
+    leading_done: bool,
+    /// End of the text emitted so far.
+    cursor: u32,
+    /// Trailing trivia of the last token, not emitted yet.
+    pending: Vec<Trivia>,
+    stack: Vec<(SyntaxKind, Vec<GreenElement>)>,
+}
+
+impl Builder<'_> {
+    fn start(&mut self, kind: SyntaxKind) {
+        // Trivia before the first token goes to the parent.
+        self.leading_trivia();
+        self.stack.push((kind, Vec::new()));
+    }
+
+    fn finish(&mut self) {
+        let (kind, children) = self.stack.pop().expect("unbalanced parser events");
+        let node = GreenElement::Node(Arc::new(GreenNode::new(kind, children)));
+        self.push(node);
+    }
+
+    fn token(&mut self) {
+        self.leading_trivia();
+        let Some(token) = self.tokens.get(self.next) else {
+            return;
+        };
+        self.emit(SyntaxKind::Token(token.kind), token.span);
+        self.pending = token.trailing_trivia.clone();
+        self.next += 1;
+        self.leading_done = false;
+    }
+
+    /// Emit the pending trailing trivia and the leading trivia of the
+    /// next token.
+    fn leading_trivia(&mut self) {
+        for trivia in core::mem::take(&mut self.pending) {
+            self.emit(SyntaxKind::Trivia(trivia.kind), trivia.span);
+        }
+        if self.leading_done {
+            return;
+        }
+        self.leading_done = true;
+        if let Some(token) = self.tokens.get(self.next) {
+            for trivia in &token.leading_trivia {
+                self.emit(SyntaxKind::Trivia(trivia.kind), trivia.span);
+            }
+        }
+    }
+
+    fn emit(&mut self, kind: SyntaxKind, span: Span) {
+        self.gap(span.start);
+        let text = &self.source[span.start as usize..span.end as usize];
+        self.push(GreenElement::Token(Arc::new(GreenToken::new(kind, text))));
+        self.cursor = span.end;
+    }
+
+    /// Emit text up to `end` that no token or trivia covers.
+    fn gap(&mut self, end: u32) {
+        if self.cursor < end {
+            let text = &self.source[self.cursor as usize..end as usize];
+            let token = GreenToken::new(SyntaxKind::Garbage, text);
+            self.push(GreenElement::Token(Arc::new(token)));
+            self.cursor = end;
+        }
+    }
+
+    fn push(&mut self, element: GreenElement) {
+        self.stack.last_mut().expect("root node").1.push(element);
+    }
+}
+
+/// Rebuild lexer tokens from the tokens of a tree, so that the parser
+/// can run on it. Garbage is dropped.
+pub(crate) fn lexer_tokens(root: &SyntaxNode) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut leading = Vec::new();
+    for token in root.tokens() {
+        let span = token.span();
+        match token.kind() {
+            SyntaxKind::Trivia(kind) => leading.push(Trivia { kind, span }),
+            SyntaxKind::Token(kind) => {
+                let (mut text_start, mut text_end) = (span.start, span.end);
+                // String tokens point at their contents, without quotes.
+                if kind == TokenKind::StringLiteral && span.len() >= 2 {
+                    text_start += 1;
+                    text_end -= 1;
+                }
+                tokens.push(Token {
+                    kind,
+                    span,
+                    text_start,
+                    text_end,
+                    leading_trivia: core::mem::take(&mut leading),
+                    trailing_trivia: Vec::new(),
+                });
+            }
+            _ => {}
+        }
+    }
+    let end = root.span().end;
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        span: Span::new(root.span().file_id, end, end),
+        text_start: end,
+        text_end: end,
+        leading_trivia: leading,
+        trailing_trivia: Vec::new(),
+    });
+    tokens
+}
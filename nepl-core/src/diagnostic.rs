@@ -3,9 +3,13 @@
 //! This module defines diagnostic structures used to report errors
 //! and warnings with precise source locations and optional notes.
 
-use alloc::string::String;
+use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
+use core::fmt;
 
+use crate::error_codes;
+use crate::messages::{self, Locale};
 use crate::span::Span;
 
 /// Severity level of a diagnostic message.
@@ -25,18 +29,85 @@ pub struct Label {
     pub message: Option<String>,
 }
 
+/// The catalog key and arguments a message was formatted from, so that
+/// it can be formatted again in another locale (see `messages`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageKey {
+    pub key: &'static str,
+    pub args: Vec<(&'static str, String)>,
+}
+
+impl MessageKey {
+    pub fn new(key: &'static str) -> MessageKey {
+        MessageKey {
+            key,
+            args: Vec::new(),
+        }
+    }
+
+    /// Add a named argument.
+    pub fn arg(mut self, name: &'static str, value: impl fmt::Display) -> MessageKey {
+        self.args.push((name, value.to_string()));
+        self
+    }
+
+    /// Format the message in `locale`.
+    pub fn format(&self, locale: Locale) -> String {
+        messages::format(locale, self.key, &self.args)
+    }
+}
+
+/// Replacement of the source text covered by `span`.
+///
+/// An empty span inserts `replacement`; an empty `replacement` deletes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// A mechanical fix for a diagnostic (“fix-it”).
+///
+/// Applying all `edits` of a suggestion together is expected to
+/// resolve the diagnostic. Edits of one suggestion never overlap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// Short description shown as `help: ...`, in English.
+    pub message: String,
+    /// Where `message` came from, if it is localizable.
+    pub localized: Option<MessageKey>,
+    pub edits: Vec<Edit>,
+}
+
+impl Suggestion {
+    /// The description in `locale`.
+    pub fn message_in(&self, locale: Locale) -> String {
+        match &self.localized {
+            Some(m) => m.format(locale),
+            None => self.message.clone(),
+        }
+    }
+}
+
 /// A single diagnostic message produced by the compiler.
 ///
 /// A diagnostic has a main message, a primary label indicating the
 /// main source location, and zero or more secondary labels for
 /// related locations (for example, “defined here”, “required here”).
+/// It may also carry suggestions that fix it.
+///
+/// `message` is always English. Diagnostics created from a catalog key
+/// (`error_key` / `warning_key`) remember it in `localized` and can be
+/// shown in other languages with `message_in`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Diagnostic {
     pub severity: Severity,
     pub code: Option<&'static str>,
     pub message: String,
+    pub localized: Option<MessageKey>,
     pub primary: Label,
     pub secondary: Vec<Label>,
+    pub suggestions: Vec<Suggestion>,
 }
 
 impl Diagnostic {
@@ -46,11 +117,13 @@ impl Diagnostic {
             severity: Severity::Error,
             code: None,
             message: message.into(),
+            localized: None,
             primary: Label {
                 span: primary_span,
                 message: None,
             },
             secondary: Vec::new(),
+            suggestions: Vec::new(),
         }
     }
 
@@ -60,11 +133,61 @@ impl Diagnostic {
             severity: Severity::Warning,
             code: None,
             message: message.into(),
+            localized: None,
+            primary: Label {
+                span: primary_span,
+                message: None,
+            },
+            secondary: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    /// Create an error from the message catalog. The code is taken from
+    /// the key (`"E0300"`, `"E0301.given"`, ...); add arguments with
+    /// `arg`.
+    pub fn error_key(key: &'static str, primary_span: Span) -> Diagnostic {
+        Diagnostic::from_key(Severity::Error, key, primary_span)
+    }
+
+    /// Create a warning from the message catalog.
+    pub fn warning_key(key: &'static str, primary_span: Span) -> Diagnostic {
+        Diagnostic::from_key(Severity::Warning, key, primary_span)
+    }
+
+    fn from_key(severity: Severity, key: &'static str, primary_span: Span) -> Diagnostic {
+        let message = MessageKey::new(key);
+        let code = key.split('.').next().and_then(error_codes::lookup).map(|c| c.code);
+        Diagnostic {
+            severity,
+            code: code.filter(|c| key.starts_with(c)),
+            message: message.format(Locale::En),
+            localized: Some(message),
             primary: Label {
                 span: primary_span,
                 message: None,
             },
             secondary: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    /// Add a named argument to a diagnostic created with `error_key` or
+    /// `warning_key`.
+    pub fn arg(mut self, name: &'static str, value: impl fmt::Display) -> Diagnostic {
+        if let Some(m) = self.localized.take() {
+            let m = m.arg(name, value);
+            self.message = m.format(Locale::En);
+            self.localized = Some(m);
+        }
+        self
+    }
+
+    /// The main message in `locale`.
+    pub fn message_in(&self, locale: Locale) -> String {
+        match &self.localized {
+            Some(m) => m.format(locale),
+            None => self.message.clone(),
         }
     }
 
@@ -86,4 +209,40 @@ impl Diagnostic {
         });
         self
     }
+
+    /// Add a suggestion that replaces `span` with `replacement`.
+    pub fn with_suggestion(
+        mut self,
+        message: impl Into<String>,
+        span: Span,
+        replacement: impl Into<String>,
+    ) -> Diagnostic {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            localized: None,
+            edits: vec![Edit {
+                span,
+                replacement: replacement.into(),
+            }],
+        });
+        self
+    }
+
+    /// Add a suggestion described by a catalog message.
+    pub fn with_suggestion_key(
+        mut self,
+        message: MessageKey,
+        span: Span,
+        replacement: impl Into<String>,
+    ) -> Diagnostic {
+        self.suggestions.push(Suggestion {
+            message: message.format(Locale::En),
+            localized: Some(message),
+            edits: vec![Edit {
+                span,
+                replacement: replacement.into(),
+            }],
+        });
+        self
+    }
 }
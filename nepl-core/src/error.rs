@@ -10,7 +10,7 @@ use core::fmt;
 use alloc::vec;
 use alloc::vec::Vec;
 
-use crate::diagnostic::Diagnostic;
+use crate::diagnostic::{Diagnostic, Severity};
 
 /// Core error type for the nepl-core crate.
 ///
@@ -52,13 +52,25 @@ impl fmt::Display for CoreError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CoreError::Diagnostics(diags) => {
-                if let Some(first) = diags.first() {
-                    // とりあえず先頭のメッセージだけを出す。
-                    // 上位レイヤーで複数の Diagnostic を整形表示することを想定している。
-                    write!(f, "{}", first.message)
-                } else {
-                    write!(f, "diagnostic error (no messages)")
+                if diags.is_empty() {
+                    return write!(f, "diagnostic error (no messages)");
                 }
+                // ソース位置付きの整形表示は `render::Renderer` を使う。
+                for (i, d) in diags.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    let severity = match d.severity {
+                        Severity::Error => "error",
+                        Severity::Warning => "warning",
+                    };
+                    write!(f, "{severity}")?;
+                    if let Some(code) = d.code {
+                        write!(f, "[{code}]")?;
+                    }
+                    write!(f, ": {}", d.message)?;
+                }
+                Ok(())
             }
             CoreError::Internal(msg) => write!(f, "internal compiler error: {msg}"),
         }
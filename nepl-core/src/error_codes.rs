@@ -0,0 +1,130 @@
+//! Catalog of diagnostic codes (no_std).
+//!
+//! Every diagnostic produced by the compiler carries one of the codes
+//! defined here. Codes are grouped by the phase that reports them:
+//!
+//! | range   | phase                                  |
+//! |---------|----------------------------------------|
+//! | `E00xx` | lexer                                  |
+//! | `E01xx` | parser                                 |
+//! | `E02xx` | name resolution                        |
+//! | `E03xx` | type checking                          |
+//! | `E04xx` | purity and mutability                  |
+//! | `E05xx` | function literals and captures         |
+//! | `E06xx` | patterns and `match`                   |
+//! | `E07xx` | control flow                           |
+//! | `E08xx` | features not implemented yet           |
+//! | `E09xx` | internal compiler errors               |
+//! | `E10xx` | runtime errors                         |
+//! | `W0xxx` | lints (warnings by default, see `lint`) |
+//!
+//! Codes are stable: once published, a code keeps its meaning and is
+//! never reused. Each code has a longer explanation in
+//! `error_codes/<CODE>.md`, shown by `nepl explain <CODE>`.
+//!
+//! Explanations are Markdown. A ` ```nepl,compile_fail ` or
+//! ` ```nepl,warn ` block must report its code; a plain ` ```nepl `
+//! block must compile without any diagnostics.
+
+/// A diagnostic code with its explanation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    /// One-line summary.
+    pub title: &'static str,
+    /// Markdown explanation with examples.
+    pub explanation: &'static str,
+}
+
+macro_rules! error_codes {
+    ($($code:ident: $title:literal,)*) => {
+        $(
+            #[doc = $title]
+            pub const $code: &str = stringify!($code);
+        )*
+
+        /// Every known code, in ascending order.
+        pub const CATALOG: &[ErrorCode] = &[
+            $(ErrorCode {
+                code: stringify!($code),
+                title: $title,
+                explanation: include_str!(concat!("error_codes/", stringify!($code), ".md")),
+            },)*
+        ];
+    };
+}
+
+error_codes! {
+    E0001: "unexpected character",
+    E0002: "unterminated string literal",
+    E0003: "unterminated block comment",
+    E0004: "invalid escape in string literal",
+    E0005: "malformed numeric literal",
+
+    E0100: "syntax error: expected a different token",
+    E0101: "extra tokens after the top-level expression",
+    E0102: "invalid literal",
+
+    E0200: "unresolved identifier",
+    E0201: "unknown type",
+    E0202: "duplicate variant or field",
+    E0203: "type parameter shadows an outer one",
+    E0204: "duplicate test name",
+
+    E0300: "mismatched types",
+    E0301: "wrong number of type arguments",
+    E0302: "P-style expression does not produce exactly one value",
+    E0303: "no matching overload",
+    E0304: "missing arguments",
+    E0305: "ambiguous overloaded function",
+    E0306: "cannot infer a type parameter",
+    E0307: "name cannot be used as a value",
+    E0308: "branches have incompatible types",
+    E0309: "literal out of range",
+
+    E0400: "impure call from a pure function",
+    E0401: "pure function with `mut` parameters",
+    E0402: "`mut` argument is not a mutable variable",
+    E0403: "variable passed to a `mut` parameter more than once",
+    E0404: "invalid assignment target",
+    E0405: "assignment to a captured variable",
+    E0406: "`let hoist` combined with `mut`",
+
+    E0500: "`fn` bound to something other than a function literal",
+    E0501: "`fn` binding captures a local variable",
+    E0502: "generic function literal used as a closure",
+    E0503: "`test` captures a local variable",
+
+    E0600: "non-exhaustive match",
+    E0601: "pattern does not fit the matched type",
+    E0602: "wrong number of fields in a variant pattern",
+    E0603: "expected a literal pattern",
+
+    E0700: "`break` or `continue` outside of a loop",
+    E0701: "`break` value does not fit the loop",
+    E0702: "invalid `return`",
+
+    E0800: "not implemented yet",
+
+    E0900: "internal compiler error",
+
+    E1000: "runtime error",
+    E1001: "evaluation ran out of fuel",
+
+    W0001: "unused variable",
+    W0002: "unused `use`",
+    W0003: "binding shadows another one",
+    W0004: "unreachable code",
+    W0005: "impure function literal could be pure",
+    W0006: "unknown lint in a pragma",
+}
+
+/// Look up a code such as `"E0300"` or `"W0001"`. Lowercase is
+/// accepted, and the leading `E` of error codes may be omitted.
+pub fn lookup(code: &str) -> Option<&'static ErrorCode> {
+    let code = match code.as_bytes().first() {
+        Some(c) if c.is_ascii_digit() => return CATALOG.iter().find(|c| &c.code[1..] == code),
+        _ => code,
+    };
+    CATALOG.iter().find(|c| c.code.eq_ignore_ascii_case(code))
+}
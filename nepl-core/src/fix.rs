@@ -0,0 +1,72 @@
+//! Applying suggestions to source text (no_std).
+//!
+//! This is what `nepl fix` uses: the first suggestion of every
+//! diagnostic is applied, unless one of its edits overlaps an edit that
+//! was already accepted. Fixing one problem can reveal (or resolve)
+//! others, so callers usually recompile and repeat until nothing is
+//! left to apply.
+//!
+//! While there are errors, the suggestions of warnings are left alone:
+//! an unused variable is often just the one a misspelled name meant.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::diagnostic::{Diagnostic, Edit, Severity};
+use crate::span::FileId;
+
+/// Result of [`apply_suggestions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixed {
+    pub source: String,
+    /// Number of suggestions that were applied.
+    pub applied: usize,
+}
+
+/// Apply the suggestions of `diagnostics` that target `file_id` to
+/// `source`.
+pub fn apply_suggestions(file_id: FileId, source: &str, diagnostics: &[Diagnostic]) -> Fixed {
+    let mut accepted: Vec<&Edit> = Vec::new();
+    let mut applied = 0;
+    let has_errors = diagnostics.iter().any(|d| d.severity == Severity::Error);
+    for d in diagnostics {
+        if has_errors && d.severity != Severity::Error {
+            continue;
+        }
+        let Some(suggestion) = d.suggestions.first() else {
+            continue;
+        };
+        let usable = suggestion.edits.iter().all(|e| {
+            e.span.file_id == file_id
+                && e.span.start <= e.span.end
+                && (e.span.end as usize) <= source.len()
+                && source.is_char_boundary(e.span.start as usize)
+                && source.is_char_boundary(e.span.end as usize)
+                && !accepted.iter().any(|a| overlaps(a, e))
+        });
+        if usable {
+            accepted.extend(suggestion.edits.iter());
+            applied += 1;
+        }
+    }
+
+    accepted.sort_by_key(|e| e.span.start);
+    let mut out = String::with_capacity(source.len());
+    let mut pos = 0usize;
+    for e in accepted {
+        out.push_str(&source[pos..e.span.start as usize]);
+        out.push_str(&e.replacement);
+        pos = e.span.end as usize;
+    }
+    out.push_str(&source[pos..]);
+    Fixed {
+        source: out,
+        applied,
+    }
+}
+
+/// Two edits conflict when their ranges intersect or when both start at
+/// the same position (the order of the results would be ambiguous).
+fn overlaps(a: &Edit, b: &Edit) -> bool {
+    a.span.start == b.span.start || (a.span.start < b.span.end && b.span.start < a.span.end)
+}
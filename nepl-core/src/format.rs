@@ -0,0 +1,480 @@
+//! Source formatter for NEPL (no_std).
+//!
+//! The formatter prints the concrete syntax tree from `cst.rs` in a
+//! canonical layout, keeping every comment:
+//!
+//! * Scoped lists (`{ ... }` blocks, `match` cases, `enum` variants and
+//!   `struct` fields) stay on one line when they were written on one
+//!   line and fit; otherwise every item goes on its own line, indented
+//!   one level. A blank line between items is kept.
+//! * Pipe chains that were broken over lines, or that do not fit, put
+//!   each `> f` on its own line, indented one level.
+//! * P-style sequences too long for a line put every argument after the
+//!   first on its own line, indented one level.
+//! * Spacing is normalised: one space between terms, none inside
+//!   parentheses, and the compact forms `<T>|T x|->R`, `Option<T>` and
+//!   `Some(x)`.
+//!
+//! Whitespace carries no meaning in NEPL, so only whitespace changes.
+//! Formatting is idempotent: formatting formatted code changes
+//! nothing.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::lexer::{TokenKind, TriviaKind};
+use crate::parser::parse_cst;
+use crate::span::{FileId, Span};
+
+/// Layout settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Spaces per indentation level.
+    pub indent_width: usize,
+    /// Lines longer than this are broken where the layout allows it.
+    pub max_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_width: 4,
+            max_width: 100,
+        }
+    }
+}
+
+/// Format a source file.
+///
+/// Files with syntax errors are not formatted; their errors are
+/// returned instead.
+pub fn format_source(
+    file_id: FileId,
+    source: &str,
+    options: &FormatOptions,
+) -> Result<String, Vec<Diagnostic>> {
+    let parsed = parse_cst(file_id, source);
+    let errors: Vec<Diagnostic> = parsed
+        .diagnostics
+        .into_iter()
+        .filter(|d| d.severity == Severity::Error)
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let formatted = format_tree(&parsed.tree, options);
+    // A formatter bug must not change the program.
+    if significant_tokens(&parse_cst(file_id, &formatted).tree) != significant_tokens(&parsed.tree)
+    {
+        let span = Span::new(file_id, 0, 0);
+        return Err(alloc::vec![Diagnostic::error_key("E0900.formatter", span)]);
+    }
+    Ok(formatted)
+}
+
+/// Format a syntax tree. The tree should be free of syntax errors;
+/// text in `Error` nodes is printed with normalised spacing.
+pub fn format_tree(tree: &SyntaxNode, options: &FormatOptions) -> String {
+    let mut formatter = Formatter::new(options, false);
+    formatter.children(tree);
+    let mut out = formatter.out;
+    let len = out.trim_end().len();
+    out.truncate(len);
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// Tokens and comments; trailing spaces of comments are dropped when
+/// formatting.
+fn significant_tokens(tree: &SyntaxNode) -> Vec<(SyntaxKind, String)> {
+    tree.tokens()
+        .filter(|t| t.kind() != SyntaxKind::Trivia(TriviaKind::Whitespace))
+        .map(|t| (t.kind(), String::from(t.text().trim_end())))
+        .collect()
+}
+
+/// What to print before the next token. Later requests only ever make
+/// the separator larger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Sep {
+    None,
+    Space,
+    Newline,
+    BlankLine,
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    out: String,
+    indent: usize,
+    pending: Sep,
+    /// The last token printed, comments excluded.
+    prev: Option<SyntaxToken>,
+    /// Line breaks in the source since the last printed token.
+    newlines: usize,
+    /// Set to print everything on one line (see [`Formatter::flat`]).
+    flat: bool,
+    /// In flat mode: the node cannot be printed on one line.
+    broken: bool,
+    /// The last thing printed is a `/* */` comment.
+    after_block_comment: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(options: &'a FormatOptions, flat: bool) -> Self {
+        Formatter {
+            options,
+            out: String::new(),
+            indent: 0,
+            pending: Sep::None,
+            prev: None,
+            newlines: 0,
+            flat,
+            broken: false,
+            after_block_comment: false,
+        }
+    }
+
+    // === printing =============================================================
+
+    fn request(&mut self, sep: Sep) {
+        self.pending = self.pending.max(sep);
+    }
+
+    fn line_break(&mut self) -> Sep {
+        if self.newlines >= 2 {
+            Sep::BlankLine
+        } else {
+            Sep::Newline
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        let mut sep = core::mem::replace(&mut self.pending, Sep::None);
+        if self.out.is_empty() {
+            sep = Sep::None;
+        }
+        if self.flat && sep >= Sep::Newline {
+            self.broken = true;
+            sep = Sep::Space;
+        }
+        // No blank line right after an opening brace.
+        if sep == Sep::BlankLine && self.out.ends_with(['{', ':', '(']) {
+            sep = Sep::Newline;
+        }
+        match sep {
+            Sep::None => {}
+            Sep::Space => self.out.push(' '),
+            Sep::Newline | Sep::BlankLine => {
+                let len = self.out.trim_end_matches(' ').len();
+                self.out.truncate(len);
+                self.out.push('\n');
+                if sep == Sep::BlankLine {
+                    self.out.push('\n');
+                }
+                for _ in 0..self.indent * self.options.indent_width {
+                    self.out.push(' ');
+                }
+            }
+        }
+        self.out.push_str(text);
+        self.newlines = 0;
+        self.after_block_comment = false;
+    }
+
+    /// The column the next token would start at.
+    fn column(&self) -> usize {
+        match self.pending {
+            Sep::None | Sep::Space => {
+                let line = self.out.rsplit('\n').next().unwrap_or("");
+                line.chars().count() + usize::from(self.pending == Sep::Space)
+            }
+            Sep::Newline | Sep::BlankLine => self.indent * self.options.indent_width,
+        }
+    }
+
+    /// `node` printed on one line, or `None` if it has to span lines.
+    fn flat(&self, node: &SyntaxNode) -> Option<String> {
+        let mut formatter = Formatter::new(self.options, true);
+        formatter.node(node);
+        (!formatter.broken).then_some(formatter.out)
+    }
+
+    /// Whether `node` printed on one line would run past the maximum
+    /// width (or cannot be printed on one line at all).
+    fn too_wide(&self, node: &SyntaxNode) -> bool {
+        match self.flat(node) {
+            Some(text) => self.column() + text.chars().count() > self.options.max_width,
+            None => true,
+        }
+    }
+
+    // === tree walking =========================================================
+
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind() {
+            SyntaxKind::Block | SyntaxKind::Match | SyntaxKind::EnumDef | SyntaxKind::StructDef => {
+                self.scoped_list(node)
+            }
+            SyntaxKind::Pipe => self.pipe(node),
+            SyntaxKind::Seq => self.seq(node),
+            _ => self.children(node),
+        }
+    }
+
+    fn children(&mut self, node: &SyntaxNode) {
+        let mut indent_body = false;
+        for child in node.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(child) if indent_body => {
+                    indent_body = false;
+                    self.indent += 1;
+                    self.request(Sep::Newline);
+                    self.node(&child);
+                    self.indent -= 1;
+                }
+                SyntaxElement::Node(child) => self.node(&child),
+                SyntaxElement::Token(token) => {
+                    // `: body` written on the next line stays there.
+                    let is_colon = token.kind() == SyntaxKind::Token(TokenKind::Colon);
+                    if !is_colon && !token.is_trivia() {
+                        indent_body = false;
+                    }
+                    if token.kind() == SyntaxKind::Trivia(TriviaKind::Whitespace)
+                        && self.is_body_colon(node)
+                        && token.text().contains('\n')
+                    {
+                        indent_body = !self.flat;
+                        self.broken |= self.flat;
+                    }
+                    self.token(&token);
+                }
+            }
+        }
+    }
+
+    /// Whether the last token printed is a `:` that starts a body.
+    fn is_body_colon(&self, node: &SyntaxNode) -> bool {
+        let Some(prev) = &self.prev else {
+            return false;
+        };
+        prev.kind() == SyntaxKind::Token(TokenKind::Colon)
+            && prev.parent() == *node
+            && !matches!(node.kind(), SyntaxKind::Let | SyntaxKind::Param)
+    }
+
+    fn token(&mut self, token: &SyntaxToken) {
+        match token.kind() {
+            SyntaxKind::Trivia(TriviaKind::Whitespace) => {
+                self.newlines += token.text().matches('\n').count();
+            }
+            SyntaxKind::Trivia(TriviaKind::LineComment | TriviaKind::DocComment) => {
+                let sep = if self.newlines == 0 {
+                    Sep::Space
+                } else {
+                    self.line_break()
+                };
+                self.request(sep);
+                self.write(token.text().trim_end());
+                if self.flat {
+                    self.broken = true;
+                }
+                self.request(Sep::Newline);
+            }
+            SyntaxKind::Trivia(TriviaKind::BlockComment) => {
+                let sep = if self.newlines == 0 {
+                    Sep::Space
+                } else {
+                    self.line_break()
+                };
+                self.request(sep);
+                self.write(token.text());
+                self.after_block_comment = true;
+                self.request(Sep::Space);
+            }
+            _ => {
+                if let Some(prev) = &self.prev
+                    && space_between(prev, token)
+                {
+                    self.request(Sep::Space);
+                }
+                if self.pending >= Sep::Newline {
+                    let sep = self.line_break();
+                    self.request(sep);
+                }
+                self.write(token.text());
+                self.prev = Some(token.clone());
+            }
+        }
+    }
+
+    /// `{ a; b }` / `: a; b` in blocks, `match`, `enum` and `struct`.
+    fn scoped_list(&mut self, node: &SyntaxNode) {
+        let written_broken = node
+            .children_with_tokens()
+            .skip_while(|c| !matches!(c, SyntaxElement::Token(t) if is_list_opener(t)))
+            .any(|c| matches!(c, SyntaxElement::Token(t) if t.text().contains('\n')));
+        if self.flat && written_broken {
+            self.broken = true;
+        }
+        let multiline = !self.flat && (written_broken || self.too_wide(node));
+
+        let mut children = node.children_with_tokens();
+
+        // Everything up to the opening `{` or `:`.
+        let opener = loop {
+            match children.next() {
+                Some(SyntaxElement::Token(token)) if is_list_opener(&token) => break token,
+                Some(SyntaxElement::Token(token)) => self.token(&token),
+                Some(SyntaxElement::Node(child)) => self.node(&child),
+                None => return,
+            }
+        };
+
+        self.token(&opener);
+        self.newlines = 0;
+        if multiline {
+            self.indent += 1;
+        }
+        let mut closed = false;
+        for child in children {
+            match child {
+                SyntaxElement::Node(item) => {
+                    // `/* note */ item` stays on one line.
+                    let sep = if multiline && !(self.after_block_comment && self.newlines == 0) {
+                        self.line_break()
+                    } else {
+                        Sep::Space
+                    };
+                    self.request(sep);
+                    self.node(&item);
+                }
+                SyntaxElement::Token(token)
+                    if token.kind() == SyntaxKind::Token(TokenKind::RBrace) =>
+                {
+                    if multiline {
+                        self.indent -= 1;
+                        self.request(Sep::Newline);
+                    }
+                    closed = true;
+                    self.token(&token);
+                }
+                SyntaxElement::Token(token) => self.token(&token),
+            }
+        }
+        if multiline && !closed {
+            self.indent -= 1;
+        }
+    }
+
+    /// `a > f > g`
+    fn pipe(&mut self, node: &SyntaxNode) {
+        let written_broken = node
+            .children_with_tokens()
+            .any(|c| matches!(c, SyntaxElement::Token(t) if t.text().contains('\n')));
+        if self.flat && written_broken {
+            self.broken = true;
+        }
+        let multiline = !self.flat && (written_broken || self.too_wide(node));
+
+        let mut indented = false;
+        for child in node.children_with_tokens() {
+            match child {
+                SyntaxElement::Token(token)
+                    if token.kind() == SyntaxKind::Token(TokenKind::Greater) =>
+                {
+                    if multiline {
+                        if !indented {
+                            indented = true;
+                            self.indent += 1;
+                        }
+                        self.request(Sep::Newline);
+                    }
+                    self.token(&token);
+                }
+                SyntaxElement::Token(token) => self.token(&token),
+                SyntaxElement::Node(child) => self.node(&child),
+            }
+        }
+        if indented {
+            self.indent -= 1;
+        }
+    }
+
+    /// `f x y`, broken after `f` when it does not fit on the line.
+    fn seq(&mut self, node: &SyntaxNode) {
+        let broken = !self.flat
+            && self
+                .flat(node)
+                .is_some_and(|text| self.column() + text.chars().count() > self.options.max_width);
+        if !broken {
+            self.children(node);
+            return;
+        }
+
+        let mut first = true;
+        self.indent += 1;
+        for child in node.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(child) => {
+                    if !first {
+                        self.request(Sep::Newline);
+                    }
+                    first = false;
+                    self.node(&child);
+                }
+                SyntaxElement::Token(token) => self.token(&token),
+            }
+        }
+        self.indent -= 1;
+    }
+}
+
+fn is_list_opener(token: &SyntaxToken) -> bool {
+    matches!(
+        token.kind(),
+        SyntaxKind::Token(TokenKind::LBrace | TokenKind::Colon)
+    )
+}
+
+/// Whether two adjacent tokens are separated by a space.
+fn space_between(prev: &SyntaxToken, next: &SyntaxToken) -> bool {
+    use TokenKind::*;
+
+    let (SyntaxKind::Token(p), SyntaxKind::Token(n)) = (prev.kind(), next.kind()) else {
+        return true;
+    };
+    let prev_parent = prev.parent().kind();
+    let next_parent = next.parent();
+    let in_types = |kind| matches!(kind, SyntaxKind::TypeParams | SyntaxKind::TypeExpr);
+    let in_func = |kind| kind == SyntaxKind::FuncLiteral;
+
+    match (p, n) {
+        (_, RParen | RBracket | Comma | Semi | Colon | DoubleColon | Dot) => false,
+        (LParen | LBracket | DoubleColon | Dot, _) => false,
+        (LBrace, RBrace) => false,
+        // `<T>|T x|->R body`, also when a parameter or the result has a
+        // function type: `|(T) -> U f|->(T) -> U`
+        (Greater, Bar) if prev_parent == SyntaxKind::TypeParams => false,
+        (Bar, _) if in_func(prev_parent) => false,
+        // The closing `|` follows a token of the same literal.
+        (_, Bar) if in_func(next_parent.kind()) => prev.span().start < next_parent.span().start,
+        (Arrow | StarArrow, _) if in_func(prev_parent) => false,
+        // `Some(x)`
+        (_, LParen) => !matches!(
+            next_parent.kind(),
+            SyntaxKind::EnumVariant | SyntaxKind::Pattern
+        ),
+        // `<T>` and `Option<T>`
+        (_, Less) if next_parent.kind() == SyntaxKind::TypeExpr => false,
+        (Ident, Less) if next_parent.kind() == SyntaxKind::TypeParams => false,
+        (Less, _) if in_types(prev_parent) => false,
+        (_, Greater) if in_types(next_parent.kind()) => false,
+        _ => true,
+    }
+}
@@ -8,9 +8,11 @@
 #![allow(dead_code)]
 
 use alloc::boxed::Box;
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 
+use crate::builtins::{BuiltinKind, builtins};
 use crate::span::Span;
 use crate::types::{ArrowKind, Type};
 
@@ -37,15 +39,37 @@ pub struct HirParam {
 ///
 /// Overloaded functions will be represented as sets of HirFunction
 /// values associated with the same name at a higher level.
+///
+/// A generic function keeps its `type_params` as `Type::Var` in its
+/// signature and body; backends instantiate it per `type_args` of the
+/// calls that reach it.
+///
+/// `captures` are the variables of enclosing functions that the body
+/// refers to. They are captured by value when the function value is
+/// created (see `HirExprKind::FuncRef`) and are visible in the body
+/// like immutable parameters.
 #[derive(Debug, Clone, PartialEq)]
 pub struct HirFunction {
     pub name: HirIdent,
+    pub type_params: Vec<String>,
     pub params: Vec<HirParam>,
+    pub captures: Vec<HirParam>,
     pub result: Type,
     pub arrow: ArrowKind,
     pub body: HirExpr,
 }
 
+/// A `test "name": body` declaration of a test build.
+///
+/// The body is lifted like a function literal: `function` is the symbol
+/// of a `HirFunction` without parameters or captures that returns unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HirTest {
+    pub name: String,
+    pub function: String,
+    pub span: Span,
+}
+
 /// A HIR module / compilation unit.
 ///
 /// Laterこの中に `enum` や `struct`、トップレベル `let` なども入れていく想定。
@@ -91,18 +115,52 @@ pub enum HirExprKind {
     // Variable reference
     Var(HirIdent),
 
-    // Function call
+    // Reference to a lifted function used as a value (a closure);
+    // `captures` are the values of its captured variables, in order
+    FuncRef {
+        name: HirIdent,
+        type_args: Vec<Type>,
+        captures: Vec<HirExpr>,
+    },
+
+    // Function call; `type_args` instantiate a generic callee
     Call {
         callee: HirIdent,
+        type_args: Vec<Type>,
+        args: Vec<HirExpr>,
+    },
+
+    // Call of a function value
+    CallIndirect {
+        callee: Box<HirExpr>,
+        args: Vec<HirExpr>,
+    },
+
+    // Struct construction; fields in declaration order
+    StructInit {
+        name: HirIdent,
+        fields: Vec<HirExpr>,
+    },
+
+    // Enum variant construction; `tag` is the variant index
+    EnumInit {
+        variant: HirIdent,
+        tag: u32,
         args: Vec<HirExpr>,
     },
 
-    // let / let mut
+    // Call of a compiler-provided builtin (operators, host functions)
+    BuiltinCall {
+        kind: BuiltinKind,
+        args: Vec<HirExpr>,
+    },
+
+    // let / let mut; the binding is in scope for the rest of the
+    // enclosing block
     Let {
         name: HirIdent,
         mutable: bool,
         init: Box<HirExpr>,
-        body: Box<HirExpr>,
     },
 
     // set expression (assignment); result typeは Unit のはず
@@ -170,8 +228,11 @@ pub enum HirPattern {
     LitBool(bool),
     LitString(String),
 
-    // identifier pattern (binds a new variable)
-    Ident(HirIdent),
+    // identifier pattern (binds a new variable of type `ty`)
+    Ident {
+        name: HirIdent,
+        ty: Type,
+    },
 
     // wildcard `_`
     Wildcard(Span),
@@ -179,6 +240,7 @@ pub enum HirPattern {
     // enum variant patterns: Variant(p1, p2, ...)
     EnumVariant {
         ctor: HirIdent,
+        tag: u32,
         args: Vec<HirPattern>,
     },
 
@@ -193,10 +255,205 @@ pub enum HirPattern {
 #[derive(Debug, Clone, PartialEq)]
 pub struct HirStructPatternField {
     pub field: HirIdent,
+    /// Position of the field in the struct declaration.
+    pub index: usize,
     pub pattern: HirPattern,
 }
 
+impl HirPattern {
+    /// Call `f` on every span in this pattern.
+    pub fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            HirPattern::Ident { name, .. } => f(&mut name.span),
+            HirPattern::Wildcard(span) => f(span),
+            HirPattern::EnumVariant { ctor, args, .. } => {
+                f(&mut ctor.span);
+                args.iter_mut().for_each(|p| p.map_spans(f));
+            }
+            HirPattern::Struct { ctor, fields } => {
+                f(&mut ctor.span);
+                for field in fields {
+                    f(&mut field.field.span);
+                    field.pattern.map_spans(f);
+                }
+            }
+            HirPattern::LitI32(_)
+            | HirPattern::LitI64(_)
+            | HirPattern::LitF32(_)
+            | HirPattern::LitF64(_)
+            | HirPattern::LitBool(_)
+            | HirPattern::LitString(_) => {}
+        }
+    }
+}
+
 impl HirExpr {
+    /// Call `f` on this expression and every expression nested in it,
+    /// parents before children.
+    pub fn walk<'a>(&'a self, f: &mut dyn FnMut(&'a HirExpr)) {
+        f(self);
+        let mut visit = |e: &'a HirExpr| e.walk(f);
+        match &self.kind {
+            HirExprKind::FuncRef { captures: es, .. }
+            | HirExprKind::Call { args: es, .. }
+            | HirExprKind::StructInit { fields: es, .. }
+            | HirExprKind::EnumInit { args: es, .. }
+            | HirExprKind::BuiltinCall { args: es, .. }
+            | HirExprKind::Block { expressions: es } => es.iter().for_each(visit),
+            HirExprKind::CallIndirect { callee, args } => {
+                visit(callee);
+                args.iter().for_each(visit);
+            }
+            HirExprKind::Let { init, .. } => visit(init),
+            HirExprKind::Set { target, value } => {
+                visit(&target.base);
+                visit(value);
+            }
+            HirExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                visit(cond);
+                visit(then_branch);
+                visit(else_branch);
+            }
+            HirExprKind::While { cond, body } => {
+                visit(cond);
+                visit(body);
+            }
+            HirExprKind::Loop { body } => visit(body),
+            HirExprKind::Break { value } | HirExprKind::Return { value } => {
+                value.iter().for_each(|e| visit(e))
+            }
+            HirExprKind::Match { scrutinee, arms } => {
+                visit(scrutinee);
+                arms.iter().for_each(|arm| visit(&arm.body));
+            }
+            HirExprKind::I32(_)
+            | HirExprKind::I64(_)
+            | HirExprKind::F32(_)
+            | HirExprKind::F64(_)
+            | HirExprKind::Bool(_)
+            | HirExprKind::Unit
+            | HirExprKind::String(_)
+            | HirExprKind::Var(_)
+            | HirExprKind::Continue => {}
+        }
+    }
+
+    /// Call `f` on every span in this expression: its own, those of
+    /// nested expressions, identifiers and patterns.
+    pub fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        f(&mut self.span);
+        match &mut self.kind {
+            HirExprKind::Var(name) => f(&mut name.span),
+            HirExprKind::FuncRef {
+                name,
+                captures: es,
+                ..
+            }
+            | HirExprKind::StructInit { name, fields: es }
+            | HirExprKind::EnumInit {
+                variant: name,
+                args: es,
+                ..
+            } => {
+                f(&mut name.span);
+                es.iter_mut().for_each(|e| e.map_spans(f));
+            }
+            HirExprKind::Call { callee, args, .. } => {
+                f(&mut callee.span);
+                args.iter_mut().for_each(|e| e.map_spans(f));
+            }
+            HirExprKind::BuiltinCall { args: es, .. } | HirExprKind::Block { expressions: es } => {
+                es.iter_mut().for_each(|e| e.map_spans(f))
+            }
+            HirExprKind::CallIndirect { callee, args } => {
+                callee.map_spans(f);
+                args.iter_mut().for_each(|e| e.map_spans(f));
+            }
+            HirExprKind::Let { name, init, .. } => {
+                f(&mut name.span);
+                init.map_spans(f);
+            }
+            HirExprKind::Set { target, value } => {
+                target.base.map_spans(f);
+                target.fields.iter_mut().for_each(|field| f(&mut field.span));
+                value.map_spans(f);
+            }
+            HirExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                cond.map_spans(f);
+                then_branch.map_spans(f);
+                else_branch.map_spans(f);
+            }
+            HirExprKind::While { cond, body } => {
+                cond.map_spans(f);
+                body.map_spans(f);
+            }
+            HirExprKind::Loop { body } => body.map_spans(f),
+            HirExprKind::Break { value } | HirExprKind::Return { value } => {
+                value.iter_mut().for_each(|e| e.map_spans(f))
+            }
+            HirExprKind::Match { scrutinee, arms } => {
+                scrutinee.map_spans(f);
+                for arm in arms {
+                    arm.pattern.map_spans(f);
+                    arm.body.map_spans(f);
+                }
+            }
+            HirExprKind::I32(_)
+            | HirExprKind::I64(_)
+            | HirExprKind::F32(_)
+            | HirExprKind::F64(_)
+            | HirExprKind::Bool(_)
+            | HirExprKind::Unit
+            | HirExprKind::String(_)
+            | HirExprKind::Continue => {}
+        }
+    }
+
+    /// A short description of the expression for internal error
+    /// messages, e.g. "`if` expression" or "call of `f`".
+    pub fn describe(&self) -> String {
+        let what = match &self.kind {
+            HirExprKind::I32(_) => "`i32` literal",
+            HirExprKind::I64(_) => "`i64` literal",
+            HirExprKind::F32(_) => "`f32` literal",
+            HirExprKind::F64(_) => "`f64` literal",
+            HirExprKind::Bool(_) => "`bool` literal",
+            HirExprKind::Unit => "unit value",
+            HirExprKind::String(_) => "string literal",
+            HirExprKind::Var(name) => return format!("variable `{}`", name.name),
+            HirExprKind::FuncRef { name, .. } => return format!("function value `{}`", name.name),
+            HirExprKind::Call { callee, .. } => return format!("call of `{}`", callee.name),
+            HirExprKind::CallIndirect { .. } => "call of a function value",
+            HirExprKind::StructInit { name, .. } => return format!("struct `{}`", name.name),
+            HirExprKind::EnumInit { variant, .. } => return format!("variant `{}`", variant.name),
+            HirExprKind::BuiltinCall { kind, .. } => {
+                return match builtins().into_iter().find(|b| b.kind == *kind) {
+                    Some(builtin) => format!("call of builtin `{}`", builtin.name),
+                    None => format!("call of builtin {kind:?}"),
+                };
+            }
+            HirExprKind::Let { name, .. } => return format!("`let {}`", name.name),
+            HirExprKind::Set { .. } => "`set` expression",
+            HirExprKind::If { .. } => "`if` expression",
+            HirExprKind::While { .. } => "`while` loop",
+            HirExprKind::Loop { .. } => "`loop` expression",
+            HirExprKind::Break { .. } => "`break`",
+            HirExprKind::Continue => "`continue`",
+            HirExprKind::Return { .. } => "`return`",
+            HirExprKind::Match { .. } => "`match` expression",
+            HirExprKind::Block { .. } => "block",
+        };
+        String::from(what)
+    }
+
     /// Helper to create a Never-typed break expression.
     pub fn never_break(span: Span, value: Option<HirExpr>) -> HirExpr {
         HirExpr {
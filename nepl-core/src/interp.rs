@@ -0,0 +1,878 @@
+//! Reference interpreter over typed HIR (no_std).
+//!
+//! Evaluates an entry expression of a [`HirModule`] directly, without
+//! generating code. It is meant to agree with `codegen_wasm` on every
+//! program the backend accepts, so it follows the same runtime model:
+//!
+//! - Integer arithmetic wraps; division by zero and `i32::MIN / -1`
+//!   trap, like the wasm instructions the backend emits.
+//! - Struct and enum values live in a linear memory and are `i32`
+//!   addresses of 8-byte slots (tag first for enums), allocated by the
+//!   same bump allocator. `alloc`, `load` and `store` work on it.
+//! - Function values are closure records: slot 0 holds an index into a
+//!   table of function instances, the following slots the captures.
+//! - `mut` parameters are copied in and out of the callee.
+//!
+//! Host builtins (`print_i32`, `random_i32`) are delegated to a
+//! [`Host`]. Runtime errors are `E1000` diagnostics pointing at the
+//! expression that failed; a fuel limit bounds the number of evaluation
+//! steps (`E1001`).
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::builtins::{BuiltinKind, Operator};
+use crate::codegen_wasm::SLOT_SIZE;
+use crate::diagnostic::Diagnostic;
+use crate::hir::{
+    HirAssignable, HirExpr, HirExprKind, HirFunction, HirMatchArm, HirModule, HirPattern,
+};
+use crate::span::Span;
+use crate::types::Type;
+
+/// Size of a page of linear memory.
+const PAGE_SIZE: u32 = 65536;
+
+/// Linear memory is not grown beyond this many pages (64 MiB).
+pub const MAX_PAGES: u32 = 1024;
+
+/// Nested calls allowed before evaluation fails with a stack overflow.
+///
+/// wasmi, which runs compiled programs, allows 1000 frames by default,
+/// one of which is taken by `main`.
+pub const DEFAULT_MAX_DEPTH: usize = 999;
+
+/// Native stack that evaluation needs at [`DEFAULT_MAX_DEPTH`].
+///
+/// Evaluation recurses on the native stack, more than 10 KiB per nested
+/// call in debug builds, so callers should run it on a thread of this
+/// size rather than on the 2 MiB default.
+pub const STACK_SIZE: usize = 64 << 20;
+
+/// Host functions available to interpreted programs.
+pub trait Host {
+    /// `print_i32`
+    fn print_i32(&mut self, value: i32);
+    /// `random_i32`
+    fn random_i32(&mut self) -> i32;
+}
+
+impl<H: Host + ?Sized> Host for &mut H {
+    fn print_i32(&mut self, value: i32) {
+        (**self).print_i32(value)
+    }
+
+    fn random_i32(&mut self) -> i32 {
+        (**self).random_i32()
+    }
+}
+
+/// A host that records what the program prints and answers
+/// `random_i32` with a fixed value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptureHost {
+    pub printed: Vec<i32>,
+    pub random: i32,
+}
+
+impl Host for CaptureHost {
+    fn print_i32(&mut self, value: i32) {
+        self.printed.push(value);
+    }
+
+    fn random_i32(&mut self) -> i32 {
+        self.random
+    }
+}
+
+/// A runtime value.
+///
+/// Records and function values are `I32` addresses into linear memory,
+/// as in compiled code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    String(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::I32(v) => write!(f, "{v}"),
+            Value::I64(v) => write!(f, "{v}"),
+            Value::F32(v) => write!(f, "{v:?}"),
+            Value::F64(v) => write!(f, "{v:?}"),
+            Value::String(s) => write!(f, "{s:?}"),
+        }
+    }
+}
+
+/// How evaluation of an expression ended other than with a value.
+enum Flow {
+    Break(Value),
+    Continue,
+    Return(Value),
+    Error(Box<Diagnostic>),
+}
+
+impl From<Diagnostic> for Flow {
+    fn from(d: Diagnostic) -> Flow {
+        Flow::Error(Box::new(d))
+    }
+}
+
+type Eval<T = Value> = Result<T, Flow>;
+
+/// A function instance: a lifted function and its type arguments.
+#[derive(Debug, Clone)]
+struct Instance<'m> {
+    func: &'m HirFunction,
+    type_args: Vec<Type>,
+}
+
+impl PartialEq for Instance<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::eq(self.func, other.func) && self.type_args == other.type_args
+    }
+}
+
+/// Variables and type arguments of one call.
+struct Frame {
+    scope: Vec<(String, Value)>,
+    subst: Vec<(String, Type)>,
+}
+
+impl Frame {
+    fn ty(&self, ty: &Type) -> Type {
+        ty.substitute(&self.subst)
+    }
+}
+
+/// Evaluates HIR expressions of one module.
+pub struct Interpreter<'m, H: Host> {
+    module: &'m HirModule,
+    host: H,
+    memory: Vec<u8>,
+    /// Next free address of the bump allocator.
+    next: u32,
+    /// Functions used as values; closure records refer to them by index.
+    table: Vec<Instance<'m>>,
+    fuel: Option<u64>,
+    steps: u64,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'m, H: Host> Interpreter<'m, H> {
+    pub fn new(module: &'m HirModule, host: H) -> Self {
+        Interpreter {
+            module,
+            host,
+            memory: vec![0; PAGE_SIZE as usize],
+            next: SLOT_SIZE,
+            table: Vec::new(),
+            fuel: None,
+            steps: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Stop evaluation with `E1001` after `fuel` steps. Every evaluated
+    /// expression takes one step.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Fail with a stack overflow beyond `depth` nested calls.
+    ///
+    /// A limit above [`DEFAULT_MAX_DEPTH`] may need more than
+    /// [`STACK_SIZE`] of native stack.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Number of steps taken so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn host(&self) -> &H {
+        &self.host
+    }
+
+    pub fn into_host(self) -> H {
+        self.host
+    }
+
+    /// Evaluate the entry expression of the module, like `main` of the
+    /// compiled module.
+    pub fn eval(&mut self, entry: &HirExpr) -> Result<Value, Box<Diagnostic>> {
+        let mut frame = Frame {
+            scope: Vec::new(),
+            subst: Vec::new(),
+        };
+        match self.expr(&mut frame, entry) {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Error(d)) => Err(d),
+            Err(Flow::Break(_) | Flow::Continue) => {
+                Err(Box::new(internal(entry.span, "loop control escaped main")))
+            }
+        }
+    }
+
+    fn expr(&mut self, frame: &mut Frame, e: &HirExpr) -> Eval {
+        self.steps += 1;
+        if let Some(fuel) = self.fuel
+            && self.steps > fuel
+        {
+            return Err(out_of_fuel(fuel, e.span));
+        }
+
+        // Every nested call recurses through here, so the larger forms
+        // live in their own functions to keep this frame small.
+        match &e.kind {
+            HirExprKind::I32(v) => Ok(Value::I32(*v)),
+            HirExprKind::I64(v) => Ok(Value::I64(*v)),
+            HirExprKind::F32(v) => Ok(Value::F32(*v)),
+            HirExprKind::F64(v) => Ok(Value::F64(*v)),
+            HirExprKind::Bool(b) => Ok(Value::Bool(*b)),
+            HirExprKind::Unit => Ok(Value::Unit),
+            HirExprKind::String(s) => Ok(Value::String(s.clone())),
+            HirExprKind::Var(name) => self.var(frame, &name.name, e.span),
+            HirExprKind::FuncRef {
+                name,
+                type_args,
+                captures,
+            } => self.func_ref(frame, &name.name, type_args, captures, e.span),
+            HirExprKind::Call {
+                callee,
+                type_args,
+                args,
+            } => self.direct_call(frame, &callee.name, type_args, args, e.span),
+            HirExprKind::CallIndirect { callee, args } => {
+                self.indirect_call(frame, callee, args, e.span)
+            }
+            HirExprKind::StructInit { fields, .. } => self.record_init(frame, None, fields, e.span),
+            HirExprKind::EnumInit { tag, args, .. } => {
+                self.record_init(frame, Some(*tag), args, e.span)
+            }
+            HirExprKind::BuiltinCall { kind, args } => {
+                self.builtin_call(frame, *kind, args, &e.ty, e.span)
+            }
+            HirExprKind::Let { name, init, .. } => self.let_binding(frame, &name.name, init),
+            HirExprKind::Set { target, value } => self.set(frame, target, value, e.span),
+            HirExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => self.if_else(frame, cond, then_branch, else_branch),
+            HirExprKind::While { cond, body } => self.while_loop(frame, cond, body),
+            HirExprKind::Loop { body } => self.loop_break(frame, body),
+            HirExprKind::Break { value } => Err(Flow::Break(self.optional(frame, value)?)),
+            HirExprKind::Continue => Err(Flow::Continue),
+            HirExprKind::Return { value } => Err(Flow::Return(self.optional(frame, value)?)),
+            HirExprKind::Match { scrutinee, arms } => self.matching(frame, scrutinee, arms, e.span),
+            HirExprKind::Block { expressions } => self.block(frame, expressions),
+        }
+    }
+
+    fn record_init(
+        &mut self,
+        frame: &mut Frame,
+        tag: Option<u32>,
+        fields: &[HirExpr],
+        span: Span,
+    ) -> Eval {
+        let values = self.exprs(frame, fields)?;
+        self.record(tag, &values, span)
+    }
+
+    fn builtin_call(
+        &mut self,
+        frame: &mut Frame,
+        kind: BuiltinKind,
+        args: &[HirExpr],
+        ty: &Type,
+        span: Span,
+    ) -> Eval {
+        let values = self.exprs(frame, args)?;
+        self.builtin(kind, values, &frame.ty(ty), span)
+    }
+
+    /// Bind `name` until the end of the enclosing block.
+    fn let_binding(&mut self, frame: &mut Frame, name: &str, init: &HirExpr) -> Eval {
+        let value = self.expr(frame, init)?;
+        frame.scope.push((name.into(), value));
+        Ok(Value::Unit)
+    }
+
+    fn if_else(
+        &mut self,
+        frame: &mut Frame,
+        cond: &HirExpr,
+        then_branch: &HirExpr,
+        else_branch: &HirExpr,
+    ) -> Eval {
+        if self.condition(frame, cond)? {
+            self.expr(frame, then_branch)
+        } else {
+            self.expr(frame, else_branch)
+        }
+    }
+
+    fn while_loop(&mut self, frame: &mut Frame, cond: &HirExpr, body: &HirExpr) -> Eval {
+        while self.condition(frame, cond)? {
+            match self.expr(frame, body) {
+                Ok(_) | Err(Flow::Continue) => {}
+                Err(Flow::Break(_)) => break,
+                Err(other) => return Err(other),
+            }
+        }
+        Ok(Value::Unit)
+    }
+
+    fn loop_break(&mut self, frame: &mut Frame, body: &HirExpr) -> Eval {
+        loop {
+            match self.expr(frame, body) {
+                Ok(_) | Err(Flow::Continue) => {}
+                Err(Flow::Break(value)) => return Ok(value),
+                Err(other) => return Err(other),
+            }
+        }
+    }
+
+    fn block(&mut self, frame: &mut Frame, expressions: &[HirExpr]) -> Eval {
+        let saved = frame.scope.len();
+        let mut result = Ok(Value::Unit);
+        for x in expressions {
+            result = self.expr(frame, x);
+            if result.is_err() {
+                break;
+            }
+        }
+        frame.scope.truncate(saved);
+        result
+    }
+
+    fn var(&mut self, frame: &Frame, name: &str, span: Span) -> Eval {
+        match frame.scope.iter().rev().find(|(n, _)| n == name) {
+            Some((_, value)) => Ok(value.clone()),
+            None => Err(Diagnostic::error_key("E1000.unbound", span)
+                .arg("name", name)
+                .into()),
+        }
+    }
+
+    fn optional(&mut self, frame: &mut Frame, value: &Option<Box<HirExpr>>) -> Eval {
+        match value {
+            Some(v) => self.expr(frame, v),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn func_ref(
+        &mut self,
+        frame: &mut Frame,
+        name: &str,
+        type_args: &[Type],
+        captures: &[HirExpr],
+        span: Span,
+    ) -> Eval {
+        let func = self.function(name, span)?;
+        let instance = Instance {
+            func,
+            type_args: type_args.iter().map(|t| frame.ty(t)).collect(),
+        };
+        let slot = match self.table.iter().position(|i| *i == instance) {
+            Some(slot) => slot,
+            None => {
+                self.table.push(instance);
+                self.table.len() - 1
+            }
+        };
+        let values = self.exprs(frame, captures)?;
+        self.record(Some(slot as u32), &values, span)
+    }
+
+    fn direct_call(
+        &mut self,
+        frame: &mut Frame,
+        callee: &str,
+        type_args: &[Type],
+        args: &[HirExpr],
+        span: Span,
+    ) -> Eval {
+        let func = self.function(callee, span)?;
+        let values = self.exprs(frame, args)?;
+        let type_args = type_args.iter().map(|t| frame.ty(t)).collect();
+        let (result, outs) = self.call(func, type_args, values, 0, span)?;
+
+        // Copy the final values of `mut` parameters back into the
+        // variables passed for them.
+        let written = func.params.iter().zip(args).filter(|(p, _)| p.mutable);
+        for ((_, arg), value) in written.zip(outs) {
+            if let HirExprKind::Var(name) = &arg.kind
+                && let Some(slot) = frame.scope.iter_mut().rev().find(|(n, _)| *n == name.name)
+            {
+                slot.1 = value;
+            }
+        }
+        Ok(result)
+    }
+
+    fn indirect_call(
+        &mut self,
+        frame: &mut Frame,
+        callee: &HirExpr,
+        args: &[HirExpr],
+        span: Span,
+    ) -> Eval {
+        let closure = self.expr(frame, callee)?;
+        let values = self.exprs(frame, args)?;
+        let Value::I32(record) = closure else {
+            return Err(Diagnostic::error_key("E1000.not-a-function", span).into());
+        };
+        let slot = self.load_raw(record as u32, 0, 4, span)?;
+        let Some(instance) = self.table.get(slot as usize).cloned() else {
+            return Err(Diagnostic::error_key("E1000.not-a-function", span).into());
+        };
+        let (result, _) = self.call(
+            instance.func,
+            instance.type_args,
+            values,
+            record as u32,
+            span,
+        )?;
+        Ok(result)
+    }
+
+    fn set(
+        &mut self,
+        frame: &mut Frame,
+        target: &HirAssignable,
+        value: &HirExpr,
+        span: Span,
+    ) -> Eval {
+        let value = self.expr(frame, value)?;
+        let HirExprKind::Var(name) = &target.base.kind else {
+            return Err(internal(span, "assignment to a non-variable").into());
+        };
+        if !target.fields.is_empty() {
+            return Err(Diagnostic::error_key("E0800.field-assignment", span).into());
+        }
+        match frame.scope.iter_mut().rev().find(|(n, _)| *n == name.name) {
+            Some(slot) => slot.1 = value,
+            None => {
+                return Err(Diagnostic::error_key("E1000.unbound", span)
+                    .arg("name", &name.name)
+                    .into());
+            }
+        }
+        Ok(Value::Unit)
+    }
+
+    fn matching(
+        &mut self,
+        frame: &mut Frame,
+        scrutinee: &HirExpr,
+        arms: &[HirMatchArm],
+        span: Span,
+    ) -> Eval {
+        let value = self.expr(frame, scrutinee)?;
+        let ty = frame.ty(&scrutinee.ty);
+        for arm in arms {
+            let saved = frame.scope.len();
+            if self.pattern(frame, &arm.pattern, &value, &ty, span)? {
+                let result = self.expr(frame, &arm.body);
+                frame.scope.truncate(saved);
+                return result;
+            }
+            frame.scope.truncate(saved);
+        }
+        Err(Diagnostic::error_key("E1000.no-match", scrutinee.span).into())
+    }
+
+    fn exprs(&mut self, frame: &mut Frame, es: &[HirExpr]) -> Eval<Vec<Value>> {
+        es.iter().map(|e| self.expr(frame, e)).collect()
+    }
+
+    fn condition(&mut self, frame: &mut Frame, cond: &HirExpr) -> Eval<bool> {
+        match self.expr(frame, cond)? {
+            Value::Bool(b) => Ok(b),
+            _ => Err(internal(cond.span, "condition is not a Bool").into()),
+        }
+    }
+
+    fn function(&self, name: &str, span: Span) -> Eval<&'m HirFunction> {
+        self.module
+            .functions
+            .iter()
+            .find(|f| f.name.name == name)
+            .ok_or_else(|| {
+                Diagnostic::error_key("E1000.unknown-function", span)
+                    .arg("name", name)
+                    .into()
+            })
+    }
+
+    /// Call `func` and return its result and the final values of its
+    /// `mut` parameters. `record` is the address of the closure record
+    /// holding the captures (0 for direct calls).
+    fn call(
+        &mut self,
+        func: &'m HirFunction,
+        type_args: Vec<Type>,
+        args: Vec<Value>,
+        record: u32,
+        span: Span,
+    ) -> Eval<(Value, Vec<Value>)> {
+        if self.depth >= self.max_depth {
+            return Err(Diagnostic::error_key("E1000.stack-overflow", span)
+                .arg("depth", self.max_depth)
+                .into());
+        }
+        let subst: Vec<(String, Type)> = func.type_params.iter().cloned().zip(type_args).collect();
+        let mut scope: Vec<(String, Value)> = func
+            .params
+            .iter()
+            .map(|p| p.name.name.clone())
+            .zip(args)
+            .collect();
+        for (i, c) in func.captures.iter().enumerate() {
+            let ty = c.ty.substitute(&subst);
+            let value = self.load(&ty, record, (i as u32 + 1) * SLOT_SIZE, span)?;
+            scope.push((c.name.name.clone(), value));
+        }
+        let mut frame = Frame { scope, subst };
+
+        self.depth += 1;
+        let result = self.expr(&mut frame, &func.body);
+        self.depth -= 1;
+        let result = match result {
+            Ok(value) | Err(Flow::Return(value)) => value,
+            Err(Flow::Error(d)) => return Err(Flow::Error(d)),
+            Err(Flow::Break(_) | Flow::Continue) => {
+                return Err(internal(span, "loop control escaped a function").into());
+            }
+        };
+        let outs = func
+            .params
+            .iter()
+            .zip(&frame.scope)
+            .filter(|(p, _)| p.mutable)
+            .map(|(_, (_, value))| value.clone())
+            .collect();
+        Ok((result, outs))
+    }
+
+    fn builtin(&mut self, kind: BuiltinKind, args: Vec<Value>, ty: &Type, span: Span) -> Eval {
+        let int = |v: &Value| match v {
+            Value::I32(v) => Ok(*v),
+            _ => Err(Flow::from(internal(span, "expected an i32 operand"))),
+        };
+        match kind {
+            BuiltinKind::WasmPageSize => Ok(Value::I32(PAGE_SIZE as i32)),
+            BuiltinKind::WasiRandomI32 => Ok(Value::I32(self.host.random_i32())),
+            BuiltinKind::WasiPrintI32 => {
+                self.host.print_i32(int(&args[0])?);
+                Ok(Value::Unit)
+            }
+            BuiltinKind::MemAlloc => {
+                Ok(Value::I32(self.alloc(int(&args[0])? as u32, span)? as i32))
+            }
+            BuiltinKind::MemLoad => self.load(ty, int(&args[0])? as u32, 0, span),
+            BuiltinKind::MemStore => {
+                self.store(int(&args[0])? as u32, 0, &args[1], span)?;
+                Ok(Value::Unit)
+            }
+            BuiltinKind::Assert => match &args[..] {
+                [Value::Bool(true), _] => Ok(Value::Unit),
+                [_, Value::String(message)] => {
+                    let diag = Diagnostic::error_key("E1000.assertion-failed", span)
+                        .arg("message", message);
+                    Err(diag.into())
+                }
+                _ => Err(internal(span, "malformed assertion").into()),
+            },
+            BuiltinKind::Operator(op) => operator(op, &args, span),
+        }
+    }
+
+    /// The bump allocator of compiled modules: addresses are multiples
+    /// of the slot size and memory grows by whole pages.
+    fn alloc(&mut self, size: u32, span: Span) -> Eval<u32> {
+        let ptr = self.next;
+        let end = ptr.wrapping_add(size).wrapping_add(SLOT_SIZE - 1) & !(SLOT_SIZE - 1);
+        self.next = end;
+        if end as usize > self.memory.len() {
+            let pages = (end as usize - self.memory.len()).div_ceil(PAGE_SIZE as usize);
+            if self.memory.len() / PAGE_SIZE as usize + pages > MAX_PAGES as usize {
+                return Err(Diagnostic::error_key("E1000.out-of-memory", span).into());
+            }
+            self.memory
+                .resize(self.memory.len() + pages * PAGE_SIZE as usize, 0);
+        }
+        Ok(ptr)
+    }
+
+    /// Allocate a record and store `values` in its slots, after `tag`
+    /// if there is one.
+    fn record(&mut self, tag: Option<u32>, values: &[Value], span: Span) -> Eval {
+        let first = tag.is_some() as u32;
+        let slots = (first + values.len() as u32).max(1);
+        let addr = self.alloc(slots * SLOT_SIZE, span)?;
+        if let Some(tag) = tag {
+            self.store(addr, 0, &Value::I32(tag as i32), span)?;
+        }
+        for (i, value) in values.iter().enumerate() {
+            self.store(addr, (first + i as u32) * SLOT_SIZE, value, span)?;
+        }
+        Ok(Value::I32(addr as i32))
+    }
+
+    fn bytes(&mut self, addr: u32, offset: u32, len: usize, span: Span) -> Eval<&mut [u8]> {
+        let start = addr as usize + offset as usize;
+        match self.memory.get_mut(start..start + len) {
+            Some(bytes) => Ok(bytes),
+            None => Err(Diagnostic::error_key("E1000.out-of-bounds", span)
+                .arg("address", start)
+                .into()),
+        }
+    }
+
+    fn load_raw(&mut self, addr: u32, offset: u32, len: usize, span: Span) -> Eval<u64> {
+        let bytes = self.bytes(addr, offset, len, span)?;
+        let mut raw = [0u8; 8];
+        raw[..len].copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(raw))
+    }
+
+    /// Read a value of type `ty` from its slot.
+    fn load(&mut self, ty: &Type, addr: u32, offset: u32, span: Span) -> Eval {
+        Ok(match ty {
+            Type::Unit | Type::Never => Value::Unit,
+            Type::I64 => Value::I64(self.load_raw(addr, offset, 8, span)? as i64),
+            Type::F32 => Value::F32(f32::from_bits(self.load_raw(addr, offset, 4, span)? as u32)),
+            Type::F64 => Value::F64(f64::from_bits(self.load_raw(addr, offset, 8, span)?)),
+            Type::Bool => Value::Bool(self.load_raw(addr, offset, 4, span)? != 0),
+            Type::Named(name) if name == "String" => {
+                return Err(Diagnostic::error_key("E1000.string-in-memory", span).into());
+            }
+            _ => Value::I32(self.load_raw(addr, offset, 4, span)? as u32 as i32),
+        })
+    }
+
+    fn store(&mut self, addr: u32, offset: u32, value: &Value, span: Span) -> Eval<()> {
+        let (raw, len) = match value {
+            Value::Unit => return Ok(()),
+            Value::Bool(b) => (*b as u64, 4),
+            Value::I32(v) => (*v as u32 as u64, 4),
+            Value::I64(v) => (*v as u64, 8),
+            Value::F32(v) => (v.to_bits() as u64, 4),
+            Value::F64(v) => (v.to_bits(), 8),
+            Value::String(_) => {
+                return Err(Diagnostic::error_key("E1000.string-in-memory", span).into());
+            }
+        };
+        let bytes = self.bytes(addr, offset, len, span)?;
+        bytes.copy_from_slice(&raw.to_le_bytes()[..len]);
+        Ok(())
+    }
+
+    /// Test `value` (of type `ty`) against `pat`, binding its variables
+    /// in `frame` when it matches.
+    fn pattern(
+        &mut self,
+        frame: &mut Frame,
+        pat: &HirPattern,
+        value: &Value,
+        ty: &Type,
+        span: Span,
+    ) -> Eval<bool> {
+        Ok(match pat {
+            HirPattern::Wildcard(_) => true,
+            HirPattern::Ident { name, .. } => {
+                frame.scope.push((name.name.clone(), value.clone()));
+                true
+            }
+            HirPattern::LitI32(v) => *value == Value::I32(*v),
+            HirPattern::LitI64(v) => *value == Value::I64(*v),
+            HirPattern::LitF32(v) => *value == Value::F32(*v),
+            HirPattern::LitF64(v) => *value == Value::F64(*v),
+            HirPattern::LitBool(v) => *value == Value::Bool(*v),
+            HirPattern::LitString(s) => matches!(value, Value::String(v) if v == s),
+            HirPattern::EnumVariant { tag, args, .. } => {
+                let Value::I32(addr) = value else {
+                    return Err(internal(span, "enum value is not an address").into());
+                };
+                if self.load_raw(*addr as u32, 0, 4, span)? != *tag as u64 {
+                    return Ok(false);
+                }
+                for (i, arg) in args.iter().enumerate() {
+                    let offset = (i as u32 + 1) * SLOT_SIZE;
+                    if !self.sub_pattern(frame, arg, *addr as u32, offset, ty, span)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            HirPattern::Struct { fields, .. } => {
+                let Value::I32(addr) = value else {
+                    return Err(internal(span, "struct value is not an address").into());
+                };
+                for field in fields {
+                    let offset = field.index as u32 * SLOT_SIZE;
+                    if !self.sub_pattern(frame, &field.pattern, *addr as u32, offset, ty, span)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+        })
+    }
+
+    /// Match the slot at `offset` of the record at `addr` against `pat`.
+    fn sub_pattern(
+        &mut self,
+        frame: &mut Frame,
+        pat: &HirPattern,
+        addr: u32,
+        offset: u32,
+        ty: &Type,
+        span: Span,
+    ) -> Eval<bool> {
+        let slot_ty = match pat {
+            HirPattern::Wildcard(_) => return Ok(true),
+            HirPattern::Ident { ty, .. } => frame.ty(ty),
+            HirPattern::LitI32(_) => Type::I32,
+            HirPattern::LitI64(_) => Type::I64,
+            HirPattern::LitF32(_) => Type::F32,
+            HirPattern::LitF64(_) => Type::F64,
+            HirPattern::LitBool(_) => Type::Bool,
+            // Nested records are addresses.
+            _ => ty.clone(),
+        };
+        let value = self.load(&slot_ty, addr, offset, span)?;
+        self.pattern(frame, pat, &value, &slot_ty, span)
+    }
+}
+
+/// Apply a primitive operator with the semantics of the wasm
+/// instructions the backend selects for it.
+fn operator(op: Operator, args: &[Value], span: Span) -> Eval {
+    use Operator::*;
+    use Value::*;
+
+    let trap = |key: &'static str| Err(Flow::from(Diagnostic::error_key(key, span)));
+    Ok(match (op, args) {
+        (Neg, [I32(a)]) => I32(a.wrapping_neg()),
+        (Neg, [I64(a)]) => I64(a.wrapping_neg()),
+        (Neg, [F32(a)]) => F32(-a),
+        (Neg, [F64(a)]) => F64(-a),
+        (BitNot, [I32(a)]) => I32(!a),
+        (BitNot, [I64(a)]) => I64(!a),
+        (Not, [Bool(a)]) => Bool(!a),
+
+        (Div | Mod, [I32(_), I32(0)]) | (Div | Mod, [I64(_), I64(0)]) => {
+            return trap("E1000.division-by-zero");
+        }
+        (Div, [I32(i32::MIN), I32(-1)]) | (Div, [I64(i64::MIN), I64(-1)]) => {
+            return trap("E1000.overflow");
+        }
+
+        (_, [I32(a), I32(b)]) => {
+            let (a, b) = (*a, *b);
+            match op {
+                Add => I32(a.wrapping_add(b)),
+                Sub => I32(a.wrapping_sub(b)),
+                Mul => I32(a.wrapping_mul(b)),
+                Div => I32(a / b),
+                Mod => I32(a.wrapping_rem(b)),
+                BitAnd => I32(a & b),
+                BitOr => I32(a | b),
+                BitXor => I32(a ^ b),
+                BitShl => I32(a.wrapping_shl(b as u32)),
+                BitShr => I32(a.wrapping_shr(b as u32)),
+                _ => Bool(compare(op, &a, &b).ok_or_else(|| unsupported(span))?),
+            }
+        }
+        (_, [I64(a), I64(b)]) => {
+            let (a, b) = (*a, *b);
+            match op {
+                Add => I64(a.wrapping_add(b)),
+                Sub => I64(a.wrapping_sub(b)),
+                Mul => I64(a.wrapping_mul(b)),
+                Div => I64(a / b),
+                Mod => I64(a.wrapping_rem(b)),
+                BitAnd => I64(a & b),
+                BitOr => I64(a | b),
+                BitXor => I64(a ^ b),
+                BitShl => I64(a.wrapping_shl(b as u32)),
+                BitShr => I64(a.wrapping_shr(b as u32)),
+                _ => Bool(compare(op, &a, &b).ok_or_else(|| unsupported(span))?),
+            }
+        }
+        (_, [F32(a), F32(b)]) => match op {
+            Add => F32(a + b),
+            Sub => F32(a - b),
+            Mul => F32(a * b),
+            Div => F32(a / b),
+            _ => Bool(compare(op, a, b).ok_or_else(|| unsupported(span))?),
+        },
+        (_, [F64(a), F64(b)]) => match op {
+            Add => F64(a + b),
+            Sub => F64(a - b),
+            Mul => F64(a * b),
+            Div => F64(a / b),
+            _ => Bool(compare(op, a, b).ok_or_else(|| unsupported(span))?),
+        },
+        (_, [Bool(a), Bool(b)]) => match op {
+            And => Bool(*a && *b),
+            Or => Bool(*a || *b),
+            Xor | Ne => Bool(a != b),
+            Eq => Bool(a == b),
+            _ => return Err(unsupported(span)),
+        },
+        _ => return Err(unsupported(span)),
+    })
+}
+
+/// Comparison operators; NaN compares unequal to everything.
+fn compare<T: PartialOrd>(op: Operator, a: &T, b: &T) -> Option<bool> {
+    Some(match op {
+        Operator::Eq => a == b,
+        Operator::Ne => a != b,
+        Operator::Lt => a < b,
+        Operator::Le => a <= b,
+        Operator::Gt => a > b,
+        Operator::Ge => a >= b,
+        _ => return None,
+    })
+}
+
+fn unsupported(span: Span) -> Flow {
+    internal(span, "operator applied to operands it is not defined for").into()
+}
+
+fn internal(span: Span, detail: &str) -> Diagnostic {
+    Diagnostic::error_key("E0900.interpreter", span).arg("detail", detail)
+}
+
+fn out_of_fuel(fuel: u64, span: Span) -> Flow {
+    Diagnostic::error_key("E1001", span)
+        .arg("steps", fuel)
+        .into()
+}
@@ -1,10 +1,60 @@
 //! Lexer for NEPL core (no_std).
-
+//!
+//! Identifiers follow UAX #31 (`XID_Start` or `_`, then `XID_Continue`),
+//! so names such as `合計` work. They are compared as written; no
+//! normalization is applied.
+//!
+//! String literals may contain the escapes `\n`, `\r`, `\t`, `\0`,
+//! `\\`, `\"`, `\'` and `\u{...}` (1 to 6 hex digits naming a Unicode
+//! scalar value). Tokens only record where the contents are;
+//! [`unescape`] decodes them. Malformed escapes are reported while
+//! lexing.
+//!
+//! Numeric literals are decimal, `0x` hex, `0o` octal or `0b` binary,
+//! may use `_` separators, and may end in a type suffix (`i32`, `i64`,
+//! `f32`, `f64`). Decimal literals with a fraction, an exponent (`1e9`)
+//! or a float suffix are float literals. [`number_parts`] splits a
+//! literal for the parser.
+
+use alloc::string::String;
 use alloc::vec::Vec;
 
-use crate::diagnostic::Diagnostic;
+use crate::diagnostic::{Diagnostic, MessageKey};
 use crate::span::{FileId, Span};
 
+/// Reserved words and the tokens they lex to. `true` and `false` are
+/// boolean literals.
+pub const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("if", TokenKind::If),
+    ("then", TokenKind::Then),
+    ("elseif", TokenKind::ElseIf),
+    ("else", TokenKind::Else),
+    ("loop", TokenKind::Loop),
+    ("while", TokenKind::While),
+    ("match", TokenKind::Match),
+    ("case", TokenKind::Case),
+    ("break", TokenKind::Break),
+    ("continue", TokenKind::Continue),
+    ("return", TokenKind::Return),
+    ("let", TokenKind::Let),
+    ("mut", TokenKind::Mut),
+    ("hoist", TokenKind::Hoist),
+    ("pub", TokenKind::Pub),
+    ("fn", TokenKind::Fn),
+    ("include", TokenKind::Include),
+    ("import", TokenKind::Import),
+    ("namespace", TokenKind::Namespace),
+    ("use", TokenKind::Use),
+    ("as", TokenKind::As),
+    ("when", TokenKind::When),
+    ("test", TokenKind::Test),
+    ("enum", TokenKind::Enum),
+    ("struct", TokenKind::Struct),
+    ("set", TokenKind::Set),
+    ("true", TokenKind::BoolLiteral),
+    ("false", TokenKind::BoolLiteral),
+];
+
 /// Kind of a token produced by the lexer.
 ///
 /// The lexer is intentionally simple: it does not attach any
@@ -34,7 +84,9 @@ pub enum TokenKind {
     Colon,      // :
     Dot,        // .
     Equal,      // =
+    Less,       // <
     Greater,    // >
+    Bar,        // |
 
     // Compound punctuation / operators
     Arrow,      // ->
@@ -67,16 +119,59 @@ pub enum TokenKind {
     Use,
     As,
     When,
+    Test,
     Enum,
     Struct,
     Set,
 }
 
+/// Kind of a piece of trivia.
+///
+/// Trivia is source text that carries no meaning for the parser
+/// (whitespace and comments). It is kept on the tokens so that tools
+/// such as a formatter or a doc generator can recover it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs and line breaks.
+    Whitespace,
+    /// `// ...` up to the end of the line.
+    LineComment,
+    /// `/// ...` documentation comment up to the end of the line.
+    DocComment,
+    /// `/* ... */`, which may be nested.
+    BlockComment,
+}
+
+/// A single piece of trivia with its span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+impl Trivia {
+    /// Returns true for every kind of comment, including doc comments.
+    pub fn is_comment(&self) -> bool {
+        !matches!(self.kind, TriviaKind::Whitespace)
+    }
+
+    /// Returns the source text covered by this trivia.
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.span.start as usize..self.span.end as usize]
+    }
+}
+
 /// A single token with its kind and span.
 ///
 /// The `text_start` / `text_end` fields are byte offsets into the
 /// original source string, so that higher layers can retrieve the
 /// concrete text when needed.
+///
+/// Trivia is attached the same way as in Roslyn-style trees:
+/// `trailing_trivia` holds whitespace and comments up to (but not
+/// including) the next line break, and everything else before a token
+/// is its `leading_trivia`. Concatenating leading trivia, token text
+/// and trailing trivia over all tokens reproduces the source.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Token {
     pub kind: TokenKind,
@@ -85,6 +180,19 @@ pub struct Token {
     /// Byte offsets into the source.
     pub text_start: u32,
     pub text_end: u32,
+    /// Whitespace and comments that precede this token.
+    pub leading_trivia: Vec<Trivia>,
+    /// Whitespace and comments that follow this token on the same line.
+    pub trailing_trivia: Vec<Trivia>,
+}
+
+impl Token {
+    /// Returns the `///` doc comments that precede this token, in order.
+    pub fn doc_comments(&self) -> impl Iterator<Item = &Trivia> {
+        self.leading_trivia
+            .iter()
+            .filter(|t| t.kind == TriviaKind::DocComment)
+    }
 }
 
 /// Result of lexing a source file.
@@ -123,13 +231,9 @@ struct Lexer<'src> {
 impl<'src> Lexer<'src> {
     fn run(&mut self) -> LexResult {
         let mut tokens = Vec::new();
+        let mut leading = self.lex_trivia(false);
 
         while let Some(ch) = self.peek_char() {
-            if is_whitespace(ch) {
-                self.consume_char();
-                continue;
-            }
-
             let start = self.index as u32;
             let token = match ch {
                 b'(' => {
@@ -186,10 +290,18 @@ impl<'src> Lexer<'src> {
                         self.simple_token(TokenKind::Equal, start)
                     }
                 }
+                b'<' => {
+                    self.consume_char();
+                    self.simple_token(TokenKind::Less, start)
+                }
                 b'>' => {
                     self.consume_char();
                     self.simple_token(TokenKind::Greater, start)
                 }
+                b'|' => {
+                    self.consume_char();
+                    self.simple_token(TokenKind::Bar, start)
+                }
                 b'-' => {
                     // Look for "->"
                     if self.peek_next() == Some(b'>') {
@@ -215,27 +327,32 @@ impl<'src> Lexer<'src> {
                 b'"' => self.lex_string(start),
                 b'0'..=b'9' => self.lex_number(start),
                 _ => {
-                    if is_ident_start(ch) {
+                    if self.peek_scalar().is_some_and(is_ident_start) {
                         self.lex_ident_or_keyword(start)
                     } else {
-                        self.consume_char();
+                        self.consume_scalar();
                         self.unexpected_char(start)
                     }
                 }
             };
 
-            if let Some(tok) = token {
+            if let Some(mut tok) = token {
+                tok.leading_trivia = core::mem::take(&mut leading);
+                tok.trailing_trivia = self.lex_trivia(true);
                 tokens.push(tok);
             }
+            leading.extend(self.lex_trivia(false));
         }
 
-        // EOF token at end
+        // EOF token at end; it owns whatever trivia is left over.
         let eof_span = Span::new(self.file_id, self.len as u32, self.len as u32);
         tokens.push(Token {
             kind: TokenKind::Eof,
             span: eof_span,
             text_start: self.len as u32,
             text_end: self.len as u32,
+            leading_trivia: leading,
+            trailing_trivia: Vec::new(),
         });
 
         LexResult {
@@ -251,18 +368,107 @@ impl<'src> Lexer<'src> {
             span: Span::new(self.file_id, start, end),
             text_start: start,
             text_end: end,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
         })
     }
 
     fn unexpected_char(&mut self, start: u32) -> Option<Token> {
         let end = self.index as u32;
         let span = Span::new(self.file_id, start, end);
-        let diag = Diagnostic::error("unexpected character", span)
-            .with_code("E0001");
+        let diag = Diagnostic::error_key("E0001", span);
         self.diagnostics.push(diag);
         None
     }
 
+    /// Collect a run of trivia starting at the current position.
+    ///
+    /// With `trailing` set, the run stops before the next line break so
+    /// that the break and anything after it become leading trivia of
+    /// the following token.
+    fn lex_trivia(&mut self, trailing: bool) -> Vec<Trivia> {
+        let mut trivia = Vec::new();
+
+        while let Some(ch) = self.peek_char() {
+            let start = self.index as u32;
+            let kind = if is_whitespace(ch) {
+                if trailing && is_line_break(ch) {
+                    break;
+                }
+                while let Some(ch) = self.peek_char() {
+                    if !is_whitespace(ch) || (trailing && is_line_break(ch)) {
+                        break;
+                    }
+                    self.consume_char();
+                }
+                TriviaKind::Whitespace
+            } else if ch == b'/' && self.peek_next() == Some(b'/') {
+                self.lex_line_comment()
+            } else if ch == b'/' && self.peek_next() == Some(b'*') {
+                self.lex_block_comment(start);
+                TriviaKind::BlockComment
+            } else {
+                break;
+            };
+
+            trivia.push(Trivia {
+                kind,
+                span: Span::new(self.file_id, start, self.index as u32),
+            });
+        }
+
+        trivia
+    }
+
+    fn lex_line_comment(&mut self) -> TriviaKind {
+        // `///` is a doc comment, but `////...` is an ordinary comment
+        // (the same convention as Rust).
+        let rest = &self.chars[self.index..];
+        let is_doc = rest.starts_with(b"///") && !rest.starts_with(b"////");
+
+        while let Some(ch) = self.peek_char() {
+            if ch == b'\n' {
+                break;
+            }
+            self.consume_char();
+        }
+
+        if is_doc {
+            TriviaKind::DocComment
+        } else {
+            TriviaKind::LineComment
+        }
+    }
+
+    fn lex_block_comment(&mut self, start: u32) {
+        // Consume the opening `/*`
+        self.consume_char();
+        self.consume_char();
+
+        let mut depth = 1usize;
+        while let Some(ch) = self.peek_char() {
+            if ch == b'/' && self.peek_next() == Some(b'*') {
+                self.consume_char();
+                self.consume_char();
+                depth += 1;
+            } else if ch == b'*' && self.peek_next() == Some(b'/') {
+                self.consume_char();
+                self.consume_char();
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            } else {
+                self.consume_char();
+            }
+        }
+
+        // Unterminated block comment
+        let span = Span::new(self.file_id, start, self.index as u32);
+        let diag = Diagnostic::error_key("E0003", span);
+        self.diagnostics.push(diag);
+    }
+
     fn lex_string(&mut self, start: u32) -> Option<Token> {
         // Consume the opening quote
         self.consume_char();
@@ -274,11 +480,16 @@ impl<'src> Lexer<'src> {
                     let content_end = self.index;
                     self.consume_char(); // closing quote
                     let span = Span::new(self.file_id, start, self.index as u32);
+                    let content = &self.source[content_start..content_end];
+                    let (_, diags) = unescape(self.file_id, content, content_start as u32);
+                    self.diagnostics.extend(diags);
                     return Some(Token {
                         kind: TokenKind::StringLiteral,
                         span,
                         text_start: content_start as u32,
                         text_end: content_end as u32,
+                        leading_trivia: Vec::new(),
+                        trailing_trivia: Vec::new(),
                     });
                 }
                 b'\\' => {
@@ -296,43 +507,96 @@ impl<'src> Lexer<'src> {
 
         // Unterminated string
         let span = Span::new(self.file_id, start, self.index as u32);
-        let diag = Diagnostic::error("unterminated string literal", span)
-            .with_code("E0002");
+        let diag = Diagnostic::error_key("E0002", span);
         self.diagnostics.push(diag);
         None
     }
 
     fn lex_number(&mut self, start: u32) -> Option<Token> {
-        // integer or float: digits [ '.' digits ]?
-        while let Some(ch) = self.peek_char() {
-            if matches!(ch, b'0'..=b'9' | b'_') {
-                self.consume_char();
-            } else {
-                break;
-            }
+        let radix = match self.chars[self.index..] {
+            [b'0', b'x' | b'X', ..] => 16,
+            [b'0', b'o' | b'O', ..] => 8,
+            [b'0', b'b' | b'B', ..] => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.consume_char();
+            self.consume_char();
         }
+        // Decimal digits are taken for every radix; the ones that are
+        // too large for it are reported below.
+        let digits_start = self.index;
+        self.eat_digits(radix == 16);
+        let digits_end = self.index;
 
         let mut is_float = false;
-
-        if self.peek_char() == Some(b'.') {
-            // Look ahead: if '.' followed by digit, treat as float.
-            if let Some(next) = self.peek_next() {
-                if (b'0'..=b'9').contains(&next) {
-                    is_float = true;
-                    self.consume_char(); // '.'
-                    while let Some(ch) = self.peek_char() {
-                        if matches!(ch, b'0'..=b'9' | b'_') {
-                            self.consume_char();
-                        } else {
-                            break;
-                        }
-                    }
+        if radix == 10 {
+            // A fraction needs a digit after the dot, so `x.0.1` style
+            // member access and `1.` stay unaffected.
+            if self.peek_char() == Some(b'.')
+                && self.peek_next().is_some_and(|next| next.is_ascii_digit())
+            {
+                is_float = true;
+                self.consume_char(); // '.'
+                self.eat_digits(false);
+            }
+            let exponent = match self.chars[self.index..] {
+                [b'e' | b'E', b'0'..=b'9', ..] => 1,
+                [b'e' | b'E', b'+' | b'-', b'0'..=b'9', ..] => 2,
+                _ => 0,
+            };
+            if exponent > 0 {
+                is_float = true;
+                for _ in 0..exponent {
+                    self.consume_char();
                 }
+                self.eat_digits(false);
             }
         }
 
+        let suffix_start = self.index;
+        while self.peek_scalar().is_some_and(is_ident_continue) {
+            self.consume_scalar();
+        }
         let end = self.index as u32;
         let span = Span::new(self.file_id, start, end);
+
+        let digits = &self.source[digits_start..digits_end];
+        if digits.bytes().all(|b| b == b'_') {
+            let prefix = &self.source[start as usize..digits_start];
+            let diag = Diagnostic::error_key("E0005.no-digits", span).arg("prefix", prefix);
+            self.diagnostics.push(diag);
+        } else if let Some((i, digit)) = digits
+            .char_indices()
+            .find(|(_, c)| *c != '_' && c.to_digit(radix).is_none())
+        {
+            let at = (digits_start + i) as u32;
+            let span = Span::new(self.file_id, at, at + 1);
+            let diag = Diagnostic::error_key("E0005.invalid-digit", span)
+                .arg("digit", digit)
+                .arg("radix", radix);
+            self.diagnostics.push(diag);
+        }
+        let suffix = &self.source[suffix_start..end as usize];
+        let suffix_span = Span::new(self.file_id, suffix_start as u32, end);
+        let key = match suffix {
+            "" => None,
+            "i32" | "i64" if is_float => Some("E0005.int-suffix-on-float"),
+            "i32" | "i64" => None,
+            "f32" | "f64" if radix != 10 => Some("E0005.float-radix"),
+            "f32" | "f64" => {
+                is_float = true;
+                None
+            }
+            _ => Some("E0005.suffix"),
+        };
+        if let Some(key) = key {
+            let diag = Diagnostic::error_key(key, suffix_span)
+                .arg("suffix", suffix)
+                .arg("radix", radix);
+            self.diagnostics.push(diag);
+        }
+
         let kind = if is_float {
             TokenKind::FloatLiteral
         } else {
@@ -343,58 +607,44 @@ impl<'src> Lexer<'src> {
             span,
             text_start: start,
             text_end: end,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
         })
     }
 
-    fn lex_ident_or_keyword(&mut self, start: u32) -> Option<Token> {
+    /// Consume decimal digits (and hex digits with `hex`) and `_`.
+    fn eat_digits(&mut self, hex: bool) {
         while let Some(ch) = self.peek_char() {
-            if is_ident_continue(ch) {
-                self.consume_char();
-            } else {
+            let digit = ch.is_ascii_digit() || (hex && ch.is_ascii_hexdigit());
+            if !(digit || ch == b'_') {
                 break;
             }
+            self.consume_char();
+        }
+    }
+
+    fn lex_ident_or_keyword(&mut self, start: u32) -> Option<Token> {
+        self.consume_scalar();
+        while self.peek_scalar().is_some_and(is_ident_continue) {
+            self.consume_scalar();
         }
 
         let end = self.index as u32;
         let span = Span::new(self.file_id, start, end);
         let text = &self.source[start as usize..end as usize];
 
-        let kind = match text {
-            "if" => TokenKind::If,
-            "then" => TokenKind::Then,
-            "elseif" => TokenKind::ElseIf,
-            "else" => TokenKind::Else,
-            "loop" => TokenKind::Loop,
-            "while" => TokenKind::While,
-            "match" => TokenKind::Match,
-            "case" => TokenKind::Case,
-            "break" => TokenKind::Break,
-            "continue" => TokenKind::Continue,
-            "return" => TokenKind::Return,
-            "let" => TokenKind::Let,
-            "mut" => TokenKind::Mut,
-            "hoist" => TokenKind::Hoist,
-            "pub" => TokenKind::Pub,
-            "fn" => TokenKind::Fn,
-            "include" => TokenKind::Include,
-            "import" => TokenKind::Import,
-            "namespace" => TokenKind::Namespace,
-            "use" => TokenKind::Use,
-            "as" => TokenKind::As,
-            "when" => TokenKind::When,
-            "enum" => TokenKind::Enum,
-            "struct" => TokenKind::Struct,
-            "set" => TokenKind::Set,
-            "true" => TokenKind::BoolLiteral,
-            "false" => TokenKind::BoolLiteral,
-            _ => TokenKind::Ident,
-        };
+        let kind = KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == text)
+            .map_or(TokenKind::Ident, |(_, kind)| *kind);
 
         Some(Token {
             kind,
             span,
             text_start: start,
             text_end: end,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
         })
     }
 
@@ -411,18 +661,137 @@ impl<'src> Lexer<'src> {
             self.index += 1;
         }
     }
+
+    /// The character at the current position. Only valid at character
+    /// boundaries, which is wherever a token or trivia starts.
+    fn peek_scalar(&self) -> Option<char> {
+        self.source[self.index..].chars().next()
+    }
+
+    fn consume_scalar(&mut self) {
+        if let Some(ch) = self.peek_scalar() {
+            self.index += ch.len_utf8();
+        }
+    }
+}
+
+/// Decode the escapes in the contents of a string literal (the text
+/// between the quotes). `offset` is the byte offset of `content` in its
+/// file and places the diagnostics. Malformed escapes are reported and
+/// decode to U+FFFD.
+pub fn unescape(file_id: FileId, content: &str, offset: u32) -> (String, Vec<Diagnostic>) {
+    let span =
+        |start: usize, end: usize| Span::new(file_id, offset + start as u32, offset + end as u32);
+    let mut out = String::with_capacity(content.len());
+    let mut diagnostics = Vec::new();
+    let mut chars = content.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        // The lexer never ends the contents with a lone backslash.
+        let Some((i, escape)) = chars.next() else {
+            break;
+        };
+        let end = i + escape.len_utf8();
+        let decoded = match escape {
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            '0' => Ok('\0'),
+            '\\' | '"' | '\'' => Ok(escape),
+            'u' => unicode_escape(&mut chars, content, end).map_err(|(key, end)| {
+                Diagnostic::error_key(key, span(start, end)).arg("escape", &content[start..end])
+            }),
+            _ => Err(Diagnostic::error_key("E0004", span(start, end))
+                .arg("escape", &content[start..end])
+                .with_suggestion_key(
+                    MessageKey::new("suggest.escape-backslash"),
+                    span(start, start + 1),
+                    "\\\\",
+                )),
+        };
+        out.push(decoded.unwrap_or_else(|diag| {
+            diagnostics.push(diag);
+            char::REPLACEMENT_CHARACTER
+        }));
+    }
+    (out, diagnostics)
+}
+
+/// A numeric literal token split into its radix, its digits (with `_`
+/// separators, without the `0x` / `0o` / `0b` prefix) and its type
+/// suffix, which is empty when there is none.
+pub fn number_parts(text: &str) -> (u32, &str, &str) {
+    let (radix, body) = match text.get(..2) {
+        Some("0x" | "0X") => (16, &text[2..]),
+        Some("0o" | "0O") => (8, &text[2..]),
+        Some("0b" | "0B") => (2, &text[2..]),
+        _ => (10, text),
+    };
+    // Hex digits include `f`, so `0x1f32` has no suffix.
+    let suffixes: &[&str] = if radix == 16 {
+        &["i32", "i64"]
+    } else {
+        &["i32", "i64", "f32", "f64"]
+    };
+    match suffixes.iter().find(|s| body.ends_with(**s)) {
+        Some(suffix) => (radix, &body[..body.len() - suffix.len()], suffix),
+        None => (radix, body, ""),
+    }
+}
+
+/// The rest of a `\u{...}` escape whose `\u` ends at `end`. On failure,
+/// returns the message key and the end of the bad escape.
+fn unicode_escape(
+    chars: &mut core::iter::Peekable<core::str::CharIndices<'_>>,
+    content: &str,
+    end: usize,
+) -> Result<char, (&'static str, usize)> {
+    if chars.next_if(|(_, c)| *c == '{').is_none() {
+        return Err(("E0004.unicode-brace", end));
+    }
+    let digits_start = end + 1;
+    let mut digits_end = digits_start;
+    while let Some((i, c)) = chars.next_if(|(_, c)| c.is_ascii_hexdigit() || *c == '_') {
+        digits_end = i + c.len_utf8();
+    }
+    let close = match chars.peek() {
+        Some((close, '}')) => *close,
+        Some((i, c)) => return Err(("E0004.unicode-digit", i + c.len_utf8())),
+        None => return Err(("E0004.unicode-unclosed", digits_end)),
+    };
+    chars.next();
+    let end = close + 1;
+    let digits: String = content[digits_start..digits_end]
+        .chars()
+        .filter(|c| *c != '_')
+        .collect();
+    match digits.len() {
+        0 => return Err(("E0004.unicode-empty", end)),
+        1..=6 => {}
+        _ => return Err(("E0004.unicode-length", end)),
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or(("E0004.unicode-range", end))
 }
 
 fn is_whitespace(ch: u8) -> bool {
     matches!(ch, b' ' | b'\t' | b'\n' | b'\r')
 }
 
-fn is_ident_start(ch: u8) -> bool {
-    (b'a'..=b'z').contains(&ch)
-        || (b'A'..=b'Z').contains(&ch)
-        || ch == b'_'
+fn is_line_break(ch: u8) -> bool {
+    matches!(ch, b'\n' | b'\r')
+}
+
+/// UAX #31 identifiers, with `_` allowed at the start.
+pub fn is_ident_start(ch: char) -> bool {
+    ch == '_' || unicode_ident::is_xid_start(ch)
 }
 
-fn is_ident_continue(ch: u8) -> bool {
-    is_ident_start(ch) || (b'0'..=b'9').contains(&ch)
+pub fn is_ident_continue(ch: char) -> bool {
+    unicode_ident::is_xid_continue(ch)
 }
@@ -23,6 +23,12 @@ extern crate alloc;
 pub mod span;
 pub mod diagnostic;
 pub mod error;
+pub mod render;
+pub mod report;
+pub mod error_codes;
+pub mod fix;
+pub mod messages;
+pub mod lint;
 
 // ---------------------------------------------------------------------
 // Front-end: lexing and parsing
@@ -30,6 +36,8 @@ pub mod error;
 
 pub mod lexer;
 pub mod parser;
+pub mod cst;
+pub mod format;
 pub mod ast;
 
 // ---------------------------------------------------------------------
@@ -39,6 +47,7 @@ pub mod ast;
 pub mod types;
 pub mod name_resolve;
 pub mod typecheck;
+pub mod call_tree;
 pub mod hir;
 
 // ---------------------------------------------------------------------
@@ -54,11 +63,15 @@ pub mod stdlib;
 
 pub mod codegen_wasm;
 pub mod codegen_llvm;
+pub mod interp;
 pub mod compiler;
 
 // ---------------------------------------------------------------------
 // Public API re-exports
 // ---------------------------------------------------------------------
 
-pub use compiler::{CompilationArtifact, compile_wasm, emit_llvm_ir};
+pub use compiler::{
+    CompilationArtifact, FrontEnd, analyze, check, compile_tests, compile_wasm, compile_wasm_with,
+    emit_llvm_ir, emit_llvm_ir_with, interpret,
+};
 pub use error::CoreError;
@@ -0,0 +1,293 @@
+//! Lints: warnings about code that compiles but is probably not what
+//! was meant (no_std).
+//!
+//! Every lint has a name, a `W` code from `error_codes` and a default
+//! level. Levels can be changed
+//!
+//! - by the caller, through `LintConfig` (`nepl -W/-D/-A <lint>`), and
+//! - in the source, with a line comment pragma:
+//!
+//! ```text
+//! // nepl: allow(unused_variables, shadowing)
+//! ```
+//!
+//! A pragma takes effect from its position to the end of the file, so
+//! one at the top covers the whole file; a later pragma for the same
+//! lint overrides it. Pragmas override the `LintConfig`.
+//!
+//! `deny_warnings` turns every lint that would warn into an error,
+//! which is what CI usually wants.
+//!
+//! Lints found while type checking are reported by `typecheck`; this
+//! module checks the ones that only need name resolution.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::lexer::{Token, TriviaKind};
+use crate::name_resolve::NameResolveResult;
+use crate::span::Span;
+
+/// What to do when a lint fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Say nothing.
+    Allow,
+    /// Report a warning.
+    Warn,
+    /// Report an error, failing the compilation.
+    Deny,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Deny => "deny",
+        }
+    }
+}
+
+/// A named lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lint {
+    pub name: &'static str,
+    pub code: &'static str,
+    pub default: Level,
+}
+
+/// A variable or parameter that is never read.
+pub const UNUSED_VARIABLES: Lint = Lint {
+    name: "unused_variables",
+    code: "W0001",
+    default: Level::Warn,
+};
+
+/// A `use` whose name is never referred to.
+pub const UNUSED_USES: Lint = Lint {
+    name: "unused_uses",
+    code: "W0002",
+    default: Level::Warn,
+};
+
+/// A binding that hides a variable of the same name. Rebinding is
+/// idiomatic, so this is opt-in.
+pub const SHADOWING: Lint = Lint {
+    name: "shadowing",
+    code: "W0003",
+    default: Level::Allow,
+};
+
+/// Code after an expression of type `Never` in a block.
+pub const UNREACHABLE_CODE: Lint = Lint {
+    name: "unreachable_code",
+    code: "W0004",
+    default: Level::Warn,
+};
+
+/// An impure (`->`) function literal that does nothing impure. `->`
+/// is the usual arrow, so this is opt-in as well.
+pub const COULD_BE_PURE: Lint = Lint {
+    name: "could_be_pure",
+    code: "W0005",
+    default: Level::Allow,
+};
+
+/// A pragma naming a lint that does not exist.
+pub const UNKNOWN_LINTS: Lint = Lint {
+    name: "unknown_lints",
+    code: "W0006",
+    default: Level::Warn,
+};
+
+/// Every lint, in code order.
+pub const LINTS: &[Lint] = &[
+    UNUSED_VARIABLES,
+    UNUSED_USES,
+    SHADOWING,
+    UNREACHABLE_CODE,
+    COULD_BE_PURE,
+    UNKNOWN_LINTS,
+];
+
+/// Find a lint by name. `-` may be written for `_`.
+pub fn lookup(name: &str) -> Option<&'static Lint> {
+    let name = name.replace('-', "_");
+    LINTS.iter().find(|l| l.name == name)
+}
+
+/// A lint name that is not in `LINTS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownLint(pub String);
+
+impl fmt::Display for UnknownLint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown lint `{}`", self.0)
+    }
+}
+
+/// Lint levels chosen by the caller (for example on the command line).
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    levels: Vec<(&'static str, Level)>,
+    /// Report lints at the `Warn` level as errors.
+    pub deny_warnings: bool,
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        LintConfig::default()
+    }
+
+    /// Set the level of the lint `name`; a later call for the same lint
+    /// replaces an earlier one.
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), UnknownLint> {
+        let lint = lookup(name).ok_or_else(|| UnknownLint(name.to_string()))?;
+        self.levels.retain(|(n, _)| *n != lint.name);
+        self.levels.push((lint.name, level));
+        Ok(())
+    }
+
+    /// The level of `lint`, before pragmas.
+    pub fn level(&self, lint: &Lint) -> Level {
+        self.levels
+            .iter()
+            .find(|(n, _)| *n == lint.name)
+            .map_or(lint.default, |(_, level)| *level)
+    }
+}
+
+/// A level change written in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pragma {
+    /// Byte offset from which the pragma applies.
+    pub offset: u32,
+    pub lint: &'static Lint,
+    pub level: Level,
+}
+
+/// Marker that starts a lint pragma inside a `//` comment.
+pub const PRAGMA_PREFIX: &str = "nepl:";
+
+/// The lint levels in effect for one file: the caller's configuration
+/// plus the pragmas in the source.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels {
+    config: LintConfig,
+    pragmas: Vec<Pragma>,
+}
+
+impl LintLevels {
+    /// Levels without any pragmas.
+    pub fn new(config: LintConfig) -> Self {
+        LintLevels {
+            config,
+            pragmas: Vec::new(),
+        }
+    }
+
+    /// Levels for a lexed file, reading the pragmas in its comments.
+    /// Pragmas that name unknown lints are reported.
+    pub fn from_source(
+        config: LintConfig,
+        source: &str,
+        tokens: &[Token],
+    ) -> (Self, Vec<Diagnostic>) {
+        let mut levels = LintLevels::new(config);
+        let mut unknown = Vec::new();
+        let comments = tokens
+            .iter()
+            .flat_map(|t| t.leading_trivia.iter().chain(&t.trailing_trivia))
+            .filter(|t| t.kind == TriviaKind::LineComment);
+        for comment in comments {
+            let text = comment.text(source);
+            let Some((level, names)) = parse_pragma(text) else {
+                continue;
+            };
+            for name in names {
+                match lookup(name) {
+                    Some(lint) => levels.pragmas.push(Pragma {
+                        offset: comment.span.start,
+                        lint,
+                        level,
+                    }),
+                    None => unknown.push((comment.span, name)),
+                }
+            }
+        }
+        let diagnostics = unknown
+            .into_iter()
+            .filter_map(|(span, name)| {
+                let diag = Diagnostic::warning_key("W0006", span).arg("name", name);
+                levels.report(&UNKNOWN_LINTS, diag)
+            })
+            .collect();
+        (levels, diagnostics)
+    }
+
+    /// The pragmas read from the source, in source order.
+    pub fn pragmas(&self) -> &[Pragma] {
+        &self.pragmas
+    }
+
+    /// The level of `lint` for a diagnostic at `span`.
+    pub fn level(&self, lint: &Lint, span: Span) -> Level {
+        self.pragmas
+            .iter()
+            .rev()
+            .find(|p| p.lint.name == lint.name && p.offset <= span.start)
+            .map_or_else(|| self.config.level(lint), |p| p.level)
+    }
+
+    /// Give `diag` (reported by `lint`) the severity of the lint's
+    /// level, or drop it when the lint is allowed.
+    pub fn report(&self, lint: &Lint, mut diag: Diagnostic) -> Option<Diagnostic> {
+        diag.severity = match self.level(lint, diag.primary.span) {
+            Level::Allow => return None,
+            Level::Warn if !self.config.deny_warnings => Severity::Warning,
+            Level::Warn | Level::Deny => Severity::Error,
+        };
+        Some(diag)
+    }
+}
+
+/// `// nepl: allow(a, b)` → `(Allow, ["a", "b"])`.
+fn parse_pragma(comment: &str) -> Option<(Level, impl Iterator<Item = &str>)> {
+    let rest = comment
+        .strip_prefix("//")?
+        .trim_start()
+        .strip_prefix(PRAGMA_PREFIX)?;
+    let (level, rest) = rest.trim().split_once('(')?;
+    let level = Level::parse(level.trim())?;
+    let names = rest.trim_end().strip_suffix(')')?;
+    Some((
+        level,
+        names.split(',').map(str::trim).filter(|n| !n.is_empty()),
+    ))
+}
+
+/// Report `use` items whose name nothing refers to. Glob imports are
+/// never reported.
+pub fn unused_uses(names: &NameResolveResult, levels: &LintLevels) -> Vec<Diagnostic> {
+    names
+        .uses
+        .iter()
+        .filter(|u| !u.used)
+        .filter_map(|u| {
+            let diag = Diagnostic::warning_key("W0002", u.span).arg("name", &u.name);
+            levels.report(&UNUSED_USES, diag)
+        })
+        .collect()
+}
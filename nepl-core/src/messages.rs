@@ -0,0 +1,128 @@
+//! Localized diagnostic messages (no_std).
+//!
+//! Messages are looked up by key in a per-locale catalog and formatted
+//! with named arguments. A key starts with the diagnostic code it
+//! belongs to, optionally followed by `.` and a variant name when one
+//! code has several messages (`E0300`, `E0301.given`, ...). Keys that
+//! do not start with a code are used for the renderer's own text.
+//!
+//! Catalogs live in `messages/<locale>.txt`, one `key = template` per
+//! line; `#` starts a comment line. Templates refer to arguments as
+//! `{name}`; `{{` and `}}` stand for literal braces.
+//!
+//! 翻訳の追加は `ja.txt` に同じキーで一行足すだけでよい。キーが
+//! 見つからない場合は英語のテンプレートにフォールバックする。
+
+use alloc::string::{String, ToString};
+
+const EN: &str = include_str!("messages/en.txt");
+const JA: &str = include_str!("messages/ja.txt");
+
+/// Language of diagnostic messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    Ja,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Ja];
+
+    /// Parse a locale tag such as `ja`, `en-US` or a POSIX locale like
+    /// `ja_JP.UTF-8`. Only the language part is considered.
+    pub fn parse(tag: &str) -> Option<Locale> {
+        let lang = tag
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match lang.as_str() {
+            "en" | "c" | "posix" => Some(Locale::En),
+            "ja" => Some(Locale::Ja),
+            _ => None,
+        }
+    }
+
+    /// The short tag of this locale (`"en"` / `"ja"`).
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Ja => "ja",
+        }
+    }
+
+    /// Raw text of this locale's catalog.
+    pub fn catalog(self) -> &'static str {
+        match self {
+            Locale::En => EN,
+            Locale::Ja => JA,
+        }
+    }
+}
+
+/// Every `(key, template)` entry of a catalog, in file order.
+pub fn entries(catalog: &'static str) -> impl Iterator<Item = (&'static str, &'static str)> {
+    catalog.lines().filter_map(|line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (key, template) = line.split_once('=')?;
+        Some((key.trim(), template.trim()))
+    })
+}
+
+/// The template of `key` in `locale`, falling back to English.
+pub fn template(locale: Locale, key: &str) -> Option<&'static str> {
+    let find = |catalog| entries(catalog).find(|(k, _)| *k == key).map(|(_, t)| t);
+    find(locale.catalog()).or_else(|| find(EN))
+}
+
+/// Format the message `key` in `locale`. Unknown keys format as the
+/// key itself so that a missing entry is visible rather than silent.
+pub fn format(locale: Locale, key: &str, args: &[(&str, String)]) -> String {
+    match template(locale, key) {
+        Some(t) => interpolate(t, args),
+        None => key.to_string(),
+    }
+}
+
+/// Replace every `{name}` in `template` by the argument of that name.
+/// Unknown names are kept as written.
+pub fn interpolate(template: &str, args: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            out.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        if let Some(stripped) = tail.strip_prefix('{')
+            && let Some(end) = stripped.find('}')
+        {
+            let name = &stripped[..end];
+            match args.iter().find(|(n, _)| *n == name) {
+                Some((_, value)) => out.push_str(value),
+                None => out.push_str(&tail[..end + 2]),
+            }
+            rest = &stripped[end + 1..];
+        } else {
+            out.push_str(&tail[..1]);
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Names of the `{placeholders}` used by a template.
+pub fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .split("{{")
+        .flat_map(|part| part.split('{').skip(1))
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+}
@@ -1,55 +1,576 @@
-//! Name resolution for NEPL (no_std, skeleton).
+//! Name resolution for NEPL (no_std).
 //!
-//! This module is responsible for resolving names across namespaces,
-//! includes, imports, uses, enums, and structs.
+//! This pass follows the lexical scoping rules of the type checker and
+//! records, for a single file, every symbol a program defines and every
+//! place a name refers to one of them. It does not compute types and
+//! does not report unresolved names (the type checker already does);
+//! its result serves tools such as go-to-definition, completion and
+//! document outlines.
 //!
-//! 現段階ではまだ実際の解決ロジックは実装せず、
-//! インタフェースとデータ構造だけを定義する。
-
-#![allow(dead_code)]
+//! Scoping mirrors `typecheck`: `fn` bindings, enum / struct
+//! definitions, namespaces and `let hoist` are visible in the whole
+//! block, a plain `let` only after itself, and `use` brings a namespace
+//! member (or, with `::*`, all members) into the rest of the block.
+//! A `use` of a path outside the file still brings its name into scope,
+//! so that the name shadows outer definitions and counts as used; see
+//! [`NameResolveResult::uses`].
 
 use alloc::string::String;
 use alloc::vec::Vec;
 
-use crate::ast;
+use crate::ast::{self, Expr, ExprKind, Pattern, TypeExpr, TypeExprKind};
 use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+
+/// Index of a symbol in [`NameResolveResult::symbols`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SymbolId(pub usize);
 
 /// Kinds of symbols that can appear in the program.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SymbolKind {
-    Value,      // variables, functions, constants
-    Type,       // enums, structs, type aliases
-    Namespace,  // namespace itself
+    /// A `let` binding or a name bound by a match pattern.
+    Variable,
+    /// A function literal parameter.
+    Parameter,
+    /// A `fn` binding.
+    Function,
+    /// A type parameter of a function literal, enum or struct.
+    TypeParam,
+    Enum,
+    Struct,
     EnumVariant,
     StructField,
+    Namespace,
+}
+
+impl SymbolKind {
+    /// Whether the name can be used as a type.
+    pub fn is_type(self) -> bool {
+        matches!(
+            self,
+            SymbolKind::Enum | SymbolKind::Struct | SymbolKind::TypeParam
+        )
+    }
+
+    /// Whether the name can be used in an expression. Struct names are,
+    /// as constructors.
+    pub fn is_value(self) -> bool {
+        matches!(
+            self,
+            SymbolKind::Variable
+                | SymbolKind::Parameter
+                | SymbolKind::Function
+                | SymbolKind::Struct
+                | SymbolKind::EnumVariant
+        )
+    }
 }
 
-/// A resolved symbol reference.
-///
-/// 後でシンボルテーブルのインデックスやファイル位置などを持たせることを想定。
+/// A name defined by the program.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ResolvedSymbol {
+pub struct Symbol {
     pub name: String,
     pub kind: SymbolKind,
-    // TODO: module path, declaration span, etc.
+    /// Span of the defining name.
+    pub span: Span,
+    /// Span of the whole definition (`fn f = ...`, `enum E { ... }`).
+    pub full_span: Span,
+    /// The namespace, enum or struct this symbol is a member of.
+    pub container: Option<SymbolId>,
+    /// Where the name can be used unqualified. Empty for struct fields.
+    pub scope: Span,
+}
+
+/// A use of a name, resolved to its definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub span: Span,
+    pub symbol: SymbolId,
+}
+
+/// A `use` item that brings one name into scope (not a glob import).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UseBinding {
+    /// The alias, or the last segment of the path.
+    pub name: String,
+    /// Span of the alias, or of the whole `use` without one.
+    pub span: Span,
+    /// Whether some later name refers to it.
+    pub used: bool,
 }
 
 /// Result of name resolution on a single AST root expression.
-///
-/// 現時点では diagnostics だけを返し、
-/// 実際の解決結果はほとんど空のままにしてある。
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct NameResolveResult {
     pub diagnostics: Vec<Diagnostic>,
-    // TODO: symbol tables, mapping from AST id -> ResolvedSymbol, etc.
+    /// Symbols in the order they were declared.
+    pub symbols: Vec<Symbol>,
+    /// References in the order they were resolved.
+    pub references: Vec<Reference>,
+    /// Non-glob `use` items in source order, whether or not their path
+    /// is defined in this file.
+    pub uses: Vec<UseBinding>,
+    /// Names brought into scope by `use`.
+    aliases: Vec<Alias>,
+}
+
+/// A name introduced by `use`, visible within `scope`.
+#[derive(Debug, Clone)]
+struct Alias {
+    name: String,
+    target: SymbolId,
+    scope: Span,
+}
+
+impl NameResolveResult {
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    /// The symbol whose name is defined or referenced at `offset`.
+    pub fn symbol_at(&self, offset: u32) -> Option<SymbolId> {
+        let covers = |span: &Span| span.start <= offset && offset <= span.end;
+        self.references
+            .iter()
+            .find(|r| covers(&r.span))
+            .map(|r| r.symbol)
+            .or_else(|| {
+                self.symbols
+                    .iter()
+                    .position(|s| covers(&s.span))
+                    .map(SymbolId)
+            })
+    }
+
+    /// The definition of the name at `offset`.
+    pub fn definition_at(&self, offset: u32) -> Option<&Symbol> {
+        self.symbol_at(offset).map(|id| self.symbol(id))
+    }
+
+    /// Spans of every reference to `id`, not including its definition.
+    pub fn references_to(&self, id: SymbolId) -> impl Iterator<Item = Span> + '_ {
+        self.references
+            .iter()
+            .filter(move |r| r.symbol == id)
+            .map(|r| r.span)
+    }
+
+    /// Members of a namespace, enum or struct.
+    pub fn members(&self, id: SymbolId) -> impl Iterator<Item = &Symbol> + '_ {
+        self.symbols.iter().filter(move |s| s.container == Some(id))
+    }
+
+    /// Symbols that can be named unqualified at `offset`. A shadowed
+    /// name yields only its innermost definition.
+    pub fn visible_at(&self, offset: u32) -> Vec<&Symbol> {
+        let in_scope =
+            |scope: &Span| !scope.is_empty() && scope.start <= offset && offset <= scope.end;
+        let mut found: Vec<(&str, Span, &Symbol)> = Vec::new();
+        let candidates = self
+            .symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.scope, s))
+            .chain(
+                self.aliases
+                    .iter()
+                    .map(|a| (a.name.as_str(), a.scope, self.symbol(a.target))),
+            );
+        for (name, scope, symbol) in candidates {
+            if !in_scope(&scope) {
+                continue;
+            }
+            match found.iter_mut().find(|(n, ..)| *n == name) {
+                // The later start is the inner scope.
+                Some(entry) if scope.start >= entry.1.start => *entry = (name, scope, symbol),
+                Some(_) => {}
+                None => found.push((name, scope, symbol)),
+            }
+        }
+        found.into_iter().map(|(.., s)| s).collect()
+    }
 }
 
 /// Perform name resolution on a single AST root expression.
-///
-/// 将来は複数ファイルを跨いで `include` / `import` を処理するが、
-/// ここではひとまず 1 ファイル単位のインタフェースを用意する。
-pub fn resolve_names(_root: &ast::Expr) -> NameResolveResult {
-    NameResolveResult {
-        diagnostics: Vec::new(),
+pub fn resolve_names(root: &Expr) -> NameResolveResult {
+    let mut resolver = Resolver {
+        result: NameResolveResult::default(),
+        scopes: Vec::new(),
+        unit_variants: Vec::new(),
+    };
+    match &root.kind {
+        ExprKind::Block(block) => resolver.block(&block.expressions, root.span, None),
+        _ => resolver.block(core::slice::from_ref(root), root.span, None),
     }
+    resolver.result
+}
+
+/// Names visible in one lexical scope, innermost last.
+type Scope = Vec<Binding>;
+
+/// A name in scope.
+struct Binding {
+    name: String,
+    /// `None` for a name a `use` brings from outside the file.
+    symbol: Option<SymbolId>,
+    /// The `use` that brought the name, as an index into
+    /// [`NameResolveResult::uses`].
+    via: Option<usize>,
+}
+
+struct Resolver {
+    result: NameResolveResult,
+    scopes: Vec<Scope>,
+    /// Enum variants without a payload, which a bare name in a pattern
+    /// matches instead of binding.
+    unit_variants: Vec<SymbolId>,
+}
+
+impl Resolver {
+    fn declare(
+        &mut self,
+        name: &ast::Ident,
+        kind: SymbolKind,
+        full_span: Span,
+        container: Option<SymbolId>,
+        scope: Span,
+    ) -> SymbolId {
+        let id = SymbolId(self.result.symbols.len());
+        self.result.symbols.push(Symbol {
+            name: name.name.clone(),
+            kind,
+            span: name.span,
+            full_span,
+            container,
+            scope,
+        });
+        if !scope.is_empty() {
+            self.bind(name.name.clone(), Some(id), None);
+        }
+        id
+    }
+
+    fn bind(&mut self, name: String, symbol: Option<SymbolId>, via: Option<usize>) {
+        if let Some(current) = self.scopes.last_mut() {
+            current.push(Binding { name, symbol, via });
+        }
+    }
+
+    /// The innermost definition of `name` of an accepted kind. A name
+    /// from outside the file is of any kind; it resolves to `None`. The
+    /// `use` the name was brought by is marked as used.
+    fn lookup(&mut self, name: &str, accept: fn(SymbolKind) -> bool) -> Option<SymbolId> {
+        let symbols = &self.result.symbols;
+        let binding = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|b| b.name == name && b.symbol.is_none_or(|id| accept(symbols[id.0].kind)))?;
+        if let Some(via) = binding.via {
+            self.result.uses[via].used = true;
+        }
+        binding.symbol
+    }
+
+    fn reference(&mut self, name: &str, span: Span, accept: fn(SymbolKind) -> bool) -> bool {
+        match self.lookup(name, accept) {
+            Some(symbol) => {
+                self.result.references.push(Reference { span, symbol });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resolve the items of a block. Items that are visible in the whole
+    /// block are declared first; members of a namespace body get
+    /// `container`.
+    fn block(&mut self, items: &[Expr], span: Span, container: Option<SymbolId>) {
+        self.scopes.push(Vec::new());
+        let mut declared_ids = Vec::new();
+        for item in items {
+            let id = match &item.kind {
+                ExprKind::LetFunction(f) => {
+                    Some(self.declare(&f.name, SymbolKind::Function, f.span, container, span))
+                }
+                ExprKind::Let(l) if l.is_hoist => {
+                    Some(self.declare(&l.name, SymbolKind::Variable, l.span, container, span))
+                }
+                ExprKind::EnumDef(e) => {
+                    let id = self.declare(&e.name, SymbolKind::Enum, e.span, container, span);
+                    for v in &e.variants.items {
+                        let variant =
+                            self.declare(&v.name, SymbolKind::EnumVariant, v.span, Some(id), span);
+                        if v.payload_types.is_empty() {
+                            self.unit_variants.push(variant);
+                        }
+                    }
+                    Some(id)
+                }
+                ExprKind::StructDef(s) => {
+                    let id = self.declare(&s.name, SymbolKind::Struct, s.span, container, span);
+                    for f in &s.fields.items {
+                        let empty = Span::empty(f.span.file_id, f.span.start);
+                        self.declare(&f.name, SymbolKind::StructField, f.span, Some(id), empty);
+                    }
+                    Some(id)
+                }
+                ExprKind::Namespace(n) => {
+                    Some(self.declare(&n.name, SymbolKind::Namespace, n.span, container, span))
+                }
+                _ => None,
+            };
+            declared_ids.push(id);
+        }
+
+        for (item, declared) in items.iter().zip(declared_ids) {
+            let rest = Span::new(span.file_id, item.span.end, span.end);
+            match &item.kind {
+                ExprKind::Let(l) => {
+                    if let Some(ty) = &l.ty {
+                        self.type_expr(ty);
+                    }
+                    self.expr(&l.value);
+                    if !l.is_hoist && l.name.name != "_" {
+                        self.declare(&l.name, SymbolKind::Variable, l.span, container, rest);
+                    }
+                }
+                ExprKind::EnumDef(e) => {
+                    self.with_type_params(&e.type_params, e.span, |this| {
+                        for v in &e.variants.items {
+                            v.payload_types.iter().for_each(|t| this.type_expr(t));
+                        }
+                    });
+                }
+                ExprKind::StructDef(s) => {
+                    self.with_type_params(&s.type_params, s.span, |this| {
+                        s.fields.items.iter().for_each(|f| this.type_expr(&f.ty));
+                    });
+                }
+                ExprKind::Namespace(n) => match &n.body.kind {
+                    ExprKind::Block(body) => self.block(&body.expressions, n.body.span, declared),
+                    _ => self.expr(&n.body),
+                },
+                ExprKind::Use(u) => self.use_expr(u, rest),
+                ExprKind::LetFunction(f) => self.expr(&f.value),
+                _ => self.expr(item),
+            }
+        }
+        self.scopes.pop();
+    }
+
+    fn with_type_params(&mut self, params: &[ast::Ident], span: Span, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Vec::new());
+        for p in params {
+            self.declare(p, SymbolKind::TypeParam, p.span, None, span);
+        }
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// `use a::b [as c]` and `use a::*`. Only namespaces of this file are
+    /// followed; anything else is left to the module system, and a name
+    /// it brings is in scope without a definition.
+    fn use_expr(&mut self, u: &ast::UseExpr, scope: Span) {
+        let (segments, glob) = match &u.path {
+            ast::Path::Simple { segments } => (segments, false),
+            ast::Path::Glob { segments } => (segments, true),
+        };
+        let Some((first, rest)) = segments.split_first() else {
+            return;
+        };
+        let mut target = self.lookup(first, |k| k == SymbolKind::Namespace);
+        for segment in rest {
+            let Some(container) = target else { break };
+            target = self
+                .result
+                .symbols
+                .iter()
+                .position(|s| s.container == Some(container) && &s.name == segment)
+                .map(SymbolId);
+        }
+
+        if !glob {
+            let (name, span) = match &u.alias {
+                Some(alias) => (alias.name.clone(), alias.span),
+                None => (segments.last().unwrap().clone(), u.span),
+            };
+            if let (Some(alias), Some(symbol)) = (&u.alias, target) {
+                self.result.references.push(Reference {
+                    span: alias.span,
+                    symbol,
+                });
+            }
+            let via = self.result.uses.len();
+            self.result.uses.push(UseBinding {
+                name: name.clone(),
+                span,
+                used: false,
+            });
+            self.bind(name.clone(), target, Some(via));
+            if let Some(target) = target {
+                self.result.aliases.push(Alias {
+                    name,
+                    target,
+                    scope,
+                });
+            }
+        } else if let Some(target) = target {
+            let members: Vec<(String, SymbolId)> = self
+                .result
+                .symbols
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.container == Some(target) && !s.scope.is_empty())
+                .map(|(i, s)| (s.name.clone(), SymbolId(i)))
+                .collect();
+            for (name, id) in members {
+                self.bind(name.clone(), Some(id), None);
+                self.result.aliases.push(Alias {
+                    name,
+                    target: id,
+                    scope,
+                });
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Ident(ident) => {
+                self.reference(&ident.name, ident.span, SymbolKind::is_value);
+            }
+            ExprKind::Block(block) => self.block(&block.expressions, expr.span, None),
+            ExprKind::FuncLiteral(lit) => {
+                self.scopes.push(Vec::new());
+                for p in &lit.type_params {
+                    self.declare(p, SymbolKind::TypeParam, p.span, None, lit.span);
+                }
+                for p in &lit.params {
+                    self.type_expr(&p.ty);
+                }
+                self.type_expr(&lit.result);
+                for p in &lit.params {
+                    self.declare(&p.name, SymbolKind::Parameter, p.span, None, lit.span);
+                }
+                self.expr(&lit.body);
+                self.scopes.pop();
+            }
+            ExprKind::Match(m) => {
+                self.expr(&m.scrutinee);
+                for case in &m.cases.items {
+                    self.scopes.push(Vec::new());
+                    self.pattern(&case.pattern, case.span);
+                    self.expr(&case.body);
+                    self.scopes.pop();
+                }
+            }
+            ExprKind::Set(set) => {
+                self.expr(&set.target.base);
+                self.expr(&set.value);
+            }
+            // Items outside a block form a block of their own.
+            ExprKind::Let(_)
+            | ExprKind::LetFunction(_)
+            | ExprKind::EnumDef(_)
+            | ExprKind::StructDef(_)
+            | ExprKind::Namespace(_)
+            | ExprKind::Use(_) => self.block(core::slice::from_ref(expr), expr.span, None),
+            ExprKind::Seq(items) => items.iter().for_each(|e| self.expr(e)),
+            ExprKind::Paren(e) | ExprKind::Loop(ast::LoopExpr { body: e }) => self.expr(e),
+            ExprKind::Pipe(chain) => {
+                self.expr(&chain.first);
+                chain.rest.iter().for_each(|e| self.expr(e));
+            }
+            ExprKind::If(e) => {
+                for branch in core::iter::once(&e.if_branch).chain(&e.elseif_branches) {
+                    self.expr(&branch.condition);
+                    self.expr(&branch.body);
+                }
+                self.expr(&e.else_branch);
+            }
+            ExprKind::While(ast::WhileExpr { condition, body })
+            | ExprKind::When(ast::WhenExpr {
+                condition, body, ..
+            }) => {
+                self.expr(condition);
+                self.expr(body);
+            }
+            ExprKind::Test(test) => {
+                self.scopes.push(Vec::new());
+                self.expr(&test.body);
+                self.scopes.pop();
+            }
+            ExprKind::Return(ast::ReturnExpr { value, .. })
+            | ExprKind::Break(ast::BreakExpr { value, .. }) => {
+                value.iter().for_each(|e| self.expr(e))
+            }
+            ExprKind::Literal(_)
+            | ExprKind::Include(_)
+            | ExprKind::Import(_)
+            | ExprKind::Continue(_) => {}
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, scope: Span) {
+        match pattern {
+            Pattern::Ident(name, span) => {
+                // A name of a payload-less variant is matched, not bound.
+                let variant = self
+                    .lookup(name, |k| k == SymbolKind::EnumVariant)
+                    .filter(|id| self.unit_variants.contains(id));
+                if let Some(symbol) = variant {
+                    self.result.references.push(Reference {
+                        span: *span,
+                        symbol,
+                    });
+                } else {
+                    let ident = ast::Ident {
+                        name: name.clone(),
+                        span: *span,
+                    };
+                    if name != "_" {
+                        self.declare(&ident, SymbolKind::Variable, *span, None, scope);
+                    }
+                }
+            }
+            Pattern::Enum(p) => {
+                let span = name_span(p.span, &p.ctor);
+                self.reference(&p.ctor, span, |k| k == SymbolKind::EnumVariant);
+                p.args.iter().for_each(|a| self.pattern(a, scope));
+            }
+            Pattern::Struct(p) => {
+                let span = name_span(p.span, &p.ctor);
+                self.reference(&p.ctor, span, |k| k == SymbolKind::Struct);
+                p.fields
+                    .iter()
+                    .for_each(|f| self.pattern(&f.pattern, scope));
+            }
+            Pattern::Literal(e) => self.expr(e),
+            Pattern::Wildcard(_) => {}
+        }
+    }
+
+    fn type_expr(&mut self, ty: &TypeExpr) {
+        match &ty.kind {
+            TypeExprKind::Named(name) => {
+                self.reference(name, ty.span, SymbolKind::is_type);
+            }
+            TypeExprKind::Apply { name, args } => {
+                self.reference(name, name_span(ty.span, name), SymbolKind::is_type);
+                args.iter().for_each(|a| self.type_expr(a));
+            }
+            TypeExprKind::Fn { params, result, .. } => {
+                params.iter().for_each(|p| self.type_expr(p));
+                self.type_expr(result);
+            }
+            TypeExprKind::Paren(inner) => self.type_expr(inner),
+        }
+    }
+}
+
+/// The span of `name` at the start of `span`.
+fn name_span(span: Span, name: &str) -> Span {
+    Span::new(span.file_id, span.start, span.start + name.len() as u32)
 }
@@ -22,13 +22,17 @@
 use alloc::borrow::ToOwned;
 use alloc::boxed::Box;
 use alloc::string::{String, ToString};
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::num::IntErrorKind;
 
 use crate::ast::*;
-use crate::diagnostic::Diagnostic;
-use crate::lexer::{lex, LexResult, Token, TokenKind};
+use crate::cst::{self, Event, SyntaxKind, SyntaxNode};
+use crate::diagnostic::{Diagnostic, MessageKey};
+use crate::lexer::{lex, number_parts, unescape, LexResult, Token, TokenKind};
 use crate::span::{FileId, Span};
+use crate::types::{ArrowKind, Type};
 
 /// Result of parsing a single NEPL source file.
 #[derive(Debug)]
@@ -72,12 +76,69 @@ pub fn parse_tokens(source: &str, lex: &LexResult) -> ParseResult {
     ParseResult { expr, diagnostics }
 }
 
+/// Result of parsing a file into a concrete syntax tree.
+#[derive(Debug)]
+pub struct CstParseResult {
+    /// The lossless tree; its text is exactly the source.
+    pub tree: SyntaxNode,
+    /// The AST built by the same parse.
+    pub expr: Option<Expr>,
+    /// All diagnostics produced during lexing **and** parsing.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Lex and parse a file, building a lossless concrete syntax tree
+/// (see `cst.rs`) next to the AST.
+pub fn parse_cst(file_id: FileId, source: &str) -> CstParseResult {
+    let LexResult {
+        tokens,
+        diagnostics: lex_diags,
+    } = lex(file_id, source);
+
+    let mut parser = Parser::new(source, &tokens);
+    parser.events = Some(Vec::new());
+    let expr = parser.parse_root_expr();
+    let events = parser.events.take().unwrap_or_default();
+    let mut diagnostics = lex_diags;
+    diagnostics.extend(parser.into_diagnostics());
+
+    let green = cst::build(source, &tokens, &events);
+    let tree = SyntaxNode::new_root(file_id, Arc::new(green));
+    CstParseResult {
+        tree,
+        expr,
+        diagnostics,
+    }
+}
+
+/// Derive the AST from a concrete syntax tree, for example one that a
+/// tool has edited. Only parser diagnostics are returned; text that
+/// did not lex is skipped.
+pub fn lower_cst(tree: &SyntaxNode) -> ParseResult {
+    let source = tree.text();
+    let tokens = cst::lexer_tokens(tree);
+    let mut parser = Parser::new(&source, &tokens);
+    let expr = parser.parse_root_expr();
+    ParseResult {
+        expr,
+        diagnostics: parser.into_diagnostics(),
+    }
+}
+
 /// Internal recursive-descent parser.
 struct Parser<'src> {
     source: &'src str,
     tokens: &'src [Token],
     pos: usize,
     diagnostics: Vec<Diagnostic>,
+    /// Set while parsing a `while` condition or `match` scrutinee, where
+    /// a following `{` or `:` starts the body rather than a block term.
+    no_scope_atom: bool,
+    /// What the parser did, when it builds a concrete syntax tree.
+    events: Option<Vec<Event>>,
+    /// Span and code of the last error, which the rules enclosing the
+    /// failed one may run into again while they recover.
+    last_error: Option<(Span, Option<&'static str>)>,
 }
 
 impl<'src> Parser<'src> {
@@ -87,6 +148,9 @@ impl<'src> Parser<'src> {
             tokens,
             pos: 0,
             diagnostics: Vec::new(),
+            no_scope_atom: false,
+            events: None,
+            last_error: None,
         }
     }
 
@@ -106,14 +170,20 @@ impl<'src> Parser<'src> {
             .unwrap_or_else(|| self.tokens.last().expect("lexer always emits at least EOF"))
     }
 
+    /// Span of the last consumed token.
+    fn previous_span(&self) -> Span {
+        let index = self.pos.saturating_sub(1);
+        self.tokens.get(index).map(|t| t.span).unwrap_or(self.current().span)
+    }
+
     fn peek_kind(&self) -> TokenKind {
-        self.current().kind.clone()
+        self.current().kind
     }
 
     fn nth_kind(&self, n: usize) -> TokenKind {
         self.tokens
             .get(self.pos + n)
-            .map(|t| t.kind.clone())
+            .map(|t| t.kind)
             .unwrap_or(TokenKind::Eof)
     }
 
@@ -121,6 +191,9 @@ impl<'src> Parser<'src> {
         let tok = self.current().clone();
         if self.pos < self.tokens.len() - 1 {
             self.pos += 1;
+            if let Some(events) = &mut self.events {
+                events.push(Event::Token);
+            }
         }
         tok
     }
@@ -133,20 +206,29 @@ impl<'src> Parser<'src> {
         }
     }
 
-    fn expect(&mut self, expected: TokenKind, msg: &str) -> Option<Token> {
+    /// Consume a token of kind `expected`, or report the catalog
+    /// message `key` (an `E0100` variant).
+    fn expect(&mut self, expected: TokenKind, key: &'static str) -> Option<Token> {
         let actual = self.peek_kind();
         if actual == expected {
             Some(self.advance())
         } else {
             let span = self.current().span;
-            let diag = Diagnostic::error(msg, span);
-            self.diagnostics.push(diag);
+            self.error(key, span);
             None
         }
     }
 
-    fn error(&mut self, span: Span, msg: &str) {
-        let diag = Diagnostic::error(msg.to_string(), span);
+    /// Report the catalog message `key` at `span`. An error with the
+    /// span and code of the previous one is the same failure seen again
+    /// by an enclosing rule while it recovers, and is dropped.
+    fn error(&mut self, key: &'static str, span: Span) {
+        let diag = Diagnostic::error_key(key, span);
+        let this = Some((span, diag.code));
+        if self.last_error == this {
+            return;
+        }
+        self.last_error = this;
         self.diagnostics.push(diag);
     }
 
@@ -181,6 +263,59 @@ impl<'src> Parser<'src> {
         }
     }
 
+    // === concrete syntax tree ================================================
+
+    /// Where a node may start; pass it to [`Self::close_node`] once the
+    /// node is parsed.
+    fn checkpoint(&self) -> usize {
+        self.events.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Wrap everything parsed since `checkpoint` in a node of `kind`,
+    /// unless no token was consumed or it already is such a node.
+    fn close_node(&mut self, checkpoint: usize, kind: SyntaxKind) {
+        let Some(events) = &mut self.events else {
+            return;
+        };
+        if !events[checkpoint..].contains(&Event::Token) {
+            return;
+        }
+        if events[checkpoint] == Event::Start(kind) {
+            // Does that node end with the last event?
+            let mut depth = 0usize;
+            let mut end = checkpoint;
+            for (i, event) in events.iter().enumerate().skip(checkpoint) {
+                match event {
+                    Event::Start(_) => depth += 1,
+                    Event::Finish => depth -= 1,
+                    Event::Token => {}
+                }
+                if depth == 0 {
+                    end = i;
+                    break;
+                }
+            }
+            if end == events.len() - 1 {
+                return;
+            }
+        }
+        events.insert(checkpoint, Event::Start(kind));
+        events.push(Event::Finish);
+    }
+
+    /// [`Self::close_node`] for a parse that may have failed, in which
+    /// case the node is an `Error` node.
+    fn close_result<T>(
+        &mut self,
+        checkpoint: usize,
+        result: Option<T>,
+        kind: SyntaxKind,
+    ) -> Option<T> {
+        let kind = if result.is_some() { kind } else { SyntaxKind::Error };
+        self.close_node(checkpoint, kind);
+        result
+    }
+
     // === roots ================================================================
 
     /// Parse the whole file as a single `<expr>`, then ensure that
@@ -198,7 +333,7 @@ impl<'src> Parser<'src> {
 
         if !self.is_eof() {
             let span = self.current().span;
-            self.error(span, "extra tokens after top-level expression");
+            self.error("E0101", span);
         }
 
         Some(expr)
@@ -213,6 +348,7 @@ impl<'src> Parser<'src> {
 
     /// `lhs > rhs1 > rhs2 > ...`
     fn parse_pipe_chain(&mut self) -> Option<Expr> {
+        let checkpoint = self.checkpoint();
         let first = self.parse_seq_expr()?;
         let mut rest = Vec::new();
 
@@ -222,7 +358,7 @@ impl<'src> Parser<'src> {
                 rest.push(rhs);
             } else {
                 // error already reported by inner parser; try to resync
-                self.error(op_tok.span, "expected expression after '>'");
+                self.error("E0100.expression-after-gt", op_tok.span);
                 self.sync_to_boundary();
                 break;
             }
@@ -231,6 +367,7 @@ impl<'src> Parser<'src> {
         if rest.is_empty() {
             Some(first)
         } else {
+            self.close_node(checkpoint, SyntaxKind::Pipe);
             let span = self
                 .merge_spans(first.span, rest.last().map(|e| e.span).unwrap_or(first.span));
             Some(Expr {
@@ -252,12 +389,17 @@ impl<'src> Parser<'src> {
     /// `f x (g y)` becomes:
     ///   `Seq([Ident(f), Ident(x), Paren(Seq([...]))])`
     fn parse_seq_expr(&mut self) -> Option<Expr> {
+        let checkpoint = self.checkpoint();
         let mut items = Vec::new();
 
         let first = self.parse_atom_expr()?;
         items.push(first);
 
         while self.can_start_atom() && !self.is_seq_terminator() {
+            if self.no_scope_atom && matches!(self.peek_kind(), TokenKind::LBrace | TokenKind::Colon)
+            {
+                break;
+            }
             if let Some(expr) = self.parse_atom_expr() {
                 items.push(expr);
             } else {
@@ -268,6 +410,7 @@ impl<'src> Parser<'src> {
         if items.len() == 1 {
             Some(items.pop().unwrap())
         } else {
+            self.close_node(checkpoint, SyntaxKind::Seq);
             let first_span = items.first().unwrap().span;
             let last_span = items.last().unwrap().span;
             let span = self.merge_spans(first_span, last_span);
@@ -279,42 +422,53 @@ impl<'src> Parser<'src> {
     }
 
     fn can_start_atom(&self) -> bool {
-        match self.peek_kind() {
+        !matches!(
+            self.peek_kind(),
             TokenKind::Eof
-            | TokenKind::RParen
-            | TokenKind::RBrace
-            | TokenKind::RBracket
-            | TokenKind::Semi
-            | TokenKind::Comma
-            | TokenKind::Then
-            | TokenKind::Else
-            | TokenKind::Case
-            | TokenKind::FatArrow
-            | TokenKind::Greater => false,
-            _ => true,
-        }
+                | TokenKind::RParen
+                | TokenKind::RBrace
+                | TokenKind::RBracket
+                | TokenKind::Semi
+                | TokenKind::Comma
+                | TokenKind::Then
+                | TokenKind::Else
+                | TokenKind::Case
+                | TokenKind::FatArrow
+                | TokenKind::Greater
+        )
     }
 
     fn is_seq_terminator(&self) -> bool {
-        match self.peek_kind() {
+        matches!(
+            self.peek_kind(),
             TokenKind::Eof
-            | TokenKind::RParen
-            | TokenKind::RBrace
-            | TokenKind::RBracket
-            | TokenKind::Semi
-            | TokenKind::Comma
-            | TokenKind::Then
-            | TokenKind::Else
-            | TokenKind::Case
-            | TokenKind::FatArrow
-            | TokenKind::Greater => true,
-            _ => false,
-        }
+                | TokenKind::RParen
+                | TokenKind::RBrace
+                | TokenKind::RBracket
+                | TokenKind::Semi
+                | TokenKind::Comma
+                | TokenKind::Then
+                | TokenKind::Else
+                | TokenKind::Case
+                | TokenKind::FatArrow
+                | TokenKind::Greater
+        )
     }
 
     /// `<atom_expr>`: any expression form that can be a single term in
     /// a P-style sequence.
     fn parse_atom_expr(&mut self) -> Option<Expr> {
+        let checkpoint = self.checkpoint();
+        let result = self.parse_atom_expr_inner();
+        let kind = match &result {
+            Some(expr) => SyntaxKind::of_expr(&expr.kind),
+            None => SyntaxKind::Error,
+        };
+        self.close_node(checkpoint, kind);
+        result
+    }
+
+    fn parse_atom_expr_inner(&mut self) -> Option<Expr> {
         match self.peek_kind() {
             TokenKind::If => self.parse_if_expr(),
             TokenKind::Loop => self.parse_loop_expr(),
@@ -324,11 +478,13 @@ impl<'src> Parser<'src> {
 
             TokenKind::Let => self.parse_let_expr(),
             TokenKind::Fn => self.parse_let_function_expr(),
+            TokenKind::Bar | TokenKind::Less => self.parse_func_literal_expr(),
             TokenKind::Include => self.parse_include_expr(),
             TokenKind::Import => self.parse_import_expr(),
             TokenKind::Namespace | TokenKind::Pub => self.parse_namespace_or_use_or_type_def(),
             TokenKind::Use => self.parse_use_expr(),
             TokenKind::When => self.parse_when_expr(),
+            TokenKind::Test => self.parse_test_expr(),
 
             TokenKind::Return => self.parse_return_expr(),
             TokenKind::Break => self.parse_break_expr(),
@@ -347,7 +503,7 @@ impl<'src> Parser<'src> {
             // Anything else is unexpected here.
             _ => {
                 let span = self.current().span;
-                self.error(span, "expected expression");
+                self.error("E0100.expression", span);
                 None
             }
         }
@@ -359,23 +515,23 @@ impl<'src> Parser<'src> {
         let tok = self.advance();
         let text = self.slice_token_text(&tok);
         let kind = match tok.kind {
-            TokenKind::IntLiteral => LiteralKind::Int(text),
-            TokenKind::FloatLiteral => LiteralKind::Float(text),
-            TokenKind::StringLiteral => LiteralKind::String(text),
+            TokenKind::IntLiteral => LiteralKind::Int(int_literal(&text)),
+            TokenKind::FloatLiteral => LiteralKind::Float(float_literal(&text)),
+            TokenKind::StringLiteral => LiteralKind::String(self.string_value(&tok)),
             TokenKind::BoolLiteral => {
                 let v = match text.as_str() {
                     "true" => true,
                     "false" => false,
                     _ => {
                         // lexing should ensure only "true"/"false"
-                        self.error(tok.span, "invalid bool literal");
+                        self.error("E0102.bool", tok.span);
                         true
                     }
                 };
                 LiteralKind::Bool(v)
             }
             _ => {
-                self.error(tok.span, "internal error: non-literal token in parse_literal_expr");
+                self.error("E0900.parser-literal", tok.span);
                 return None;
             }
         };
@@ -387,7 +543,7 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_ident_expr(&mut self) -> Option<Expr> {
-        let tok = self.expect(TokenKind::Ident, "expected identifier")?;
+        let tok = self.expect(TokenKind::Ident, "E0100.identifier")?;
         let name = self.slice_token_text(&tok);
         let ident = Ident {
             name,
@@ -400,16 +556,16 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_paren_expr(&mut self) -> Option<Expr> {
-        let l = self.expect(TokenKind::LParen, "expected '('")?;
-        let expr = match self.parse_expr() {
+        let l = self.expect(TokenKind::LParen, "E0100.lparen")?;
+        let expr = match self.with_scope_atoms(|p| p.parse_expr()) {
             Some(e) => e,
             None => {
-                self.error(l.span, "expected expression inside parentheses");
+                self.error("E0100.expression-inside-parentheses", l.span);
                 self.sync_to_boundary();
                 return None;
             }
         };
-        let r = self.expect(TokenKind::RParen, "expected ')'")?;
+        let r = self.expect(TokenKind::RParen, "E0100.rparen")?;
         let span = self.merge_spans(l.span, r.span);
         Some(Expr {
             kind: ExprKind::Paren(Box::new(expr)),
@@ -418,7 +574,14 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_block_expr(&mut self) -> Option<Expr> {
-        let lbrace = self.expect(TokenKind::LBrace, "expected '{'")?;
+        // Blocks are also bodies, which are not atoms.
+        let checkpoint = self.checkpoint();
+        let result = self.with_scope_atoms(|p| p.parse_block_expr_inner());
+        self.close_result(checkpoint, result, SyntaxKind::Block)
+    }
+
+    fn parse_block_expr_inner(&mut self) -> Option<Expr> {
+        let lbrace = self.expect(TokenKind::LBrace, "E0100.lbrace")?;
         let mut exprs = Vec::new();
 
         while !self.is_eof() && self.peek_kind() != TokenKind::RBrace {
@@ -427,6 +590,7 @@ impl<'src> Parser<'src> {
                 continue;
             }
 
+            let start = self.pos;
             if let Some(e) = self.parse_expr() {
                 exprs.push(e);
                 // optional semicolon after each expression
@@ -436,10 +600,14 @@ impl<'src> Parser<'src> {
                 if self.peek_kind() == TokenKind::RBrace {
                     break;
                 }
+                if self.pos == start {
+                    // e.g. a stray `case`: skip it so that we make progress
+                    self.advance();
+                }
             }
         }
 
-        let rbrace = self.expect(TokenKind::RBrace, "expected '}' to close block")?;
+        let rbrace = self.expect(TokenKind::RBrace, "E0100.rbrace-to-close-block")?;
         let span = self.merge_spans(lbrace.span, rbrace.span);
         Some(Expr {
             kind: ExprKind::Block(BlockExpr { expressions: exprs }),
@@ -450,18 +618,18 @@ impl<'src> Parser<'src> {
     // === control flow: if / loop / while / match ============================
 
     fn parse_if_expr(&mut self) -> Option<Expr> {
-        let if_tok = self.expect(TokenKind::If, "expected 'if'")?;
+        let if_tok = self.expect(TokenKind::If, "E0100.if")?;
         let cond = self.parse_expr().unwrap_or_else(|| {
-            self.error(if_tok.span, "expected condition after 'if'");
+            self.error("E0100.condition-after-if", if_tok.span);
             Expr {
                 kind: ExprKind::Literal(LiteralKind::Bool(true)),
                 span: if_tok.span,
             }
         });
 
-        let _then = self.expect(TokenKind::Then, "expected 'then' after if condition")?;
+        let _then = self.expect(TokenKind::Then, "E0100.then-after-if-condition")?;
         let then_body = self.parse_scoped_expr().unwrap_or_else(|| {
-            self.error(cond.span, "expected body after 'then'");
+            self.error("E0100.body-after-then", cond.span);
             Expr {
                 kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                 span: cond.span,
@@ -478,15 +646,15 @@ impl<'src> Parser<'src> {
         while self.peek_kind() == TokenKind::ElseIf {
             let elseif_tok = self.advance();
             let cond = self.parse_expr().unwrap_or_else(|| {
-                self.error(elseif_tok.span, "expected condition after 'elseif'");
+                self.error("E0100.condition-after-elseif", elseif_tok.span);
                 Expr {
                     kind: ExprKind::Literal(LiteralKind::Bool(true)),
                     span: elseif_tok.span,
                 }
             });
-            let _then = self.expect(TokenKind::Then, "expected 'then' after elseif condition")?;
+            let _then = self.expect(TokenKind::Then, "E0100.then-after-elseif-condition")?;
             let body = self.parse_scoped_expr().unwrap_or_else(|| {
-                self.error(cond.span, "expected body after 'then'");
+                self.error("E0100.body-after-then", cond.span);
                 Expr {
                     kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                     span: cond.span,
@@ -499,9 +667,9 @@ impl<'src> Parser<'src> {
         }
 
         // optional `else`, required by the spec
-        let else_tok = self.expect(TokenKind::Else, "expected 'else' in if-expression")?;
+        let else_tok = self.expect(TokenKind::Else, "E0100.else-in-if-expression")?;
         let else_body = self.parse_scoped_expr().unwrap_or_else(|| {
-            self.error(else_tok.span, "expected else-body");
+            self.error("E0100.else-body", else_tok.span);
             Expr {
                 kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                 span: else_tok.span,
@@ -520,9 +688,9 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_loop_expr(&mut self) -> Option<Expr> {
-        let loop_tok = self.expect(TokenKind::Loop, "expected 'loop'")?;
+        let loop_tok = self.expect(TokenKind::Loop, "E0100.loop")?;
         let body = self.parse_scoped_expr().unwrap_or_else(|| {
-            self.error(loop_tok.span, "expected body after 'loop'");
+            self.error("E0100.body-after-loop", loop_tok.span);
             Expr {
                 kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                 span: loop_tok.span,
@@ -538,16 +706,16 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_while_expr(&mut self) -> Option<Expr> {
-        let while_tok = self.expect(TokenKind::While, "expected 'while'")?;
-        let cond = self.parse_expr().unwrap_or_else(|| {
-            self.error(while_tok.span, "expected condition after 'while'");
+        let while_tok = self.expect(TokenKind::While, "E0100.while")?;
+        let cond = self.without_scope_atoms(|p| p.parse_expr()).unwrap_or_else(|| {
+            self.error("E0100.condition-after-while", while_tok.span);
             Expr {
                 kind: ExprKind::Literal(LiteralKind::Bool(true)),
                 span: while_tok.span,
             }
         });
         let body = self.parse_scoped_expr().unwrap_or_else(|| {
-            self.error(cond.span, "expected body after while condition");
+            self.error("E0100.body-after-while-condition", cond.span);
             Expr {
                 kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                 span: cond.span,
@@ -564,9 +732,9 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_match_expr(&mut self) -> Option<Expr> {
-        let match_tok = self.expect(TokenKind::Match, "expected 'match'")?;
-        let scrutinee = self.parse_expr().unwrap_or_else(|| {
-            self.error(match_tok.span, "expected scrutinee after 'match'");
+        let match_tok = self.expect(TokenKind::Match, "E0100.match")?;
+        let scrutinee = self.without_scope_atoms(|p| p.parse_expr()).unwrap_or_else(|| {
+            self.error("E0100.scrutinee-after-match", match_tok.span);
             Expr {
                 kind: ExprKind::Literal(LiteralKind::Bool(true)),
                 span: match_tok.span,
@@ -586,14 +754,32 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_match_case(&mut self) -> Option<MatchCase> {
-        let case_tok = self.expect(TokenKind::Case, "expected 'case'")?;
+        let checkpoint = self.checkpoint();
+        let result = self.parse_match_case_inner();
+        self.close_result(checkpoint, result, SyntaxKind::MatchCase)
+    }
+
+    fn parse_match_case_inner(&mut self) -> Option<MatchCase> {
+        let case_tok = self.expect(TokenKind::Case, "E0100.case")?;
         let pattern = self.parse_pattern()?;
-        let arrow = self.expect(TokenKind::FatArrow, "expected '=>' after pattern")?;
+        let arrow = match self.consume_if(TokenKind::FatArrow) {
+            Some(arrow) => arrow.span,
+            None => {
+                // `case p body`: report the missing arrow and keep parsing
+                // as if it were there.
+                let end = self.previous_span();
+                let at = Span::empty(end.file_id, end.end);
+                let diag = Diagnostic::error_key("E0100.fat-arrow-after-pattern", self.current().span)
+                    .with_suggestion_key(MessageKey::new("suggest.match-arrow"), at, " =>");
+                self.diagnostics.push(diag);
+                at
+            }
+        };
         let body = self.parse_expr().unwrap_or_else(|| {
-            self.error(arrow.span, "expected expression after '=>'");
+            self.error("E0100.expression-after-fat-arrow", arrow);
             Expr {
                 kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
-                span: arrow.span,
+                span: arrow,
             }
         });
         let span = self.merge_spans(case_tok.span, body.span);
@@ -619,7 +805,7 @@ impl<'src> Parser<'src> {
                 // For now, treat `: expr` as "expr in a block" with
                 // Offside scope. We don't yet track indentation.
                 let expr = self.parse_expr().unwrap_or_else(|| {
-                    self.error(colon.span, "expected expression after ':'");
+                    self.error("E0100.expression-after-colon", colon.span);
                     Expr {
                         kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                         span: colon.span,
@@ -652,7 +838,7 @@ impl<'src> Parser<'src> {
             TokenKind::Colon => self.parse_scoped_list_offside(&mut parse_item),
             _ => {
                 let span = self.current().span;
-                self.error(span, "expected '{' or ':' to start scoped list");
+                self.error("E0100.lbrace-or-colon-to-start-scoped-list", span);
                 None
             }
         }
@@ -662,7 +848,7 @@ impl<'src> Parser<'src> {
         &mut self,
         parse_item: &mut impl FnMut(&mut Parser<'src>) -> Option<T>,
     ) -> Option<ScopedList<T>> {
-        let lbrace = self.expect(TokenKind::LBrace, "expected '{'")?;
+        let lbrace = self.expect(TokenKind::LBrace, "E0100.lbrace")?;
         let mut items = Vec::new();
 
         while !self.is_eof() && self.peek_kind() != TokenKind::RBrace {
@@ -670,6 +856,7 @@ impl<'src> Parser<'src> {
                 self.advance();
                 continue;
             }
+            let start = self.pos;
             if let Some(item) = parse_item(self) {
                 items.push(item);
                 // optional semicolon after each item
@@ -679,10 +866,13 @@ impl<'src> Parser<'src> {
                 if self.peek_kind() == TokenKind::RBrace {
                     break;
                 }
+                if self.pos == start {
+                    self.advance();
+                }
             }
         }
 
-        let rbrace = self.expect(TokenKind::RBrace, "expected '}'")?;
+        let rbrace = self.expect(TokenKind::RBrace, "E0100.rbrace")?;
         let span = self.merge_spans(lbrace.span, rbrace.span);
 
         Some(ScopedList {
@@ -696,7 +886,7 @@ impl<'src> Parser<'src> {
         &mut self,
         parse_item: &mut impl FnMut(&mut Parser<'src>) -> Option<T>,
     ) -> Option<ScopedList<T>> {
-        let colon = self.expect(TokenKind::Colon, "expected ':'")?;
+        let colon = self.expect(TokenKind::Colon, "E0100.colon")?;
         let mut items = Vec::new();
 
         // Very simple offside-style parsing:
@@ -740,7 +930,7 @@ impl<'src> Parser<'src> {
     // === let / fn / include / import / namespace / use / when ================
 
     fn parse_let_expr(&mut self) -> Option<Expr> {
-        let let_tok = self.expect(TokenKind::Let, "expected 'let'")?;
+        let let_tok = self.expect(TokenKind::Let, "E0100.let")?;
 
         let mut is_mut = false;
         let mut is_hoist = false;
@@ -754,24 +944,32 @@ impl<'src> Parser<'src> {
             is_hoist = true;
         }
 
-        let name_tok = self.expect(TokenKind::Ident, "expected identifier after 'let'")?;
+        let name_tok = self.expect(TokenKind::Ident, "E0100.identifier-after-let")?;
         let name = self.slice_token_text(&name_tok);
         let ident = Ident {
             name,
             span: name_tok.span,
         };
 
+        // Optional annotation: `let x: T = expr`
+        let ty = if self.peek_kind() == TokenKind::Colon {
+            self.advance();
+            Some(self.parse_type_expr()?)
+        } else {
+            None
+        };
+
         let value = if self.peek_kind() == TokenKind::Equal {
             self.advance();
             self.parse_expr().unwrap_or_else(|| {
-                self.error(name_tok.span, "expected expression after '='");
+                self.error("E0100.expression-after-eq", name_tok.span);
                 Expr {
                     kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                     span: name_tok.span,
                 }
             })
         } else {
-            self.error(name_tok.span, "expected '=' after let binding name");
+            self.error("E0100.eq-after-let-binding-name", name_tok.span);
             return None;
         };
 
@@ -782,6 +980,7 @@ impl<'src> Parser<'src> {
                 is_mut,
                 is_hoist,
                 name: ident,
+                ty,
                 value: Box::new(value),
                 span,
             }),
@@ -790,8 +989,8 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_let_function_expr(&mut self) -> Option<Expr> {
-        let fn_tok = self.expect(TokenKind::Fn, "expected 'fn'")?;
-        let name_tok = self.expect(TokenKind::Ident, "expected function name after 'fn'")?;
+        let fn_tok = self.expect(TokenKind::Fn, "E0100.fn")?;
+        let name_tok = self.expect(TokenKind::Ident, "E0100.function-name-after-fn")?;
         let name = self.slice_token_text(&name_tok);
         let ident = Ident {
             name,
@@ -801,14 +1000,14 @@ impl<'src> Parser<'src> {
         let value = if self.peek_kind() == TokenKind::Equal {
             self.advance();
             self.parse_expr().unwrap_or_else(|| {
-                self.error(name_tok.span, "expected function body after '='");
+                self.error("E0100.function-body-after-eq", name_tok.span);
                 Expr {
                     kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                     span: name_tok.span,
                 }
             })
         } else {
-            self.error(name_tok.span, "expected '=' after function name");
+            self.error("E0100.eq-after-function-name", name_tok.span);
             return None;
         };
 
@@ -824,10 +1023,74 @@ impl<'src> Parser<'src> {
         })
     }
 
+    /// `|T1 a, T2 mut b|->R body` / `|...|*>R body`
+    fn parse_func_literal_expr(&mut self) -> Option<Expr> {
+        let start = self.current().span;
+        let type_params = self.parse_type_params()?;
+        self.expect(TokenKind::Bar, "E0100.bar")?;
+
+        let mut params = Vec::new();
+        while !self.is_eof() && self.peek_kind() != TokenKind::Bar {
+            let checkpoint = self.checkpoint();
+            let ty = self.parse_type_expr()?;
+            let is_mut = self.consume_if(TokenKind::Mut).is_some();
+            let name_tok = self.expect(TokenKind::Ident, "E0100.parameter-name-after-type")?;
+            let name = self.slice_token_text(&name_tok);
+            self.close_node(checkpoint, SyntaxKind::Param);
+            let span = self.merge_spans(ty.span, name_tok.span);
+            params.push(FuncParam {
+                ty,
+                is_mut,
+                name: Ident {
+                    name,
+                    span: name_tok.span,
+                },
+                span,
+            });
+            // optional comma between parameters
+            self.consume_if(TokenKind::Comma);
+        }
+        self.expect(TokenKind::Bar, "E0100.bar-to-close-parameter-list")?;
+
+        let arrow = match self.peek_kind() {
+            TokenKind::Arrow => ArrowKind::Impure,
+            TokenKind::StarArrow => ArrowKind::Pure,
+            _ => {
+                let span = self.current().span;
+                self.error("E0100.arrow-or-pure-arrow-after-parameter-list", span);
+                return None;
+            }
+        };
+        let arrow_span = self.advance().span;
+
+        let result = self.parse_type_expr()?;
+        let body = self.parse_scoped_expr().unwrap_or_else(|| {
+            self.error("E0100.function-body", result.span);
+            Expr {
+                kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
+                span: result.span,
+            }
+        });
+
+        let span = self.merge_spans(start, body.span);
+        Some(Expr {
+            kind: ExprKind::FuncLiteral(FuncLiteralExpr {
+                type_params,
+                params,
+                arrow,
+                arrow_span,
+                result,
+                body: Box::new(body),
+                span,
+            }),
+            span,
+        })
+    }
+
     fn parse_include_expr(&mut self) -> Option<Expr> {
-        let inc_tok = self.expect(TokenKind::Include, "expected 'include'")?;
-        let path_tok = self.expect(TokenKind::StringLiteral, "expected string after 'include'")?;
-        let path = self.slice_token_text(&path_tok);
+        let inc_tok = self.expect(TokenKind::Include, "E0100.include")?;
+        let path_tok = self.expect(TokenKind::StringLiteral, "E0100.string-after-include")?;
+        let path = self.string_value(&path_tok);
 
         let span = self.merge_spans(inc_tok.span, path_tok.span);
         Some(Expr {
@@ -837,8 +1100,8 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_import_expr(&mut self) -> Option<Expr> {
-        let imp_tok = self.expect(TokenKind::Import, "expected 'import'")?;
-        let name_tok = self.expect(TokenKind::Ident, "expected identifier after 'import'")?;
+        let imp_tok = self.expect(TokenKind::Import, "E0100.import")?;
+        let name_tok = self.expect(TokenKind::Ident, "E0100.identifier-after-import")?;
         let name = self.slice_token_text(&name_tok);
         let ident = Ident {
             name,
@@ -867,7 +1130,7 @@ impl<'src> Parser<'src> {
                     self.parse_enum_or_struct_def_with_pub(pub_tok.span, true)
                 }
                 _ => {
-                    self.error(pub_tok.span, "expected 'namespace', 'use', 'enum' or 'struct' after 'pub'");
+                    self.error("E0100.namespace-use-enum-or-struct-after-pub", pub_tok.span);
                     None
                 }
             }
@@ -880,7 +1143,7 @@ impl<'src> Parser<'src> {
                 }
                 _ => {
                     let span = self.current().span;
-                    self.error(span, "expected 'namespace', 'use', 'enum', or 'struct'");
+                    self.error("E0100.namespace-use-enum-or-struct", span);
                     None
                 }
             }
@@ -888,15 +1151,15 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_namespace_expr_with_pub(&mut self, pub_span: Span, is_pub: bool) -> Option<Expr> {
-        let ns_tok = self.expect(TokenKind::Namespace, "expected 'namespace'")?;
-        let name_tok = self.expect(TokenKind::Ident, "expected namespace name")?;
+        let ns_tok = self.expect(TokenKind::Namespace, "E0100.namespace")?;
+        let name_tok = self.expect(TokenKind::Ident, "E0100.namespace-name")?;
         let name = self.slice_token_text(&name_tok);
         let ident = Ident {
             name,
             span: name_tok.span,
         };
         let body = self.parse_scoped_expr().unwrap_or_else(|| {
-            self.error(ns_tok.span, "expected namespace body");
+            self.error("E0100.namespace-body", ns_tok.span);
             Expr {
                 kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                 span: ns_tok.span,
@@ -923,13 +1186,13 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_use_expr_with_pub(&mut self, pub_span: Span, is_pub: bool) -> Option<Expr> {
-        let use_tok = self.expect(TokenKind::Use, "expected 'use'")?;
+        let use_tok = self.expect(TokenKind::Use, "E0100.use")?;
 
         // Parse a path like `ns1::ns2::*` or a plain identifier.
         let path = self.parse_use_path()?;
         let alias = if self.peek_kind() == TokenKind::As {
             self.advance();
-            let alias_tok = self.expect(TokenKind::Ident, "expected alias name after 'as'")?;
+            let alias_tok = self.expect(TokenKind::Ident, "E0100.alias-name-after-as")?;
             let name = self.slice_token_text(&alias_tok);
             Some(Ident {
                 name,
@@ -939,7 +1202,8 @@ impl<'src> Parser<'src> {
             None
         };
 
-        let span = self.merge_spans(if is_pub { pub_span } else { use_tok.span }, path_span(&path));
+        let start = if is_pub { pub_span } else { use_tok.span };
+        let span = self.merge_spans(start, self.previous_span());
         Some(Expr {
             kind: ExprKind::Use(UseExpr {
                 is_pub,
@@ -954,7 +1218,7 @@ impl<'src> Parser<'src> {
     fn parse_use_path(&mut self) -> Option<Path> {
         // Simple path: `segment (:: segment)* [:: *]`
         let mut segments = Vec::new();
-        let first = self.expect(TokenKind::Ident, "expected path segment")?;
+        let first = self.expect(TokenKind::Ident, "E0100.path-segment")?;
         segments.push(self.slice_token_text(&first));
 
         while self.peek_kind() == TokenKind::DoubleColon {
@@ -966,7 +1230,7 @@ impl<'src> Parser<'src> {
                     // `*` span is approximated as current token's span
                 });
             }
-            let seg_tok = self.expect(TokenKind::Ident, "expected path segment after '::'")?;
+            let seg_tok = self.expect(TokenKind::Ident, "E0100.path-segment-after-path-sep")?;
             segments.push(self.slice_token_text(&seg_tok));
         }
 
@@ -974,16 +1238,16 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_when_expr(&mut self) -> Option<Expr> {
-        let when_tok = self.expect(TokenKind::When, "expected 'when'")?;
+        let when_tok = self.expect(TokenKind::When, "E0100.when")?;
         let cond = self.parse_expr().unwrap_or_else(|| {
-            self.error(when_tok.span, "expected condition after 'when'");
+            self.error("E0100.condition-after-when", when_tok.span);
             Expr {
                 kind: ExprKind::Literal(LiteralKind::Bool(true)),
                 span: when_tok.span,
             }
         });
         let body = self.parse_scoped_expr().unwrap_or_else(|| {
-            self.error(cond.span, "expected body after 'when' condition");
+            self.error("E0100.body-after-when-condition", cond.span);
             Expr {
                 kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
                 span: cond.span,
@@ -1000,10 +1264,34 @@ impl<'src> Parser<'src> {
         })
     }
 
+    /// `test "name" <scoped_expr>`
+    fn parse_test_expr(&mut self) -> Option<Expr> {
+        let test_tok = self.expect(TokenKind::Test, "E0100.test")?;
+        let name_tok = self.expect(TokenKind::StringLiteral, "E0100.test-name")?;
+        let name = self.string_value(&name_tok);
+        let body = self.parse_scoped_expr().unwrap_or_else(|| {
+            self.error("E0100.body-after-test-name", name_tok.span);
+            Expr {
+                kind: ExprKind::Block(BlockExpr { expressions: Vec::new() }),
+                span: name_tok.span,
+            }
+        });
+        let span = self.merge_spans(test_tok.span, body.span);
+        Some(Expr {
+            kind: ExprKind::Test(TestExpr {
+                name,
+                name_span: name_tok.span,
+                body: Box::new(body),
+                span,
+            }),
+            span,
+        })
+    }
+
     // === return / break / continue / set ======================================
 
     fn parse_return_expr(&mut self) -> Option<Expr> {
-        let ret_tok = self.expect(TokenKind::Return, "expected 'return'")?;
+        let ret_tok = self.expect(TokenKind::Return, "E0100.return")?;
         let expr = if self.is_seq_terminator() {
             None
         } else {
@@ -1024,7 +1312,7 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_break_expr(&mut self) -> Option<Expr> {
-        let br_tok = self.expect(TokenKind::Break, "expected 'break'")?;
+        let br_tok = self.expect(TokenKind::Break, "E0100.break")?;
         let expr = if self.is_seq_terminator() {
             None
         } else {
@@ -1045,7 +1333,7 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_continue_expr(&mut self) -> Option<Expr> {
-        let c_tok = self.expect(TokenKind::Continue, "expected 'continue'")?;
+        let c_tok = self.expect(TokenKind::Continue, "E0100.continue")?;
         Some(Expr {
             kind: ExprKind::Continue(ContinueExpr { span: c_tok.span }),
             span: c_tok.span,
@@ -1053,9 +1341,9 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_set_expr(&mut self) -> Option<Expr> {
-        let set_tok = self.expect(TokenKind::Set, "expected 'set'")?;
+        let set_tok = self.expect(TokenKind::Set, "E0100.set")?;
         let target = self.parse_assignable().unwrap_or_else(|| {
-            self.error(set_tok.span, "expected assignment target after 'set'");
+            self.error("E0100.assignment-target-after-set", set_tok.span);
             Assignable {
                 base: Box::new(Expr {
                     kind: ExprKind::Ident(Ident {
@@ -1068,7 +1356,7 @@ impl<'src> Parser<'src> {
             }
         });
         let value = self.parse_expr().unwrap_or_else(|| {
-            self.error(set_tok.span, "expected value expression after assignment target");
+            self.error("E0100.value-expression-after-assignment-target", set_tok.span);
             Expr {
                 kind: ExprKind::Literal(LiteralKind::Bool(true)),
                 span: set_tok.span,
@@ -1094,7 +1382,7 @@ impl<'src> Parser<'src> {
             if self.peek_kind() == TokenKind::Dot {
                 self.advance();
                 let field_tok =
-                    self.expect(TokenKind::Ident, "expected field name after '.' in assignment")?;
+                    self.expect(TokenKind::Ident, "E0100.field-name-after-dot-in-assignment")?;
                 let name = self.slice_token_text(&field_tok);
                 fields.push(Ident {
                     name,
@@ -1127,20 +1415,21 @@ impl<'src> Parser<'src> {
             TokenKind::Struct => self.parse_struct_def(pub_span, is_pub),
             _ => {
                 let span = self.current().span;
-                self.error(span, "expected 'enum' or 'struct'");
+                self.error("E0100.enum-or-struct", span);
                 None
             }
         }
     }
 
     fn parse_enum_def(&mut self, pub_span: Span, is_pub: bool) -> Option<Expr> {
-        let enum_tok = self.expect(TokenKind::Enum, "expected 'enum'")?;
-        let name_tok = self.expect(TokenKind::Ident, "expected enum name")?;
+        let enum_tok = self.expect(TokenKind::Enum, "E0100.enum")?;
+        let name_tok = self.expect(TokenKind::Ident, "E0100.enum-name")?;
         let name = self.slice_token_text(&name_tok);
         let ident = Ident {
             name,
             span: name_tok.span,
         };
+        let type_params = self.parse_type_params()?;
 
         let variants = self.parse_scoped_list(|p| p.parse_enum_variant())?;
         let span = self.merge_spans(if is_pub { pub_span } else { enum_tok.span }, variants.span);
@@ -1149,6 +1438,7 @@ impl<'src> Parser<'src> {
             kind: ExprKind::EnumDef(EnumDefExpr {
                 is_pub,
                 name: ident,
+                type_params,
                 variants,
                 span,
             }),
@@ -1157,7 +1447,13 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_enum_variant(&mut self) -> Option<EnumVariant> {
-        let name_tok = self.expect(TokenKind::Ident, "expected enum variant name")?;
+        let checkpoint = self.checkpoint();
+        let result = self.parse_enum_variant_inner();
+        self.close_result(checkpoint, result, SyntaxKind::EnumVariant)
+    }
+
+    fn parse_enum_variant_inner(&mut self) -> Option<EnumVariant> {
+        let name_tok = self.expect(TokenKind::Ident, "E0100.enum-variant-name")?;
         let name = self.slice_token_text(&name_tok);
         let name_ident = Ident {
             name,
@@ -1180,7 +1476,7 @@ impl<'src> Parser<'src> {
                     }
                 }
             }
-            let _ = self.expect(TokenKind::RParen, "expected ')' after enum variant payload");
+            let _ = self.expect(TokenKind::RParen, "E0100.rparen-after-enum-variant-payload");
         }
 
         let span = payload_types
@@ -1196,13 +1492,14 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_struct_def(&mut self, pub_span: Span, is_pub: bool) -> Option<Expr> {
-        let struct_tok = self.expect(TokenKind::Struct, "expected 'struct'")?;
-        let name_tok = self.expect(TokenKind::Ident, "expected struct name")?;
+        let struct_tok = self.expect(TokenKind::Struct, "E0100.struct")?;
+        let name_tok = self.expect(TokenKind::Ident, "E0100.struct-name")?;
         let name = self.slice_token_text(&name_tok);
         let ident = Ident {
             name,
             span: name_tok.span,
         };
+        let type_params = self.parse_type_params()?;
 
         let fields = self.parse_scoped_list(|p| p.parse_struct_field())?;
         let span =
@@ -1212,6 +1509,7 @@ impl<'src> Parser<'src> {
             kind: ExprKind::StructDef(StructDefExpr {
                 is_pub,
                 name: ident,
+                type_params,
                 fields,
                 span,
             }),
@@ -1220,13 +1518,19 @@ impl<'src> Parser<'src> {
     }
 
     fn parse_struct_field(&mut self) -> Option<StructField> {
-        let name_tok = self.expect(TokenKind::Ident, "expected field name")?;
+        let checkpoint = self.checkpoint();
+        let result = self.parse_struct_field_inner();
+        self.close_result(checkpoint, result, SyntaxKind::StructField)
+    }
+
+    fn parse_struct_field_inner(&mut self) -> Option<StructField> {
+        let name_tok = self.expect(TokenKind::Ident, "E0100.field-name")?;
         let name = self.slice_token_text(&name_tok);
         let name_ident = Ident {
             name,
             span: name_tok.span,
         };
-        self.expect(TokenKind::Colon, "expected ':' after field name")?;
+        self.expect(TokenKind::Colon, "E0100.colon-after-field-name")?;
         let ty = self.parse_type_expr()?;
         let span = self.merge_spans(name_tok.span, ty.span);
         Some(StructField { name: name_ident, ty, span })
@@ -1235,6 +1539,12 @@ impl<'src> Parser<'src> {
     // === pattern parsing ======================================================
 
     fn parse_pattern(&mut self) -> Option<Pattern> {
+        let checkpoint = self.checkpoint();
+        let result = self.parse_pattern_inner();
+        self.close_result(checkpoint, result, SyntaxKind::Pattern)
+    }
+
+    fn parse_pattern_inner(&mut self) -> Option<Pattern> {
         match self.peek_kind() {
             TokenKind::Ident => self.parse_ident_or_composite_pattern(),
             TokenKind::IntLiteral
@@ -1246,14 +1556,14 @@ impl<'src> Parser<'src> {
             }
             _ => {
                 let span = self.current().span;
-                self.error(span, "expected pattern");
+                self.error("E0100.pattern", span);
                 None
             }
         }
     }
 
     fn parse_ident_or_composite_pattern(&mut self) -> Option<Pattern> {
-        let head_tok = self.expect(TokenKind::Ident, "expected identifier in pattern")?;
+        let head_tok = self.expect(TokenKind::Ident, "E0100.identifier-in-pattern")?;
         let head_name = self.slice_token_text(&head_tok);
 
         if head_name == "_" {
@@ -1284,7 +1594,7 @@ impl<'src> Parser<'src> {
                         }
                     }
                 }
-                let _ = self.expect(TokenKind::RParen, "expected ')' in enum pattern");
+                let _ = self.expect(TokenKind::RParen, "E0100.rparen-in-enum-pattern");
                 Some(Pattern::Enum(EnumPattern {
                     ctor: head_name,
                     args,
@@ -1297,9 +1607,9 @@ impl<'src> Parser<'src> {
                 let mut fields = Vec::new();
                 while !self.is_eof() && self.peek_kind() != TokenKind::RBrace {
                     let field_tok =
-                        self.expect(TokenKind::Ident, "expected field name in struct pattern")?;
+                        self.expect(TokenKind::Ident, "E0100.field-name-in-struct-pattern")?;
                     let field_name = self.slice_token_text(&field_tok);
-                    self.expect(TokenKind::Colon, "expected ':' after field name in pattern")?;
+                    self.expect(TokenKind::Colon, "E0100.colon-after-field-name-in-pattern")?;
                     let pat = self.parse_pattern()?;
                     fields.push(StructPatternField {
                         field: field_name,
@@ -1311,7 +1621,7 @@ impl<'src> Parser<'src> {
                         break;
                     }
                 }
-                let _ = self.expect(TokenKind::RBrace, "expected '}' to close struct pattern");
+                let _ = self.expect(TokenKind::RBrace, "E0100.rbrace-to-close-struct-pattern");
                 Some(Pattern::Struct(StructPattern {
                     ctor: head_name,
                     fields,
@@ -1325,72 +1635,218 @@ impl<'src> Parser<'src> {
         }
     }
 
-    // === type expressions (for enum payloads / struct fields) =================
+    // === type expressions ======================================================
+    //
+    // <type>      = <type_atom> [ ( '->' | '*>' ) <type> ]
+    // <type_atom> = <ident> [ '<' <type> { ',' <type> } '>' ]
+    //             | '(' [ <type> { ',' <type> } ] ')'
     //
-    // For now we use a very simple subset that matches the current
-    // specification enough to build AST nodes. This can be extended
-    // later.
+    // A parenthesised list directly followed by an arrow is the
+    // parameter list of a function type: `(i32, i32) -> i32`.
 
     fn parse_type_expr(&mut self) -> Option<TypeExpr> {
-        // Very simple type grammar:
-        // <type> = <type_atom> [ '->' <type> ]
-        let lhs = self.parse_type_atom()?;
-        if self.peek_kind() == TokenKind::Arrow {
-            let arrow_tok = self.advance();
-            let rhs = self.parse_type_expr().unwrap_or_else(|| {
-                self.error(arrow_tok.span, "expected type after '->'");
-                lhs.clone()
-            });
-            let span = self.merge_spans(lhs.span, rhs.span);
-            Some(TypeExpr {
-                span,
-                kind: TypeExprKind::Fn(Vec::new(), Box::new(lhs), Box::new(rhs)),
-            })
-        } else {
-            Some(lhs)
-        }
+        let checkpoint = self.checkpoint();
+        let result = self.parse_type_expr_inner();
+        self.close_result(checkpoint, result, SyntaxKind::TypeExpr)
+    }
+
+    fn parse_type_expr_inner(&mut self) -> Option<TypeExpr> {
+        let start = self.current().span;
+        let (lhs, list) = self.parse_type_atom()?;
+
+        let arrow = match self.peek_kind() {
+            TokenKind::Arrow => ArrowKind::Impure,
+            TokenKind::StarArrow => ArrowKind::Pure,
+            _ => {
+                return match list {
+                    Some(mut items) if items.len() == 1 => {
+                        let inner = items.pop().unwrap();
+                        Some(TypeExpr {
+                            span: lhs.span,
+                            kind: TypeExprKind::Paren(Box::new(inner)),
+                        })
+                    }
+                    Some(_) => {
+                        self.error("E0100.arrow-or-pure-arrow-after-parameter-type-list", lhs.span);
+                        None
+                    }
+                    None => Some(lhs),
+                };
+            }
+        };
+        let arrow_tok = self.advance();
+
+        let result = match self.parse_type_expr() {
+            Some(t) => t,
+            None => {
+                self.error("E0100.result-type-after-arrow", arrow_tok.span);
+                return None;
+            }
+        };
+        let params = list.unwrap_or_else(|| vec![lhs]);
+        let span = self.merge_spans(start, result.span);
+        Some(TypeExpr {
+            span,
+            kind: TypeExprKind::Fn {
+                params,
+                result: Box::new(result),
+                arrow,
+            },
+        })
     }
 
-    fn parse_type_atom(&mut self) -> Option<TypeExpr> {
+    /// Parses a type atom. For a parenthesised form, also returns the
+    /// list of types inside the parentheses so that the caller can
+    /// turn it into a function parameter list.
+    fn parse_type_atom(&mut self) -> Option<(TypeExpr, Option<Vec<TypeExpr>>)> {
         match self.peek_kind() {
             TokenKind::Ident => {
                 let tok = self.advance();
                 let name = self.slice_token_text(&tok);
-                Some(TypeExpr {
-                    span: tok.span,
-                    kind: TypeExprKind::Named(name),
-                })
+                if self.peek_kind() != TokenKind::Less {
+                    return Some((
+                        TypeExpr {
+                            span: tok.span,
+                            kind: TypeExprKind::Named(name),
+                        },
+                        None,
+                    ));
+                }
+                self.advance(); // '<'
+                let mut args = Vec::new();
+                loop {
+                    args.push(self.parse_type_expr()?);
+                    if self.consume_if(TokenKind::Comma).is_none() {
+                        break;
+                    }
+                }
+                let close = self.expect(TokenKind::Greater, "E0100.gt-after-type-arguments")?;
+                Some((
+                    TypeExpr {
+                        span: self.merge_spans(tok.span, close.span),
+                        kind: TypeExprKind::Apply { name, args },
+                    },
+                    None,
+                ))
             }
             TokenKind::LParen => {
                 let l = self.advance();
-                let inner = self.parse_type_expr()?;
-                let r = self.expect(TokenKind::RParen, "expected ')' in type")?;
+                let mut items = Vec::new();
+                if self.peek_kind() != TokenKind::RParen {
+                    loop {
+                        items.push(self.parse_type_expr()?);
+                        if self.consume_if(TokenKind::Comma).is_none() {
+                            break;
+                        }
+                    }
+                }
+                let r = self.expect(TokenKind::RParen, "E0100.rparen-in-type")?;
                 let span = self.merge_spans(l.span, r.span);
-                Some(TypeExpr {
+                // The placeholder node is replaced by the caller.
+                let placeholder = TypeExpr {
                     span,
-                    kind: TypeExprKind::Paren(Box::new(inner)),
-                })
+                    kind: TypeExprKind::Named(String::new()),
+                };
+                Some((placeholder, Some(items)))
             }
             _ => {
                 let span = self.current().span;
-                self.error(span, "expected type");
+                self.error("E0100.type", span);
                 None
             }
         }
     }
 
+    /// Optional type parameter list `<T, U>` of a generic function
+    /// literal, enum or struct.
+    fn parse_type_params(&mut self) -> Option<Vec<Ident>> {
+        let checkpoint = self.checkpoint();
+        let result = self.parse_type_params_inner();
+        self.close_result(checkpoint, result, SyntaxKind::TypeParams)
+    }
+
+    fn parse_type_params_inner(&mut self) -> Option<Vec<Ident>> {
+        let mut params = Vec::new();
+        if self.consume_if(TokenKind::Less).is_none() {
+            return Some(params);
+        }
+        loop {
+            let tok = self.expect(TokenKind::Ident, "E0100.type-parameter-name")?;
+            params.push(Ident {
+                name: self.slice_token_text(&tok),
+                span: tok.span,
+            });
+            if self.consume_if(TokenKind::Comma).is_none() {
+                break;
+            }
+        }
+        self.expect(TokenKind::Greater, "E0100.gt-after-type-parameters")?;
+        Some(params)
+    }
+
     // === helpers =============================================================
 
+    /// Run `f` with `{` / `:` ending P-style sequences (see `no_scope_atom`).
+    fn without_scope_atoms<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = core::mem::replace(&mut self.no_scope_atom, true);
+        let result = f(self);
+        self.no_scope_atom = saved;
+        result
+    }
+
+    /// Run `f` with blocks allowed as terms again, e.g. inside `( ... )`.
+    fn with_scope_atoms<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = core::mem::replace(&mut self.no_scope_atom, false);
+        let result = f(self);
+        self.no_scope_atom = saved;
+        result
+    }
+
     fn slice_token_text(&self, tok: &Token) -> String {
         let start = tok.text_start as usize;
         let end = tok.text_end as usize;
         self.source[start..end].to_owned()
     }
+
+    /// The decoded contents of a string literal token. Malformed escapes
+    /// were already reported by the lexer.
+    fn string_value(&self, tok: &Token) -> String {
+        let text = &self.source[tok.text_start as usize..tok.text_end as usize];
+        unescape(tok.span.file_id, text, tok.text_start).0
+    }
+}
+
+/// The value and suffix of an integer literal token. Malformed digits
+/// were reported by the lexer and read as 0; `None` means the value
+/// does not fit in 64 bits.
+fn int_literal(text: &str) -> IntLiteral {
+    let (radix, digits, suffix) = number_parts(text);
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    let value = match u64::from_str_radix(&digits, radix) {
+        Ok(value) => Some(value),
+        Err(e) if *e.kind() == IntErrorKind::PosOverflow => None,
+        Err(_) => Some(0),
+    };
+    IntLiteral {
+        value,
+        suffix: suffix_type(suffix),
+    }
+}
+
+fn float_literal(text: &str) -> FloatLiteral {
+    let (_, digits, suffix) = number_parts(text);
+    FloatLiteral {
+        text: digits.chars().filter(|c| *c != '_').collect(),
+        suffix: suffix_type(suffix),
+    }
 }
 
-/// Helper to approximate a `Span` covering a `Path`.
-fn path_span(path: &Path) -> Span {
-    match path {
-        Path::Simple { .. } | Path::Glob { .. } => Span::dummy(),
+fn suffix_type(suffix: &str) -> Option<Type> {
+    match suffix {
+        "i32" => Some(Type::I32),
+        "i64" => Some(Type::I64),
+        "f32" => Some(Type::F32),
+        "f64" => Some(Type::F64),
+        _ => None,
     }
 }
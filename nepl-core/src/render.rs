@@ -0,0 +1,556 @@
+//! Human-readable rendering of diagnostics (no_std).
+//!
+//! Diagnostics are printed in the familiar compiler layout:
+//!
+//! ```text
+//! error[E0300]: mismatched types: expected `Bool`, found `i32`
+//!  --> main.nepl:1:17
+//!   |
+//! 1 | { let b: Bool = add 1 2; b }
+//!   |                 ^^^^^^^
+//! ```
+//!
+//! A label that spans several lines is drawn in a margin from its first
+//! character to its last, with the lines in between elided when there
+//! are many:
+//!
+//! ```text
+//! error[E0300]: mismatched types: expected `Bool`, found `i32`
+//!  --> main.nepl:2:19
+//!   |
+//! 2 |       let b: Bool = add
+//!   |  ___________________^
+//! 3 | |         1
+//! 4 | |         2;
+//!   | |_________^
+//! ```
+//!
+//! Locations are `line:column` with columns counted in characters, as
+//! in the JSON and SARIF output; underlines follow the display width of
+//! the text, so wide (e.g. CJK) characters get two carets.
+//!
+//! The renderer only produces a `String`; writing it to a terminal (and
+//! deciding whether colors are wanted) is up to the caller.
+//!
+//! Messages are shown in the renderer's locale (`with_locale`). The
+//! `error` / `warning` / `help` / `note` headings stay English so that
+//! output remains easy to grep.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::diagnostic::{Diagnostic, Label, Severity, Suggestion};
+use crate::messages::{self, Locale};
+use crate::span::{ColumnUnit, FileId, LineIndex, SourceMap, Span};
+use unicode_width::UnicodeWidthChar;
+
+/// Tabs are expanded to this many columns in source excerpts.
+const TAB_WIDTH: usize = 4;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+
+/// A source file known to the renderer.
+#[derive(Debug, Clone)]
+struct RenderFile<'a> {
+    id: FileId,
+    name: &'a str,
+    source: &'a str,
+    index: LineIndex,
+}
+
+impl RenderFile<'_> {
+    /// `name:line:column` of a byte offset, 1-based, with the column
+    /// in characters.
+    fn location(&self, offset: u32) -> String {
+        let pos = self.index.position(offset, ColumnUnit::Char);
+        format!("{}:{}:{}", self.name, pos.line + 1, pos.col + 1)
+    }
+
+    /// The lines of the first and last character of `span`.
+    fn lines_of(&self, span: Span) -> (u32, u32) {
+        let span = clamp(span, self.source);
+        let first = self.index.line_col(span.start).0;
+        let last = span.end.saturating_sub(1).max(span.start);
+        let last = self.index.line_col(last).0;
+        (first, last)
+    }
+}
+
+/// A label drawn across several lines.
+struct Multiline<'d> {
+    label: &'d Label,
+    primary: bool,
+    first: u32,
+    last: u32,
+}
+
+/// Renders diagnostics against the source files they refer to.
+#[derive(Debug, Clone, Default)]
+pub struct Renderer<'a> {
+    files: Vec<RenderFile<'a>>,
+    color: bool,
+    locale: Locale,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new() -> Self {
+        Renderer::default()
+    }
+
+    /// Enable or disable ANSI colors.
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Language of the messages.
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Register the source text of a file under a display name.
+    pub fn add_file(&mut self, id: FileId, name: &'a str, source: &'a str) {
+        self.files.retain(|f| f.id != id);
+        self.files.push(RenderFile {
+            id,
+            name,
+            source,
+            index: LineIndex::new(source),
+        });
+    }
+
+    /// Register every file of `map`.
+    pub fn add_source_map(&mut self, map: &'a SourceMap) {
+        for file in map.files() {
+            self.add_file(file.id(), file.name(), file.source());
+        }
+    }
+
+    /// Render every diagnostic, followed by a summary line when there
+    /// are errors or warnings.
+    pub fn render_all(&self, diagnostics: &[Diagnostic]) -> String {
+        let mut out = String::new();
+        for d in diagnostics {
+            out.push_str(&self.render(d));
+            out.push('\n');
+        }
+
+        let errors = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count();
+        let warnings = diagnostics.len() - errors;
+        if errors > 0 {
+            let msg = self.count("render.abort", errors);
+            let msg = match warnings {
+                0 => msg,
+                n => {
+                    let args = [("abort", msg), ("warnings", self.count("render.warnings", n))];
+                    messages::format(self.locale, "render.abort-and-warnings", &args)
+                }
+            };
+            self.header(&mut out, Severity::Error, None, &msg);
+        } else if warnings > 0 {
+            let msg = self.count("render.warnings", warnings);
+            self.header(&mut out, Severity::Warning, None, &msg);
+        }
+        out
+    }
+
+    /// The `.one` or `.many` form of a counted summary message.
+    fn count(&self, key: &str, n: usize) -> String {
+        let key = format!("{key}.{}", if n == 1 { "one" } else { "many" });
+        messages::format(self.locale, &key, &[("count", n.to_string())])
+    }
+
+    /// Render a single diagnostic.
+    pub fn render(&self, d: &Diagnostic) -> String {
+        let mut out = String::new();
+        self.header(&mut out, d.severity, d.code, &d.message_in(self.locale));
+
+        let Some(file) = self.file(d.primary.span.file_id) else {
+            for s in &d.suggestions {
+                self.suggestion(&mut out, s);
+            }
+            return out;
+        };
+
+        // Every line that carries a label, in source order.
+        let mut labels: Vec<(&Label, bool)> = Vec::new();
+        labels.push((&d.primary, true));
+        labels.extend(
+            d.secondary
+                .iter()
+                .filter(|l| l.span.file_id == file.id)
+                .map(|l| (l, false)),
+        );
+        // One label spanning several lines is drawn in a margin left of
+        // the source, from its first character to its last; any others
+        // are underlined on their first line only.
+        let multi = labels
+            .iter()
+            .map(|&(l, primary)| (l, primary, file.lines_of(l.span)))
+            .find(|(_, _, (first, last))| first < last)
+            .map(|(l, primary, (first, last))| Multiline {
+                label: l,
+                primary,
+                first,
+                last,
+            });
+        let mut lines: Vec<u32> = labels
+            .iter()
+            .map(|(l, _)| file.index.line_col(l.span.start).0)
+            .collect();
+        if let Some(m) = &multi {
+            // The lines next to either end; longer spans are elided.
+            lines.extend([m.first + 1, m.last - 1, m.last]);
+            labels.retain(|(l, _)| !core::ptr::eq(*l, m.label));
+        }
+        lines.sort_unstable();
+        lines.dedup();
+
+        let gutter = digits(lines.last().copied().unwrap_or(0) + 1);
+        let _ = writeln!(
+            out,
+            "{}{}-->{} {}",
+            " ".repeat(gutter),
+            self.paint(BLUE),
+            self.paint(RESET),
+            file.location(d.primary.span.start)
+        );
+        self.empty_gutter(&mut out, gutter);
+
+        let mut previous: Option<u32> = None;
+        for line in lines {
+            if let Some(p) = previous
+                && line > p + 1
+            {
+                let _ = writeln!(out, "{}...{}", self.paint(BLUE), self.paint(RESET));
+            }
+            previous = Some(line);
+
+            let range = file.index.line_range(line);
+            let text = &file.source[range.clone()];
+            let margin = self.margin(multi.as_ref(), line, d.severity);
+            let _ = writeln!(
+                out,
+                "{}{:>gutter$} |{} {}{}",
+                self.paint(BLUE),
+                line + 1,
+                self.paint(RESET),
+                margin,
+                expand_tabs(text)
+            );
+
+            // Primary label first, then secondary ones, each on its own
+            // underline row.
+            let mut on_line: Vec<&(&Label, bool)> = labels
+                .iter()
+                .filter(|(l, _)| file.index.line_col(l.span.start).0 == line)
+                .collect();
+            on_line.sort_by_key(|(_, primary)| !*primary);
+            for (label, primary) in on_line {
+                let _ = write!(
+                    out,
+                    "{} {}|{} {}",
+                    " ".repeat(gutter),
+                    self.paint(BLUE),
+                    self.paint(RESET),
+                    margin
+                );
+                self.underline(&mut out, file, text, range.start, label, *primary, d.severity);
+            }
+
+            if let Some(m) = multi.as_ref().filter(|m| line == m.first || line == m.last) {
+                let _ = write!(
+                    out,
+                    "{} {}|{} ",
+                    " ".repeat(gutter),
+                    self.paint(BLUE),
+                    self.paint(RESET)
+                );
+                let first = line == m.first;
+                self.multiline_end(&mut out, file, text, range.start, m, first, d.severity);
+            }
+        }
+
+        // Labels in other files are listed as notes.
+        for l in d.secondary.iter().filter(|l| l.span.file_id != file.id) {
+            let location = match self.file(l.span.file_id) {
+                Some(f) => f.location(l.span.start),
+                None => String::from("<unknown file>"),
+            };
+            let _ = write!(
+                out,
+                "{}{} = {}note{}: {location}",
+                " ".repeat(gutter),
+                self.paint(BLUE),
+                self.paint(BOLD),
+                self.paint(RESET)
+            );
+            if let Some(msg) = &l.message {
+                let _ = write!(out, ": {msg}");
+            }
+            out.push('\n');
+        }
+
+        for s in &d.suggestions {
+            self.suggestion(&mut out, s);
+        }
+        out
+    }
+
+    /// `help: message`, followed by the fixed source line when all
+    /// edits are on one line of a known file:
+    ///
+    /// ```text
+    /// help: consider making this binding mutable
+    ///   |
+    /// 1 | { let mut n = 1; set n 2; n }
+    ///   |       ++++
+    /// ```
+    ///
+    /// Inserted text is marked with `+`, replacing text with `~`.
+    fn suggestion(&self, out: &mut String, s: &Suggestion) {
+        let _ = writeln!(
+            out,
+            "{}help{}: {}",
+            self.paint(CYAN),
+            self.paint(RESET),
+            s.message_in(self.locale)
+        );
+
+        let Some(first) = s.edits.first() else {
+            return;
+        };
+        let Some(file) = self.file(first.span.file_id) else {
+            return;
+        };
+        let line = file.index.line_col(first.span.start).0;
+        let range = file.index.line_range(line);
+        let mut edits: Vec<_> = s.edits.iter().collect();
+        edits.sort_by_key(|e| e.span.start);
+        let on_line = edits.iter().all(|e| {
+            e.span.file_id == file.id
+                && range.start <= e.span.start as usize
+                && e.span.end as usize <= range.end
+                && file.source.is_char_boundary(e.span.start as usize)
+                && file.source.is_char_boundary(e.span.end as usize)
+        });
+        if !on_line {
+            return;
+        }
+
+        // The fixed line and the (column, width, mark) of each edit in it.
+        let mut text = String::new();
+        let mut marks = Vec::new();
+        let mut pos = range.start;
+        for e in edits {
+            let start = (e.span.start as usize).max(pos);
+            text.push_str(&file.source[pos..start]);
+            let mark = if e.span.is_empty() { '+' } else { '~' };
+            marks.push((width(&text), width(&e.replacement), mark));
+            text.push_str(&e.replacement);
+            pos = (e.span.end as usize).max(start);
+        }
+        text.push_str(&file.source[pos..range.end]);
+
+        let gutter = digits(line + 1);
+        self.empty_gutter(out, gutter);
+        let _ = writeln!(
+            out,
+            "{}{:>gutter$} |{} {}",
+            self.paint(BLUE),
+            line + 1,
+            self.paint(RESET),
+            expand_tabs(&text)
+        );
+        let mut row = String::new();
+        for (col, len, mark) in marks {
+            while width(&row) < col {
+                row.push(' ');
+            }
+            for _ in 0..len {
+                row.push(mark);
+            }
+        }
+        let _ = writeln!(
+            out,
+            "{} {}|{} {}{row}{}",
+            " ".repeat(gutter),
+            self.paint(BLUE),
+            self.paint(RESET),
+            self.paint(CYAN),
+            self.paint(RESET)
+        );
+    }
+
+    fn file(&self, id: FileId) -> Option<&RenderFile<'a>> {
+        self.files.iter().find(|f| f.id == id)
+    }
+
+    fn header(&self, out: &mut String, severity: Severity, code: Option<&str>, message: &str) {
+        let (name, color) = match severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+        let _ = write!(out, "{}{name}", self.paint(color));
+        if let Some(code) = code {
+            let _ = write!(out, "[{code}]");
+        }
+        let _ = writeln!(
+            out,
+            "{}{}: {message}{}",
+            self.paint(RESET),
+            self.paint(BOLD),
+            self.paint(RESET)
+        );
+    }
+
+    fn empty_gutter(&self, out: &mut String, gutter: usize) {
+        let _ = writeln!(
+            out,
+            "{} {}|{}",
+            " ".repeat(gutter),
+            self.paint(BLUE),
+            self.paint(RESET)
+        );
+    }
+
+    /// The margin left of the rows of `line` when a label spans several
+    /// lines: a bar on the rows inside it.
+    fn margin(&self, multi: Option<&Multiline<'_>>, line: u32, severity: Severity) -> String {
+        match multi {
+            Some(m) if m.first < line && line <= m.last => {
+                let color = label_color(m.primary, severity).1;
+                format!("{}|{} ", self.paint(color), self.paint(RESET))
+            }
+            Some(_) => String::from("  "),
+            None => String::new(),
+        }
+    }
+
+    /// Underline `label` on the current row, which the caller has
+    /// started with the gutter.
+    #[allow(clippy::too_many_arguments)]
+    fn underline(
+        &self,
+        out: &mut String,
+        file: &RenderFile<'_>,
+        text: &str,
+        line_start: usize,
+        label: &Label,
+        primary: bool,
+        severity: Severity,
+    ) {
+        let span = clamp(label.span, file.source);
+        let start = span.start as usize - line_start;
+        let end = (span.end as usize).saturating_sub(line_start).min(text.len());
+        let pad = width(&text[..start]);
+        let len = width(&text[start..end.max(start)]).max(1);
+
+        let (mark, color) = label_color(primary, severity);
+        let _ = write!(out, "{}{}", " ".repeat(pad), self.paint(color));
+        for _ in 0..len {
+            out.push(mark);
+        }
+        if let Some(msg) = &label.message {
+            let _ = write!(out, " {msg}");
+        }
+        let _ = writeln!(out, "{}", self.paint(RESET));
+    }
+
+    /// The row below the first or last line of a multi-line label: a
+    /// line of `_` from the margin to its first or last character, and
+    /// the label's message after the last.
+    #[allow(clippy::too_many_arguments)]
+    fn multiline_end(
+        &self,
+        out: &mut String,
+        file: &RenderFile<'_>,
+        text: &str,
+        line_start: usize,
+        m: &Multiline<'_>,
+        first: bool,
+        severity: Severity,
+    ) {
+        let span = clamp(m.label.span, file.source);
+        let (mark, color) = label_color(m.primary, severity);
+        let _ = write!(out, "{}", self.paint(color));
+        let column = if first {
+            out.push(' ');
+            width(&text[..span.start as usize - line_start])
+        } else {
+            out.push('|');
+            // The column of the last character.
+            let end = (span.end as usize - line_start).min(text.len());
+            let last = text[..end].chars().next_back().map_or(0, |c| c.len_utf8());
+            width(&text[..end - last])
+        };
+        for _ in 0..=column {
+            out.push('_');
+        }
+        out.push(mark);
+        if !first && let Some(msg) = &m.label.message {
+            let _ = write!(out, " {msg}");
+        }
+        let _ = writeln!(out, "{}", self.paint(RESET));
+    }
+
+    fn paint(&self, code: &'static str) -> &'static str {
+        if self.color { code } else { "" }
+    }
+}
+
+/// The underline character and color of a label.
+fn label_color(primary: bool, severity: Severity) -> (char, &'static str) {
+    match (primary, severity) {
+        (true, Severity::Error) => ('^', RED),
+        (true, Severity::Warning) => ('^', YELLOW),
+        (false, _) => ('-', BLUE),
+    }
+}
+
+/// Keep a span inside its file and on character boundaries.
+fn clamp(span: Span, source: &str) -> Span {
+    let mut start = (span.start as usize).min(source.len());
+    while !source.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (span.end as usize).clamp(start, source.len());
+    while !source.is_char_boundary(end) {
+        end += 1;
+    }
+    Span::new(span.file_id, start as u32, end as u32)
+}
+
+/// Display width of `text` in terminal columns, with tabs expanded.
+fn width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c {
+            '\t' => TAB_WIDTH,
+            c => c.width().unwrap_or(0),
+        })
+        .sum()
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+fn digits(mut n: u32) -> usize {
+    let mut d = 1;
+    while n >= 10 {
+        n /= 10;
+        d += 1;
+    }
+    d
+}
@@ -0,0 +1,398 @@
+//! Machine-readable diagnostic reports (no_std).
+//!
+//! Two formats are supported:
+//!
+//! - **JSON lines**: one JSON object per diagnostic, each on its own
+//!   line. This is what `nepl --error-format=json` prints and what the
+//!   web playground hands to its editor.
+//! - **SARIF 2.1.0**: a single log document understood by CI systems
+//!   and code-scanning tools (`--error-format=sarif`).
+//!
+//! The JSON diagnostic for `{ let n = 1; set n 2; n }` looks like this
+//! (wrapped for readability):
+//!
+//! ```text
+//! {"severity":"error","code":"E0404",
+//!  "message":"cannot assign to immutable variable `n`",
+//!  "file":"main.nepl","span":{"start":17,"end":18},
+//!  "range":{"start":{"line":1,"column":18},"end":{"line":1,"column":19}},
+//!  "label":null,"secondary":[],
+//!  "suggestions":[{"message":"consider making this binding mutable",
+//!   "edits":[{"file":"main.nepl","span":{"start":6,"end":6},
+//!    "range":{"start":{"line":1,"column":7},"end":{"line":1,"column":7}},
+//!    "replacement":"mut "}]}]}
+//! ```
+//!
+//! `span` holds byte offsets; `range` holds 1-based lines and 1-based
+//! columns counted in Unicode scalar values. Secondary labels carry the
+//! same `file`/`span`/`range` fields plus their `message`. Suggestions
+//! have a `message` and a list of `edits`, each with a location and its
+//! `replacement` text; in SARIF they become `fixes`.
+//!
+//! Messages are written in the reporter's locale (`with_locale`);
+//! `code` is the stable way to identify a diagnostic.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::diagnostic::{Diagnostic, Label, Severity, Suggestion};
+use crate::messages::Locale;
+use crate::span::{ColumnUnit, FileId, LineIndex, SourceMap, Span};
+
+/// `$schema` of the emitted SARIF logs.
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// A source file known to the reporter.
+#[derive(Debug, Clone)]
+struct ReportFile<'a> {
+    id: FileId,
+    path: &'a str,
+    source: &'a str,
+    index: LineIndex,
+}
+
+/// 1-based line / column of a byte offset.
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    line: u32,
+    column: u32,
+}
+
+/// Serializes diagnostics against the source files they refer to.
+#[derive(Debug, Clone, Default)]
+pub struct Reporter<'a> {
+    files: Vec<ReportFile<'a>>,
+    locale: Locale,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new() -> Self {
+        Reporter::default()
+    }
+
+    /// Language of the messages.
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Register the source text of a file under the path that should
+    /// appear in reports.
+    pub fn add_file(&mut self, id: FileId, path: &'a str, source: &'a str) {
+        self.files.retain(|f| f.id != id);
+        self.files.push(ReportFile {
+            id,
+            path,
+            source,
+            index: LineIndex::new(source),
+        });
+    }
+
+    /// Register every file of `map`, using the file names as paths.
+    pub fn add_source_map(&mut self, map: &'a SourceMap) {
+        for file in map.files() {
+            self.add_file(file.id(), file.name(), file.source());
+        }
+    }
+
+    /// One JSON object per diagnostic, each terminated by a newline.
+    pub fn json_lines(&self, diagnostics: &[Diagnostic]) -> String {
+        let mut out = String::new();
+        for d in diagnostics {
+            out.push_str(&self.json(d));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// A single diagnostic as a JSON object (without a trailing newline).
+    pub fn json(&self, d: &Diagnostic) -> String {
+        let mut out = String::new();
+        out.push_str("{\"severity\":");
+        push_string(&mut out, severity_name(d.severity));
+        out.push_str(",\"code\":");
+        push_optional(&mut out, d.code);
+        out.push_str(",\"message\":");
+        push_string(&mut out, &d.message_in(self.locale));
+        out.push(',');
+        self.push_location(&mut out, d.primary.span);
+        out.push_str(",\"label\":");
+        push_optional(&mut out, d.primary.message.as_deref());
+        out.push_str(",\"secondary\":[");
+        for (i, label) in d.secondary.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push('{');
+            self.push_location(&mut out, label.span);
+            out.push_str(",\"message\":");
+            push_optional(&mut out, label.message.as_deref());
+            out.push('}');
+        }
+        out.push_str("],\"suggestions\":[");
+        for (i, s) in d.suggestions.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"message\":");
+            push_string(&mut out, &s.message_in(self.locale));
+            out.push_str(",\"edits\":[");
+            for (j, e) in s.edits.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                out.push('{');
+                self.push_location(&mut out, e.span);
+                out.push_str(",\"replacement\":");
+                push_string(&mut out, &e.replacement);
+                out.push('}');
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// A SARIF 2.1.0 log with one run of the `nepl` tool.
+    pub fn sarif(&self, diagnostics: &[Diagnostic]) -> String {
+        let mut rules: Vec<&str> = diagnostics.iter().filter_map(|d| d.code).collect();
+        rules.sort_unstable();
+        rules.dedup();
+
+        let mut out = String::new();
+        out.push_str("{\"$schema\":");
+        push_string(&mut out, SARIF_SCHEMA);
+        out.push_str(",\"version\":\"2.1.0\",\"runs\":[{\"tool\":{\"driver\":{");
+        out.push_str("\"name\":\"nepl\",\"version\":");
+        push_string(&mut out, env!("CARGO_PKG_VERSION"));
+        out.push_str(",\"rules\":[");
+        for (i, rule) in rules.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"id\":");
+            push_string(&mut out, rule);
+            out.push('}');
+        }
+        out.push_str("]}},\"columnKind\":\"unicodeCodePoints\",\"results\":[");
+        for (i, d) in diagnostics.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            self.push_sarif_result(&mut out, d);
+        }
+        out.push_str("]}]}");
+        out
+    }
+
+    fn push_sarif_result(&self, out: &mut String, d: &Diagnostic) {
+        out.push('{');
+        if let Some(code) = d.code {
+            out.push_str("\"ruleId\":");
+            push_string(out, code);
+            out.push(',');
+        }
+        out.push_str("\"level\":");
+        push_string(out, severity_name(d.severity));
+        out.push_str(",\"message\":{\"text\":");
+        push_string(out, &d.message_in(self.locale));
+        out.push_str("},\"locations\":[");
+        self.push_sarif_location(out, &d.primary, None);
+        out.push(']');
+        if !d.secondary.is_empty() {
+            out.push_str(",\"relatedLocations\":[");
+            for (i, label) in d.secondary.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                self.push_sarif_location(out, label, Some(i));
+            }
+            out.push(']');
+        }
+        if !d.suggestions.is_empty() {
+            out.push_str(",\"fixes\":[");
+            for (i, s) in d.suggestions.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                self.push_sarif_fix(out, s);
+            }
+            out.push(']');
+        }
+        out.push('}');
+    }
+
+    /// A `fix` with one `artifactChange` per file touched.
+    fn push_sarif_fix(&self, out: &mut String, s: &Suggestion) {
+        out.push_str("{\"description\":{\"text\":");
+        push_string(out, &s.message_in(self.locale));
+        out.push_str("},\"artifactChanges\":[");
+        let mut files: Vec<FileId> = Vec::new();
+        for e in &s.edits {
+            if !files.contains(&e.span.file_id) {
+                files.push(e.span.file_id);
+            }
+        }
+        for (i, file) in files.into_iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"artifactLocation\":{\"uri\":");
+            push_string(out, self.path(file));
+            out.push_str("},\"replacements\":[");
+            for (j, e) in s.edits.iter().filter(|e| e.span.file_id == file).enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                out.push_str("{\"deletedRegion\":");
+                self.push_sarif_region(out, e.span);
+                out.push_str(",\"insertedContent\":{\"text\":");
+                push_string(out, &e.replacement);
+                out.push_str("}}");
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+    }
+
+    fn push_sarif_location(&self, out: &mut String, label: &Label, id: Option<usize>) {
+        out.push('{');
+        if let Some(id) = id {
+            let _ = write!(out, "\"id\":{id},");
+        }
+        out.push_str("\"physicalLocation\":{\"artifactLocation\":{\"uri\":");
+        push_string(out, self.path(label.span.file_id));
+        out.push('}');
+        if self.file(label.span.file_id).is_some() {
+            out.push_str(",\"region\":");
+            self.push_sarif_region(out, label.span);
+        }
+        out.push('}');
+        if let Some(msg) = &label.message {
+            out.push_str(",\"message\":{\"text\":");
+            push_string(out, msg);
+            out.push('}');
+        }
+        out.push('}');
+    }
+
+    /// A SARIF region; only the byte range is known for unknown files.
+    fn push_sarif_region(&self, out: &mut String, span: Span) {
+        match self.resolve(span) {
+            Some((span, start, end)) => {
+                let _ = write!(
+                    out,
+                    "{{\"startLine\":{},\"startColumn\":{},\"endLine\":{},\"endColumn\":{},\"byteOffset\":{},\"byteLength\":{}}}",
+                    start.line,
+                    start.column,
+                    end.line,
+                    end.column,
+                    span.start,
+                    span.len()
+                );
+            }
+            None => {
+                let _ = write!(
+                    out,
+                    "{{\"byteOffset\":{},\"byteLength\":{}}}",
+                    span.start,
+                    span.len()
+                );
+            }
+        }
+    }
+
+    /// `"file":...,"span":{...},"range":{...}`; `range` is `null` when
+    /// the file is unknown.
+    fn push_location(&self, out: &mut String, span: Span) {
+        out.push_str("\"file\":");
+        push_string(out, self.path(span.file_id));
+        let _ = write!(
+            out,
+            ",\"span\":{{\"start\":{},\"end\":{}}},\"range\":",
+            span.start, span.end
+        );
+        match self.resolve(span) {
+            Some((_, start, end)) => {
+                let _ = write!(
+                    out,
+                    "{{\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}}}}",
+                    start.line, start.column, end.line, end.column
+                );
+            }
+            None => out.push_str("null"),
+        }
+    }
+
+    fn path(&self, id: FileId) -> &str {
+        self.file(id).map(|f| f.path).unwrap_or("<unknown>")
+    }
+
+    fn file(&self, id: FileId) -> Option<&ReportFile<'a>> {
+        self.files.iter().find(|f| f.id == id)
+    }
+
+    /// The span clamped to its file, with the positions of both ends.
+    fn resolve(&self, span: Span) -> Option<(Span, Position, Position)> {
+        let file = self.file(span.file_id)?;
+        let span = clamp(span, file.source);
+        let start = position(file, span.start);
+        let end = position(file, span.end);
+        Some((span, start, end))
+    }
+}
+
+fn position(file: &ReportFile<'_>, offset: u32) -> Position {
+    let pos = file.index.position(offset, ColumnUnit::Char);
+    Position {
+        line: pos.line + 1,
+        column: pos.col + 1,
+    }
+}
+
+/// Keep a span inside its file and on character boundaries.
+fn clamp(span: Span, source: &str) -> Span {
+    let mut start = (span.start as usize).min(source.len());
+    while !source.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (span.end as usize).clamp(start, source.len());
+    while !source.is_char_boundary(end) {
+        end += 1;
+    }
+    Span::new(span.file_id, start as u32, end as u32)
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    }
+}
+
+fn push_optional(out: &mut String, value: Option<&str>) {
+    match value {
+        Some(value) => push_string(out, value),
+        None => out.push_str("null"),
+    }
+}
+
+/// Append `value` as a JSON string literal.
+fn push_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
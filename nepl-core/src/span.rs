@@ -3,6 +3,16 @@
 //! This module defines file identifiers and byte-range spans used
 //! for diagnostics and error reporting. All tokens and AST/HIR nodes
 //! are expected to be associated with a Span in later phases.
+//!
+//! Spans are byte offsets. `LineIndex` turns them into lines and
+//! columns, counted in UTF-8 bytes, UTF-16 code units (what LSP and
+//! JavaScript editors use) or Unicode scalar values, and back.
+//! `SourceFile` keeps a file's name, text and index together, and
+//! `SourceMap` holds every file of a compilation by `FileId`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
 
 /// Identifier for a source file.
 ///
@@ -15,8 +25,8 @@ pub struct FileId(pub u32);
 /// A half-open byte range `[start, end)` within a given file.
 ///
 /// Positions are expressed in bytes relative to the file content,
-/// not in character indices or line/column. Line/column information
-/// can be derived separately if needed.
+/// not in character indices or line/column. Use `LineIndex` (or
+/// `SourceFile`) to convert them to lines and columns.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Span {
     pub file_id: FileId,
@@ -73,3 +83,322 @@ impl Span {
         }
     }
 }
+
+/// The unit in which columns are counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColumnUnit {
+    /// Bytes of UTF-8.
+    #[default]
+    Utf8,
+    /// UTF-16 code units, as used by LSP and JavaScript.
+    Utf16,
+    /// Unicode scalar values (`char`s).
+    Char,
+}
+
+/// A 0-based line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+impl LineCol {
+    pub fn new(line: u32, col: u32) -> LineCol {
+        LineCol { line, col }
+    }
+}
+
+/// A character that takes more than one byte in UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WideChar {
+    /// Byte offset of the character in the file.
+    offset: u32,
+    len_utf8: u8,
+    len_utf16: u8,
+}
+
+impl WideChar {
+    fn len(self, unit: ColumnUnit) -> u32 {
+        match unit {
+            ColumnUnit::Utf8 => self.len_utf8 as u32,
+            ColumnUnit::Utf16 => self.len_utf16 as u32,
+            ColumnUnit::Char => 1,
+        }
+    }
+}
+
+/// Maps byte offsets of one source file to line / column positions.
+///
+/// Lines and columns are 0-based; `line_col` gives byte columns,
+/// `position` and `offset` convert in any `ColumnUnit`. Renderers add 1
+/// when printing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// Byte offset of the start of every line.
+    line_starts: Vec<u32>,
+    /// Byte offset of the end of every line, before `\n` / `\r\n`.
+    line_ends: Vec<u32>,
+    /// Non-ASCII characters in offset order; everything else is one
+    /// unit wide in every encoding.
+    wide_chars: Vec<WideChar>,
+    len: u32,
+}
+
+impl LineIndex {
+    /// Build the index of `source`. Lines end at `\n`; a preceding `\r`
+    /// belongs to the line it ends.
+    pub fn new(source: &str) -> LineIndex {
+        let mut line_starts = alloc::vec![0];
+        let mut line_ends = Vec::new();
+        let mut wide_chars = Vec::new();
+        let bytes = source.as_bytes();
+        for (i, c) in source.char_indices() {
+            if c == '\n' {
+                let cr = i > 0 && bytes[i - 1] == b'\r';
+                line_ends.push(i as u32 - cr as u32);
+                line_starts.push(i as u32 + 1);
+            } else if !c.is_ascii() {
+                wide_chars.push(WideChar {
+                    offset: i as u32,
+                    len_utf8: c.len_utf8() as u8,
+                    len_utf16: c.len_utf16() as u8,
+                });
+            }
+        }
+        line_ends.push(source.len() as u32);
+        LineIndex {
+            line_starts,
+            line_ends,
+            wide_chars,
+            len: source.len() as u32,
+        }
+    }
+
+    /// Number of lines (a trailing newline starts an empty last line).
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Length of the indexed text in bytes.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Returns true if the indexed text is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Line and byte column of `offset`, clamped to the end of the file.
+    pub fn line_col(&self, offset: u32) -> (u32, u32) {
+        let offset = offset.min(self.len);
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        (line as u32, offset - self.line_starts[line])
+    }
+
+    /// Byte range of `line` without its line terminator. Lines past the
+    /// end are empty ranges at the end of the file.
+    pub fn line_range(&self, line: u32) -> Range<usize> {
+        let line = line as usize;
+        match (self.line_starts.get(line), self.line_ends.get(line)) {
+            (Some(start), Some(end)) => *start as usize..*end as usize,
+            _ => self.len as usize..self.len as usize,
+        }
+    }
+
+    /// Position of the byte `offset`, with the column counted in `unit`.
+    /// The offset is clamped to the end of the file; an offset inside a
+    /// character counts as the start of that character.
+    pub fn position(&self, offset: u32, unit: ColumnUnit) -> LineCol {
+        let (line, byte_col) = self.line_col(offset);
+        let start = self.line_starts[line as usize];
+        let offset = start + byte_col;
+        let mut col = byte_col;
+        if unit != ColumnUnit::Utf8 {
+            for w in self.wide_chars_in(start..offset) {
+                let inside = offset < w.offset + w.len_utf8 as u32;
+                col -= if inside {
+                    offset - w.offset
+                } else {
+                    w.len_utf8 as u32 - w.len(unit)
+                };
+            }
+        }
+        LineCol { line, col }
+    }
+
+    /// Byte offset of `pos`, whose column is counted in `unit`. Columns
+    /// past the end of the line mean the end of the line (as in LSP).
+    /// Returns `None` for lines past the end of the file and for columns
+    /// that fall inside a character, such as between the two halves of
+    /// a UTF-16 surrogate pair.
+    pub fn offset(&self, pos: LineCol, unit: ColumnUnit) -> Option<u32> {
+        let line = pos.line as usize;
+        let start = *self.line_starts.get(line)?;
+        let end = self.line_ends[line];
+        // Walk the wide characters of the line, keeping the byte offset
+        // and column reached so far.
+        let (mut byte, mut col) = (start, 0);
+        for w in self.wide_chars_in(start..end) {
+            let ascii = w.offset - byte;
+            if pos.col < col + ascii {
+                return Some(byte + (pos.col - col));
+            }
+            col += ascii;
+            if pos.col == col {
+                return Some(w.offset);
+            }
+            if pos.col < col + w.len(unit) {
+                return None;
+            }
+            col += w.len(unit);
+            byte = w.offset + w.len_utf8 as u32;
+        }
+        Some((byte + (pos.col - col)).min(end))
+    }
+
+    /// Convert a column of `line` from one unit to another.
+    pub fn convert_col(
+        &self,
+        line: u32,
+        col: u32,
+        from: ColumnUnit,
+        to: ColumnUnit,
+    ) -> Option<u32> {
+        let offset = self.offset(LineCol::new(line, col), from)?;
+        Some(self.position(offset, to).col)
+    }
+
+    fn wide_chars_in(&self, range: Range<u32>) -> &[WideChar] {
+        let from = self.wide_chars.partition_point(|w| w.offset < range.start);
+        let to = self.wide_chars.partition_point(|w| w.offset < range.end);
+        &self.wide_chars[from..to]
+    }
+}
+
+/// A source file: its id, display name, text and line index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    id: FileId,
+    name: String,
+    source: String,
+    index: LineIndex,
+}
+
+impl SourceFile {
+    pub fn new(id: FileId, name: impl Into<String>, source: impl Into<String>) -> SourceFile {
+        let source = source.into();
+        let index = LineIndex::new(&source);
+        SourceFile {
+            id,
+            name: name.into(),
+            source,
+            index,
+        }
+    }
+
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    /// Display name, usually the path the file was read from.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn line_index(&self) -> &LineIndex {
+        &self.index
+    }
+
+    /// Text of `line` without its line terminator.
+    pub fn line_text(&self, line: u32) -> &str {
+        &self.source[self.index.line_range(line)]
+    }
+
+    /// Text covered by `span`, if it lies inside this file on character
+    /// boundaries.
+    pub fn snippet(&self, span: Span) -> Option<&str> {
+        if span.file_id != self.id {
+            return None;
+        }
+        self.source.get(span.start as usize..span.end as usize)
+    }
+
+    /// Position of the byte `offset`; see `LineIndex::position`.
+    pub fn position(&self, offset: u32, unit: ColumnUnit) -> LineCol {
+        self.index.position(offset, unit)
+    }
+
+    /// Byte offset of `pos`; see `LineIndex::offset`.
+    pub fn offset(&self, pos: LineCol, unit: ColumnUnit) -> Option<u32> {
+        self.index.offset(pos, unit)
+    }
+
+    /// Start and end position of `span`.
+    pub fn range(&self, span: Span, unit: ColumnUnit) -> (LineCol, LineCol) {
+        (
+            self.position(span.start, unit),
+            self.position(span.end, unit),
+        )
+    }
+}
+
+/// The source files of a compilation, by `FileId`.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    /// Add a file under the next unused id.
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        let id = FileId(self.files.iter().map(|f| f.id.0 + 1).max().unwrap_or(0));
+        self.files.push(SourceFile::new(id, name, source));
+        id
+    }
+
+    /// Add a file under a chosen id, replacing any file with that id.
+    pub fn insert(&mut self, file: SourceFile) {
+        self.files.retain(|f| f.id != file.id);
+        self.files.push(file);
+    }
+
+    pub fn get(&self, id: FileId) -> Option<&SourceFile> {
+        self.files.iter().find(|f| f.id == id)
+    }
+
+    pub fn name(&self, id: FileId) -> Option<&str> {
+        self.get(id).map(SourceFile::name)
+    }
+
+    pub fn source(&self, id: FileId) -> Option<&str> {
+        self.get(id).map(SourceFile::source)
+    }
+
+    /// Every file, in the order they were added.
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+
+    /// Text covered by `span`.
+    pub fn snippet(&self, span: Span) -> Option<&str> {
+        self.get(span.file_id)?.snippet(span)
+    }
+
+    /// Start and end position of `span` in its file.
+    pub fn range(&self, span: Span, unit: ColumnUnit) -> Option<(LineCol, LineCol)> {
+        Some(self.get(span.file_id)?.range(span, unit))
+    }
+}
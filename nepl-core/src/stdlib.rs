@@ -67,20 +67,10 @@ pub fn iter_stdlib_modules() -> &'static [StdlibModuleSpec] {
 
 /// Find a stdlib module by its logical name (e.g. "core.math").
 pub fn find_by_logical_name(name: &str) -> Option<&'static StdlibModuleSpec> {
-    for m in STDLIB_MODULES {
-        if m.logical_name == name {
-            return Some(m);
-        }
-    }
-    None
+    STDLIB_MODULES.iter().find(|m| m.logical_name == name)
 }
 
 /// Find a stdlib module by its relative path (e.g. "core/math.nepl").
 pub fn find_by_relative_path(path: &str) -> Option<&'static StdlibModuleSpec> {
-    for m in STDLIB_MODULES {
-        if m.relative_path == path {
-            return Some(m);
-        }
-    }
-    None
+    STDLIB_MODULES.iter().find(|m| m.relative_path == path)
 }
@@ -1,111 +1,904 @@
-//! Type checking and P-style resolution for NEPL (no_std, skeleton).
+//! Type checking and P-style resolution for NEPL (no_std).
 //!
 //! このモジュールは AST から HIR への変換と型検査を担当する。
 //!
 //! - P-style の記法（`f x y` のような曖昧な prefix 列）は、
-//!   ここでオーバーロード情報と型情報を見ながら解決する。
-//! - 現段階ではまだ完全なアルゴリズムは実装せず、
-//!   インタフェースと基本的な型付けロジックだけを用意する。
+//!   ここでオーバーロード情報と型情報を見ながら Frame スタックで解決する。
+//! - 型注釈 (`let x: T = e` や `T expr`) と関数リテラルのシグネチャは、
+//!   期待型 (expected type) としてリテラルの型付けやオーバーロード選択に使われる。
+//! - 関数リテラルは `HirFunction` としてモジュールレベルに持ち上げる (lifting)。
 
 #![allow(dead_code)]
 
+use alloc::boxed::Box;
+use alloc::format;
 use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
+use core::fmt;
 
 use crate::ast;
-use crate::diagnostic::Diagnostic;
-use crate::hir::{HirExpr, HirExprKind, HirIdent};
+use crate::builtins::{self, BuiltinKind};
+use crate::call_tree::{CallTree, ResolvedSeq};
+use crate::diagnostic::{Diagnostic, MessageKey};
+use crate::lint::{self, Lint, LintLevels};
+use crate::hir::{
+    HirAssignable, HirExpr, HirExprKind, HirFunction, HirIdent, HirMatchArm, HirParam, HirPattern,
+    HirStructPatternField, HirTest,
+};
 use crate::span::Span;
-use crate::types::{Type, least_common_supertype};
+use crate::types::{ArrowKind, SubtypeResult, Type, is_subtype, least_common_supertype};
 
 /// Result of type checking a single AST expression.
 ///
 /// - `expr` : 型付き HIR 表現（失敗したときは None の場合もある）
+/// - `functions` : `expr` の中の関数リテラルを持ち上げた関数
 /// - `diagnostics` : 収集したエラーや警告
+/// - `seqs` : P-style 列ごとの解決結果（呼び出しのグループ分け）
+/// - `tests` : `test` 宣言（テストビルドのときだけ）
 #[derive(Debug)]
 pub struct TypeCheckResult {
     pub expr: Option<HirExpr>,
+    pub functions: Vec<HirFunction>,
     pub diagnostics: Vec<Diagnostic>,
+    pub seqs: Vec<ResolvedSeq>,
+    pub tests: Vec<HirTest>,
 }
 
 /// Public entry point: type-check a root expression and produce HIR.
 ///
 /// 将来的には `HirModule` や複数ファイルにまたがる解析を行うが、
 /// ここではまず「ひとつの Expr」を対象とした関数を用意する。
+///
+/// Lints are reported at their default levels.
 pub fn typecheck_expr(root: &ast::Expr) -> TypeCheckResult {
-    let mut checker = TypeChecker::new();
-    let hir = checker.check_expr(root);
-    TypeCheckResult {
-        expr: hir,
-        diagnostics: checker.diagnostics,
+    typecheck_expr_with(root, &LintLevels::default())
+}
+
+/// Type-check a root expression, reporting lints at `levels`.
+pub fn typecheck_expr_with(root: &ast::Expr, levels: &LintLevels) -> TypeCheckResult {
+    TypeChecker::new(levels.clone(), false).run(root)
+}
+
+/// Type-check a root expression for a test build: the bodies of `test`
+/// declarations are checked and lifted into `functions`, and the tests
+/// are listed in `tests`. Other builds skip them entirely.
+pub fn typecheck_tests(root: &ast::Expr, levels: &LintLevels) -> TypeCheckResult {
+    TypeChecker::new(levels.clone(), true).run(root)
+}
+
+/// What a call resolves to once its overload is chosen.
+#[derive(Debug, Clone, PartialEq)]
+enum CalleeTarget {
+    /// A lifted `HirFunction`, by its unique symbol.
+    Function(String),
+    Builtin(BuiltinKind),
+    /// Constructor of the struct with this name.
+    Struct(String),
+    /// Constructor of an enum variant.
+    Variant { name: String, tag: u32 },
+    /// A function value (a variable of function type), called
+    /// indirectly.
+    Value(Box<HirExpr>),
+}
+
+/// Signature of something callable by name: a `fn` binding, an
+/// immutable `let` bound directly to a function literal, a builtin or
+/// a struct / enum constructor.
+#[derive(Debug, Clone, PartialEq)]
+struct FunctionSig {
+    target: CalleeTarget,
+    /// Type parameters, as they appear as `Type::Var` in `params` and
+    /// `result`.
+    type_params: Vec<String>,
+    /// Number of leading `type_params` inherited from enclosing generic
+    /// functions. Those are not inferred but passed through unchanged.
+    inherited: usize,
+    params: Vec<Type>,
+    /// Which parameters are `mut` (in-out). Empty when none are.
+    mut_params: Vec<bool>,
+    result: Type,
+    arrow: ArrowKind,
+}
+
+impl FunctionSig {
+    fn ty(&self) -> Type {
+        Type::Function {
+            params: self.params.clone(),
+            result: Box::new(self.result.clone()),
+            arrow: self.arrow,
+        }
+    }
+
+    fn symbol(&self) -> &str {
+        match &self.target {
+            CalleeTarget::Function(symbol) => symbol,
+            _ => "",
+        }
+    }
+}
+
+/// A user-defined enum or struct.
+#[derive(Debug, Clone)]
+enum TypeDef {
+    Struct {
+        params: Vec<String>,
+        fields: Vec<(String, Type)>,
+    },
+    Enum {
+        params: Vec<String>,
+        variants: Vec<(String, Vec<Type>)>,
+    },
+}
+
+impl TypeDef {
+    fn params(&self) -> &[String] {
+        match self {
+            TypeDef::Struct { params, .. } | TypeDef::Enum { params, .. } => params,
+        }
     }
 }
 
-/// Simple type environment for variables / functions.
+/// A name bound in some scope.
+#[derive(Debug, Clone)]
+enum Binding {
+    /// A local variable or parameter. `depth` is the function nesting
+    /// depth at which it was bound (0 = top level).
+    ///
+    /// `decl` is the span of the declared name, where `mut` can be
+    /// inserted to make the binding mutable (`None` when that is not
+    /// possible, e.g. for pattern bindings). `span` is the span of the
+    /// name in every case; `used` records whether it has been read and
+    /// `written` whether it is the target of a `set`. `param` marks
+    /// function parameters.
+    Value {
+        ty: Type,
+        mutable: bool,
+        depth: usize,
+        decl: Option<Span>,
+        span: Span,
+        used: bool,
+        written: bool,
+        param: bool,
+    },
+    /// One overload of a statically known function.
+    Function(FunctionSig),
+}
+
+/// Simple type environment for variables / functions / types.
 ///
 /// no_std のためハッシュマップは使わず、線形探索のベクタで実装する。
+/// `scopes` には各スコープの開始位置 (値, 型) を積む。
 #[derive(Debug, Default)]
 struct TypeEnv {
-    entries: Vec<(String, Type)>,
+    entries: Vec<(String, Binding)>,
+    types: Vec<(String, TypeDef)>,
+    scopes: Vec<(usize, usize)>,
 }
 
 impl TypeEnv {
     fn new() -> Self {
-        TypeEnv { entries: Vec::new() }
+        TypeEnv::default()
     }
 
-    fn insert(&mut self, name: String, ty: Type) {
-        self.entries.push((name, ty));
+    fn push_scope(&mut self) {
+        self.scopes.push((self.entries.len(), self.types.len()));
     }
 
-    fn lookup(&self, name: &str) -> Option<&Type> {
-        self.entries.iter().rev().find_map(|(n, t)| {
-            if n == name {
-                Some(t)
-            } else {
-                None
+    /// Leave the innermost scope, returning the values bound in it.
+    fn pop_scope(&mut self) -> Vec<(String, Binding)> {
+        match self.scopes.pop() {
+            Some((values, types)) => {
+                self.types.truncate(types);
+                self.entries.split_off(values)
             }
-        })
+            None => Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, name: String, binding: Binding) {
+        self.entries.push((name, binding));
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.entries
+            .iter()
+            .rev()
+            .find_map(|(n, b)| if n == name { Some(b) } else { None })
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Binding> {
+        self.entries
+            .iter_mut()
+            .rev()
+            .find_map(|(n, b)| if n == name { Some(b) } else { None })
+    }
+
+    fn lookup_type(&self, name: &str) -> Option<&TypeDef> {
+        self.types
+            .iter()
+            .rev()
+            .find_map(|(n, d)| if n == name { Some(d) } else { None })
+    }
+
+    fn lookup_type_mut(&mut self, name: &str) -> Option<&mut TypeDef> {
+        self.types
+            .iter_mut()
+            .rev()
+            .find_map(|(n, d)| if n == name { Some(d) } else { None })
+    }
+
+    /// Collect the visible function overloads for `name`, innermost
+    /// first. A value binding hides every function further out, and an
+    /// inner overload hides an outer one with the same parameter types.
+    ///
+    /// Returns the overloads and whether the search reached the
+    /// outermost scope (i.e. builtins are still visible).
+    fn functions(&self, name: &str) -> (Vec<FunctionSig>, bool) {
+        let mut out: Vec<FunctionSig> = Vec::new();
+        for (n, b) in self.entries.iter().rev() {
+            if n != name {
+                continue;
+            }
+            match b {
+                Binding::Value { .. } => return (out, false),
+                Binding::Function(sig) => {
+                    if !out.iter().any(|o| o.params == sig.params) {
+                        out.push(sig.clone());
+                    }
+                }
+            }
+        }
+        (out, true)
     }
 }
 
-/// Core type checker.
+/// One overload candidate of a call frame.
+///
+/// The type parameters of the signature are renamed to `?T` so that
+/// they cannot be confused with the (rigid) type parameters of the
+/// function being checked; `subst` records what has been inferred so
+/// far.
+#[derive(Debug, Clone)]
+struct Candidate {
+    target: CalleeTarget,
+    type_params: Vec<String>,
+    params: Vec<Type>,
+    mut_params: Vec<bool>,
+    result: Type,
+    arrow: ArrowKind,
+    subst: Vec<(String, Type)>,
+}
+
+impl Candidate {
+    fn instantiate(sig: FunctionSig) -> Candidate {
+        let renaming: Vec<(String, Type)> = sig
+            .type_params
+            .iter()
+            .map(|p| (p.clone(), Type::Var(format!("?{p}"))))
+            .collect();
+        // Inherited parameters stay what they are in the enclosing
+        // function.
+        let subst = sig
+            .type_params
+            .iter()
+            .take(sig.inherited)
+            .map(|p| (format!("?{p}"), Type::Var(p.clone())))
+            .collect();
+        Candidate {
+            target: sig.target,
+            type_params: sig.type_params.iter().map(|p| format!("?{p}")).collect(),
+            params: sig.params.iter().map(|p| p.substitute(&renaming)).collect(),
+            mut_params: sig.mut_params,
+            result: sig.result.substitute(&renaming),
+            arrow: sig.arrow,
+            subst,
+        }
+    }
+
+    fn is_generic(&self) -> bool {
+        !self.type_params.is_empty()
+    }
+
+    fn is_mut_param(&self, n: usize) -> bool {
+        self.mut_params.get(n).copied().unwrap_or(false)
+    }
+
+    /// Parameter `n` with the inferred type arguments applied, or
+    /// `None` if it still depends on an uninferred type parameter.
+    fn resolved_param(&self, n: usize) -> Option<Type> {
+        let t = self.params.get(n)?.substitute(&self.subst);
+        if self.mentions_params(&t) { None } else { Some(t) }
+    }
+
+    fn mentions_params(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Var(v) => self.type_params.contains(v),
+            Type::Apply { args, .. } => args.iter().any(|a| self.mentions_params(a)),
+            Type::Function { params, result, .. } => {
+                params.iter().any(|p| self.mentions_params(p)) || self.mentions_params(result)
+            }
+            _ => false,
+        }
+    }
+
+    /// Try to pass `arg` as argument `n`, inferring type arguments.
+    fn accept(&mut self, n: usize, arg: &Arg) -> bool {
+        let Some(param) = self.params.get(n).cloned() else {
+            return false;
+        };
+        match arg {
+            Arg::Typed(h) => unify(&param, &h.ty, &self.type_params, &mut self.subst),
+            Arg::Literal(lit, _) => {
+                let default = match lit {
+                    ast::LiteralKind::Int(_) => Type::I32,
+                    ast::LiteralKind::Float(_) => Type::F64,
+                    _ => return false,
+                };
+                let param = param.substitute(&self.subst);
+                if let Type::Var(v) = &param
+                    && self.type_params.contains(v)
+                {
+                    self.subst.push((v.clone(), default));
+                    return true;
+                }
+                match lit {
+                    ast::LiteralKind::Int(_) => matches!(param, Type::I32 | Type::I64),
+                    _ => matches!(param, Type::F32 | Type::F64),
+                }
+            }
+        }
+    }
+
+    /// Narrow the type parameters using the type the call must have.
+    /// Returns false if the result can never have that type.
+    fn expect_result(&mut self, expected: &Type) -> bool {
+        let mut subst = self.subst.clone();
+        if unify(&self.result, expected, &self.type_params, &mut subst) {
+            self.subst = subst;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// An argument collected by a frame.
 ///
-/// ここではまだ P-style の解決や複雑な制約処理は実装していない。
+/// Numeric literals whose type is not yet determined stay `Literal`
+/// until the overload is chosen, so that `add 1 x` with `x: i64`
+/// picks the `i64` overload instead of defaulting `1` to `i32`.
+#[derive(Debug, Clone)]
+enum Arg {
+    Typed(HirExpr),
+    Literal(ast::LiteralKind, Span),
+}
+
+impl Arg {
+    fn span(&self) -> Span {
+        match self {
+            Arg::Typed(h) => h.span,
+            Arg::Literal(_, span) => *span,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum FrameKind {
+    /// A (possibly overloaded) function waiting for arguments.
+    Call {
+        name: String,
+        name_span: Span,
+        candidates: Vec<Candidate>,
+        /// Set once no candidate accepts the arguments; the frame then
+        /// just swallows `arity` arguments for error recovery.
+        failed: bool,
+        arity: usize,
+        /// Type expected of the whole application; used when the name
+        /// ends up being taken as a function value.
+        expected: Option<Type>,
+    },
+    /// A type annotation `T expr` waiting for its single operand.
+    Annotate(Type),
+}
+
+/// One in-progress application on the P-style frame stack.
+#[derive(Debug)]
+struct Frame {
+    kind: FrameKind,
+    args: Vec<Arg>,
+    /// How each argument was grouped, for `TypeCheckResult::seqs`.
+    trees: Vec<CallTree>,
+    span: Span,
+}
+
+impl Frame {
+    /// The grouping of this application and its arguments so far.
+    fn call_tree(&self) -> CallTree {
+        let span = self
+            .trees
+            .last()
+            .and_then(|t| self.span.join(t.span()))
+            .unwrap_or(self.span);
+        CallTree::Apply {
+            head: self.span,
+            args: self.trees.clone(),
+            span,
+        }
+    }
+
+    /// The parameter type the next argument must have, if every
+    /// remaining candidate agrees on it.
+    fn next_expected(&self) -> Option<Type> {
+        let n = self.args.len();
+        match &self.kind {
+            FrameKind::Annotate(ty) => Some(ty.clone()),
+            FrameKind::Call {
+                candidates, failed, ..
+            } => {
+                if *failed {
+                    return None;
+                }
+                let mut it = candidates.iter().filter(|c| c.params.len() > n);
+                let first = it.next()?.resolved_param(n)?;
+                if it.all(|c| c.resolved_param(n).as_ref() == Some(&first)) {
+                    Some(first)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        let n = self.args.len();
+        match &self.kind {
+            FrameKind::Annotate(_) => n == 1,
+            FrameKind::Call {
+                candidates,
+                failed,
+                arity,
+                ..
+            } => {
+                if *failed {
+                    n >= *arity
+                } else {
+                    !candidates.is_empty() && candidates.iter().all(|c| c.params.len() == n)
+                }
+            }
+        }
+    }
+}
+
+/// An item of a P-style sequence. Pipes inject an already checked
+/// left-hand side into the sequence of the right-hand side.
+enum SeqItem<'a> {
+    Ast(&'a ast::Expr),
+    Typed(HirExpr),
+}
+
+/// How an identifier in a P-style sequence is interpreted.
+enum IdentClass {
+    Value { ty: Type, depth: usize },
+    Callable(Vec<Candidate>),
+    Type(Type),
+    Unknown,
+}
+
+/// Enclosing function literal being checked.
+struct FnCtx {
+    result: Type,
+    arrow: ArrowKind,
+    /// Whether the body calls an impure function.
+    impure: bool,
+    /// Variables of enclosing functions referred to so far.
+    captures: Vec<(String, Type)>,
+}
+
+/// Enclosing `loop` / `while` being checked.
+struct LoopCtx {
+    is_while: bool,
+    break_ty: Option<Type>,
+    has_break: bool,
+    plain_break: bool,
+}
+
+/// Outcome of checking a `let` binding inside a block.
+enum LetOutcome {
+    /// A runtime binding, in scope for the rest of the block.
+    Bound {
+        name: HirIdent,
+        mutable: bool,
+        init: HirExpr,
+    },
+    /// `let _ = expr`: evaluated for its effects only.
+    Discard(HirExpr),
+    /// Bound to a lifted function; no runtime value.
+    Static,
+}
+
+/// Core type checker.
 struct TypeChecker {
     env: TypeEnv,
     pub diagnostics: Vec<Diagnostic>,
+    levels: LintLevels,
+    functions: Vec<HirFunction>,
+    symbols: Vec<(String, usize)>,
+    fn_stack: Vec<FnCtx>,
+    loops: Vec<LoopCtx>,
+    /// Type parameters in scope, outermost function first.
+    type_vars: Vec<String>,
+    /// Resolved source sequences, and the groups of the sequence being
+    /// resolved.
+    seqs: Vec<ResolvedSeq>,
+    seq_groups: Vec<CallTree>,
+    /// Whether `test` declarations are compiled (see `typecheck_tests`).
+    test_mode: bool,
+    tests: Vec<HirTest>,
 }
 
 impl TypeChecker {
-    fn new() -> Self {
+    fn new(levels: LintLevels, test_mode: bool) -> Self {
         TypeChecker {
             env: TypeEnv::new(),
             diagnostics: Vec::new(),
+            levels,
+            functions: Vec::new(),
+            symbols: Vec::new(),
+            fn_stack: Vec::new(),
+            loops: Vec::new(),
+            type_vars: Vec::new(),
+            seqs: Vec::new(),
+            seq_groups: Vec::new(),
+            test_mode,
+            tests: Vec::new(),
+        }
+    }
+
+    fn run(mut self, root: &ast::Expr) -> TypeCheckResult {
+        let hir = self.check_expr(root);
+        TypeCheckResult {
+            expr: hir,
+            functions: self.functions,
+            diagnostics: self.diagnostics,
+            seqs: self.seqs,
+            tests: self.tests,
+        }
+    }
+
+    /// Report the catalog message `key` with named arguments.
+    fn error(&mut self, key: &'static str, span: Span, args: &[(&'static str, &dyn fmt::Display)]) {
+        let diag = keyed(key, span, args);
+        self.diagnostics.push(diag);
+    }
+
+    /// Report `diag` if `lint` is not allowed here.
+    fn lint(&mut self, lint: &Lint, diag: Diagnostic) {
+        if let Some(diag) = self.levels.report(lint, diag) {
+            self.diagnostics.push(diag);
+        }
+    }
+
+    /// Bring a variable into scope, noting when it shadows another one.
+    fn bind_value(&mut self, name: String, binding: Binding) {
+        if let (Binding::Value { span, .. }, Some(Binding::Value { span: previous, .. })) =
+            (&binding, self.env.lookup(&name))
+            && !name.starts_with('_')
+        {
+            let diag = Diagnostic::warning_key("W0003", *span)
+                .arg("name", &name)
+                .with_secondary_label(*previous, None);
+            self.lint(&lint::SHADOWING, diag);
+        }
+        self.env.insert(name, binding);
+    }
+
+    /// Record that the variable `name` is read.
+    fn mark_used(&mut self, name: &str) {
+        if let Some(Binding::Value { used, .. }) = self.env.lookup_mut(name) {
+            *used = true;
+        }
+    }
+
+    /// Record that the variable `name` is assigned with `set`. The
+    /// final value of a `mut` parameter is returned to the caller, so
+    /// writing it counts as a use.
+    fn mark_written(&mut self, name: &str) {
+        if let Some(Binding::Value {
+            used,
+            written,
+            param,
+            ..
+        }) = self.env.lookup_mut(name)
+        {
+            *written = true;
+            *used |= *param;
+        }
+    }
+
+    /// Leave a scope, reporting the variables that were never read.
+    fn pop_scope(&mut self) {
+        for (name, binding) in self.env.pop_scope() {
+            if let Binding::Value {
+                span,
+                used: false,
+                written,
+                ..
+            } = binding
+                && !name.starts_with('_')
+            {
+                let mut diag = Diagnostic::warning_key("W0001", span).arg("name", &name);
+                // Renaming the declaration alone would break the `set`s
+                // that refer to it.
+                if !written {
+                    diag = diag.with_suggestion_key(
+                        MessageKey::new("suggest.unused-underscore"),
+                        Span::empty(span.file_id, span.start),
+                        "_",
+                    );
+                }
+                self.lint(&lint::UNUSED_VARIABLES, diag);
+            }
+        }
+    }
+
+    /// Report an unknown name, suggesting a visible name that is one
+    /// edit away.
+    fn unresolved(&mut self, ident: &ast::Ident) {
+        let mut diag = Diagnostic::error_key("E0200", ident.span).arg("name", &ident.name);
+        if let Some((name, key)) = self.similar_name(&ident.name) {
+            let message = MessageKey::new(key).arg("name", &name);
+            diag = diag.with_suggestion_key(message, ident.span, name);
+        }
+        self.diagnostics.push(diag);
+    }
+
+    /// A visible name one edit (insertion, deletion, substitution or
+    /// transposition) away from `name`; innermost bindings come first.
+    fn similar_name(&self, name: &str) -> Option<(String, &'static str)> {
+        let locals = self.env.entries.iter().rev().map(|(n, b)| {
+            let kind = match b {
+                Binding::Value { .. } => "suggest.similar-variable",
+                Binding::Function(_) => "suggest.similar-function",
+            };
+            (n.as_str(), kind)
+        });
+        let builtins = builtins::builtins()
+            .into_iter()
+            .map(|b| (b.name, "suggest.similar-builtin"));
+        locals
+            .chain(builtins)
+            .find(|(candidate, _)| {
+                // 1 文字同士は何でも 1 編集で届くので候補にしない。
+                name.len().max(candidate.len()) >= 3 && edit_distance(name, candidate) == 1
+            })
+            .map(|(candidate, kind)| (candidate.to_string(), kind))
+    }
+
+    /// Suggest declaring the variable `name` as `mut`, if possible.
+    fn suggest_mut(&self, diag: Diagnostic, name: &str) -> Diagnostic {
+        match self.env.lookup(name) {
+            Some(Binding::Value {
+                decl: Some(decl), ..
+            }) => diag.with_suggestion_key(
+                MessageKey::new("suggest.mut"),
+                Span::empty(decl.file_id, decl.start),
+                "mut ",
+            ),
+            _ => diag,
+        }
+    }
+
+    /// Placeholder for an expression that failed to type-check.
+    ///
+    /// It is typed `Error` so that it fits any context and does not
+    /// cause follow-up errors.
+    fn error_expr(span: Span) -> HirExpr {
+        HirExpr {
+            kind: HirExprKind::Unit,
+            ty: Type::Error,
+            span,
+        }
+    }
+
+    fn unit_expr(span: Span) -> HirExpr {
+        HirExpr {
+            kind: HirExprKind::Unit,
+            ty: Type::Unit,
+            span,
+        }
+    }
+
+    fn merge_spans(a: Span, b: Span) -> Span {
+        a.join(b).unwrap_or(a)
+    }
+
+    /// Current function nesting depth (0 = top level).
+    fn depth(&self) -> usize {
+        self.fn_stack.len()
+    }
+
+    /// Allocate a unique symbol name for a lifted function.
+    fn fresh_symbol(&mut self, base: &str) -> String {
+        for (name, count) in self.symbols.iter_mut() {
+            if name == base {
+                *count += 1;
+                return format!("{base}#{count}");
+            }
+        }
+        self.symbols.push((base.to_string(), 0));
+        base.to_string()
+    }
+
+    fn require_subtype(&mut self, expr: &HirExpr, expected: &Type) {
+        if is_subtype(&expr.ty, expected) == SubtypeResult::NotSubtype {
+            self.error("E0300", expr.span, &[("expected", expected), ("found", &expr.ty)]);
+        }
+    }
+
+    /// Check an expression against an expected type and report a
+    /// mismatch. The result is always an expression (possibly an error
+    /// placeholder).
+    fn check_expected(&mut self, expr: &ast::Expr, expected: Option<&Type>) -> HirExpr {
+        let hir = self
+            .check_expr_with(expr, expected)
+            .unwrap_or_else(|| Self::error_expr(expr.span));
+        if let Some(exp) = expected {
+            self.require_subtype(&hir, exp);
         }
+        hir
     }
 
-    fn error(&mut self, span: Span, msg: &str) {
-        self.diagnostics.push(Diagnostic::error(msg.to_string(), span));
+    // === types ================================================================
+
+    /// Resolve a parsed type expression into a `Type`.
+    fn resolve_type_expr(&mut self, ty: &ast::TypeExpr) -> Type {
+        match &ty.kind {
+            ast::TypeExprKind::Named(name) => {
+                if self.type_vars.contains(name) {
+                    return Type::Var(name.clone());
+                }
+                if let Some(t) = lookup_type_name(name) {
+                    return t;
+                }
+                match self.env.lookup_type(name).map(|d| d.params().len()) {
+                    Some(0) => Type::Named(name.clone()),
+                    Some(n) => {
+                        self.error("E0301", ty.span, &[("name", name), ("count", &n)]);
+                        Type::Error
+                    }
+                    None => {
+                        self.error("E0201", ty.span, &[("name", name)]);
+                        Type::Error
+                    }
+                }
+            }
+            ast::TypeExprKind::Apply { name, args } => {
+                let args: Vec<Type> = args.iter().map(|a| self.resolve_type_expr(a)).collect();
+                match self.env.lookup_type(name).map(|d| d.params().len()) {
+                    Some(n) if n == args.len() => Type::Apply {
+                        name: name.clone(),
+                        args,
+                    },
+                    Some(n) => {
+                        let given = args.len();
+                        self.error("E0301.given", ty.span, &[("name", name), ("count", &n), ("given", &given)]);
+                        Type::Error
+                    }
+                    None => {
+                        self.error("E0201.generic", ty.span, &[("name", name)]);
+                        Type::Error
+                    }
+                }
+            }
+            ast::TypeExprKind::Fn {
+                params,
+                result,
+                arrow,
+            } => {
+                let params = params.iter().map(|p| self.resolve_type_expr(p)).collect();
+                let result = self.resolve_type_expr(result);
+                Type::Function {
+                    params,
+                    result: Box::new(result),
+                    arrow: *arrow,
+                }
+            }
+            ast::TypeExprKind::Paren(inner) => self.resolve_type_expr(inner),
+        }
     }
 
+    // === expressions ==========================================================
+
     /// 型推論のメイン入口。
     fn check_expr(&mut self, expr: &ast::Expr) -> Option<HirExpr> {
+        self.check_expr_with(expr, None)
+    }
+
+    /// Type-check `expr`, using `expected` as a hint for literal typing
+    /// and overload selection. The caller is responsible for verifying
+    /// the resulting type (see `check_expected`).
+    fn check_expr_with(&mut self, expr: &ast::Expr, expected: Option<&Type>) -> Option<HirExpr> {
         use ast::ExprKind;
 
         match &expr.kind {
-            ExprKind::Literal(lit) => self.check_literal(expr.span, lit),
-            ExprKind::Ident(ident) => self.check_ident(expr.span, ident),
+            ExprKind::Literal(lit) => self.check_literal(expr.span, lit, expected),
+            ExprKind::Ident(_) => {
+                self.check_pstyle_seq(expr.span, vec![SeqItem::Ast(expr)], expected)
+            }
+
+            ExprKind::Seq(items) => {
+                let items = items.iter().map(SeqItem::Ast).collect();
+                self.check_pstyle_seq(expr.span, items, expected)
+            }
+
+            ExprKind::Paren(inner) => self.check_expr_with(inner, expected),
 
-            ExprKind::Seq(items) => self.check_pstyle_seq(expr.span, items),
+            ExprKind::Pipe(pipe) => self.check_pipe(expr.span, pipe, expected),
 
-            ExprKind::Pipe(pipe) => self.check_pipe(expr.span, pipe),
+            ExprKind::Block(block) => self.check_block(expr.span, block, expected),
 
-            ExprKind::Block(block) => self.check_block(expr.span, block),
+            ExprKind::Let(let_expr) => {
+                // A `let` outside of a block scopes over nothing; it is
+                // wrapped in a block of its own for the backends.
+                self.env.push_scope();
+                let outcome = self.check_let(let_expr);
+                let hir = self.finish_let(expr.span, outcome);
+                self.pop_scope();
+                Some(HirExpr {
+                    kind: HirExprKind::Block {
+                        expressions: vec![hir],
+                    },
+                    ty: Type::Unit,
+                    span: expr.span,
+                })
+            }
+
+            ExprKind::LetFunction(func) => {
+                if let Some(sig) = self.declare_function(func) {
+                    self.define_function(func, &sig);
+                }
+                Some(Self::unit_expr(expr.span))
+            }
+
+            ExprKind::FuncLiteral(lit) => {
+                let sig = self.signature_of(lit, "lambda");
+                let captures = self.check_func_literal(lit, &sig, lit.span);
+                if !lit.type_params.is_empty() {
+                    self.error("E0502", lit.span, &[]);
+                    return Some(Self::error_expr(expr.span));
+                }
+                Some(self.closure(&sig, &captures, expr.span))
+            }
+
+            ExprKind::If(if_expr) => self.check_if(expr.span, if_expr, expected),
+            ExprKind::While(while_expr) => self.check_while(expr.span, while_expr),
+            ExprKind::Loop(loop_expr) => self.check_loop(expr.span, loop_expr),
+            ExprKind::Break(br) => self.check_break(expr.span, br),
+            ExprKind::Continue(_) => {
+                if self.loops.is_empty() {
+                    self.error("E0700.continue", expr.span, &[]);
+                }
+                Some(HirExpr::never_continue(expr.span))
+            }
+            ExprKind::Return(ret) => self.check_return(expr.span, ret),
+            ExprKind::Set(set) => self.check_set(expr.span, set),
+            ExprKind::Match(m) => self.check_match(expr.span, m, expected),
+            ExprKind::Test(test) => {
+                if self.test_mode {
+                    self.check_test(test);
+                }
+                Some(Self::unit_expr(expr.span))
+            }
+
+            ExprKind::EnumDef(_) | ExprKind::StructDef(_) => {
+                self.declare_type_defs(&[expr]);
+                Some(Self::unit_expr(expr.span))
+            }
 
             // ここではまだ未対応の構文については Unit 型＋診断を返す。
             _ => {
-                self.error(expr.span, "type checking for this expression kind is not implemented yet");
+                self.error("E0800.expression", expr.span, &[]);
                 Some(HirExpr {
                     kind: HirExprKind::Unit,
                     ty: Type::Unit,
@@ -119,18 +912,58 @@ impl TypeChecker {
         &mut self,
         span: Span,
         lit: &ast::LiteralKind,
+        expected: Option<&Type>,
     ) -> Option<HirExpr> {
         use ast::LiteralKind as L;
 
         let (kind, ty) = match lit {
-            L::Int(text) => {
-                // とりあえず i32 として扱う（将来は suffix などで変更可能）
-                let value = text.parse::<i32>().unwrap_or(0);
-                (HirExprKind::I32(value), Type::I32)
+            L::Int(int) => {
+                // 接尾辞があればその型、なければ期待型が i64 なら i64（既定は i32）
+                let ty = match (&int.suffix, expected) {
+                    (Some(ty), _) => ty.clone(),
+                    (None, Some(Type::I64)) => Type::I64,
+                    _ => Type::I32,
+                };
+                let max = if ty == Type::I64 {
+                    i64::MAX as u64
+                } else {
+                    i32::MAX as u64
+                };
+                let value = match int.value {
+                    Some(value) if value <= max => value,
+                    _ => {
+                        self.literal_out_of_range(span, &ty, int);
+                        0
+                    }
+                };
+                let kind = if ty == Type::I64 {
+                    HirExprKind::I64(value as i64)
+                } else {
+                    HirExprKind::I32(value as i32)
+                };
+                (kind, ty)
             }
-            L::Float(text) => {
-                let value = text.parse::<f64>().unwrap_or(0.0);
-                (HirExprKind::F64(value), Type::F64)
+            L::Float(float) => {
+                let ty = match (&float.suffix, expected) {
+                    (Some(ty), _) => ty.clone(),
+                    (None, Some(Type::F32)) => Type::F32,
+                    _ => Type::F64,
+                };
+                // Malformed literals were reported by the lexer.
+                let kind = if ty == Type::F32 {
+                    let value = float.text.parse::<f32>().unwrap_or(0.0);
+                    if value.is_infinite() {
+                        self.error("E0309", span, &[("ty", &ty)]);
+                    }
+                    HirExprKind::F32(value)
+                } else {
+                    let value = float.text.parse::<f64>().unwrap_or(0.0);
+                    if value.is_infinite() {
+                        self.error("E0309", span, &[("ty", &ty)]);
+                    }
+                    HirExprKind::F64(value)
+                };
+                (kind, ty)
             }
             L::String(s) => (HirExprKind::String(s.clone()), Type::Named("String".into())),
             L::Bool(b) => (HirExprKind::Bool(*b), Type::Bool),
@@ -139,151 +972,1776 @@ impl TypeChecker {
         Some(HirExpr { kind, ty, span })
     }
 
-    fn check_ident(
-        &mut self,
-        span: Span,
-        ident: &ast::Ident,
-    ) -> Option<HirExpr> {
-        let name = ident.name.clone();
-        let ty = match self.env.lookup(&name) {
-            Some(t) => t.clone(),
-            None => {
-                // 未定義識別子として警告し、とりあえず Unit を付ける
-                self.error(span, "unresolved identifier");
-                Type::Unit
+    /// Report an integer literal that does not fit `ty`, suggesting an
+    /// `i64` suffix when that would fit.
+    fn literal_out_of_range(&mut self, span: Span, ty: &Type, int: &ast::IntLiteral) {
+        let mut diag = keyed("E0309", span, &[("ty", ty)]);
+        if int.suffix.is_none() && int.value.is_some_and(|v| v <= i64::MAX as u64) {
+            diag = diag.with_suggestion_key(
+                MessageKey::new("suggest.i64-suffix"),
+                Span::empty(span.file_id, span.end),
+                "i64",
+            );
+        }
+        self.diagnostics.push(diag);
+    }
+
+    /// Classify an identifier that appears in a P-style sequence.
+    fn classify_ident(&self, name: &str) -> IdentClass {
+        if let Some(Binding::Value { ty, depth, .. }) = self.env.lookup(name) {
+            return IdentClass::Value {
+                ty: ty.clone(),
+                depth: *depth,
+            };
+        }
+
+        let (mut sigs, builtins_visible) = self.env.functions(name);
+        if builtins_visible {
+            for b in builtins::find_builtins(name) {
+                if let Type::Function {
+                    params,
+                    result,
+                    arrow,
+                } = b.ty
+                    && !sigs.iter().any(|s| s.params == params)
+                {
+                    sigs.push(FunctionSig {
+                        target: CalleeTarget::Builtin(b.kind),
+                        type_params: b.type_params.iter().map(|p| p.to_string()).collect(),
+                        inherited: 0,
+                        params,
+                        mut_params: Vec::new(),
+                        result: *result,
+                        arrow,
+                    });
+                }
             }
-        };
+        }
 
-        let hid = HirIdent { name, span };
-        Some(HirExpr {
-            kind: HirExprKind::Var(hid),
-            ty,
-            span,
-        })
+        if !sigs.is_empty() {
+            return IdentClass::Callable(sigs.into_iter().map(Candidate::instantiate).collect());
+        }
+        if let Some(ty) = lookup_type_name(name) {
+            return IdentClass::Type(ty);
+        }
+        if self.type_vars.iter().any(|v| v == name) {
+            return IdentClass::Type(Type::Var(name.to_string()));
+        }
+        if let Some(def) = self.env.lookup_type(name)
+            && def.params().is_empty()
+        {
+            return IdentClass::Type(Type::Named(name.to_string()));
+        }
+        IdentClass::Unknown
     }
 
     /// P-style prefix sequence `f x y ...`.
     ///
-    /// 現段階では「最後の term の型を結果とみなす」簡易版で、
-    /// 実際の関数適用やオーバーロード解決は未実装。
+    /// 左から順に term を読み、関数を見たら Frame を push し、
+    /// 値を見たらトップの Frame に引数として渡す。
+    /// 引数が揃った Frame は閉じて `Call` ノードとなり、親 Frame の引数になる。
     fn check_pstyle_seq(
         &mut self,
         span: Span,
-        items: &[ast::Expr],
+        items: Vec<SeqItem<'_>>,
+        expected: Option<&Type>,
     ) -> Option<HirExpr> {
         if items.is_empty() {
-            self.error(span, "empty P-style sequence");
-            return Some(HirExpr {
-                kind: HirExprKind::Unit,
-                ty: Type::Unit,
-                span,
-            });
+            self.error("E0302.empty", span, &[]);
+            return Some(Self::unit_expr(span));
         }
 
-        // 今は単純に「各 term の型を推論し、最後の型を返す」だけにしている。
-        let mut last_hir: Option<HirExpr> = None;
-        for e in items {
-            let h = self.check_expr(e);
-            if h.is_some() {
-                last_hir = h;
+        let single = items.len() == 1;
+        // Only sequences written in the source are recorded, not the
+        // steps of a pipe.
+        let from_source = !single && items.iter().all(|i| matches!(i, SeqItem::Ast(_)));
+        let outer_groups = core::mem::take(&mut self.seq_groups);
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut values: Vec<HirExpr> = Vec::new();
+
+        for item in items {
+            // Expected type for the next term: the parameter type the
+            // innermost frame wants, or the overall expectation for a
+            // lone term.
+            let hint = match frames.last() {
+                Some(f) => f.next_expected(),
+                None if single => expected.cloned(),
+                None => None,
+            };
+
+            let expr = match item {
+                SeqItem::Typed(h) => {
+                    let term = CallTree::Term(h.span);
+                    self.feed(&mut frames, &mut values, Arg::Typed(h), term);
+                    continue;
+                }
+                SeqItem::Ast(e) => e,
+            };
+
+            match &expr.kind {
+                ast::ExprKind::Ident(ident) => match self.classify_ident(&ident.name) {
+                    IdentClass::Value { ty, depth } => {
+                        self.mark_used(&ident.name);
+                        self.capture(&ident.name, &ty, depth);
+                        let h = HirExpr {
+                            kind: HirExprKind::Var(HirIdent {
+                                name: ident.name.clone(),
+                                span: ident.span,
+                            }),
+                            ty: ty.clone(),
+                            span: ident.span,
+                        };
+                        match ty {
+                            // A function value is called like a function,
+                            // unless a function is what is expected here.
+                            Type::Function {
+                                params,
+                                result,
+                                arrow,
+                            } if !matches!(hint, Some(Type::Function { .. })) => {
+                                let sig = FunctionSig {
+                                    target: CalleeTarget::Value(Box::new(h)),
+                                    type_params: Vec::new(),
+                                    inherited: 0,
+                                    params,
+                                    mut_params: Vec::new(),
+                                    result: *result,
+                                    arrow,
+                                };
+                                let frame_expected = if frames.is_empty() {
+                                    expected.cloned()
+                                } else {
+                                    hint
+                                };
+                                self.push_call_frame(
+                                    &mut frames,
+                                    &mut values,
+                                    ident,
+                                    vec![Candidate::instantiate(sig)],
+                                    frame_expected.as_ref(),
+                                );
+                            }
+                            _ => self.feed(
+                                &mut frames,
+                                &mut values,
+                                Arg::Typed(h),
+                                CallTree::Term(ident.span),
+                            ),
+                        }
+                    }
+                    IdentClass::Callable(candidates) => {
+                        if let Some(fn_ty @ Type::Function { .. }) = hint.as_ref()
+                            && !frames.is_empty()
+                        {
+                            // The enclosing call wants a function value here.
+                            let h = self.function_value(ident, &candidates, Some(fn_ty));
+                            let term = CallTree::Term(ident.span);
+                            self.feed(&mut frames, &mut values, Arg::Typed(h), term);
+                        } else {
+                            let frame_expected = if frames.is_empty() {
+                                expected.cloned()
+                            } else {
+                                hint
+                            };
+                            self.push_call_frame(
+                                &mut frames,
+                                &mut values,
+                                ident,
+                                candidates,
+                                frame_expected.as_ref(),
+                            );
+                        }
+                    }
+                    IdentClass::Type(ty) => {
+                        frames.push(Frame {
+                            kind: FrameKind::Annotate(ty),
+                            args: Vec::new(),
+                            trees: Vec::new(),
+                            span: ident.span,
+                        });
+                    }
+                    IdentClass::Unknown => {
+                        self.unresolved(ident);
+                        let h = Self::error_expr(ident.span);
+                        let term = CallTree::Term(ident.span);
+                        self.feed(&mut frames, &mut values, Arg::Typed(h), term);
+                    }
+                },
+                ast::ExprKind::Literal(
+                    lit @ (ast::LiteralKind::Int(_) | ast::LiteralKind::Float(_)),
+                ) if hint.is_none() && !frames.is_empty() && lit.suffix().is_none() => {
+                    // Defer numeric literal typing until the overload is known.
+                    let arg = Arg::Literal(lit.clone(), expr.span);
+                    self.feed(&mut frames, &mut values, arg, CallTree::Term(expr.span));
+                }
+                _ => {
+                    let h = self
+                        .check_expr_with(expr, hint.as_ref())
+                        .unwrap_or_else(|| Self::error_expr(expr.span));
+                    self.feed(&mut frames, &mut values, Arg::Typed(h), CallTree::Term(expr.span));
+                }
             }
         }
 
-        if let Some(h) = last_hir {
-            // TODO: P-style の解決と関数呼び出しへの変換をここで行う
-            // 今は「実装されていない」という warning 的な Diagnostic を出す。
-            self.error(span, "P-style call resolution is not implemented yet");
-            Some(h)
-        } else {
-            self.error(span, "failed to type-check P-style sequence");
-            Some(HirExpr {
-                kind: HirExprKind::Unit,
-                ty: Type::Unit,
-                span,
-            })
+        // Close the frames that are still open at the end of the sequence.
+        while let Some(frame) = frames.pop() {
+            let tree = frame.call_tree();
+            let h = self.finish_frame(frame, true);
+            self.feed(&mut frames, &mut values, Arg::Typed(h), tree);
         }
-    }
 
-    /// Pipe chain `lhs > rhs1 > rhs2 > ...`.
-    ///
-    /// 現段階では「各 expr を順に型付けし、最後の型を結果」とするだけ。
-    fn check_pipe(
-        &mut self,
-        span: Span,
-        pipe: &ast::PipeChain,
-    ) -> Option<HirExpr> {
-        let mut last: Option<HirExpr> = None;
+        let groups = core::mem::replace(&mut self.seq_groups, outer_groups);
+        if from_source {
+            self.seqs.push(ResolvedSeq { span, groups });
+        }
 
-        let first = self.check_expr(&pipe.first);
-        if first.is_some() {
-            last = first;
+        if values.len() > 1 {
+            self.error("E0302.values", span, &[("count", &values.len())]);
         }
+        values.pop().or_else(|| Some(Self::error_expr(span)))
+    }
 
-        for rhs in &pipe.rest {
-            let h = self.check_expr(rhs);
-            if h.is_some() {
-                last = h;
-            }
+    /// Push a call frame for `ident`, narrowing the candidates by the
+    /// expected result type, and close it at once if it takes no
+    /// arguments.
+    fn push_call_frame(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        values: &mut Vec<HirExpr>,
+        ident: &ast::Ident,
+        mut candidates: Vec<Candidate>,
+        expected: Option<&Type>,
+    ) {
+        if let Some(exp) = expected
+            && candidates.iter().any(|c| c.clone().expect_result(exp))
+        {
+            candidates.retain_mut(|c| c.expect_result(exp));
         }
 
-        if let Some(h) = last {
-            Some(HirExpr {
-                span,
-                ty: h.ty.clone(),
-                kind: h.kind,
-            })
+        let arity = candidates.iter().map(|c| c.params.len()).max().unwrap_or(0);
+        let frame = Frame {
+            kind: FrameKind::Call {
+                name: ident.name.clone(),
+                name_span: ident.span,
+                candidates,
+                failed: false,
+                arity,
+                expected: expected.cloned(),
+            },
+            args: Vec::new(),
+            trees: Vec::new(),
+            span: ident.span,
+        };
+
+        if frame.is_complete() {
+            let tree = frame.call_tree();
+            let h = self.finish_frame(frame, false);
+            self.feed(frames, values, Arg::Typed(h), tree);
         } else {
-            self.error(span, "failed to type-check pipe expression");
-            Some(HirExpr {
-                kind: HirExprKind::Unit,
-                ty: Type::Unit,
-                span,
-            })
+            frames.push(frame);
         }
     }
 
-    fn check_block(
+    /// Hand an argument to the innermost frame, closing frames as long
+    /// as they become complete. `tree` records how the argument was
+    /// grouped.
+    fn feed(
         &mut self,
-        span: Span,
-        block: &ast::BlockExpr,
-    ) -> Option<HirExpr> {
-        if block.expressions.is_empty() {
-            return Some(HirExpr {
-                kind: HirExprKind::Block { expressions: Vec::new() },
-                ty: Type::Unit,
-                span,
-            });
-        }
+        frames: &mut Vec<Frame>,
+        values: &mut Vec<HirExpr>,
+        arg: Arg,
+        tree: CallTree,
+    ) {
+        let (mut arg, mut tree) = (arg, tree);
+        loop {
+            let Some(top) = frames.last_mut() else {
+                let h = self.finalize_arg(arg, None);
+                values.push(h);
+                self.seq_groups.push(tree);
+                return;
+            };
 
-        let mut hir_exprs = Vec::new();
-        let mut last_ty = Type::Unit;
+            let n = top.args.len();
+            let mut failure: Option<(String, Span)> = None;
+            if let FrameKind::Call {
+                name,
+                candidates,
+                failed,
+                ..
+            } = &mut top.kind
+                && !*failed
+            {
+                candidates.retain_mut(|c| c.params.len() > n && c.accept(n, &arg));
+                if candidates.is_empty() {
+                    *failed = true;
+                    failure = Some((name.clone(), arg.span()));
+                }
+            }
+            top.args.push(arg);
+            top.trees.push(tree);
+
+            if let Some((name, span)) = failure {
+                self.error("E0303", span, &[("name", &name), ("position", &(n + 1))]);
+            }
 
-        for e in &block.expressions {
-            if let Some(h) = self.check_expr(e) {
-                last_ty = h.ty.clone();
-                hir_exprs.push(h);
+            if !top.is_complete() {
+                return;
             }
+            let frame = frames.pop().unwrap();
+            tree = frame.call_tree();
+            arg = Arg::Typed(self.finish_frame(frame, false));
         }
+    }
 
-        Some(HirExpr {
-            kind: HirExprKind::Block { expressions: hir_exprs },
+    /// Turn a deferred argument into HIR, typing pending literals with
+    /// the given parameter type (or their default type).
+    fn finalize_arg(&mut self, arg: Arg, param: Option<&Type>) -> HirExpr {
+        match arg {
+            Arg::Typed(h) => h,
+            Arg::Literal(lit, span) => self
+                .check_literal(span, &lit, param)
+                .unwrap_or_else(|| Self::error_expr(span)),
+        }
+    }
+
+    /// Close a frame and build the resulting HIR expression.
+    ///
+    /// `at_end` is set when the frame is closed because the sequence
+    /// ended; an unapplied function name then becomes a function value.
+    fn finish_frame(&mut self, frame: Frame, at_end: bool) -> HirExpr {
+        let Frame {
+            kind, args, span, ..
+        } = frame;
+        let full_span = args
+            .last()
+            .map(|a| Self::merge_spans(span, a.span()))
+            .unwrap_or(span);
+
+        match kind {
+            FrameKind::Annotate(ty) => {
+                let Some(arg) = args.into_iter().next() else {
+                    self.error("E0302.annotation", span, &[]);
+                    return Self::error_expr(span);
+                };
+                let mut h = self.finalize_arg(arg, Some(&ty));
+                self.require_subtype(&h, &ty);
+                if !h.ty.is_never() {
+                    h.ty = ty;
+                }
+                h.span = full_span;
+                h
+            }
+            FrameKind::Call {
+                name,
+                name_span,
+                candidates,
+                failed,
+                expected,
+                ..
+            } => {
+                if failed {
+                    return Self::error_expr(full_span);
+                }
+
+                let ident = ast::Ident {
+                    name: name.clone(),
+                    span: name_span,
+                };
+                if at_end && args.is_empty() {
+                    let fn_ty = expected.filter(|t| matches!(t, Type::Function { .. }));
+                    return self.function_value(&ident, &candidates, fn_ty.as_ref());
+                }
+
+                let n = args.len();
+                let mut complete: Vec<Candidate> =
+                    candidates.into_iter().filter(|c| c.params.len() == n).collect();
+                if complete.is_empty() {
+                    self.error("E0304", full_span, &[("name", &name)]);
+                    return Self::error_expr(full_span);
+                }
+
+                // 単相のオーバーロードはジェネリックなものより優先される。
+                if complete.len() > 1 && complete.iter().any(|c| !c.is_generic()) {
+                    complete.retain(|c| !c.is_generic());
+                }
+                if complete.len() > 1 {
+                    // Prefer the overload in which undetermined literals
+                    // take their default types (`i32` / `f64`).
+                    let defaults: Vec<Candidate> = complete
+                        .iter()
+                        .filter(|c| {
+                            c.params.iter().zip(args.iter()).all(|(p, a)| match a {
+                                Arg::Literal(ast::LiteralKind::Int(_), _) => {
+                                    p.substitute(&c.subst) == Type::I32
+                                }
+                                Arg::Literal(ast::LiteralKind::Float(_), _) => {
+                                    p.substitute(&c.subst) == Type::F64
+                                }
+                                _ => true,
+                            })
+                        })
+                        .cloned()
+                        .collect();
+                    if !defaults.is_empty() {
+                        complete = defaults;
+                    }
+                }
+                let has_never_arg = args
+                    .iter()
+                    .any(|a| matches!(a, Arg::Typed(h) if h.ty.is_never() || h.ty.is_error()));
+                if complete.len() > 1 && !has_never_arg {
+                    self.error("E0305", full_span, &[("name", &name)]);
+                }
+                let chosen = complete.swap_remove(0);
+
+                if let Some(ctx) = self.fn_stack.last_mut()
+                    && chosen.arrow == ArrowKind::Impure
+                {
+                    ctx.impure = true;
+                    if ctx.arrow == ArrowKind::Pure {
+                        self.error("E0400", name_span, &[("name", &name)]);
+                    }
+                }
+
+                let Some(type_args) = self.inferred_type_args(&chosen, &name, full_span) else {
+                    return Self::error_expr(full_span);
+                };
+
+                let hir_args: Vec<HirExpr> = args
+                    .into_iter()
+                    .zip(chosen.params.iter())
+                    .map(|(a, p)| {
+                        let p = p.substitute(&chosen.subst);
+                        self.finalize_arg(a, Some(&p))
+                    })
+                    .collect();
+                self.check_mut_args(&chosen, &name, &hir_args);
+
+                let ident = HirIdent {
+                    name: name.clone(),
+                    span: name_span,
+                };
+                let kind = match chosen.target {
+                    CalleeTarget::Function(symbol) => HirExprKind::Call {
+                        callee: HirIdent {
+                            name: symbol,
+                            span: name_span,
+                        },
+                        type_args,
+                        args: hir_args,
+                    },
+                    CalleeTarget::Builtin(kind) => HirExprKind::BuiltinCall {
+                        kind,
+                        args: hir_args,
+                    },
+                    CalleeTarget::Struct(_) => HirExprKind::StructInit {
+                        name: ident,
+                        fields: hir_args,
+                    },
+                    CalleeTarget::Variant { tag, .. } => HirExprKind::EnumInit {
+                        variant: ident,
+                        tag,
+                        args: hir_args,
+                    },
+                    CalleeTarget::Value(callee) => HirExprKind::CallIndirect {
+                        callee,
+                        args: hir_args,
+                    },
+                };
+                HirExpr {
+                    kind,
+                    ty: chosen.result.substitute(&chosen.subst),
+                    span: full_span,
+                }
+            }
+        }
+    }
+
+    /// Check the arguments passed to `mut` parameters: each must be a
+    /// mutable variable, and such a variable cannot be passed to the
+    /// same call a second time.
+    fn check_mut_args(&mut self, chosen: &Candidate, name: &str, args: &[HirExpr]) {
+        for (i, arg) in args.iter().enumerate() {
+            if !chosen.is_mut_param(i) || arg.ty.is_never() || arg.ty.is_error() {
+                continue;
+            }
+            let HirExprKind::Var(var) = &arg.kind else {
+                self.error("E0402.expression", arg.span, &[("name", &name), ("position", &(i + 1))]);
+                continue;
+            };
+            match self.env.lookup(&var.name) {
+                Some(Binding::Value { mutable: false, .. }) => {
+                    let diag = Diagnostic::error_key("E0402.immutable", arg.span)
+                        .arg("variable", &var.name)
+                        .arg("position", i + 1)
+                        .arg("name", name);
+                    let diag = self.suggest_mut(diag, &var.name);
+                    self.diagnostics.push(diag);
+                }
+                Some(Binding::Value { depth, .. }) if *depth < self.depth() => {
+                    let args: [(&str, &dyn fmt::Display); 3] =
+                        [("variable", &var.name), ("position", &(i + 1)), ("name", &name)];
+                    self.error("E0402.captured", arg.span, &args);
+                }
+                _ => {}
+            }
+            let aliased = args.iter().enumerate().any(|(j, other)| {
+                j != i
+                    && matches!(&other.kind, HirExprKind::Var(o) if o.name == var.name)
+                    && (j > i || !chosen.is_mut_param(j))
+            });
+            if aliased {
+                self.error("E0403", arg.span, &[("variable", &var.name), ("name", &name)]);
+            }
+        }
+    }
+
+    /// The type arguments inferred for a chosen candidate, in the order
+    /// of its type parameters. Reports parameters that could not be
+    /// inferred.
+    fn inferred_type_args(&mut self, c: &Candidate, name: &str, span: Span) -> Option<Vec<Type>> {
+        let mut type_args = Vec::new();
+        for p in &c.type_params {
+            match c.subst.iter().find(|(n, _)| n == p) {
+                Some((_, t)) => type_args.push(t.clone()),
+                None => {
+                    let param = p.trim_start_matches('?');
+                    self.error("E0306", span, &[("param", &param), ("name", &name)]);
+                    return None;
+                }
+            }
+        }
+        Some(type_args)
+    }
+
+    /// Use a function name as a value (for example as an argument to a
+    /// higher-order function).
+    fn function_value(
+        &mut self,
+        ident: &ast::Ident,
+        candidates: &[Candidate],
+        expected: Option<&Type>,
+    ) -> HirExpr {
+        if let [c] = candidates
+            && let CalleeTarget::Value(h) = &c.target
+        {
+            return (**h).clone();
+        }
+        let mut matching: Vec<Candidate> = candidates
+            .iter()
+            .filter_map(|c| {
+                let mut c = c.clone();
+                let ty = Type::Function {
+                    params: c.params.clone(),
+                    result: Box::new(c.result.clone()),
+                    arrow: c.arrow,
+                };
+                match expected {
+                    Some(e) => {
+                        unify(&ty, e, &c.type_params, &mut c.subst).then_some(c)
+                    }
+                    None => Some(c),
+                }
+            })
+            .collect();
+        if matching.len() > 1 && matching.iter().any(|c| !c.is_generic()) {
+            matching.retain(|c| !c.is_generic());
+        }
+
+        let chosen = match matching.len() {
+            1 => matching.pop().unwrap(),
+            0 => {
+                self.error("E0303.value", ident.span, &[("name", &ident.name)]);
+                return Self::error_expr(ident.span);
+            }
+            _ => {
+                self.error("E0305.value", ident.span, &[("name", &ident.name)]);
+                return Self::error_expr(ident.span);
+            }
+        };
+
+        let CalleeTarget::Function(symbol) = &chosen.target else {
+            self.error("E0307", ident.span, &[("name", &ident.name)]);
+            return Self::error_expr(ident.span);
+        };
+        if chosen.mut_params.contains(&true) {
+            self.error("E0307.mut", ident.span, &[("name", &ident.name)]);
+            return Self::error_expr(ident.span);
+        }
+        let Some(type_args) = self.inferred_type_args(&chosen, &ident.name, ident.span) else {
+            return Self::error_expr(ident.span);
+        };
+        let ty = Type::Function {
+            params: chosen.params.clone(),
+            result: Box::new(chosen.result.clone()),
+            arrow: chosen.arrow,
+        };
+        HirExpr {
+            kind: HirExprKind::FuncRef {
+                name: HirIdent {
+                    name: symbol.clone(),
+                    span: ident.span,
+                },
+                type_args,
+                captures: Vec::new(),
+            },
+            ty: ty.substitute(&chosen.subst),
+            span: ident.span,
+        }
+    }
+
+    /// Record a reference to a variable bound at `depth`. A variable of
+    /// an enclosing function is captured by every function literal
+    /// between its binding and the reference.
+    fn capture(&mut self, name: &str, ty: &Type, depth: usize) {
+        for ctx in self.fn_stack.iter_mut().skip(depth) {
+            if !ctx.captures.iter().any(|(n, _)| n == name) {
+                ctx.captures.push((name.to_string(), ty.clone()));
+            }
+        }
+    }
+
+    /// The value of a (non-generic) function literal: a reference to the
+    /// lifted function together with the current values of its captures.
+    fn closure(&self, sig: &FunctionSig, captures: &[(String, Type)], span: Span) -> HirExpr {
+        let captures = captures
+            .iter()
+            .map(|(name, ty)| HirExpr {
+                kind: HirExprKind::Var(HirIdent {
+                    name: name.clone(),
+                    span,
+                }),
+                ty: ty.clone(),
+                span,
+            })
+            .collect();
+        HirExpr {
+            kind: HirExprKind::FuncRef {
+                name: HirIdent {
+                    name: sig.symbol().to_string(),
+                    span,
+                },
+                type_args: sig.type_params.iter().map(|p| Type::Var(p.clone())).collect(),
+                captures,
+            },
+            ty: sig.ty(),
+            span,
+        }
+    }
+
+    /// Pipe chain `lhs > rhs1 > rhs2 > ...`.
+    ///
+    /// `lhs > f a b` は `f lhs a b` として P-style 解決する。
+    fn check_pipe(
+        &mut self,
+        span: Span,
+        pipe: &ast::PipeChain,
+        expected: Option<&Type>,
+    ) -> Option<HirExpr> {
+        let mut acc = self
+            .check_expr(&pipe.first)
+            .unwrap_or_else(|| Self::error_expr(pipe.first.span));
+
+        for (i, rhs) in pipe.rest.iter().enumerate() {
+            let is_last = i + 1 == pipe.rest.len();
+            let items: Vec<SeqItem<'_>> = match &rhs.kind {
+                ast::ExprKind::Seq(terms) => {
+                    let mut items = vec![SeqItem::Ast(&terms[0]), SeqItem::Typed(acc)];
+                    items.extend(terms[1..].iter().map(SeqItem::Ast));
+                    items
+                }
+                ast::ExprKind::Ident(_) => vec![SeqItem::Ast(rhs), SeqItem::Typed(acc)],
+                _ => {
+                    self.error("E0302.pipe", rhs.span, &[]);
+                    return Some(Self::error_expr(span));
+                }
+            };
+            let seq_span = Self::merge_spans(span, rhs.span);
+            acc = self
+                .check_pstyle_seq(seq_span, items, if is_last { expected } else { None })
+                .unwrap_or_else(|| Self::error_expr(seq_span));
+        }
+
+        acc.span = span;
+        Some(acc)
+    }
+
+    // === blocks and bindings ==================================================
+
+    fn check_block(
+        &mut self,
+        span: Span,
+        block: &ast::BlockExpr,
+        expected: Option<&Type>,
+    ) -> Option<HirExpr> {
+        self.env.push_scope();
+
+        // enum / struct definitions are implicitly hoisted, and so are
+        // `fn` bindings: declare every type and signature first so that
+        // definitions in the same scope can refer to each other.
+        let defs: Vec<&ast::Expr> = block
+            .expressions
+            .iter()
+            .filter(|e| matches!(e.kind, ast::ExprKind::EnumDef(_) | ast::ExprKind::StructDef(_)))
+            .collect();
+        self.declare_type_defs(&defs);
+
+        let sigs: Vec<Option<FunctionSig>> = block
+            .expressions
+            .iter()
+            .map(|e| match &e.kind {
+                ast::ExprKind::LetFunction(func) => self.declare_function(func),
+                _ => None,
+            })
+            .collect();
+
+        let hir = self.check_block_items(span, &block.expressions, &sigs, expected);
+        self.pop_scope();
+        Some(hir)
+    }
+
+    /// Check the expressions of a block. A `let` binds its name for the
+    /// rest of the block.
+    fn check_block_items(
+        &mut self,
+        span: Span,
+        exprs: &[ast::Expr],
+        sigs: &[Option<FunctionSig>],
+        expected: Option<&Type>,
+    ) -> HirExpr {
+        let mut hir_exprs = Vec::new();
+        let mut last_ty = Type::Unit;
+        let mut unreachable = false;
+
+        for (i, e) in exprs.iter().enumerate() {
+            let is_last = i + 1 == exprs.len();
+            match &e.kind {
+                ast::ExprKind::Let(let_expr) => {
+                    let outcome = self.check_let(let_expr);
+                    let h = self.finish_let(e.span, outcome);
+                    last_ty = h.ty.clone();
+                    hir_exprs.push(h);
+                }
+                ast::ExprKind::LetFunction(func) => {
+                    if let Some(sig) = &sigs[i] {
+                        self.define_function(func, sig);
+                    }
+                    last_ty = Type::Unit;
+                    hir_exprs.push(Self::unit_expr(e.span));
+                }
+                ast::ExprKind::EnumDef(_) | ast::ExprKind::StructDef(_) => {
+                    last_ty = Type::Unit;
+                    hir_exprs.push(Self::unit_expr(e.span));
+                }
+                _ => {
+                    let hint = if is_last { expected } else { None };
+                    let h = self
+                        .check_expr_with(e, hint)
+                        .unwrap_or_else(|| Self::error_expr(e.span));
+                    if !is_last && h.ty.is_never() && !unreachable {
+                        unreachable = true;
+                        let last = exprs[exprs.len() - 1].span;
+                        let rest = Self::merge_spans(exprs[i + 1].span, last);
+                        let diag = Diagnostic::warning_key("W0004", rest)
+                            .with_secondary_label(h.span, None);
+                        self.lint(&lint::UNREACHABLE_CODE, diag);
+                    }
+                    last_ty = h.ty.clone();
+                    hir_exprs.push(h);
+                }
+            }
+        }
+
+        HirExpr {
+            kind: HirExprKind::Block { expressions: hir_exprs },
             ty: last_ty,
             span,
+        }
+    }
+
+    /// Check a `let` binding and bring its name into the current scope.
+    fn check_let(&mut self, let_expr: &ast::LetExpr) -> LetOutcome {
+        if let_expr.is_mut && let_expr.is_hoist {
+            self.error("E0406", let_expr.span, &[]);
+        }
+
+        let annotation = let_expr.ty.as_ref().map(|t| self.resolve_type_expr(t));
+
+        // An immutable binding of a function literal is a static function.
+        if let ast::ExprKind::FuncLiteral(lit) = &let_expr.value.kind
+            && !let_expr.is_mut
+            && let_expr.name.name != "_"
+        {
+            let sig = self.signature_of(lit, &let_expr.name.name);
+            if let Some(ann) = &annotation
+                && is_subtype(&sig.ty(), ann) == SubtypeResult::NotSubtype
+            {
+                self.error("E0300", let_expr.value.span, &[("expected", ann), ("found", &sig.ty())]);
+            }
+            let captures = self.check_func_literal(lit, &sig, let_expr.name.span);
+            if captures.is_empty() {
+                self.env
+                    .insert(let_expr.name.name.clone(), Binding::Function(sig));
+                return LetOutcome::Static;
+            }
+
+            // A closure is a runtime value holding its captures.
+            if !lit.type_params.is_empty() {
+                self.error("E0502.capture", let_expr.value.span, &[]);
+            }
+            let init = self.closure(&sig, &captures, let_expr.value.span);
+            self.bind_value(
+                let_expr.name.name.clone(),
+                Binding::Value {
+                    ty: sig.ty(),
+                    mutable: false,
+                    depth: self.depth(),
+                    decl: None,
+                    span: let_expr.name.span,
+                    used: false,
+                    written: false,
+                    param: false,
+                },
+            );
+            return LetOutcome::Bound {
+                name: HirIdent {
+                    name: let_expr.name.name.clone(),
+                    span: let_expr.name.span,
+                },
+                mutable: false,
+                init,
+            };
+        }
+
+        let init = self.check_expected(&let_expr.value, annotation.as_ref());
+        if let_expr.name.name == "_" {
+            return LetOutcome::Discard(init);
+        }
+
+        let ty = annotation.unwrap_or_else(|| init.ty.clone());
+        self.bind_value(
+            let_expr.name.name.clone(),
+            Binding::Value {
+                ty,
+                mutable: let_expr.is_mut,
+                depth: self.depth(),
+                decl: Some(let_expr.name.span),
+                span: let_expr.name.span,
+                used: false,
+                written: false,
+                param: false,
+            },
+        );
+        LetOutcome::Bound {
+            name: HirIdent {
+                name: let_expr.name.name.clone(),
+                span: let_expr.name.span,
+            },
+            mutable: let_expr.is_mut,
+            init,
+        }
+    }
+
+    /// Build the HIR for a checked `let`.
+    fn finish_let(&mut self, span: Span, outcome: LetOutcome) -> HirExpr {
+        match outcome {
+            LetOutcome::Bound {
+                name,
+                mutable,
+                init,
+            } => HirExpr {
+                kind: HirExprKind::Let {
+                    name,
+                    mutable,
+                    init: Box::new(init),
+                },
+                ty: Type::Unit,
+                span,
+            },
+            LetOutcome::Discard(init) => HirExpr {
+                kind: HirExprKind::Block {
+                    expressions: vec![init, Self::unit_expr(span)],
+                },
+                ty: Type::Unit,
+                span,
+            },
+            LetOutcome::Static => Self::unit_expr(span),
+        }
+    }
+
+    // === functions ============================================================
+
+    /// Compute the signature of a function literal from its parameter
+    /// and result annotations, without checking the body.
+    ///
+    /// A literal nested in a generic function inherits the type
+    /// parameters in scope, in front of its own.
+    fn signature_of(&mut self, lit: &ast::FuncLiteralExpr, name: &str) -> FunctionSig {
+        let inherited = self.type_vars.len();
+        for p in &lit.type_params {
+            if self.type_vars.contains(&p.name) {
+                self.error("E0203", p.span, &[("name", &p.name)]);
+            }
+            self.type_vars.push(p.name.clone());
+        }
+        let params = lit
+            .params
+            .iter()
+            .map(|p| self.resolve_type_expr(&p.ty))
+            .collect();
+        let result = self.resolve_type_expr(&lit.result);
+        let type_params = self.type_vars.clone();
+        self.type_vars.truncate(inherited);
+        FunctionSig {
+            target: CalleeTarget::Function(self.fresh_symbol(name)),
+            type_params,
+            inherited,
+            params,
+            mut_params: lit.params.iter().map(|p| p.is_mut).collect(),
+            result,
+            arrow: lit.arrow,
+        }
+    }
+
+    /// Declare a `fn` binding in the current scope (hoisting).
+    fn declare_function(&mut self, func: &ast::LetFunctionExpr) -> Option<FunctionSig> {
+        let ast::ExprKind::FuncLiteral(lit) = &func.value.kind else {
+            self.error("E0500", func.value.span, &[]);
+            return None;
+        };
+        let sig = self.signature_of(lit, &func.name.name);
+        self.env
+            .insert(func.name.name.clone(), Binding::Function(sig.clone()));
+        Some(sig)
+    }
+
+    /// Check the body of a previously declared `fn` binding.
+    fn define_function(&mut self, func: &ast::LetFunctionExpr, sig: &FunctionSig) {
+        if let ast::ExprKind::FuncLiteral(lit) = &func.value.kind {
+            let captures = self.check_func_literal(lit, sig, func.name.span);
+            if let Some((name, _)) = captures.first() {
+                self.error("E0501", func.name.span, &[("name", &func.name.name), ("variable", name)]);
+            }
+        }
+    }
+
+    /// Check a function literal body against its signature and lift it
+    /// into `self.functions`. Returns the variables it captures.
+    fn check_func_literal(
+        &mut self,
+        lit: &ast::FuncLiteralExpr,
+        sig: &FunctionSig,
+        name_span: Span,
+    ) -> Vec<(String, Type)> {
+        if lit.arrow == ArrowKind::Pure
+            && let Some(p) = lit.params.iter().find(|p| p.is_mut)
+        {
+            self.error("E0401", p.span, &[]);
+        }
+
+        self.env.push_scope();
+        self.fn_stack.push(FnCtx {
+            result: sig.result.clone(),
+            arrow: sig.arrow,
+            impure: false,
+            captures: Vec::new(),
+        });
+        let saved_loops = core::mem::take(&mut self.loops);
+        let saved_vars = core::mem::replace(&mut self.type_vars, sig.type_params.clone());
+
+        let depth = self.depth();
+        let mut params = Vec::new();
+        for (p, ty) in lit.params.iter().zip(sig.params.iter()) {
+            self.bind_value(
+                p.name.name.clone(),
+                Binding::Value {
+                    ty: ty.clone(),
+                    mutable: p.is_mut,
+                    depth,
+                    decl: Some(p.name.span),
+                    span: p.name.span,
+                    used: false,
+                    written: false,
+                    param: true,
+                },
+            );
+            params.push(HirParam {
+                name: HirIdent {
+                    name: p.name.name.clone(),
+                    span: p.name.span,
+                },
+                ty: ty.clone(),
+                mutable: p.is_mut,
+            });
+        }
+
+        let body = self.check_expected(&lit.body, Some(&sig.result));
+
+        self.loops = saved_loops;
+        self.type_vars = saved_vars;
+        let ctx = self.fn_stack.pop();
+        if lit.arrow == ArrowKind::Impure
+            && ctx.as_ref().is_some_and(|ctx| !ctx.impure)
+            && !lit.params.iter().any(|p| p.is_mut)
+        {
+            let diag = Diagnostic::warning_key("W0005", lit.arrow_span).with_suggestion_key(
+                MessageKey::new("suggest.pure-arrow"),
+                lit.arrow_span,
+                "*>",
+            );
+            self.lint(&lint::COULD_BE_PURE, diag);
+        }
+        let captures = ctx.map(|ctx| ctx.captures).unwrap_or_default();
+        self.pop_scope();
+
+        self.functions.push(HirFunction {
+            name: HirIdent {
+                name: sig.symbol().to_string(),
+                span: name_span,
+            },
+            type_params: sig.type_params.clone(),
+            params,
+            captures: captures
+                .iter()
+                .map(|(name, ty)| HirParam {
+                    name: HirIdent {
+                        name: name.clone(),
+                        span: name_span,
+                    },
+                    ty: ty.clone(),
+                    mutable: false,
+                })
+                .collect(),
+            result: sig.result.clone(),
+            arrow: sig.arrow,
+            body,
+        });
+        captures
+    }
+
+    /// Check the body of a `test` declaration and lift it into a
+    /// function of its own.
+    fn check_test(&mut self, test: &ast::TestExpr) {
+        if self.tests.iter().any(|t| t.name == test.name) {
+            self.error("E0204", test.name_span, &[("name", &test.name)]);
+        }
+
+        self.env.push_scope();
+        self.fn_stack.push(FnCtx {
+            result: Type::Unit,
+            arrow: ArrowKind::Impure,
+            impure: false,
+            captures: Vec::new(),
+        });
+        let saved_loops = core::mem::take(&mut self.loops);
+        let saved_vars = core::mem::take(&mut self.type_vars);
+
+        let body = self.check_expected(&test.body, Some(&Type::Unit));
+
+        self.loops = saved_loops;
+        self.type_vars = saved_vars;
+        let ctx = self.fn_stack.pop();
+        self.pop_scope();
+        if let Some((name, _)) = ctx.as_ref().and_then(|ctx| ctx.captures.first()) {
+            self.error("E0503", test.name_span, &[("name", &test.name), ("variable", name)]);
+        }
+
+        let symbol = self.fresh_symbol("test");
+        self.functions.push(HirFunction {
+            name: HirIdent {
+                name: symbol.clone(),
+                span: test.name_span,
+            },
+            type_params: Vec::new(),
+            params: Vec::new(),
+            captures: Vec::new(),
+            result: Type::Unit,
+            arrow: ArrowKind::Impure,
+            body,
+        });
+        self.tests.push(HirTest {
+            name: test.name.clone(),
+            function: symbol,
+            span: test.span,
+        });
+    }
+
+    // === enums and structs ====================================================
+
+    /// Declare enum / struct definitions of one scope and register their
+    /// constructors.
+    ///
+    /// All names are declared before any field or payload type is
+    /// resolved, so definitions may refer to each other (and to
+    /// themselves, as in `enum List<T> { Cons(T, List<T>); Nil }`).
+    fn declare_type_defs(&mut self, defs: &[&ast::Expr]) {
+        for e in defs {
+            let def = match &e.kind {
+                ast::ExprKind::EnumDef(d) => TypeDef::Enum {
+                    params: d.type_params.iter().map(|p| p.name.clone()).collect(),
+                    variants: Vec::new(),
+                },
+                ast::ExprKind::StructDef(d) => TypeDef::Struct {
+                    params: d.type_params.iter().map(|p| p.name.clone()).collect(),
+                    fields: Vec::new(),
+                },
+                _ => continue,
+            };
+            let Some((name, _)) = type_def_header(e) else {
+                continue;
+            };
+            self.env.types.push((name.name.clone(), def));
+        }
+
+        for e in defs {
+            let Some((name, type_params)) = type_def_header(e) else {
+                continue;
+            };
+            let params: Vec<String> = type_params.iter().map(|p| p.name.clone()).collect();
+            let saved_vars = core::mem::replace(&mut self.type_vars, params.clone());
+            let self_ty = if params.is_empty() {
+                Type::Named(name.name.clone())
+            } else {
+                Type::Apply {
+                    name: name.name.clone(),
+                    args: params.iter().map(|p| Type::Var(p.clone())).collect(),
+                }
+            };
+
+            match &e.kind {
+                ast::ExprKind::EnumDef(d) => {
+                    let mut variants: Vec<(String, Vec<Type>)> = Vec::new();
+                    for v in &d.variants.items {
+                        if variants.iter().any(|(n, _)| *n == v.name.name) {
+                            self.error("E0202.variant", v.name.span, &[("name", &v.name.name)]);
+                            continue;
+                        }
+                        let payload: Vec<Type> =
+                            v.payload_types.iter().map(|t| self.resolve_type_expr(t)).collect();
+                        self.env.insert(
+                            v.name.name.clone(),
+                            Binding::Function(FunctionSig {
+                                target: CalleeTarget::Variant {
+                                    name: v.name.name.clone(),
+                                    tag: variants.len() as u32,
+                                },
+                                type_params: params.clone(),
+                                inherited: 0,
+                                params: payload.clone(),
+                                mut_params: Vec::new(),
+                                result: self_ty.clone(),
+                                arrow: ArrowKind::Pure,
+                            }),
+                        );
+                        variants.push((v.name.name.clone(), payload));
+                    }
+                    if let Some(TypeDef::Enum { variants: slot, .. }) =
+                        self.env.lookup_type_mut(&name.name)
+                    {
+                        *slot = variants;
+                    }
+                }
+                ast::ExprKind::StructDef(d) => {
+                    let mut fields: Vec<(String, Type)> = Vec::new();
+                    for f in &d.fields.items {
+                        if fields.iter().any(|(n, _)| *n == f.name.name) {
+                            self.error("E0202.field", f.name.span, &[("name", &f.name.name)]);
+                            continue;
+                        }
+                        let ty = self.resolve_type_expr(&f.ty);
+                        fields.push((f.name.name.clone(), ty));
+                    }
+                    self.env.insert(
+                        name.name.clone(),
+                        Binding::Function(FunctionSig {
+                            target: CalleeTarget::Struct(name.name.clone()),
+                            type_params: params.clone(),
+                            inherited: 0,
+                            params: fields.iter().map(|(_, t)| t.clone()).collect(),
+                            mut_params: Vec::new(),
+                            result: self_ty.clone(),
+                            arrow: ArrowKind::Pure,
+                        }),
+                    );
+                    if let Some(TypeDef::Struct { fields: slot, .. }) =
+                        self.env.lookup_type_mut(&name.name)
+                    {
+                        *slot = fields;
+                    }
+                }
+                _ => {}
+            }
+            self.type_vars = saved_vars;
+        }
+    }
+
+    /// Type arguments of an enum / struct type, paired with the
+    /// definition's parameters.
+    fn type_def_of(&self, ty: &Type) -> Option<(TypeDef, Vec<(String, Type)>)> {
+        let (name, args) = match ty {
+            Type::Named(name) => (name, &[][..]),
+            Type::Apply { name, args } => (name, &args[..]),
+            _ => return None,
+        };
+        let def = self.env.lookup_type(name)?.clone();
+        let subst = def.params().iter().cloned().zip(args.iter().cloned()).collect();
+        Some((def, subst))
+    }
+
+    /// Tag and payload types of the variant `name` of the enum type `ty`.
+    fn variant_of(&self, ty: &Type, name: &str) -> Option<(u32, Vec<Type>)> {
+        let (TypeDef::Enum { variants, .. }, subst) = self.type_def_of(ty)? else {
+            return None;
+        };
+        variants.iter().enumerate().find_map(|(i, (n, payload))| {
+            (n == name).then(|| (i as u32, payload.iter().map(|t| t.substitute(&subst)).collect()))
+        })
+    }
+
+    fn check_match(
+        &mut self,
+        span: Span,
+        m: &ast::MatchExpr,
+        expected: Option<&Type>,
+    ) -> Option<HirExpr> {
+        let scrutinee = self
+            .check_expr(&m.scrutinee)
+            .unwrap_or_else(|| Self::error_expr(m.scrutinee.span));
+
+        let mut ty = Type::Never;
+        let mut arms = Vec::new();
+        for case in &m.cases.items {
+            self.env.push_scope();
+            let pattern = self.check_pattern(&case.pattern, &scrutinee.ty);
+            let body = self
+                .check_expr_with(&case.body, expected)
+                .unwrap_or_else(|| Self::error_expr(case.body.span));
+            self.pop_scope();
+            ty = merge_types_for_branch(&ty, &body.ty, case.span, &mut self.diagnostics);
+            arms.push(HirMatchArm { pattern, body });
+        }
+
+        if !scrutinee.ty.is_never() && !scrutinee.ty.is_error() {
+            self.check_exhaustive(span, &scrutinee.ty, &arms);
+        }
+
+        Some(HirExpr {
+            kind: HirExprKind::Match {
+                scrutinee: Box::new(scrutinee),
+                arms,
+            },
+            ty,
+            span,
+        })
+    }
+
+    /// Check a pattern against the type of the value it matches and bind
+    /// its variables in the current scope.
+    fn check_pattern(&mut self, pat: &ast::Pattern, ty: &Type) -> HirPattern {
+        match pat {
+            ast::Pattern::Wildcard(span) => HirPattern::Wildcard(*span),
+            ast::Pattern::Literal(expr) => {
+                let ast::ExprKind::Literal(lit) = &expr.kind else {
+                    self.error("E0603", expr.span, &[]);
+                    return HirPattern::Wildcard(expr.span);
+                };
+                let known = !ty.is_never() && !ty.is_error();
+                let hint = if known { Some(ty) } else { None };
+                let h = self
+                    .check_literal(expr.span, lit, hint)
+                    .unwrap_or_else(|| Self::error_expr(expr.span));
+                if known {
+                    self.require_subtype(&h, ty);
+                }
+                match h.kind {
+                    HirExprKind::I32(v) => HirPattern::LitI32(v),
+                    HirExprKind::I64(v) => HirPattern::LitI64(v),
+                    HirExprKind::F32(v) => HirPattern::LitF32(v),
+                    HirExprKind::F64(v) => HirPattern::LitF64(v),
+                    HirExprKind::Bool(v) => HirPattern::LitBool(v),
+                    HirExprKind::String(v) => HirPattern::LitString(v),
+                    _ => HirPattern::Wildcard(expr.span),
+                }
+            }
+            ast::Pattern::Ident(name, span) => {
+                // A lone name that is a payload-less variant of the
+                // matched enum is a variant pattern, not a binding.
+                if let Some((tag, payload)) = self.variant_of(ty, name)
+                    && payload.is_empty()
+                {
+                    return HirPattern::EnumVariant {
+                        ctor: HirIdent {
+                            name: name.clone(),
+                            span: *span,
+                        },
+                        tag,
+                        args: Vec::new(),
+                    };
+                }
+                self.bind_value(
+                    name.clone(),
+                    Binding::Value {
+                        ty: ty.clone(),
+                        mutable: false,
+                        depth: self.depth(),
+                        decl: None,
+                        span: *span,
+                        // The name may be a variant of a type that failed
+                        // to resolve; do not warn that it is unused.
+                        used: ty.is_error(),
+                        written: false,
+                        param: false,
+                    },
+                );
+                HirPattern::Ident {
+                    name: HirIdent {
+                        name: name.clone(),
+                        span: *span,
+                    },
+                    ty: ty.clone(),
+                }
+            }
+            // The matched type already failed to check: bind the names,
+            // but do not report anything about the pattern.
+            ast::Pattern::Enum(p) if ty.is_error() => {
+                for a in &p.args {
+                    self.check_pattern(a, ty);
+                }
+                HirPattern::Wildcard(p.span)
+            }
+            ast::Pattern::Struct(p) if ty.is_error() => {
+                for f in &p.fields {
+                    self.check_pattern(&f.pattern, ty);
+                }
+                HirPattern::Wildcard(p.span)
+            }
+            ast::Pattern::Enum(p) => {
+                let Some((tag, payload)) = self.variant_of(ty, &p.ctor) else {
+                    self.error("E0601.variant", p.span, &[("name", &p.ctor), ("type", ty)]);
+                    return HirPattern::Wildcard(p.span);
+                };
+                if payload.len() != p.args.len() {
+                    let args: [(&str, &dyn fmt::Display); 3] =
+                        [("name", &p.ctor), ("expected", &payload.len()), ("found", &p.args.len())];
+                    self.error("E0602", p.span, &args);
+                }
+                let args = p
+                    .args
+                    .iter()
+                    .zip(payload.iter())
+                    .map(|(a, t)| self.check_pattern(a, t))
+                    .collect();
+                HirPattern::EnumVariant {
+                    ctor: HirIdent {
+                        name: p.ctor.clone(),
+                        span: p.span,
+                    },
+                    tag,
+                    args,
+                }
+            }
+            ast::Pattern::Struct(p) => {
+                let fields = match self.type_def_of(ty) {
+                    Some((TypeDef::Struct { fields, .. }, subst))
+                        if matches!(ty, Type::Named(n) | Type::Apply { name: n, .. } if *n == p.ctor) =>
+                    {
+                        fields
+                            .into_iter()
+                            .map(|(n, t)| (n, t.substitute(&subst)))
+                            .collect::<Vec<_>>()
+                    }
+                    _ => {
+                        self.error("E0601.struct", p.span, &[("name", &p.ctor), ("type", ty)]);
+                        return HirPattern::Wildcard(p.span);
+                    }
+                };
+                let mut out = Vec::new();
+                for f in &p.fields {
+                    let Some(index) = fields.iter().position(|(n, _)| *n == f.field) else {
+                        self.error("E0601.field", p.span, &[("name", &p.ctor), ("field", &f.field)]);
+                        continue;
+                    };
+                    let pattern = self.check_pattern(&f.pattern, &fields[index].1);
+                    out.push(HirStructPatternField {
+                        field: HirIdent {
+                            name: f.field.clone(),
+                            span: p.span,
+                        },
+                        index,
+                        pattern,
+                    });
+                }
+                HirPattern::Struct {
+                    ctor: HirIdent {
+                        name: p.ctor.clone(),
+                        span: p.span,
+                    },
+                    fields: out,
+                }
+            }
+        }
+    }
+
+    /// Report a `match` that does not cover every value of `ty`.
+    ///
+    /// This is a shallow check: only the top-level variants of an enum
+    /// and the two `Bool` literals are tracked; any other type needs a
+    /// catch-all case.
+    fn check_exhaustive(&mut self, span: Span, ty: &Type, arms: &[HirMatchArm]) {
+        if arms.iter().any(|a| is_irrefutable(&a.pattern)) {
+            return;
+        }
+
+        let missing: Vec<String> = match (self.type_def_of(ty), ty) {
+            (Some((TypeDef::Enum { variants, .. }, _)), _) => variants
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    !arms.iter().any(|a| matches!(&a.pattern,
+                        HirPattern::EnumVariant { tag, args, .. }
+                            if *tag == *i as u32 && args.iter().all(is_irrefutable)))
+                })
+                .map(|(_, (n, _))| format!("`{n}`"))
+                .collect(),
+            (_, Type::Bool) => [true, false]
+                .into_iter()
+                .filter(|b| {
+                    !arms
+                        .iter()
+                        .any(|a| a.pattern == HirPattern::LitBool(*b))
+                })
+                .map(|b| format!("`{b}`"))
+                .collect(),
+            _ => vec!["`_`".to_string()],
+        };
+
+        if !missing.is_empty() {
+            self.error("E0600", span, &[("patterns", &missing.join(", "))]);
+        }
+    }
+
+    // === control flow =========================================================
+
+    fn check_if(
+        &mut self,
+        span: Span,
+        if_expr: &ast::IfExpr,
+        expected: Option<&Type>,
+    ) -> Option<HirExpr> {
+        // `if a then x elseif b then y else z` は
+        // `if a then x else (if b then y else z)` として扱う。
+        let mut else_branch = self
+            .check_expr_with(&if_expr.else_branch, expected)
+            .unwrap_or_else(|| Self::error_expr(if_expr.else_branch.span));
+
+        for branch in if_expr.elseif_branches.iter().rev() {
+            let branch_span = Self::merge_spans(branch.condition.span, else_branch.span);
+            else_branch = self.build_if(branch_span, branch, else_branch, expected);
+        }
+
+        Some(self.build_if(span, &if_expr.if_branch, else_branch, expected))
+    }
+
+    fn build_if(
+        &mut self,
+        span: Span,
+        branch: &ast::IfBranch,
+        else_branch: HirExpr,
+        expected: Option<&Type>,
+    ) -> HirExpr {
+        let cond = self.check_expected(&branch.condition, Some(&Type::Bool));
+        let then_branch = self
+            .check_expr_with(&branch.body, expected)
+            .unwrap_or_else(|| Self::error_expr(branch.body.span));
+        let ty = merge_types_for_branch(&then_branch.ty, &else_branch.ty, span, &mut self.diagnostics);
+        HirExpr {
+            kind: HirExprKind::If {
+                cond: Box::new(cond),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            },
+            ty,
+            span,
+        }
+    }
+
+    fn check_while(&mut self, span: Span, while_expr: &ast::WhileExpr) -> Option<HirExpr> {
+        let cond = self.check_expected(&while_expr.condition, Some(&Type::Bool));
+        self.loops.push(LoopCtx {
+            is_while: true,
+            break_ty: None,
+            has_break: false,
+            plain_break: false,
+        });
+        let body = self
+            .check_expr(&while_expr.body)
+            .unwrap_or_else(|| Self::error_expr(while_expr.body.span));
+        self.loops.pop();
+
+        Some(HirExpr {
+            kind: HirExprKind::While {
+                cond: Box::new(cond),
+                body: Box::new(body),
+            },
+            ty: Type::Unit,
+            span,
         })
     }
 
-    // ここに If / Match / Loop / While / Let / Set / Return / Break / Continue
-    // などの型検査処理を、段階的に追加していく予定です。
-    //
-    // それらの実装では:
-    //  - Never を bottom として扱う (least_common_supertype を利用)
-    //  - Loop の中の break expr の型から Loop の型を決定する
-    //  - 純粋関数( *>) と副作用あり関数( -> ) の制約をチェックする
+    /// `loop` の型は本体中の `break` から決まる。
+    ///
+    /// - 値付き `break expr` があればその型 `T`
+    /// - 値なし `break` だけなら `Unit`
+    /// - `break` が一つもなければ決して終わらないので `Never`
+    fn check_loop(&mut self, span: Span, loop_expr: &ast::LoopExpr) -> Option<HirExpr> {
+        self.loops.push(LoopCtx {
+            is_while: false,
+            break_ty: None,
+            has_break: false,
+            plain_break: false,
+        });
+        let body = self
+            .check_expr(&loop_expr.body)
+            .unwrap_or_else(|| Self::error_expr(loop_expr.body.span));
+        let ctx = self.loops.pop().unwrap();
+
+        let ty = match ctx.break_ty {
+            Some(t) => {
+                if ctx.plain_break && !t.is_unit() {
+                    self.error("E0701", span, &[("type", &t)]);
+                }
+                t
+            }
+            None if ctx.has_break => Type::Unit,
+            None => Type::Never,
+        };
+
+        Some(HirExpr {
+            kind: HirExprKind::Loop {
+                body: Box::new(body),
+            },
+            ty,
+            span,
+        })
+    }
+
+    fn check_break(&mut self, span: Span, br: &ast::BreakExpr) -> Option<HirExpr> {
+        let Some(ctx) = self.loops.last() else {
+            self.error("E0700.break", span, &[]);
+            return Some(HirExpr::never_break(span, None));
+        };
+        let is_while = ctx.is_while;
+        let break_ty = ctx.break_ty.clone();
+
+        let value = match &br.value {
+            Some(v) => {
+                if is_while {
+                    self.error("E0701.while", span, &[]);
+                }
+                let h = self.check_expected(v, break_ty.as_ref());
+                if break_ty.is_none() && !h.ty.is_never() {
+                    self.loops.last_mut().unwrap().break_ty = Some(h.ty.clone());
+                }
+                Some(h)
+            }
+            None => {
+                self.loops.last_mut().unwrap().plain_break = true;
+                None
+            }
+        };
+        self.loops.last_mut().unwrap().has_break = true;
+
+        Some(HirExpr::never_break(span, value))
+    }
+
+    fn check_return(&mut self, span: Span, ret: &ast::ReturnExpr) -> Option<HirExpr> {
+        let Some(ctx) = self.fn_stack.last() else {
+            self.error("E0702.outside", span, &[]);
+            return Some(HirExpr::never_return(span, None));
+        };
+        let result = ctx.result.clone();
+
+        let value = match &ret.value {
+            Some(v) => Some(self.check_expected(v, Some(&result))),
+            None => {
+                if !result.is_unit() {
+                    self.error("E0702", span, &[("type", &result)]);
+                }
+                None
+            }
+        };
+        Some(HirExpr::never_return(span, value))
+    }
+
+    fn check_set(&mut self, span: Span, set: &ast::SetExpr) -> Option<HirExpr> {
+        let ast::ExprKind::Ident(ident) = &set.target.base.kind else {
+            self.error("E0404.target", set.target.base.span, &[]);
+            return Some(Self::error_expr(span));
+        };
+        if !set.target.fields.is_empty() {
+            self.error("E0800.field-assignment", span, &[]);
+            return Some(Self::error_expr(span));
+        }
+
+        let (ty, mutable, depth) = match self.env.lookup(&ident.name) {
+            Some(Binding::Value {
+                ty, mutable, depth, ..
+            }) => (ty.clone(), *mutable, *depth),
+            Some(Binding::Function(_)) => {
+                self.error("E0404.function", ident.span, &[("name", &ident.name)]);
+                return Some(Self::error_expr(span));
+            }
+            None => {
+                self.unresolved(ident);
+                return Some(Self::error_expr(span));
+            }
+        };
+        self.mark_written(&ident.name);
+        if depth < self.depth() {
+            self.error("E0405", ident.span, &[("name", &ident.name)]);
+        } else if !mutable {
+            let diag = Diagnostic::error_key("E0404", ident.span).arg("name", &ident.name);
+            let diag = self.suggest_mut(diag, &ident.name);
+            self.diagnostics.push(diag);
+        }
+
+        let value = self.check_expected(&set.value, Some(&ty));
+        let base = HirExpr {
+            kind: HirExprKind::Var(HirIdent {
+                name: ident.name.clone(),
+                span: ident.span,
+            }),
+            ty,
+            span: ident.span,
+        };
+
+        Some(HirExpr {
+            kind: HirExprKind::Set {
+                target: Box::new(HirAssignable {
+                    base: Box::new(base),
+                    fields: Vec::new(),
+                }),
+                value: Box::new(value),
+            },
+            ty: Type::Unit,
+            span,
+        })
+    }
+}
+
+/// Check that a value of type `arg` can be passed where `param` is
+/// expected, binding the flexible type parameters `vars` of `param`.
+///
+/// Arguments themselves never contain flexible parameters; a
+/// `Type::Var` on the argument side is a rigid parameter of the
+/// function being checked.
+fn unify(param: &Type, arg: &Type, vars: &[String], subst: &mut Vec<(String, Type)>) -> bool {
+    if arg.is_error() {
+        // Infer `Error` for the parameters the argument would have
+        // decided, so that they are not reported as uninferable.
+        bind_errors(param, vars, subst);
+        return true;
+    }
+    if arg.is_never() {
+        return true;
+    }
+    match (param, arg) {
+        (Type::Var(v), _) if vars.contains(v) => {
+            match subst.iter().find(|(n, _)| n == v) {
+                Some((_, bound)) => is_subtype(arg, bound) != SubtypeResult::NotSubtype,
+                None => {
+                    subst.push((v.clone(), arg.clone()));
+                    true
+                }
+            }
+        }
+        (
+            Type::Apply { name: pn, args: pa },
+            Type::Apply { name: an, args: aa },
+        ) => {
+            pn == an
+                && pa.len() == aa.len()
+                && pa.iter().zip(aa.iter()).all(|(p, a)| unify(p, a, vars, subst))
+        }
+        (
+            Type::Function {
+                params: pp,
+                result: pr,
+                arrow: pk,
+            },
+            Type::Function {
+                params: ap,
+                result: ar,
+                arrow: ak,
+            },
+        ) => {
+            (pk == ak || *ak == ArrowKind::Pure)
+                && pp.len() == ap.len()
+                && pp.iter().zip(ap.iter()).all(|(p, a)| unify(p, a, vars, subst))
+                && unify(pr, ar, vars, subst)
+        }
+        _ => is_subtype(arg, param) != SubtypeResult::NotSubtype,
+    }
+}
+
+/// Bind the flexible parameters occurring in `ty` that are still
+/// unbound to `Type::Error`.
+fn bind_errors(ty: &Type, vars: &[String], subst: &mut Vec<(String, Type)>) {
+    match ty {
+        Type::Var(v) if vars.contains(v) && !subst.iter().any(|(n, _)| n == v) => {
+            subst.push((v.clone(), Type::Error));
+        }
+        Type::Apply { args, .. } => args.iter().for_each(|a| bind_errors(a, vars, subst)),
+        Type::Function { params, result, .. } => {
+            params.iter().for_each(|p| bind_errors(p, vars, subst));
+            bind_errors(result, vars, subst);
+        }
+        _ => {}
+    }
+}
+
+/// Name and type parameters of an enum / struct definition.
+fn type_def_header(e: &ast::Expr) -> Option<(&ast::Ident, &[ast::Ident])> {
+    match &e.kind {
+        ast::ExprKind::EnumDef(d) => Some((&d.name, &d.type_params)),
+        ast::ExprKind::StructDef(d) => Some((&d.name, &d.type_params)),
+        _ => None,
+    }
+}
+
+/// Whether a pattern matches every value of its type.
+fn is_irrefutable(p: &HirPattern) -> bool {
+    match p {
+        HirPattern::Ident { .. } | HirPattern::Wildcard(_) => true,
+        HirPattern::Struct { fields, .. } => fields.iter().all(|f| is_irrefutable(&f.pattern)),
+        _ => false,
+    }
+}
+
+/// Built-in type names.
+fn lookup_type_name(name: &str) -> Option<Type> {
+    match name {
+        "i32" => Some(Type::I32),
+        "i64" => Some(Type::I64),
+        "f32" => Some(Type::F32),
+        "f64" => Some(Type::F64),
+        "Bool" => Some(Type::Bool),
+        "Unit" => Some(Type::Unit),
+        "Never" => Some(Type::Never),
+        "String" => Some(Type::Named("String".into())),
+        _ => None,
+    }
 }
 
-/// 型の共通スーパータイプを「ブロックの最後の式」とマージする例。
+/// 分岐の型を `Never` を底型として合成する。
 ///
-/// 今は使っていないが、If / Match 実装時に利用する予定。
+/// If / Match の各分岐の型から式全体の型を決めるのに使う。
+/// An error from the message catalog with the given arguments.
+fn keyed(key: &'static str, span: Span, args: &[(&'static str, &dyn fmt::Display)]) -> Diagnostic {
+    args.iter()
+        .fold(Diagnostic::error_key(key, span), |d, (name, value)| d.arg(name, value))
+}
+
 fn merge_types_for_branch(
     a: &Type,
     b: &Type,
@@ -293,11 +2751,31 @@ fn merge_types_for_branch(
     match least_common_supertype(a, b) {
         Some(t) => t,
         None => {
-            diags.push(Diagnostic::error(
-                "branches have incompatible types",
-                span,
-            ));
-            Type::Unit
+            diags.push(keyed("E0308", span, &[("first", a), ("second", b)]));
+            Type::Error
+        }
+    }
+}
+
+/// Optimal string alignment distance between two names: the number of
+/// single-character insertions, deletions, substitutions and adjacent
+/// transpositions that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // 3 行だけ保持する DP（2 行前は転置のため）。
+    let mut prev2: Vec<usize> = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut cur = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                cur[j] = cur[j].min(prev2[j - 2] + 1);
+            }
         }
+        prev2 = core::mem::replace(&mut prev, cur);
     }
+    prev[b.len()]
 }
@@ -7,6 +7,8 @@
 
 #![allow(dead_code)]
 
+use core::fmt;
+
 use alloc::string::String;
 use alloc::vec::Vec;
 
@@ -37,12 +39,25 @@ pub enum Type {
     /// Bottom type used for expressions that never return
     /// (return, break, continue, etc.).
     Never,
+    /// Type of an expression or annotation that failed to check. It is
+    /// compatible with every type, so that the error is reported once
+    /// and not again wherever the value is used.
+    Error,
 
     /// User-defined named types (enum, struct, etc.).
     ///
     /// In later phases, this will be tied to symbol table entries.
     Named(String),
 
+    /// Instance of a generic enum or struct: `Option<i32>`.
+    Apply { name: String, args: Vec<Type> },
+
+    /// Type parameter of a generic function, enum or struct.
+    ///
+    /// Inside a generic definition it stands for an unknown type;
+    /// callers instantiate it by substitution.
+    Var(String),
+
     /// Function types: (T1, ..., Tn) -> R or (T1, ..., Tn) *> R.
     Function {
         params: Vec<Type>,
@@ -57,11 +72,56 @@ impl Type {
         matches!(self, Type::Never)
     }
 
+    /// Returns true if this type is the Error type.
+    pub fn is_error(&self) -> bool {
+        matches!(self, Type::Error)
+    }
+
     /// Returns true if this type is the Unit type.
     pub fn is_unit(&self) -> bool {
         matches!(self, Type::Unit)
     }
 
+    /// Returns true if a type parameter occurs anywhere in this type.
+    pub fn has_vars(&self) -> bool {
+        match self {
+            Type::Var(_) => true,
+            Type::Apply { args, .. } => args.iter().any(Type::has_vars),
+            Type::Function { params, result, .. } => {
+                params.iter().any(Type::has_vars) || result.has_vars()
+            }
+            _ => false,
+        }
+    }
+
+    /// Replace type parameters according to `subst`.
+    ///
+    /// Parameters without an entry are left untouched. The replacement
+    /// types are not substituted again.
+    pub fn substitute(&self, subst: &[(String, Type)]) -> Type {
+        match self {
+            Type::Var(name) => subst
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, t)| t.clone())
+                .unwrap_or_else(|| self.clone()),
+            Type::Apply { name, args } => Type::Apply {
+                name: name.clone(),
+                args: args.iter().map(|a| a.substitute(subst)).collect(),
+            },
+            Type::Function {
+                params,
+                result,
+                arrow,
+            } => Type::Function {
+                params: params.iter().map(|p| p.substitute(subst)).collect(),
+                result: alloc::boxed::Box::new(result.substitute(subst)),
+                arrow: *arrow,
+            },
+            _ => self.clone(),
+        }
+    }
+
     /// Construct an impure function type.
     pub fn impure_function(params: Vec<Type>, result: Type) -> Type {
         Type::Function {
@@ -81,6 +141,50 @@ impl Type {
     }
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Unit => write!(f, "Unit"),
+            Type::Never => write!(f, "Never"),
+            Type::Error => write!(f, "{{error}}"),
+            Type::Named(name) | Type::Var(name) => write!(f, "{name}"),
+            Type::Apply { name, args } => {
+                write!(f, "{name}<")?;
+                for (i, a) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{a}")?;
+                }
+                write!(f, ">")
+            }
+            Type::Function {
+                params,
+                result,
+                arrow,
+            } => {
+                write!(f, "(")?;
+                for (i, p) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{p}")?;
+                }
+                let arrow = match arrow {
+                    ArrowKind::Impure => "->",
+                    ArrowKind::Pure => "*>",
+                };
+                write!(f, ") {arrow} {result}")
+            }
+        }
+    }
+}
+
 /// Result of a subtyping check.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SubtypeResult {
@@ -96,11 +200,16 @@ pub enum SubtypeResult {
 ///
 /// 最重要ルール:
 /// * Never <: T for all T
+/// * Error は何とでも等しいものとして扱う (後続のエラーを出さない)
 ///
 /// 関数型は構造的に比較します (現段階では「同じ型ならOK」という単純な扱い)。
 pub fn is_subtype(left: &Type, right: &Type) -> SubtypeResult {
     use SubtypeResult::*;
 
+    if matches!(left, Type::Error) || matches!(right, Type::Error) {
+        return Equal;
+    }
+
     // Never is bottom: Never <: T
     if matches!(left, Type::Never) {
         if matches!(right, Type::Never) {
@@ -122,7 +231,11 @@ pub fn is_subtype(left: &Type, right: &Type) -> SubtypeResult {
         | (Type::F64, _)
         | (Type::Bool, _)
         | (Type::Unit, _)
-        | (Type::Named(_), _) => NotSubtype,
+        | (Type::Named(_), _)
+        | (Type::Var(_), _) => NotSubtype,
+
+        // Generic instances are invariant in their arguments.
+        (Type::Apply { .. }, _) => NotSubtype,
 
         // Function subtyping can be refined later if needed
         // (contra-variance in params, co-variance in result).
@@ -167,12 +280,17 @@ pub fn is_subtype(left: &Type, right: &Type) -> SubtypeResult {
 /// * lcs(Never, T) = T
 /// * lcs(T, Never) = T
 /// * lcs(T, T)     = T
+/// * lcs(Error, T) = lcs(T, Error) = Error
 ///
 /// For now, this function supports a few simple cases and returns
 /// None when a unique common supertype cannot be determined.
 pub fn least_common_supertype(a: &Type, b: &Type) -> Option<Type> {
     use Type::*;
 
+    if matches!(a, Error) || matches!(b, Error) {
+        return Some(Error);
+    }
+
     // Handle Never bottom rules first.
     if matches!(a, Never) {
         return Some(b.clone());
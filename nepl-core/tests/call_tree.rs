@@ -0,0 +1,58 @@
+use nepl_core::call_tree::{CallTree, explain, resolve_calls};
+use nepl_core::span::{FileId, Span};
+
+fn explained(source: &str) -> String {
+    let result = resolve_calls(FileId(0), source);
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    let whole = Span::new(FileId(0), 0, source.len() as u32);
+    explain(source, &result.seqs, whole)
+}
+
+#[test]
+fn implicit_parentheses() {
+    assert_eq!(explained("add 1 mul 2 3"), "add 1 (mul 2 3)");
+    assert_eq!(explained("add mul 1 2 3"), "add (mul 1 2) 3");
+    assert_eq!(explained("i64 add 1 2"), "i64 (add 1 2)");
+    // Parentheses in the source are kept and not doubled.
+    assert_eq!(
+        explained("add (mul 1 2) sub 3 4"),
+        "add (mul 1 2) (sub 3 4)"
+    );
+    assert_eq!(
+        explained("{\n    fn f = |i32 a, i32 b|->i32 add a b;\n    f 1 neg f 2 3\n}"),
+        "{\n    fn f = |i32 a, i32 b|->i32 add a b;\n    f 1 (neg (f 2 3))\n}"
+    );
+    // Pipes are not sequences of the source.
+    assert_eq!(explained("1 > neg > add 2"), "1 > neg > add 2");
+}
+
+#[test]
+fn trees_have_spans() {
+    let source = "add 1 mul 2 3";
+    let result = resolve_calls(FileId(0), source);
+    let span = |start, end| Span::new(FileId(0), start, end);
+    assert_eq!(result.seqs.len(), 1);
+    let seq = &result.seqs[0];
+    assert_eq!(seq.span, span(0, 13));
+    assert_eq!(
+        seq.groups,
+        vec![CallTree::Apply {
+            head: span(0, 3),
+            args: vec![
+                CallTree::Term(span(4, 5)),
+                CallTree::Apply {
+                    head: span(6, 9),
+                    args: vec![CallTree::Term(span(10, 11)), CallTree::Term(span(12, 13))],
+                    span: span(6, 13),
+                },
+            ],
+            span: span(0, 13),
+        }]
+    );
+    assert_eq!(seq.implicit_parens(), vec![span(6, 13)]);
+
+    // An ill-formed sequence keeps every value it reduced to.
+    let result = resolve_calls(FileId(0), "1 2");
+    assert_eq!(result.seqs[0].groups.len(), 2);
+    assert!(result.seqs[0].implicit_parens().is_empty());
+}
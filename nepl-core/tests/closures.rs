@@ -0,0 +1,85 @@
+mod common;
+
+use common::{check, messages, run};
+use nepl_core::hir::HirExprKind;
+
+#[test]
+fn captures_are_recorded() {
+    let result = check("{ let k = 10; let add_k = |i32 x|->i32 add x k; add_k 5 }");
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    let captures: Vec<&str> = result.functions[0]
+        .captures
+        .iter()
+        .map(|c| c.name.name.as_str())
+        .collect();
+    assert_eq!(captures, vec!["k"]);
+
+    let hir = result.expr.unwrap();
+    let HirExprKind::Block { expressions } = &hir.kind else {
+        panic!("expected block, got {:?}", hir.kind);
+    };
+    assert!(format!("{:?}", expressions).contains("CallIndirect"));
+}
+
+#[test]
+fn closures_capture_by_value() {
+    let source = "{
+        let k = 10;
+        let add_k = |i32 x|->i32 add x k;
+        let k = 100;
+        add_k 5
+    }";
+    assert_eq!(run(source), 15);
+}
+
+#[test]
+fn closures_are_passed_to_higher_order_functions() {
+    let source = "{
+        fn apply_twice = |(i32) -> i32 f, i32 x|->i32 f f x;
+        let step = 3;
+        add (apply_twice (|i32 x|->i32 add x step) 1) (apply_twice (|i32 x|*>i32 mul x 2) 1)
+    }";
+    assert_eq!(run(source), 11);
+}
+
+#[test]
+fn closures_are_returned_from_functions() {
+    let source = "{
+        fn adder = |i32 n|->(i32) -> i32 |i32 x|->i32 add x n;
+        let add3 = adder 3;
+        let add4 = adder 4;
+        mul (add3 1) (add4 1)
+    }";
+    assert_eq!(run(source), 20);
+}
+
+#[test]
+fn nested_closures_capture_through_each_level() {
+    let source = "{
+        let a = 1;
+        let outer = |i32 b|->i32 {
+            let inner = |i32 c|->i32 add add a b c;
+            inner 100
+        };
+        outer 10
+    }";
+    assert_eq!(run(source), 111);
+}
+
+#[test]
+fn captured_variables_cannot_be_assigned() {
+    let result = check("{ let mut n = 0; let inc = ||->Unit set n add n 1; inc; n }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["cannot assign to `n`: function literals capture variables by value"]
+    );
+}
+
+#[test]
+fn fn_bindings_cannot_capture() {
+    let result = check("{ let k = 1; fn f = |i32 x|->i32 add x k; f 2 }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["`fn f` cannot capture the local variable `k`; bind the function literal with `let` instead"]
+    );
+}
@@ -0,0 +1,68 @@
+//! Helpers shared by the integration tests. Each test crate uses only
+//! some of them.
+#![allow(dead_code)]
+
+use nepl_core::ast::ExprKind;
+use nepl_core::compile_wasm;
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::parser::parse_file;
+use nepl_core::span::FileId;
+use nepl_core::typecheck::{TypeCheckResult, typecheck_expr};
+use wasmi::{Engine, Linker, Module, Store};
+
+/// Type-check `source`, which must parse.
+pub fn check(source: &str) -> TypeCheckResult {
+    let parsed = parse_file(FileId(0), source);
+    assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+    typecheck_expr(parsed.expr.as_ref().unwrap())
+}
+
+pub fn messages(diags: &[Diagnostic]) -> Vec<&str> {
+    diags.iter().map(|d| d.message.as_str()).collect()
+}
+
+/// Compile `source` and return what its `main` returns.
+pub fn run(source: &str) -> i32 {
+    let artifact = compile_wasm(FileId(0), source).unwrap_or_else(|e| panic!("{e:?}"));
+    let engine = Engine::default();
+    let module = Module::new(&engine, &artifact.wasm).unwrap();
+    let mut store = Store::new(&engine, ());
+    let mut linker = Linker::new(&engine);
+    // The stdlib asserts; a failed `assert` traps after the call.
+    linker
+        .func_wrap("nepl", "assert_failed", |_: i32| {})
+        .unwrap();
+    let instance = linker.instantiate_and_start(&mut store, &module).unwrap();
+    let main = instance.get_typed_func::<(), i32>(&store, "main").unwrap();
+    main.call(&mut store, ()).unwrap()
+}
+
+/// Run `f` on a thread with the native stack the interpreter needs.
+pub fn on_interp_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|s| {
+        std::thread::Builder::new()
+            .stack_size(nepl_core::interp::STACK_SIZE)
+            .spawn_scoped(s, f)
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Definitions inside the `pub namespace { ... }` of a stdlib file.
+pub fn stdlib_defs(path: &str) -> String {
+    let path = format!("{}/../stdlib/{path}", env!("CARGO_MANIFEST_DIR"));
+    let source = std::fs::read_to_string(&path).unwrap();
+    let parsed = parse_file(FileId(0), &source);
+    assert!(
+        parsed.diagnostics.is_empty(),
+        "{path}: {:?}",
+        parsed.diagnostics
+    );
+    let expr = parsed.expr.unwrap();
+    let ExprKind::Namespace(ns) = expr.kind else {
+        panic!("{path}: expected a namespace, got {:?}", expr.kind);
+    };
+    let span = ns.body.span;
+    source[span.start as usize + 1..span.end as usize - 1].to_string()
+}
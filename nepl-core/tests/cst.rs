@@ -0,0 +1,151 @@
+use nepl_core::cst::{SyntaxElement, SyntaxKind, SyntaxNode};
+use nepl_core::lexer::{TokenKind, TriviaKind};
+use nepl_core::parser::{lower_cst, parse_cst, parse_file};
+use nepl_core::span::FileId;
+
+fn tree(source: &str) -> SyntaxNode {
+    let result = parse_cst(FileId(0), source);
+    assert_eq!(result.tree.text(), source);
+    result.tree
+}
+
+#[test]
+fn trees_reproduce_the_source() {
+    let root = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+    for dir in ["stdlib", "examples"] {
+        for entry in walkdir::WalkDir::new(format!("{root}/{dir}")) {
+            let entry = entry.unwrap();
+            if entry.path().extension().is_some_and(|e| e == "nepl") {
+                let source = std::fs::read_to_string(entry.path()).unwrap();
+                let result = parse_cst(FileId(0), &source);
+                assert_eq!(result.tree.text(), source, "{}", entry.path().display());
+                assert_eq!(result.expr, parse_file(FileId(0), &source).expr);
+            }
+        }
+    }
+
+    // Broken code round-trips too, including text the lexer skipped.
+    for source in ["{ let x = ; @ }", "f \"open", "1 2 )) /* open", "", "  \n"] {
+        tree(source);
+    }
+}
+
+#[test]
+fn nodes_follow_the_grammar() {
+    let source = "// sum\nadd 1 /* one */ (f x) > g";
+    assert_eq!(
+        tree(source).debug_tree(),
+        "\
+Root@0..32
+  LineComment@0..6 \"// sum\"
+  Whitespace@6..7 \"\\n\"
+  Pipe@7..32
+    Seq@7..28
+      Ident@7..10
+        Ident@7..10 \"add\"
+      Whitespace@10..11 \" \"
+      Literal@11..12
+        IntLiteral@11..12 \"1\"
+      Whitespace@12..13 \" \"
+      BlockComment@13..22 \"/* one */\"
+      Whitespace@22..23 \" \"
+      Paren@23..28
+        LParen@23..24 \"(\"
+        Seq@24..27
+          Ident@24..25
+            Ident@24..25 \"f\"
+          Whitespace@25..26 \" \"
+          Ident@26..27
+            Ident@26..27 \"x\"
+        RParen@27..28 \")\"
+    Whitespace@28..29 \" \"
+    Greater@29..30 \">\"
+    Whitespace@30..31 \" \"
+    Ident@31..32
+      Ident@31..32 \"g\"
+"
+    );
+}
+
+#[test]
+fn nested_nodes_and_errors() {
+    let source = "match v {\n    case Some(x) => x;\n    case _ => 0\n}";
+    let root = tree(source);
+    let kinds: Vec<SyntaxKind> = root.descendants().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds
+            .iter()
+            .filter(|k| **k == SyntaxKind::MatchCase)
+            .count(),
+        2
+    );
+    // `Some(x)`, `x` and `_`.
+    assert_eq!(
+        kinds.iter().filter(|k| **k == SyntaxKind::Pattern).count(),
+        3
+    );
+
+    let x = root
+        .token_at_offset(source.rfind('x').unwrap() as u32)
+        .unwrap();
+    assert_eq!(x.kind(), SyntaxKind::Token(TokenKind::Ident));
+    let ancestors: Vec<SyntaxKind> = x.parent().ancestors().map(|n| n.kind()).collect();
+    assert_eq!(
+        ancestors,
+        vec![
+            SyntaxKind::Ident,
+            SyntaxKind::MatchCase,
+            SyntaxKind::Match,
+            SyntaxKind::Root
+        ]
+    );
+
+    // Junk after the top-level expression and characters the lexer
+    // rejected are kept.
+    let root = tree("1 ) @");
+    let last = root.children_with_tokens().last().unwrap();
+    let SyntaxElement::Token(garbage) = last else {
+        panic!("{last:?}");
+    };
+    assert_eq!((garbage.kind(), garbage.text()), (SyntaxKind::Garbage, "@"));
+    assert!(root.children().any(|n| n.kind() == SyntaxKind::Error));
+
+    let comments = tree("f /// doc\n x")
+        .tokens()
+        .filter(|t| t.kind() == SyntaxKind::Trivia(TriviaKind::DocComment))
+        .count();
+    assert_eq!(comments, 1);
+}
+
+#[test]
+fn recovery_reports_a_position_once() {
+    // Every open `(` expects a `)` where the list literal starts; only
+    // the first error there is reported.
+    let parsed = parse_file(FileId(0), "(f (g (h [1 2] 1)))");
+    let errors: Vec<_> = parsed
+        .diagnostics
+        .iter()
+        .map(|d| (d.message.as_str(), d.primary.span.start))
+        .collect();
+    assert_eq!(errors, [("expected expression", 9)]);
+
+    // A different error at the same position is still reported.
+    let parsed = parse_file(FileId(0), "add 1 (f [1])");
+    let codes: Vec<_> = parsed
+        .diagnostics
+        .iter()
+        .map(|d| (d.code, d.primary.span.start))
+        .collect();
+    assert_eq!(codes, [(Some("E0100"), 9), (Some("E0101"), 9)]);
+}
+
+#[test]
+fn the_ast_is_derived_from_the_tree() {
+    let source = "{\n    let s = \"a\\tb\"; // tab\n    fn id = <T> |T x|->T x;\n    id s\n}";
+    let result = parse_cst(FileId(3), source);
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    let lowered = lower_cst(&result.tree);
+    assert!(lowered.diagnostics.is_empty(), "{:?}", lowered.diagnostics);
+    assert_eq!(lowered.expr, result.expr);
+    assert_eq!(lowered.expr, parse_file(FileId(3), source).expr);
+}
@@ -0,0 +1,622 @@
+//! Differential tests: every program is run both by the HIR interpreter
+//! and as a wasm module in wasmi, and the two must agree on the printed
+//! output and on the result or the trap.
+//!
+//! Programs come from `examples/`, `tests/programs/` and a generator of
+//! random well-typed programs. Generated programs are reproducible from
+//! their seed; a failure prints the source.
+//!
+//! Both sides run with fuel and a call depth limit. The limits are not
+//! counted in the same units, so a run that exhausts either only has to
+//! agree on what was printed until then.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::on_interp_stack;
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::interp::{CaptureHost, Value};
+use nepl_core::span::FileId;
+use nepl_core::types::Type;
+use nepl_core::{CoreError, compile_wasm, interpret};
+use wasmi::core::TrapCode;
+use wasmi::{Caller, Config, Engine, Linker, Module, Store, Val};
+
+/// What `random_i32` returns on both sides.
+const RANDOM: i32 = 4;
+/// Evaluation steps allowed to the interpreter.
+const FUEL: u64 = 1_000_000;
+/// Fuel given to wasmi, which charges about one unit per instruction.
+const WASM_FUEL: u64 = 10_000_000;
+
+/// How a run ended.
+#[derive(Debug)]
+enum Outcome {
+    Value(Value),
+    /// A trap, named by the `E1000` message key it corresponds to.
+    Trap(&'static str),
+}
+
+/// Printed values and outcome of one run.
+type Run = (Vec<i32>, Outcome);
+
+fn same(a: &Outcome, b: &Outcome) -> bool {
+    match (a, b) {
+        (Outcome::Value(Value::F32(x)), Outcome::Value(Value::F32(y))) => {
+            x.to_bits() == y.to_bits() || (x.is_nan() && y.is_nan())
+        }
+        (Outcome::Value(Value::F64(x)), Outcome::Value(Value::F64(y))) => {
+            x.to_bits() == y.to_bits() || (x.is_nan() && y.is_nan())
+        }
+        (Outcome::Value(x), Outcome::Value(y)) => x == y,
+        (Outcome::Trap(x), Outcome::Trap(y)) => x == y,
+        _ => false,
+    }
+}
+
+/// Whether the run stopped because it ran out of fuel or call depth.
+fn exhausted(outcome: &Outcome) -> bool {
+    matches!(outcome, Outcome::Trap("E1001" | "E1000.stack-overflow"))
+}
+
+/// Whether two runs agree. An exhausted run must have printed a prefix
+/// of what the other run printed.
+fn agree(a: &Run, b: &Run) -> bool {
+    match (exhausted(&a.1), exhausted(&b.1)) {
+        (false, false) => a.0 == b.0 && same(&a.1, &b.1),
+        (true, false) => b.0.starts_with(&a.0),
+        (false, true) => a.0.starts_with(&b.0),
+        (true, true) => a.0.starts_with(&b.0) || b.0.starts_with(&a.0),
+    }
+}
+
+fn trap_key(diagnostic: &Diagnostic) -> &'static str {
+    match diagnostic.localized.as_ref().map(|m| m.key) {
+        // Compiled code traps with `unreachable` in both cases.
+        Some("E1000.out-of-memory") => "E1000.no-match",
+        Some(key) => key,
+        None => "unknown",
+    }
+}
+
+fn interpreted(source: &str) -> Run {
+    let mut host = CaptureHost {
+        printed: Vec::new(),
+        random: RANDOM,
+    };
+    let outcome = match on_interp_stack(|| interpret(FileId(0), source, &mut host, Some(FUEL))) {
+        Ok(value) => Outcome::Value(value),
+        Err(CoreError::Diagnostics(diags)) => Outcome::Trap(trap_key(&diags[0])),
+        Err(other) => panic!("{other:?}"),
+    };
+    (host.printed, outcome)
+}
+
+fn compiled(wasm: &[u8], ty: &Type) -> Run {
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, wasm).unwrap();
+    let mut linker = Linker::new(&engine);
+    linker
+        .func_wrap(
+            "nepl",
+            "print_i32",
+            |mut caller: Caller<'_, Vec<i32>>, v: i32| caller.data_mut().push(v),
+        )
+        .unwrap();
+    linker.func_wrap("nepl", "random_i32", || RANDOM).unwrap();
+    let mut store = Store::new(&engine, Vec::new());
+    store.set_fuel(WASM_FUEL).unwrap();
+    let instance = linker.instantiate_and_start(&mut store, &module).unwrap();
+    let main = instance.get_func(&store, "main").unwrap();
+    let mut results: Vec<Val> = main
+        .ty(&store)
+        .results()
+        .iter()
+        .map(|t| Val::default(*t))
+        .collect();
+    let outcome = match main.call(&mut store, &[], &mut results) {
+        Ok(()) => Outcome::Value(match (results.pop(), ty) {
+            (None, _) => Value::Unit,
+            (Some(Val::I32(v)), Type::Bool) => Value::Bool(v != 0),
+            (Some(Val::I32(v)), _) => Value::I32(v),
+            (Some(Val::I64(v)), _) => Value::I64(v),
+            (Some(Val::F32(v)), _) => Value::F32(v.into()),
+            (Some(Val::F64(v)), _) => Value::F64(v.into()),
+            (Some(other), _) => panic!("unexpected result {other:?}"),
+        }),
+        Err(e) => Outcome::Trap(match e.as_trap_code() {
+            Some(TrapCode::IntegerDivisionByZero) => "E1000.division-by-zero",
+            Some(TrapCode::IntegerOverflow) => "E1000.overflow",
+            Some(TrapCode::MemoryOutOfBounds) => "E1000.out-of-bounds",
+            Some(TrapCode::UnreachableCodeReached) => "E1000.no-match",
+            Some(TrapCode::StackOverflow) => "E1000.stack-overflow",
+            Some(TrapCode::OutOfFuel) => "E1001",
+            _ => panic!("unexpected error {e}"),
+        }),
+    };
+    (store.into_data(), outcome)
+}
+
+/// The type of the entry expression, which decides how to read the
+/// result of `main`.
+fn entry_type(source: &str) -> Type {
+    let parsed = nepl_core::parser::parse_file(FileId(0), source);
+    let typed = nepl_core::typecheck::typecheck_expr(parsed.expr.as_ref().unwrap());
+    typed.expr.unwrap().ty
+}
+
+/// Run `source` both ways; `Err` describes a disagreement. Programs
+/// that do not compile return `Ok(false)`.
+fn compare(source: &str) -> Result<bool, String> {
+    let wasm = match compile_wasm(FileId(0), source) {
+        Ok(artifact) => artifact.wasm,
+        Err(_) => return Ok(false),
+    };
+    let expected = compiled(&wasm, &entry_type(source));
+    let actual = interpreted(source);
+    if !agree(&expected, &actual) {
+        return Err(format!(
+            "wasm printed {:?} and ended with {:?}\ninterpreter printed {:?} and ended with {:?}",
+            expected.0, expected.1, actual.0, actual.1
+        ));
+    }
+    Ok(true)
+}
+
+fn nepl_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "nepl"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn examples_and_test_programs_agree() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = nepl_files(&root.join("../examples"));
+    files.extend(nepl_files(&root.join("tests/programs")));
+    let mut ran = 0;
+    for path in &files {
+        let source = fs::read_to_string(path).unwrap();
+        match compare(&source) {
+            Ok(compiled) => ran += compiled as usize,
+            Err(e) => panic!("{}:\n{e}", path.display()),
+        }
+    }
+    // Every test program compiles; only some examples may not.
+    assert!(ran >= nepl_files(&root.join("tests/programs")).len() + 3);
+}
+
+#[test]
+fn generated_programs_agree() {
+    for seed in 1..=300 {
+        let source = Generator::new(seed).program();
+        match compare(&source) {
+            Ok(true) => {}
+            Ok(false) => {
+                let err = compile_wasm(FileId(0), &source).unwrap_err();
+                panic!("seed {seed} generated an invalid program:\n{source}\n{err:?}");
+            }
+            Err(e) => panic!("seed {seed}:\n{source}\n{e}"),
+        }
+    }
+}
+
+#[test]
+fn long_let_chains_agree() {
+    // Each `let` scopes over the rest of its block; the phases must not
+    // recurse once per binding.
+    let mut source = String::from("{\n    let x0 = 0;\n");
+    for i in 1..=2000 {
+        source += &format!("    let x{i} = add x{} 1;\n", i - 1);
+    }
+    source += "    print_i32 x2000;\n    x1000\n}\n";
+    assert_eq!(compare(&source), Ok(true));
+    let (printed, outcome) = interpreted(&source);
+    assert_eq!(printed, [2000]);
+    assert!(same(&outcome, &Outcome::Value(Value::I32(1000))));
+}
+
+#[test]
+fn resource_limits_agree() {
+    let sum = |n: i32| {
+        format!("{{ fn sum = |i32 n|->i32 if eq n 0 then 0 else add n sum sub n 1; sum {n} }}")
+    };
+    let wasm = |source: &str| compiled(&compile_wasm(FileId(0), source).unwrap().wasm, &Type::I32);
+
+    // Both sides allow the same call depth.
+    for (source, outcome) in [
+        (sum(998), Outcome::Value(Value::I32(498501))),
+        (sum(999), Outcome::Trap("E1000.stack-overflow")),
+    ] {
+        assert!(same(&wasm(&source).1, &outcome), "{source}");
+        assert!(same(&interpreted(&source).1, &outcome), "{source}");
+    }
+
+    // Both run out of fuel, after a different number of iterations.
+    let source = "{ let mut i = 0; loop { print_i32 i; set i add i 1 } }";
+    let (expected, actual) = (wasm(source), interpreted(source));
+    assert!(same(&expected.1, &Outcome::Trap("E1001")));
+    assert!(same(&actual.1, &Outcome::Trap("E1001")));
+    assert_ne!(expected.0.len(), actual.0.len());
+    assert!(agree(&expected, &actual));
+}
+
+// ---------------------------------------------------------------------
+// Random programs
+// ---------------------------------------------------------------------
+
+/// xorshift64*: small, deterministic and good enough for test inputs.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Ty {
+    I32,
+    I64,
+    F64,
+    Bool,
+}
+
+impl Ty {
+    fn name(self) -> &'static str {
+        match self {
+            Ty::I32 => "i32",
+            Ty::I64 => "i64",
+            Ty::F64 => "f64",
+            Ty::Bool => "Bool",
+        }
+    }
+}
+
+const TYPES: [Ty; 4] = [Ty::I32, Ty::I64, Ty::F64, Ty::Bool];
+
+struct Var {
+    name: String,
+    ty: Ty,
+    mutable: bool,
+}
+
+/// Generates well-typed programs of bounded size. Every subexpression
+/// is parenthesised so that the P-style grouping is never in doubt.
+struct Generator {
+    rng: Rng,
+    vars: Vec<Var>,
+    /// Functions `fK` of type `(i32, i32) -> i32` defined so far.
+    functions: usize,
+    names: usize,
+}
+
+impl Generator {
+    fn new(seed: u64) -> Self {
+        Generator {
+            rng: Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1),
+            vars: Vec::new(),
+            functions: 0,
+            names: 0,
+        }
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{prefix}{}", self.names)
+    }
+
+    fn program(&mut self) -> String {
+        let mut items = vec![
+            "enum Opt { Some(i32); None }".to_string(),
+            "struct Pair { left: i64; right: f64 }".to_string(),
+            "fn id = <T>|T x|*>T x".to_string(),
+        ];
+        for _ in 0..self.rng.below(4) {
+            items.push(self.function());
+        }
+        let ty = *self.rng.pick(&TYPES);
+        items.push(self.expr(ty, 4));
+        format!("{{\n    {}\n}}", items.join(";\n    "))
+    }
+
+    /// `fn fK = |i32 a, i32 b|->i32 body`, possibly returning early.
+    fn function(&mut self) -> String {
+        let name = format!("f{}", self.functions);
+        let (a, b) = (self.fresh("a"), self.fresh("b"));
+        let outer = std::mem::take(&mut self.vars);
+        for param in [&a, &b] {
+            self.vars.push(Var {
+                name: param.clone(),
+                ty: Ty::I32,
+                mutable: false,
+            });
+        }
+        let body = if self.rng.below(3) == 0 {
+            let cond = self.expr(Ty::Bool, 2);
+            let early = self.expr(Ty::I32, 2);
+            let rest = self.expr(Ty::I32, 3);
+            format!("{{ if {cond} then {{ return {early} }} else {{}}; {rest} }}")
+        } else {
+            self.expr(Ty::I32, 3)
+        };
+        self.vars = outer;
+        self.functions += 1;
+        format!("fn {name} = |i32 {a}, i32 {b}|->i32 {body}")
+    }
+
+    fn literal(&mut self, ty: Ty) -> String {
+        let rng = &mut self.rng;
+        match ty {
+            Ty::I32 => (*rng.pick(&[
+                "0",
+                "1",
+                "2",
+                "7",
+                "100",
+                "(neg 1)",
+                "(neg 8)",
+                "2147483647",
+                "(sub (neg 2147483647) 1)",
+            ]))
+            .to_string(),
+            Ty::I64 => (*rng.pick(&[
+                "0i64",
+                "3i64",
+                "(neg 5i64)",
+                "4294967296i64",
+                "9223372036854775807i64",
+            ]))
+            .to_string(),
+            Ty::F64 => (*rng.pick(&["0.0", "1.5", "(neg 2.25)", "0.1", "1e10"])).to_string(),
+            Ty::Bool => (*rng.pick(&["true", "false"])).to_string(),
+        }
+    }
+
+    fn leaf(&mut self, ty: Ty) -> String {
+        let candidates: Vec<&Var> = self.vars.iter().filter(|v| v.ty == ty).collect();
+        if !candidates.is_empty() && self.rng.below(2) == 0 {
+            return candidates[self.rng.below(candidates.len())].name.clone();
+        }
+        self.literal(ty)
+    }
+
+    fn expr(&mut self, ty: Ty, size: usize) -> String {
+        if size == 0 || self.rng.below(5) == 0 {
+            return self.leaf(ty);
+        }
+        let s = size - 1;
+        match self.rng.below(12) {
+            0 => {
+                let cond = self.expr(Ty::Bool, s);
+                let (a, b) = (self.expr(ty, s), self.expr(ty, s));
+                format!("(if {cond} then {{ {a} }} else {{ {b} }})")
+            }
+            1 => self.let_block(ty, s),
+            2 => {
+                let scrutinee = self.expr(Ty::I32, s);
+                let arms: Vec<String> = (0..2)
+                    .map(|n| format!("case {n} => {}", self.expr(ty, s)))
+                    .collect();
+                let other = self.expr(ty, s);
+                format!(
+                    "(match {scrutinee} {{ {}; case _ => {other} }})",
+                    arms.join("; ")
+                )
+            }
+            3 => {
+                let value = self.expr(ty, s);
+                format!("(id {value})")
+            }
+            4 => {
+                let printed = self.expr(Ty::I32, s);
+                let value = self.expr(ty, s);
+                format!("{{ print_i32 {printed}; {value} }}")
+            }
+            5 => self.closure(ty, s),
+            6 => self.records(ty, s),
+            7 if ty != Ty::Bool => self.loop_break(ty, s),
+            8 if matches!(ty, Ty::I32 | Ty::I64) => self.while_loop(ty, s),
+            9 if ty == Ty::I32 && self.functions > 0 => {
+                let f = self.rng.below(self.functions);
+                let (a, b) = (self.expr(Ty::I32, s), self.expr(Ty::I32, s));
+                format!("(f{f} {a} {b})")
+            }
+            _ => self.operator(ty, s),
+        }
+    }
+
+    fn operator(&mut self, ty: Ty, s: usize) -> String {
+        match ty {
+            Ty::I32 | Ty::I64 => {
+                if self.rng.below(6) == 0 {
+                    let op = self.rng.pick(&["neg", "bit_not"]);
+                    return format!("({op} {})", self.expr(ty, s));
+                }
+                let op = *self.rng.pick(&[
+                    "add", "sub", "mul", "div", "mod", "bit_and", "bit_or", "bit_xor", "bit_shl",
+                    "bit_shr",
+                ]);
+                let a = self.expr(ty, s);
+                let mut b = self.expr(ty, s);
+                // Mostly avoid dividing by zero, but keep some traps.
+                if matches!(op, "div" | "mod") && self.rng.below(8) != 0 {
+                    let one = if ty == Ty::I32 { "1" } else { "1i64" };
+                    b = format!("(bit_or {b} {one})");
+                }
+                format!("({op} {a} {b})")
+            }
+            Ty::F64 => {
+                if self.rng.below(6) == 0 {
+                    return format!("(neg {})", self.expr(ty, s));
+                }
+                let op = self.rng.pick(&["add", "sub", "mul", "div"]);
+                format!("({op} {} {})", self.expr(ty, s), self.expr(ty, s))
+            }
+            Ty::Bool => match self.rng.below(4) {
+                0 => format!("(not {})", self.expr(Ty::Bool, s)),
+                1 => {
+                    let op = self.rng.pick(&["and", "or", "xor", "eq"]);
+                    format!(
+                        "({op} {} {})",
+                        self.expr(Ty::Bool, s),
+                        self.expr(Ty::Bool, s)
+                    )
+                }
+                _ => {
+                    let operand = *self.rng.pick(&[Ty::I32, Ty::I64, Ty::F64]);
+                    let op = self.rng.pick(&["eq", "ne", "lt", "le", "gt", "ge"]);
+                    format!("({op} {} {})", self.expr(operand, s), self.expr(operand, s))
+                }
+            },
+        }
+    }
+
+    /// Evaluate `body` with `vars` in scope, then drop them.
+    fn scoped(&mut self, vars: Vec<Var>, ty: Ty, s: usize) -> String {
+        let saved = self.vars.len();
+        self.vars.extend(vars);
+        let body = self.expr(ty, s);
+        self.vars.truncate(saved);
+        body
+    }
+
+    fn let_block(&mut self, ty: Ty, s: usize) -> String {
+        let var_ty = *self.rng.pick(&TYPES);
+        let init = self.expr(var_ty, s);
+        let name = self.fresh("v");
+        let var = Var {
+            name: name.clone(),
+            ty: var_ty,
+            mutable: false,
+        };
+        let body = self.scoped(vec![var], ty, s);
+        format!("{{ let {name} = {init}; {body} }}")
+    }
+
+    /// A closure over the immutable variables in scope, called once.
+    fn closure(&mut self, ty: Ty, s: usize) -> String {
+        let (c, p) = (self.fresh("c"), self.fresh("p"));
+        let outer: Vec<Var> = self
+            .vars
+            .iter()
+            .filter(|v| !v.mutable)
+            .map(|v| Var {
+                name: v.name.clone(),
+                ty: v.ty,
+                mutable: false,
+            })
+            .collect();
+        let saved = std::mem::replace(&mut self.vars, outer);
+        let param = Var {
+            name: p.clone(),
+            ty: Ty::I32,
+            mutable: false,
+        };
+        let body = self.scoped(vec![param], ty, s);
+        self.vars = saved;
+        let arg = self.expr(Ty::I32, s);
+        format!("{{ let {c} = |i32 {p}|->{} {body}; {c} {arg} }}", ty.name())
+    }
+
+    /// Build an enum and a struct value and take them apart again.
+    fn records(&mut self, ty: Ty, s: usize) -> String {
+        let (x, l, r) = (self.fresh("x"), self.fresh("l"), self.fresh("r"));
+        let cond = self.expr(Ty::Bool, s);
+        let payload = self.expr(Ty::I32, s);
+        let some = self.scoped(
+            vec![Var {
+                name: x.clone(),
+                ty: Ty::I32,
+                mutable: false,
+            }],
+            ty,
+            s,
+        );
+        let none = self.expr(ty, s);
+        let (left, right) = (self.expr(Ty::I64, s), self.expr(Ty::F64, s));
+        let fields = vec![
+            Var {
+                name: l.clone(),
+                ty: Ty::I64,
+                mutable: false,
+            },
+            Var {
+                name: r.clone(),
+                ty: Ty::F64,
+                mutable: false,
+            },
+        ];
+        let pair = self.scoped(fields, ty, s);
+        if self.rng.below(2) == 0 {
+            format!(
+                "(match (if {cond} then {{ Some {payload} }} else {{ None }}) \
+                 {{ case Some({x}) => {some}; case None => {none} }})"
+            )
+        } else {
+            format!(
+                "(match (Pair {left} {right}) {{ case Pair {{ left: {l}, right: {r} }} => {pair} }})"
+            )
+        }
+    }
+
+    /// `loop` that counts up and breaks with a value.
+    fn loop_break(&mut self, ty: Ty, s: usize) -> String {
+        let i = self.fresh("i");
+        let limit = self.rng.below(4);
+        let counter = Var {
+            name: i.clone(),
+            ty: Ty::I32,
+            mutable: true,
+        };
+        let value = self.scoped(vec![counter], ty, s);
+        format!(
+            "{{ let mut {i} = 0; loop {{ set {i} add {i} 1; \
+             if gt {i} {limit} then {{ break {value} }} else {{}} }} }}"
+        )
+    }
+
+    /// `while` loop folding a value into an accumulator.
+    fn while_loop(&mut self, ty: Ty, s: usize) -> String {
+        let (acc, i) = (self.fresh("acc"), self.fresh("i"));
+        let init = self.expr(ty, s);
+        let limit = self.rng.below(5);
+        let op = self.rng.pick(&["add", "sub", "mul", "bit_xor"]);
+        let vars = vec![
+            Var {
+                name: acc.clone(),
+                ty,
+                mutable: true,
+            },
+            Var {
+                name: i.clone(),
+                ty: Ty::I32,
+                mutable: true,
+            },
+        ];
+        let step = self.scoped(vars, ty, s);
+        format!(
+            "{{ let mut {acc} = {init}; let mut {i} = 0; \
+             while lt {i} {limit} {{ set {acc} {op} {acc} {step}; set {i} add {i} 1 }}; {acc} }}"
+        )
+    }
+}
@@ -0,0 +1,83 @@
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::error_codes::{CATALOG, lookup};
+use nepl_core::lint::LintConfig;
+use nepl_core::span::FileId;
+use nepl_core::{CoreError, check, compile_wasm};
+
+/// Diagnostics of the front end (lexer, parser, type checker and lints).
+fn front_end(source: &str) -> Vec<Diagnostic> {
+    check(FileId(0), source, &LintConfig::default())
+}
+
+/// Fenced code blocks of an explanation with their info strings.
+fn code_blocks(markdown: &str) -> Vec<(&str, String)> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(&str, String)> = None;
+    for line in markdown.lines() {
+        match (line.strip_prefix("```"), current.take()) {
+            (Some(info), None) => current = Some((info, String::new())),
+            (Some(_), Some(block)) => blocks.push(block),
+            (None, Some((info, mut text))) => {
+                text.push_str(line);
+                text.push('\n');
+                current = Some((info, text));
+            }
+            (None, None) => {}
+        }
+    }
+    blocks
+}
+
+#[test]
+fn catalog_is_sorted_and_unique() {
+    for pair in CATALOG.windows(2) {
+        assert!(
+            pair[0].code < pair[1].code,
+            "{} / {}",
+            pair[0].code,
+            pair[1].code
+        );
+    }
+    for entry in CATALOG {
+        assert!(!entry.explanation.trim().is_empty(), "{}", entry.code);
+    }
+}
+
+#[test]
+fn lookup_accepts_loose_spellings() {
+    assert_eq!(lookup("E0300").unwrap().code, "E0300");
+    assert_eq!(lookup("e0300").unwrap().code, "E0300");
+    assert_eq!(lookup("0300").unwrap().code, "E0300");
+    assert!(lookup("E9999").is_none());
+}
+
+#[test]
+fn explanation_examples_behave_as_documented() {
+    let mut failures = Vec::new();
+    for entry in CATALOG {
+        for (info, source) in code_blocks(entry.explanation) {
+            let diags = front_end(&source);
+            let ok = match info {
+                "nepl,compile_fail" | "nepl,warn" => {
+                    diags.iter().any(|d| d.code == Some(entry.code))
+                }
+                "nepl" => diags.is_empty(),
+                _ => continue,
+            };
+            if !ok {
+                let reported: Vec<_> = diags.iter().map(|d| (d.code, &d.message)).collect();
+                failures.push(format!("{} ({info}): {reported:?}\n{source}", entry.code));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn compiler_diagnostics_carry_codes() {
+    let Err(CoreError::Diagnostics(diags)) = compile_wasm(FileId(0), "{ let x = 1; add x y }")
+    else {
+        panic!("expected an error");
+    };
+    assert_eq!(diags[0].code, Some("E0200"));
+}
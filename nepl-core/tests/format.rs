@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use nepl_core::format::{FormatOptions, format_source};
+use nepl_core::span::FileId;
+
+fn format(source: &str) -> String {
+    format_source(FileId(0), source, &FormatOptions::default())
+        .unwrap_or_else(|diags| panic!("{diags:?}"))
+}
+
+/// Every `NAME.in.nepl` under `tests/format` formats to `NAME.out.nepl`,
+/// which is already formatted.
+#[test]
+fn corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/format");
+    let mut cases = 0;
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let Some(name) = path.to_str().unwrap().strip_suffix(".in.nepl") else {
+            continue;
+        };
+        let input = std::fs::read_to_string(&path).unwrap();
+        let expected = std::fs::read_to_string(format!("{name}.out.nepl")).unwrap();
+        let formatted = format(&input);
+        assert_eq!(formatted, expected, "{}", path.display());
+        assert_eq!(format(&formatted), formatted, "not idempotent: {name}");
+        cases += 1;
+    }
+    assert!(cases >= 5);
+}
+
+#[test]
+fn stdlib_is_formatted() {
+    let root = concat!(env!("CARGO_MANIFEST_DIR"), "/../stdlib");
+    for name in ["option.nepl", "vec.nepl"] {
+        let source = std::fs::read_to_string(format!("{root}/{name}")).unwrap();
+        assert_eq!(format(&source), source, "{name}");
+    }
+}
+
+#[test]
+fn options_and_edge_cases() {
+    let options = FormatOptions {
+        indent_width: 2,
+        max_width: 20,
+    };
+    let formatted = format_source(FileId(0), "{ let x = add 1 2; x }", &options).unwrap();
+    assert_eq!(formatted, "{\n  let x = add 1 2;\n  x\n}\n");
+
+    assert_eq!(format(""), "");
+    assert_eq!(format("  // only a comment  \n\n"), "// only a comment\n");
+    assert_eq!(format("{}"), "{}\n");
+
+    // Code with syntax errors is left alone.
+    let errors =
+        format_source(FileId(0), "{ let x = ; x }", &FormatOptions::default()).unwrap_err();
+    assert_eq!(errors[0].code, Some("E0100"));
+}
@@ -0,0 +1,128 @@
+//! Runs the checks of the fuzz targets (`fuzz/src/lib.rs`) on stable
+//! Rust: over the seed corpus, and over seeded random mutations of it.
+//! A failure prints the input, which can be added to the corpus.
+
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+#[path = "../fuzz/src/lib.rs"]
+mod checks;
+
+/// Mutated inputs checked per run.
+const MUTANTS: usize = 3000;
+
+/// Fragments inserted by mutations: delimiters, keywords, literals and
+/// multi-byte characters, where recovery and byte indexing are likely
+/// to go wrong.
+#[rustfmt::skip]
+const FRAGMENTS: &[&str] = &[
+    "{", "}", "(", ")", "<", ">", "|", ":", ";", ",", "=>", "->", "*>", "::", "\"", "'", "\\",
+    "//", "/*", "*/", "\n", "    ", "let ", "mut ", "fn ", "if ", "then ", "else ", "match ",
+    "case ", "while ", "loop ", "break ", "set ", "test ", "enum ", "struct ", "0x", "1e",
+    "9999999999999999999", "1.5f32", "_", "é", "合計", "🦀", "\u{0}",
+];
+
+fn corpus() -> Vec<(PathBuf, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/compile");
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    files.sort();
+    files
+        .into_iter()
+        .map(|p| {
+            let source = fs::read_to_string(&p).unwrap();
+            (p, source)
+        })
+        .collect()
+}
+
+fn check_all(source: &str) {
+    checks::check_lex(source);
+    checks::check_parse(source);
+    checks::check_compile(source);
+}
+
+/// xorshift64*, so that runs are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 33) as usize % n.max(1)
+    }
+
+    /// A character boundary of `s`.
+    fn boundary(&mut self, s: &str) -> usize {
+        let mut i = self.below(s.len() + 1);
+        while !s.is_char_boundary(i) {
+            i -= 1;
+        }
+        i
+    }
+}
+
+fn mutate(rng: &mut Rng, source: &str) -> String {
+    let mut s = source.to_string();
+    for _ in 0..1 + rng.below(3) {
+        let (a, b) = (rng.boundary(&s), rng.boundary(&s));
+        let (a, b) = (a.min(b), a.max(b));
+        match rng.below(4) {
+            0 => s.replace_range(a..b, ""),
+            1 => s.insert_str(a, FRAGMENTS[rng.below(FRAGMENTS.len())]),
+            2 => {
+                let slice = s[a..b].to_string();
+                let at = rng.boundary(&s);
+                s.insert_str(at, &slice);
+            }
+            _ => s.truncate(a),
+        }
+    }
+    s
+}
+
+#[test]
+fn seed_corpus_passes_the_fuzz_checks() {
+    let corpus = corpus();
+    assert!(corpus.len() >= 10);
+    for (path, source) in &corpus {
+        let result = panic::catch_unwind(|| check_all(source));
+        assert!(result.is_ok(), "{}", path.display());
+    }
+}
+
+#[test]
+fn mutated_seeds_pass_the_fuzz_checks() {
+    let corpus = corpus();
+    let mut rng = Rng(0x6e65_706c);
+    for _ in 0..MUTANTS {
+        let (_, seed) = &corpus[rng.below(corpus.len())];
+        let source = mutate(&mut rng, seed);
+        let result = panic::catch_unwind(|| check_all(&source));
+        assert!(result.is_ok(), "input:\n{source}");
+    }
+}
+
+#[test]
+fn corpus_covers_examples_and_stdlib() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let corpus = corpus();
+    for dir in ["examples", "stdlib", "stdlib/platform"] {
+        for entry in fs::read_dir(root.join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "nepl") {
+                continue;
+            }
+            let source = fs::read_to_string(&path).unwrap();
+            assert!(
+                corpus.iter().any(|(_, s)| *s == source),
+                "{} is not in the seed corpus; run scripts/fuzz_seed_corpus.sh",
+                path.display()
+            );
+        }
+    }
+}
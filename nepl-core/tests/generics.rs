@@ -0,0 +1,138 @@
+mod common;
+
+use common::{check, messages, run, stdlib_defs};
+use nepl_core::hir::HirExprKind;
+use nepl_core::span::FileId;
+use nepl_core::types::Type;
+use nepl_core::{CoreError, compile_wasm};
+
+#[test]
+fn type_arguments_are_inferred_at_call_sites() {
+    let result = check("{ fn id = <T>|T x|*>T x; let _a = id 1; let b = id true; b }");
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    assert_eq!(result.expr.unwrap().ty, Type::Bool);
+    assert_eq!(result.functions[0].type_params, vec!["T".to_string()]);
+}
+
+#[test]
+fn monomorphic_overloads_win() {
+    let result = check("{ fn show = <T>|T _x|*>i32 0; fn show = |i32 x|*>i32 x; show 5 }");
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    let hir = result.expr.unwrap();
+    let HirExprKind::Block { expressions } = &hir.kind else {
+        panic!("expected block, got {:?}", hir.kind);
+    };
+    let HirExprKind::Call { type_args, .. } = &expressions.last().unwrap().kind else {
+        panic!("expected call");
+    };
+    assert!(type_args.is_empty());
+}
+
+#[test]
+fn uninferable_type_parameter_is_reported() {
+    let result = check("{ fn none = <T>||*>i32 0; none }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["cannot infer type parameter `T` of `none`; add a type annotation"]
+    );
+}
+
+#[test]
+fn generic_functions_are_monomorphized() {
+    let source = "{
+        fn id = <T>|T x|*>T x;
+        let b = id true;
+        if b then { add (id 40) (i32 id 2) } else { 0 }
+    }";
+    assert_eq!(run(source), 42);
+}
+
+#[test]
+fn polymorphic_recursion_is_reported() {
+    // Each call of `f` needs an instance for a larger type than its own.
+    let source = "{
+        struct W<T> { v: T };
+        fn f = <T>|T x, i32 n|->i32 if le n 0 then 0 else f (W x) (sub n 1);
+        f 1 3
+    }";
+    let Err(CoreError::Diagnostics(diags)) = compile_wasm(FileId(0), source) else {
+        panic!("expected an error");
+    };
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!(diags[0].code, Some("E0800"));
+    assert_eq!(
+        diags[0].message,
+        "the wasm backend cannot instantiate `f`: it is called with ever larger type arguments \
+         (polymorphic recursion)"
+    );
+
+    // Instances of a function may still lead to other instances of it.
+    let source = "{
+        fn f = <T>|T x, i32 n|->i32 if le n 0 then 0 else add 1 f true (sub n 1);
+        f 1 3
+    }";
+    assert_eq!(run(source), 3);
+}
+
+#[test]
+fn generic_enums_and_matching() {
+    let source = "{
+        enum Option<T> { Some(T); None };
+        fn unwrap_or = <T>|Option<T> o, T default|*>T match o {
+            case Some(x) => x;
+            case None => default
+        };
+        let a: Option<i32> = None;
+        add (unwrap_or (Some 40) 0) (unwrap_or a 2)
+    }";
+    assert_eq!(run(source), 42);
+}
+
+#[test]
+fn stdlib_generic_modules_typecheck() {
+    for path in ["option.nepl", "vec.nepl"] {
+        let result = check(&format!("{{ {} }}", stdlib_defs(path)));
+        assert!(result.diagnostics.is_empty(), "{path}: {:?}", result.diagnostics);
+    }
+}
+
+#[test]
+fn stdlib_vec_grows_and_reads_back() {
+    let source = format!(
+        "{{ {}
+        let mut v: Vec<i32> = new 1;
+        let mut i = 0;
+        while lt i 10 {{ set v push v mul i i; set i add i 1 }};
+        add (len v) (get v 6)
+    }}",
+        stdlib_defs("vec.nepl")
+    );
+    assert_eq!(run(&source), 46);
+}
+
+#[test]
+fn stdlib_higher_order_functions_take_closures() {
+    let source = format!(
+        "{{ {}
+        let base = 100;
+        let mut v: Vec<i32> = new 0;
+        set v push v 1;
+        set v push v 2;
+        set v push v 3;
+        let doubled = map v (|i32 x|->Bool gt x 1);
+        let count = fold doubled 0 (|i32 n, Bool b|*>i32 if b then add n 1 else n);
+        fold v (add base count) (|i32 acc, i32 x|*>i32 add acc x)
+    }}",
+        stdlib_defs("vec.nepl")
+    );
+    assert_eq!(run(&source), 108);
+
+    let source = format!(
+        "{{ {}
+        let k = 2;
+        match map (Some 20) (|i32 x|->i32 mul x k) {{ case Some(y) => add y 2; case None => 0 }}
+    }}",
+        stdlib_defs("option.nepl")
+    );
+    assert_eq!(run(&source), 42);
+}
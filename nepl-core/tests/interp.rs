@@ -0,0 +1,183 @@
+mod common;
+
+use common::{on_interp_stack, stdlib_defs};
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::hir::HirModule;
+use nepl_core::interp::{CaptureHost, Interpreter, Value};
+use nepl_core::parser::parse_file;
+use nepl_core::span::FileId;
+use nepl_core::typecheck::typecheck_expr;
+use nepl_core::{CoreError, interpret};
+
+fn eval(source: &str) -> Value {
+    on_interp_stack(|| interpret(FileId(0), source, CaptureHost::default(), None))
+        .unwrap_or_else(|e| panic!("{e:?}"))
+}
+
+/// The runtime error of `source` with the text its span covers.
+fn failure(source: &str, fuel: Option<u64>) -> (Diagnostic, String) {
+    let Err(CoreError::Diagnostics(diags)) =
+        on_interp_stack(|| interpret(FileId(0), source, CaptureHost::default(), fuel))
+    else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    let span = diags[0].primary.span;
+    let text = source[span.start as usize..span.end as usize].to_string();
+    (diags[0].clone(), text)
+}
+
+#[test]
+fn arithmetic_follows_wasm() {
+    assert_eq!(eval("add 1 mul 2 3"), Value::I32(7));
+    assert_eq!(eval("add 2147483647 1"), Value::I32(i32::MIN));
+    assert_eq!(eval("div neg 7 2"), Value::I32(-3));
+    assert_eq!(eval("mod neg 7 2"), Value::I32(-1));
+    assert_eq!(eval("bit_shl 1 33"), Value::I32(2));
+    assert_eq!(eval("mul 3i64 4i64"), Value::I64(12));
+    assert_eq!(eval("div 1.0 4.0"), Value::F64(0.25));
+    assert_eq!(eval("neg 1.5f32"), Value::F32(-1.5));
+    assert_eq!(eval("and lt 1 2 not eq 1 2"), Value::Bool(true));
+    assert_eq!(eval("{}"), Value::Unit);
+}
+
+#[test]
+fn control_flow() {
+    let source = "{
+        let mut i = 0;
+        let mut sum = 0;
+        while lt i 10 {
+            set i add i 1;
+            if eq mod i 2 0 then { continue } else {};
+            set sum add sum i
+        };
+        let found = loop {
+            set i sub i 1;
+            if lt i 4 then { break i } else {}
+        };
+        fn first_even = |i32 a, i32 b|->i32 {
+            if eq mod a 2 0 then { return a } else {};
+            b
+        };
+        add (mul sum 100) (add (mul found 10) (first_even 3 8))
+    }";
+    assert_eq!(eval(source), Value::I32(2538));
+}
+
+#[test]
+fn recursion_as_deep_as_wasm() {
+    let source = "{ fn sum = |i32 n|->i32 if eq n 0 then 0 else add n sum sub n 1; sum 998 }";
+    assert_eq!(eval(source), Value::I32(498501));
+}
+
+#[test]
+fn records_closures_and_generics() {
+    let source = "{
+        enum Option<T> { Some(T); None };
+        struct Point { x: i32; y: i32 };
+        fn unwrap_or = <T>|Option<T> o, T default|*>T match o {
+            case Some(x) => x;
+            case None => default
+        };
+        fn norm = |Point p|*>i32 match p { case Point { x: a, y: b } => add (mul a a) (mul b b) };
+        let k = 10;
+        let add_k = |i32 x|->i32 add x k;
+        let k = 100;
+        let none: Option<i32> = None;
+        add (add_k (norm Point 1 2)) (add (unwrap_or (Some k) 0) (unwrap_or none 3))
+    }";
+    assert_eq!(eval(source), Value::I32(118));
+}
+
+#[test]
+fn mut_parameters_write_back() {
+    let source = "{
+        fn swap = <T>|T mut a, T mut b|->Unit { let t = a; set a b; set b t };
+        let mut x = 7;
+        let mut y = 3;
+        swap x y;
+        sub x y
+    }";
+    assert_eq!(eval(source), Value::I32(-4));
+}
+
+#[test]
+fn stdlib_vec_runs() {
+    let source = format!(
+        "{{ {}
+        let mut v: Vec<i32> = new 1;
+        let mut i = 0;
+        while lt i 10 {{ set v push v mul i i; set i add i 1 }};
+        let doubled = map v (|i32 x|->Bool gt x 1);
+        let count = fold doubled 0 (|i32 n, Bool b|*>i32 if b then add n 1 else n);
+        add (add (len v) (get v 6)) count
+    }}",
+        stdlib_defs("vec.nepl")
+    );
+    assert_eq!(eval(&source), Value::I32(54));
+
+    let source = format!(
+        "{{ {}
+        let v: Vec<i32> = push new 1 7;
+        let v = pop v;
+        pop v
+    }}",
+        stdlib_defs("vec.nepl")
+    );
+    let (diag, text) = failure(&source, None);
+    assert_eq!(diag.message, "assertion failed: pop of an empty vec");
+    assert_eq!(text, "assert gt n 0 \"pop of an empty vec\"");
+}
+
+#[test]
+fn host_builtins() {
+    let source = "{ print_i32 1; print_i32 random_i32; 0 }";
+    let parsed = parse_file(FileId(0), source);
+    let typed = typecheck_expr(parsed.expr.as_ref().unwrap());
+    let module = HirModule {
+        functions: typed.functions,
+    };
+    let host = CaptureHost {
+        printed: Vec::new(),
+        random: 9,
+    };
+    let mut interpreter = Interpreter::new(&module, host);
+    assert_eq!(
+        interpreter.eval(typed.expr.as_ref().unwrap()),
+        Ok(Value::I32(0))
+    );
+    assert_eq!(interpreter.into_host().printed, vec![1, 9]);
+}
+
+#[test]
+fn runtime_errors_point_at_the_failing_expression() {
+    let (diag, text) = failure("{ let zero = 0; add 1 div 5 zero }", None);
+    assert_eq!(diag.code, Some("E1000"));
+    assert_eq!(diag.message, "integer division by zero");
+    assert_eq!(text, "div 5 zero");
+
+    let (diag, text) = failure("{ let m = neg 2147483647; div sub m 1 neg 1 }", None);
+    assert_eq!(diag.message, "integer overflow in division");
+    assert_eq!(text, "div sub m 1 neg 1");
+
+    let (diag, text) = failure("{ let a = 0; i32 load sub a 8 }", None);
+    assert_eq!(
+        diag.message,
+        "memory access out of bounds at address 4294967288"
+    );
+    assert_eq!(text, "i32 load sub a 8");
+
+    let (diag, _) = failure("{ fn f = |i32 n|->i32 f add n 1; f 0 }", None);
+    assert_eq!(diag.message, "call stack exhausted after 999 nested calls");
+}
+
+#[test]
+fn fuel_bounds_evaluation() {
+    let (diag, text) = failure("{ let mut i = 0; loop { set i add i 1 } }", Some(1000));
+    assert_eq!(diag.code, Some("E1001"));
+    assert_eq!(diag.message, "evaluation ran out of fuel after 1000 steps");
+    assert!(!text.is_empty());
+
+    let source = "{ let mut i = 0; while lt i 10 { set i add i 1 }; i }";
+    assert!(interpret(FileId(0), source, CaptureHost::default(), Some(1000)).is_ok());
+}
@@ -0,0 +1,236 @@
+use nepl_core::ast::{ExprKind, LiteralKind};
+use nepl_core::fix::apply_suggestions;
+use nepl_core::lexer::{lex, number_parts, unescape, TokenKind, TriviaKind};
+use nepl_core::parser::parse_file;
+use nepl_core::span::FileId;
+
+fn kinds(source: &str) -> Vec<TokenKind> {
+    lex(FileId(0), source).tokens.iter().map(|t| t.kind).collect()
+}
+
+#[test]
+fn comments_are_not_tokens() {
+    let source = "// leading\nlet x = 1 // trailing\n/* block */ x";
+    let result = lex(FileId(0), source);
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    assert_eq!(
+        kinds(source),
+        vec![
+            TokenKind::Let,
+            TokenKind::Ident,
+            TokenKind::Equal,
+            TokenKind::IntLiteral,
+            TokenKind::Ident,
+            TokenKind::Eof,
+        ]
+    );
+}
+
+#[test]
+fn trivia_is_attached_and_lossless() {
+    let source = "/// doc\n//// not doc\nfn f = 1 /* a /* nested */ b */\n// tail\n";
+    let result = lex(FileId(0), source);
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+
+    let fn_tok = &result.tokens[0];
+    let comment_kinds: Vec<TriviaKind> = fn_tok
+        .leading_trivia
+        .iter()
+        .filter(|t| t.is_comment())
+        .map(|t| t.kind)
+        .collect();
+    assert_eq!(comment_kinds, vec![TriviaKind::DocComment, TriviaKind::LineComment]);
+    assert_eq!(fn_tok.doc_comments().next().unwrap().text(source), "/// doc");
+
+    let one = &result.tokens[3];
+    assert_eq!(one.kind, TokenKind::IntLiteral);
+    let trailing = one.trailing_trivia.last().unwrap();
+    assert_eq!(trailing.kind, TriviaKind::BlockComment);
+    assert_eq!(trailing.text(source), "/* a /* nested */ b */");
+
+    let mut rebuilt = String::new();
+    for tok in &result.tokens {
+        for t in &tok.leading_trivia {
+            rebuilt.push_str(t.text(source));
+        }
+        rebuilt.push_str(&source[tok.span.start as usize..tok.span.end as usize]);
+        for t in &tok.trailing_trivia {
+            rebuilt.push_str(t.text(source));
+        }
+    }
+    assert_eq!(rebuilt, source);
+}
+
+#[test]
+fn unterminated_block_comment_is_reported() {
+    let result = lex(FileId(0), "1 /* open /* inner */");
+    assert_eq!(result.diagnostics.len(), 1);
+    assert_eq!(result.diagnostics[0].code, Some("E0003"));
+}
+
+#[test]
+fn stdlib_headers_lex_without_unexpected_characters() {
+    let root = concat!(env!("CARGO_MANIFEST_DIR"), "/../stdlib");
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry.unwrap();
+        if entry.path().extension().is_some_and(|e| e == "nepl") {
+            let source = std::fs::read_to_string(entry.path()).unwrap();
+            let result = lex(FileId(0), &source);
+            assert!(
+                result.diagnostics.iter().all(|d| d.code != Some("E0001")),
+                "{}: {:?}",
+                entry.path().display(),
+                result.diagnostics
+            );
+        }
+    }
+}
+
+#[test]
+fn identifiers_follow_uax31() {
+    let source = "{ let 合計 = 1; let _x2 = 合計; café }";
+    let result = lex(FileId(0), source);
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    let idents: Vec<&str> = result
+        .tokens
+        .iter()
+        .filter(|t| t.kind == TokenKind::Ident)
+        .map(|t| &source[t.span.start as usize..t.span.end as usize])
+        .collect();
+    assert_eq!(idents, vec!["合計", "_x2", "合計", "café"]);
+
+    // Not identifier characters: reported once, covering the whole character.
+    let result = lex(FileId(0), "a → b");
+    assert_eq!(result.diagnostics.len(), 1);
+    let span = result.diagnostics[0].primary.span;
+    assert_eq!((span.start, span.end), (2, 5));
+    assert_eq!(kinds("１"), vec![TokenKind::Eof]);
+}
+
+#[test]
+fn string_escapes_are_decoded() {
+    let source = r#""a\n\t\\\"\'\0\u{3042}\u{1F6_00}""#;
+    let (value, diags) = unescape(FileId(0), &source[1..source.len() - 1], 1);
+    assert!(diags.is_empty(), "{diags:?}");
+    assert_eq!(value, "a\n\t\\\"'\0あ😀");
+
+    let parsed = parse_file(FileId(0), r#"{ let s = "x\ty"; s }"#);
+    assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+    let mut strings = Vec::new();
+    parsed.expr.unwrap().walk(&mut |e| {
+        if let ExprKind::Literal(LiteralKind::String(s)) = &e.kind {
+            strings.push(s.clone());
+        }
+    });
+    assert_eq!(strings, vec!["x\ty"]);
+}
+
+#[test]
+fn malformed_escapes_are_reported_precisely() {
+    let cases = [
+        (r#""a\qb""#, "unknown character escape `\\q`", r"\q"),
+        (r#""\u0041""#, "expected `{` after `\\u`", r"\u"),
+        (
+            r#""\u{41""#,
+            "unterminated unicode escape `\\u{41`; expected `}`",
+            r"\u{41",
+        ),
+        (
+            r#""\u{4G}""#,
+            "invalid character in unicode escape `\\u{4G`; only hex digits are allowed",
+            r"\u{4G",
+        ),
+        (r#""\u{}""#, "empty unicode escape `\\u{}`", r"\u{}"),
+        (
+            r#""\u{1234567}""#,
+            "unicode escape `\\u{1234567}` has more than 6 hex digits",
+            r"\u{1234567}",
+        ),
+        (
+            r#""\u{D800}""#,
+            "`\\u{D800}` is not a valid unicode scalar value",
+            r"\u{D800}",
+        ),
+    ];
+    for (source, message, text) in cases {
+        let result = lex(FileId(0), source);
+        assert_eq!(result.diagnostics.len(), 1, "{source}");
+        let diag = &result.diagnostics[0];
+        assert_eq!(diag.code, Some("E0004"));
+        assert_eq!(diag.message, message);
+        let span = diag.primary.span;
+        assert_eq!(&source[span.start as usize..span.end as usize], text);
+        // The string is still a token, decoded with a replacement character.
+        assert_eq!(result.tokens[0].kind, TokenKind::StringLiteral);
+    }
+
+    let source = r#""C:\bin""#;
+    let fixed = apply_suggestions(FileId(0), source, &lex(FileId(0), source).diagnostics);
+    assert_eq!(fixed.source, r#""C:\\bin""#);
+}
+
+#[test]
+fn numeric_literal_forms() {
+    let source = "0xff 0o17 0b1010 1_000 1.5 2e10 6.02e+23 10i64 1.5f32 3f64 0x1f32";
+    let result = lex(FileId(0), source);
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    let tokens: Vec<(TokenKind, &str)> = result.tokens[..result.tokens.len() - 1]
+        .iter()
+        .map(|t| (t.kind, &source[t.span.start as usize..t.span.end as usize]))
+        .collect();
+    use TokenKind::{FloatLiteral as F, IntLiteral as I};
+    assert_eq!(
+        tokens,
+        vec![
+            (I, "0xff"),
+            (I, "0o17"),
+            (I, "0b1010"),
+            (I, "1_000"),
+            (F, "1.5"),
+            (F, "2e10"),
+            (F, "6.02e+23"),
+            (I, "10i64"),
+            (F, "1.5f32"),
+            (F, "3f64"),
+            (I, "0x1f32"),
+        ]
+    );
+    assert_eq!(number_parts("0x1f_i64"), (16, "1f_", "i64"));
+    assert_eq!(number_parts("1.5e3f32"), (10, "1.5e3", "f32"));
+}
+
+#[test]
+fn malformed_numeric_literals_are_reported() {
+    let cases = [
+        ("0x", "no digits after `0x`", "0x"),
+        ("0b_", "no digits after `0b`", "0b_"),
+        ("0b102", "invalid digit `2` in a base 2 literal", "2"),
+        ("0o8", "invalid digit `8` in a base 8 literal", "8"),
+        (
+            "10u8",
+            "invalid suffix `u8` for a numeric literal; expected `i32`, `i64`, `f32` or `f64`",
+            "u8",
+        ),
+        (
+            "1.5i32",
+            "a float literal cannot have the integer suffix `i32`",
+            "i32",
+        ),
+        (
+            "0b1f32",
+            "a base 2 literal cannot have the float suffix `f32`",
+            "f32",
+        ),
+    ];
+    for (source, message, text) in cases {
+        let result = lex(FileId(0), source);
+        assert_eq!(result.diagnostics.len(), 1, "{source}");
+        let diag = &result.diagnostics[0];
+        assert_eq!(diag.code, Some("E0005"));
+        assert_eq!(diag.message, message);
+        let span = diag.primary.span;
+        assert_eq!(&source[span.start as usize..span.end as usize], text);
+        // The whole literal is still one token.
+        assert_eq!(result.tokens.len(), 2, "{source}");
+    }
+}
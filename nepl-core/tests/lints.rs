@@ -0,0 +1,196 @@
+use nepl_core::diagnostic::{Diagnostic, Severity};
+use nepl_core::lint::{self, Level, LintConfig};
+use nepl_core::span::FileId;
+use nepl_core::{CoreError, check, compile_wasm_with};
+
+fn lints(source: &str, config: &LintConfig) -> Vec<(Severity, &'static str, String)> {
+    let diags: Vec<Diagnostic> = check(FileId(0), source, config);
+    diags
+        .into_iter()
+        .map(|d| (d.severity, d.code.unwrap(), d.message))
+        .collect()
+}
+
+fn warnings(source: &str) -> Vec<(Severity, &'static str, String)> {
+    lints(source, &LintConfig::default())
+}
+
+fn warning(code: &'static str, message: &str) -> (Severity, &'static str, String) {
+    (Severity::Warning, code, message.to_string())
+}
+
+#[test]
+fn unused_variables_and_parameters_are_reported() {
+    assert_eq!(
+        warnings("{ let x = 1; let y = 2; y }"),
+        vec![warning("W0001", "unused variable `x`")]
+    );
+    assert_eq!(
+        warnings("|i32 a, i32 b|->i32 b"),
+        vec![warning("W0001", "unused variable `a`")]
+    );
+    assert!(warnings("{ let _x = 1; |i32 _a|->i32 0 }").is_empty());
+
+    // Writing to a variable does not count as reading it, but renaming
+    // only its declaration would break the `set`.
+    let source = "{ let mut n = 1; set n 2; 0 }";
+    assert_eq!(
+        warnings(source),
+        vec![warning("W0001", "unused variable `n`")]
+    );
+    let diags = check(FileId(0), source, &LintConfig::default());
+    assert!(diags[0].suggestions.is_empty());
+
+    // The final value of a `mut` parameter goes back to the caller.
+    assert!(warnings("{ fn f = |i32 mut x|->Unit set x 9; 0 }").is_empty());
+}
+
+#[test]
+fn unused_uses_are_reported() {
+    // `use` is not type checked yet; only look at the lint.
+    let unused_uses = |source| {
+        let mut found = warnings(source);
+        found.retain(|(_, code, _)| *code == "W0002");
+        found
+    };
+    assert_eq!(
+        unused_uses("{ use core::math::sqrt; use core::math::abs; abs 1 }"),
+        vec![warning("W0002", "unused `use` of `sqrt`")]
+    );
+    assert_eq!(
+        unused_uses("{ use core::math::abs as a; abs 1 }"),
+        vec![warning("W0002", "unused `use` of `a`")]
+    );
+    assert!(unused_uses("{ use core::math::*; 1 }").is_empty());
+    // Names are resolved: a shadowed `use` is unused, and one in a
+    // nested block is only in scope there.
+    assert_eq!(
+        unused_uses("{ use core::math::abs; let abs = 1; abs }"),
+        vec![warning("W0002", "unused `use` of `abs`")]
+    );
+    assert_eq!(
+        unused_uses("{ { use core::math::abs; 0 }; abs 1 }"),
+        vec![warning("W0002", "unused `use` of `abs`")]
+    );
+    assert!(unused_uses("{ use core::math::abs; { abs 1 } }").is_empty());
+
+    // Compilations report them too.
+    let source = "{ use core::math::sqrt; 1 }";
+    let Err(CoreError::Diagnostics(diags)) =
+        compile_wasm_with(FileId(0), source, &LintConfig::default())
+    else {
+        panic!("expected an error");
+    };
+    assert!(diags.iter().any(|d| d.code == Some("W0002")), "{diags:?}");
+}
+
+#[test]
+fn shadowing_is_opt_in() {
+    let source = "{ let x = 1; let x = add x 1; x }";
+    assert!(warnings(source).is_empty());
+
+    let mut config = LintConfig::new();
+    config.set("shadowing", Level::Warn).unwrap();
+    assert_eq!(
+        lints(source, &config),
+        vec![warning("W0003", "`x` shadows an earlier binding")]
+    );
+}
+
+#[test]
+fn code_after_never_is_unreachable() {
+    let source = "|i32 x|->i32 { return x; add x 1; x }";
+    let diags = check(FileId(0), source, &LintConfig::default());
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!(diags[0].code, Some("W0004"));
+    assert_eq!(
+        &source[diags[0].primary.span.start as usize..][..12],
+        "add x 1; x }"
+    );
+    assert_eq!(diags[0].secondary.len(), 1);
+}
+
+#[test]
+fn impure_literals_that_could_be_pure() {
+    let mut config = LintConfig::new();
+    config.set("could-be-pure", Level::Warn).unwrap();
+    let source = "{ fn inc = |i32 x|->i32 add x 1; inc 1 }";
+    let diags = check(FileId(0), source, &config);
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!(diags[0].code, Some("W0005"));
+    let edit = &diags[0].suggestions[0].edits[0];
+    assert_eq!(
+        &source[edit.span.start as usize..edit.span.end as usize],
+        "->"
+    );
+    assert_eq!(edit.replacement, "*>");
+
+    // `mut` parameters need `->`.
+    let source = "{ fn inc = |i32 mut x|->Unit set x add x 1; let mut n = 1; inc n; n }";
+    assert!(lints(source, &config).is_empty());
+}
+
+#[test]
+fn pragmas_change_levels_from_their_position() {
+    let source =
+        "{\n    let a = 1;\n    // nepl: allow(unused_variables)\n    let b = 2;\n    0\n}";
+    assert_eq!(
+        warnings(source),
+        vec![warning("W0001", "unused variable `a`")]
+    );
+
+    let source = "// nepl: deny(unused-variables)\n{ let a = 1; 0 }";
+    assert_eq!(
+        warnings(source),
+        vec![(Severity::Error, "W0001", "unused variable `a`".to_string())]
+    );
+
+    // A pragma overrides the configuration.
+    let mut config = LintConfig::new();
+    config.set("unused_variables", Level::Deny).unwrap();
+    let source = "// nepl: warn(unused_variables)\n{ let a = 1; 0 }";
+    assert_eq!(
+        lints(source, &config),
+        vec![warning("W0001", "unused variable `a`")]
+    );
+}
+
+#[test]
+fn unknown_lints_in_pragmas_are_reported() {
+    assert_eq!(
+        warnings("// nepl: allow(unused_varaibles)\n0"),
+        vec![warning("W0006", "unknown lint `unused_varaibles`")]
+    );
+    // Ordinary comments are not pragmas.
+    assert!(warnings("// allow(anything)\n0").is_empty());
+
+    let mut config = LintConfig::new();
+    assert_eq!(
+        config
+            .set("no_such_lint", Level::Allow)
+            .unwrap_err()
+            .to_string(),
+        "unknown lint `no_such_lint`"
+    );
+    assert!(lint::lookup("unreachable-code").is_some());
+}
+
+#[test]
+fn denied_lints_and_deny_warnings_fail_compilation() {
+    let source = "{ let a = 1; 0 }";
+    let artifact = compile_wasm_with(FileId(0), source, &LintConfig::default()).unwrap();
+    assert_eq!(artifact.warnings.len(), 1);
+
+    let mut config = LintConfig::new();
+    config.deny_warnings = true;
+    let Err(CoreError::Diagnostics(diags)) = compile_wasm_with(FileId(0), source, &config) else {
+        panic!("expected an error");
+    };
+    assert_eq!(diags[0].severity, Severity::Error);
+    assert_eq!(diags[0].code, Some("W0001"));
+
+    // Allowed lints stay silent even with `deny_warnings`.
+    config.set("unused_variables", Level::Allow).unwrap();
+    let artifact = compile_wasm_with(FileId(0), source, &config).unwrap();
+    assert!(artifact.warnings.is_empty());
+}
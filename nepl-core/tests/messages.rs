@@ -0,0 +1,120 @@
+use std::collections::BTreeSet;
+
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::error_codes;
+use nepl_core::messages::{self, Locale};
+use nepl_core::parser::parse_file;
+use nepl_core::render::Renderer;
+use nepl_core::span::{FileId, Span};
+use nepl_core::typecheck::typecheck_expr;
+
+fn keys(locale: Locale) -> BTreeSet<&'static str> {
+    messages::entries(locale.catalog()).map(|(k, _)| k).collect()
+}
+
+fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let parsed = parse_file(FileId(0), source);
+    let mut diags = parsed.diagnostics;
+    if let Some(expr) = &parsed.expr {
+        diags.extend(typecheck_expr(expr).diagnostics);
+    }
+    diags
+}
+
+#[test]
+fn catalogs_define_the_same_messages() {
+    assert_eq!(keys(Locale::En), keys(Locale::Ja));
+    for locale in Locale::ALL {
+        let entries: Vec<_> = messages::entries(locale.catalog()).collect();
+        assert_eq!(entries.len(), keys(locale).len(), "duplicate key in {}", locale.tag());
+    }
+
+    for (key, en) in messages::entries(Locale::En.catalog()) {
+        let ja = messages::template(Locale::Ja, key).unwrap();
+        let en_args: BTreeSet<_> = messages::placeholders(en).collect();
+        let ja_args: BTreeSet<_> = messages::placeholders(ja).collect();
+        assert_eq!(en_args, ja_args, "placeholders of `{key}`");
+    }
+}
+
+#[test]
+fn message_keys_belong_to_known_codes() {
+    for key in keys(Locale::En) {
+        let prefix = key.split('.').next().unwrap();
+        if matches!(prefix, "suggest" | "render" | "repl") {
+            continue;
+        }
+        let entry = error_codes::lookup(prefix).unwrap_or_else(|| panic!("`{key}` has no code"));
+        assert_eq!(entry.code, prefix, "`{key}`");
+    }
+}
+
+/// Every `"E…"` / `"W…"` / `"suggest.…"` key literal in the compiler
+/// sources has an English template.
+#[test]
+fn compiler_uses_only_defined_keys() {
+    let defined = keys(Locale::En);
+    let src = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+    for file in std::fs::read_dir(src).unwrap() {
+        let path = file.unwrap().path();
+        if path.extension().is_none_or(|e| e != "rs") || path.ends_with("error_codes.rs") {
+            continue;
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        for literal in text.split('"').skip(1).step_by(2) {
+            let is_key = literal.starts_with("suggest.")
+                || (literal.len() >= 5
+                    && (literal.starts_with('E') || literal.starts_with('W'))
+                    && literal[1..5].bytes().all(|b| b.is_ascii_digit()));
+            if is_key {
+                assert!(defined.contains(literal), "{}: `{literal}`", path.display());
+            }
+        }
+    }
+}
+
+#[test]
+fn templates_interpolate_named_arguments() {
+    let args = [("name", "x".to_string()), ("count", "2".to_string())];
+    assert_eq!(
+        messages::interpolate("`{name}` takes {count} {{braces}} {missing}", &args),
+        "`x` takes 2 {braces} {missing}"
+    );
+    assert_eq!(
+        messages::format(Locale::Ja, "E0304", &[("name", "add".to_string())]),
+        "`add` の引数が足りません"
+    );
+    assert_eq!(messages::format(Locale::Ja, "no.such.key", &[]), "no.such.key");
+}
+
+#[test]
+fn locales_parse_from_tags() {
+    assert_eq!(Locale::parse("ja"), Some(Locale::Ja));
+    assert_eq!(Locale::parse("ja_JP.UTF-8"), Some(Locale::Ja));
+    assert_eq!(Locale::parse("EN-us"), Some(Locale::En));
+    assert_eq!(Locale::parse("C"), Some(Locale::En));
+    assert_eq!(Locale::parse("fr_FR"), None);
+}
+
+#[test]
+fn diagnostics_are_shown_in_the_selected_language() {
+    let diags = diagnostics("{ let b: Bool = add 1 2; b }");
+    assert_eq!(diags[0].code, Some("E0300"));
+    assert_eq!(diags[0].message, "mismatched types: expected `Bool`, found `i32`");
+    assert_eq!(
+        diags[0].message_in(Locale::Ja),
+        "型が一致しません: `Bool` を期待しましたが `i32` が見つかりました"
+    );
+
+    let source = "{ let n = 1; set n 2; n }";
+    let mut renderer = Renderer::new().with_locale(Locale::Ja);
+    renderer.add_file(FileId(0), "main.nepl", source);
+    let out = renderer.render_all(&diagnostics(source));
+    assert!(out.starts_with("error[E0404]: 不変の変数 `n` に代入することはできません\n"), "{out}");
+    assert!(out.contains("help: この束縛を可変にしてください\n"), "{out}");
+    assert!(out.ends_with("error: 1 件のエラーのため中断しました\n"), "{out}");
+
+    // Diagnostics built from plain strings are shown as written.
+    let plain = Diagnostic::error("custom", Span::empty(FileId(0), 0));
+    assert_eq!(plain.message_in(Locale::Ja), "custom");
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{check, messages, run};
+
+#[test]
+fn mut_parameters_write_back() {
+    let source = "{
+        fn bump = |i32 mut x, i32 by|->Unit set x add x by;
+        let mut n = 40;
+        bump n 2;
+        n
+    }";
+    assert_eq!(run(source), 42);
+}
+
+#[test]
+fn mut_parameters_write_back_on_return() {
+    let source = "{
+        fn swap_if = |i32 mut a, i32 mut b|->Bool {
+            if le a b then { return false } else {};
+            let t = a;
+            set a b;
+            set b t;
+            true
+        };
+        let mut x = 7;
+        let mut y = 3;
+        let swapped = swap_if x y;
+        let again = swap_if x y;
+        if and swapped not again then { sub y x } else { 0 }
+    }";
+    assert_eq!(run(source), 4);
+}
+
+#[test]
+fn generic_mut_parameters() {
+    let source = "{
+        fn replace = <T>|T mut slot, T value|->T { let old = slot; set slot value; old };
+        let mut v = 1;
+        let old = replace v 5;
+        add (mul old 10) v
+    }";
+    assert_eq!(run(source), 15);
+}
+
+#[test]
+fn mut_arguments_must_be_mutable_variables() {
+    let result = check("{ fn inc = |i32 mut x|->Unit set x add x 1; inc 1 }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["argument 1 of `inc` is a `mut` parameter and must be a mutable variable"]
+    );
+
+    let result = check("{ fn inc = |i32 mut x|->Unit set x add x 1; let y = 1; inc y }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["cannot pass immutable variable `y` to `mut` parameter 1 of `inc`"]
+    );
+}
+
+#[test]
+fn mut_arguments_cannot_alias() {
+    let result = check("{ fn f = |i32 mut _a, i32 mut _b|->Unit {}; let mut x = 1; f x x }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["`x` is passed to `f` more than once, but one of them is `mut`"]
+    );
+
+    let result = check("{ fn g = |i32 mut _a, i32 _b|->Unit {}; let mut x = 1; g x x }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["`x` is passed to `g` more than once, but one of them is `mut`"]
+    );
+}
+
+#[test]
+fn functions_with_mut_parameters_are_not_values() {
+    let result = check("{ fn inc = |i32 mut x|->Unit set x add x 1; let _f = inc; 0 }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["`inc` takes `mut` parameters and cannot be used as a value"]
+    );
+}
@@ -0,0 +1,123 @@
+use nepl_core::name_resolve::{NameResolveResult, SymbolKind, resolve_names};
+use nepl_core::parser::parse_file;
+use nepl_core::span::FileId;
+
+fn resolve(source: &str) -> NameResolveResult {
+    let parsed = parse_file(FileId(0), source);
+    assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+    resolve_names(&parsed.expr.unwrap())
+}
+
+/// The definition of the `n`th occurrence of `name` in `source`, as the
+/// offset of the defining name.
+fn definition(source: &str, name: &str, n: usize) -> Option<u32> {
+    let offset = source.match_indices(name).nth(n).unwrap().0 as u32;
+    resolve(source).definition_at(offset).map(|s| s.span.start)
+}
+
+#[test]
+fn lexical_scopes() {
+    let source = "{
+    let x = 1;
+    fn f = |i32 x|->i32 add x (g x);
+    fn g = |i32 y|->i32 y;
+    let x = f x;
+    x
+}";
+    let first_x = source.find("x").unwrap() as u32;
+    let param_x = source.find("i32 x").unwrap() as u32 + 4;
+    let last_x = source.rfind("let x").unwrap() as u32 + 4;
+
+    // Parameters shadow outer bindings.
+    assert_eq!(definition(source, "x", 2), Some(param_x));
+    // `fn` bindings are hoisted.
+    assert_eq!(
+        definition(source, "g", 0),
+        Some(source.find("fn g").unwrap() as u32 + 3)
+    );
+    // `let x = f x` sees the earlier `x`; the tail sees the new one.
+    assert_eq!(definition(source, "x", 5), Some(first_x));
+    assert_eq!(definition(source, "x", 6), Some(last_x));
+
+    let result = resolve(source);
+    let f = result
+        .symbol_at(source.find("fn f").unwrap() as u32 + 3)
+        .unwrap();
+    assert_eq!(result.symbol(f).kind, SymbolKind::Function);
+    assert_eq!(result.references_to(f).count(), 1);
+
+    // At the end of the block only the last `x` is visible, once.
+    let visible = result.visible_at(source.len() as u32 - 2);
+    let xs: Vec<u32> = visible
+        .iter()
+        .filter(|s| s.name == "x")
+        .map(|s| s.span.start)
+        .collect();
+    assert_eq!(xs, vec![last_x]);
+    assert!(visible.iter().any(|s| s.name == "g"));
+    assert!(!visible.iter().any(|s| s.name == "y"));
+}
+
+#[test]
+fn types_patterns_and_namespaces() {
+    let source = "{
+    enum Shape { Circle(i32); Empty };
+    struct Pair<T> { a: T; b: T };
+    namespace geo {
+        fn area = |Shape s|->i32 match s { case Circle(r) => r; case Empty => 0 };
+    };
+    use geo::area as size;
+    size Empty
+}";
+    let result = resolve(source);
+    let kind_at = |needle: &str, skip: u32| {
+        let offset = source.find(needle).unwrap() as u32 + skip;
+        result.definition_at(offset).map(|s| s.kind)
+    };
+    assert_eq!(kind_at("Shape s", 0), Some(SymbolKind::Enum));
+    assert_eq!(kind_at("T; b", 0), Some(SymbolKind::TypeParam));
+    assert_eq!(kind_at("Circle(r)", 0), Some(SymbolKind::EnumVariant));
+    // `r` is bound by the pattern; `Empty` names a variant.
+    assert_eq!(kind_at("=> r;", 3), Some(SymbolKind::Variable));
+    assert_eq!(kind_at("Empty =>", 0), Some(SymbolKind::EnumVariant));
+    assert_eq!(kind_at("size Empty", 0), Some(SymbolKind::Function));
+    assert_eq!(kind_at("size Empty", 5), Some(SymbolKind::EnumVariant));
+
+    let geo = result
+        .symbols
+        .iter()
+        .position(|s| s.kind == SymbolKind::Namespace)
+        .unwrap();
+    let members: Vec<&str> = result
+        .members(nepl_core::name_resolve::SymbolId(geo))
+        .map(|s| s.name.as_str())
+        .collect();
+    assert_eq!(members, vec!["area"]);
+
+    let pair = result.symbols.iter().find(|s| s.name == "Pair").unwrap();
+    assert_eq!(pair.full_span.start, source.find("struct").unwrap() as u32);
+    let fields = result
+        .symbols
+        .iter()
+        .filter(|s| s.kind == SymbolKind::StructField);
+    assert_eq!(fields.count(), 2);
+}
+
+#[test]
+fn uses_from_outside_the_file() {
+    let source = "{
+    fn abs = |i32 x|->i32 x;
+    use core::math::abs;
+    use core::math::sqrt as root;
+    abs 1
+}";
+    // The name shadows the local `abs` but has no definition here.
+    assert_eq!(definition(source, "abs", 2), None);
+    let result = resolve(source);
+    let used: Vec<(&str, bool)> = result
+        .uses
+        .iter()
+        .map(|u| (u.name.as_str(), u.used))
+        .collect();
+    assert_eq!(used, vec![("abs", true), ("root", false)]);
+}
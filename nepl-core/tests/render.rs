@@ -0,0 +1,173 @@
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::render::Renderer;
+use nepl_core::span::{FileId, LineIndex, Span};
+use nepl_core::{CoreError, compile_wasm};
+
+const SOURCE: &str = "let x = 1;\nlet y = add x true;\n\n\tset x 2\n";
+
+fn span(start: u32, end: u32) -> Span {
+    Span::new(FileId(0), start, end)
+}
+
+fn renderer() -> Renderer<'static> {
+    let mut r = Renderer::new();
+    r.add_file(FileId(0), "main.nepl", SOURCE);
+    r
+}
+
+#[test]
+fn line_index_maps_offsets() {
+    let index = LineIndex::new("ab\r\ncd\n");
+    assert_eq!(index.line_count(), 3);
+    assert_eq!(index.line_col(0), (0, 0));
+    assert_eq!(index.line_col(4), (1, 0));
+    assert_eq!(index.line_col(5), (1, 1));
+    assert_eq!(index.line_col(100), (2, 0));
+    assert_eq!(index.line_range(0), 0..2);
+}
+
+#[test]
+fn primary_and_secondary_labels_with_code() {
+    let mut d = Diagnostic::error("mismatched types", span(25, 29))
+        .with_code("E0001")
+        .with_secondary_label(span(4, 5), Some("`x` defined here".to_string()));
+    d.primary.message = Some("expected `i32`".to_string());
+
+    assert_eq!(
+        renderer().render(&d),
+        "\
+error[E0001]: mismatched types
+ --> main.nepl:2:15
+  |
+1 | let x = 1;
+  |     - `x` defined here
+2 | let y = add x true;
+  |               ^^^^ expected `i32`
+"
+    );
+}
+
+#[test]
+fn tabs_are_expanded_and_distant_lines_elided() {
+    let d = Diagnostic::warning("unused assignment", span(33, 36))
+        .with_secondary_label(span(0, 3), None);
+    assert_eq!(
+        renderer().render(&d),
+        "\
+warning: unused assignment
+ --> main.nepl:4:2
+  |
+1 | let x = 1;
+  | ---
+...
+4 |     set x 2
+  |     ^^^
+"
+    );
+}
+
+#[test]
+fn multi_line_spans_are_drawn_to_their_end() {
+    let source = "{\n    let b: Bool = add\n        1\n        2;\n    b\n}\n";
+    let Err(CoreError::Diagnostics(diags)) = compile_wasm(FileId(0), source) else {
+        panic!("expected diagnostics");
+    };
+    let mut r = Renderer::new();
+    r.add_file(FileId(0), "main.nepl", source);
+    assert_eq!(
+        r.render(&diags[0]),
+        "\
+error[E0300]: mismatched types: expected `Bool`, found `i32`
+ --> main.nepl:2:19
+  |
+2 |       let b: Bool = add
+  |  ___________________^
+3 | |         1
+4 | |         2;
+  | |_________^
+"
+    );
+}
+
+#[test]
+fn long_multi_line_spans_are_elided_and_keep_their_labels() {
+    let source = "f(\n  a,\n  b,\n  c,\n  d\n);\nlet x = f;\n";
+    let mut d = Diagnostic::error("wrong call", Span::new(FileId(0), 0, 23))
+        .with_secondary_label(Span::new(FileId(0), 33, 34), Some("used here".to_string()));
+    d.primary.message = Some("this call".to_string());
+    let mut r = Renderer::new();
+    r.add_file(FileId(0), "main.nepl", source);
+    assert_eq!(
+        r.render(&d),
+        "\
+error: wrong call
+ --> main.nepl:1:1
+  |
+1 |   f(
+  |  _^
+2 | |   a,
+...
+5 | |   d
+6 | | );
+  | |_^ this call
+7 |   let x = f;
+  |           - used here
+"
+    );
+}
+
+#[test]
+fn every_diagnostic_is_rendered_with_a_summary() {
+    let diags = vec![
+        Diagnostic::error("first", span(0, 3)),
+        Diagnostic::warning("second", span(11, 14)),
+        Diagnostic::error("third", span(31, 31)),
+    ];
+    let out = renderer().render_all(&diags);
+    assert!(out.contains("error: first\n"));
+    assert!(out.contains("warning: second\n"));
+    assert!(out.contains("error: third\n --> main.nepl:3:1\n"));
+    assert!(out.ends_with("error: aborting due to 2 previous errors; 1 warning emitted\n"));
+}
+
+#[test]
+fn lexer_errors_are_reported_once() {
+    let source = "{ 1 $ }";
+    let Err(CoreError::Diagnostics(diags)) = compile_wasm(FileId(0), source) else {
+        panic!("expected diagnostics");
+    };
+    let codes: Vec<_> = diags.iter().filter_map(|d| d.code).collect();
+    assert_eq!(codes, ["E0001"]);
+
+    let mut r = Renderer::new();
+    r.add_file(FileId(0), "main.nepl", source);
+    let out = r.render_all(&diags);
+    assert_eq!(out.matches("error[E0001]").count(), 1);
+    assert!(out.ends_with("error: aborting due to 1 previous error\n"));
+}
+
+#[test]
+fn columns_count_characters_and_carets_follow_display_width() {
+    let source = "{ let 合計 = 1; 合言 }";
+    let Err(CoreError::Diagnostics(diags)) = compile_wasm(FileId(0), source) else {
+        panic!("expected diagnostics");
+    };
+    let mut r = Renderer::new();
+    r.add_file(FileId(0), "main.nepl", source);
+    let out = r.render(&diags[0]);
+    assert!(out.contains(" --> main.nepl:1:15\n"), "{out}");
+    assert!(
+        out.contains("1 | { let 合計 = 1; 合言 }\n  |                 ^^^^"),
+        "{out}"
+    );
+}
+
+#[test]
+fn colors_are_optional() {
+    let d = Diagnostic::error("boom", span(0, 3));
+    let mut r = Renderer::new().with_color(true);
+    r.add_file(FileId(0), "main.nepl", SOURCE);
+    let colored = r.render(&d);
+    assert!(colored.starts_with("\x1b[1;31merror"));
+    assert!(!renderer().render(&d).contains('\x1b'));
+}
@@ -0,0 +1,132 @@
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::report::Reporter;
+use nepl_core::span::{FileId, Span};
+use nepl_core::{CoreError, compile_wasm};
+
+const SOURCE: &str = "let x = 1;\nlet y = add x \"é\";\n";
+
+fn span(start: u32, end: u32) -> Span {
+    Span::new(FileId(0), start, end)
+}
+
+fn reporter() -> Reporter<'static> {
+    let mut r = Reporter::new();
+    r.add_file(FileId(0), "src/main.nepl", SOURCE);
+    r
+}
+
+fn diagnostic() -> Diagnostic {
+    let mut d = Diagnostic::error(
+        "mismatched types: expected `i32`, found \"str\"",
+        span(25, 29),
+    )
+    .with_code("E0001")
+    .with_secondary_label(span(4, 5), Some("`x` defined here".to_string()));
+    d.primary.message = Some("expected `i32`".to_string());
+    d
+}
+
+#[test]
+fn json_lines_carry_spans_and_line_columns() {
+    let json = reporter().json_lines(&[diagnostic(), Diagnostic::warning("unused", span(4, 5))]);
+    let lines: Vec<&str> = json.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[0],
+        concat!(
+            r#"{"severity":"error","code":"E0001","#,
+            r#""message":"mismatched types: expected `i32`, found \"str\"","#,
+            r#""file":"src/main.nepl","span":{"start":25,"end":29},"#,
+            r#""range":{"start":{"line":2,"column":15},"end":{"line":2,"column":18}},"#,
+            r#""label":"expected `i32`","#,
+            r#""secondary":[{"file":"src/main.nepl","span":{"start":4,"end":5},"#,
+            r#""range":{"start":{"line":1,"column":5},"end":{"line":1,"column":6}},"#,
+            r#""message":"`x` defined here"}],"suggestions":[]}"#
+        )
+    );
+    assert!(lines[1].starts_with(r#"{"severity":"warning","code":null,"#));
+}
+
+#[test]
+fn unknown_files_have_no_range() {
+    let d = Diagnostic::error("somewhere else", Span::new(FileId(7), 0, 1));
+    assert_eq!(
+        reporter().json(&d),
+        concat!(
+            r#"{"severity":"error","code":null,"message":"somewhere else","#,
+            r#""file":"<unknown>","span":{"start":0,"end":1},"range":null,"#,
+            r#""label":null,"secondary":[],"suggestions":[]}"#
+        )
+    );
+}
+
+#[test]
+fn sarif_log_has_rules_results_and_related_locations() {
+    let sarif = reporter().sarif(&[diagnostic()]);
+    assert!(sarif.starts_with(
+        r#"{"$schema":"https://json.schemastore.org/sarif-2.1.0.json","version":"2.1.0","#
+    ));
+    assert!(sarif.contains(r#""rules":[{"id":"E0001"}]"#), "{sarif}");
+    assert!(
+        sarif.contains(concat!(
+            r#""ruleId":"E0001","level":"error","#,
+            r#""message":{"text":"mismatched types: expected `i32`, found \"str\""},"#,
+            r#""locations":[{"physicalLocation":{"artifactLocation":{"uri":"src/main.nepl"},"#,
+            r#""region":{"startLine":2,"startColumn":15,"endLine":2,"endColumn":18,"#,
+            r#""byteOffset":25,"byteLength":4}},"message":{"text":"expected `i32`"}}]"#
+        )),
+        "{sarif}"
+    );
+    assert!(
+        sarif.contains(r#""relatedLocations":[{"id":0,"physicalLocation""#),
+        "{sarif}"
+    );
+}
+
+#[test]
+fn suggestions_become_edits_and_sarif_fixes() {
+    let d = Diagnostic::error("unresolved identifier `y`", span(25, 29)).with_suggestion(
+        "a variable with a similar name exists: `x`",
+        span(25, 29),
+        "x",
+    );
+    let json = reporter().json(&d);
+    assert!(
+        json.ends_with(concat!(
+            r#""suggestions":[{"message":"a variable with a similar name exists: `x`","#,
+            r#""edits":[{"file":"src/main.nepl","span":{"start":25,"end":29},"#,
+            r#""range":{"start":{"line":2,"column":15},"end":{"line":2,"column":18}},"#,
+            r#""replacement":"x"}]}]}"#
+        )),
+        "{json}"
+    );
+
+    let sarif = reporter().sarif(&[d]);
+    assert!(
+        sarif.contains(concat!(
+            r#""fixes":[{"description":{"text":"a variable with a similar name exists: `x`"},"#,
+            r#""artifactChanges":[{"artifactLocation":{"uri":"src/main.nepl"},"#,
+            r#""replacements":[{"deletedRegion":{"startLine":2,"startColumn":15,"#,
+            r#""endLine":2,"endColumn":18,"byteOffset":25,"byteLength":4},"#,
+            r#""insertedContent":{"text":"x"}}]}]}]"#
+        )),
+        "{sarif}"
+    );
+}
+
+#[test]
+fn lexer_errors_are_reported_once() {
+    let source = "{ 1 $ }";
+    let Err(CoreError::Diagnostics(diags)) = compile_wasm(FileId(0), source) else {
+        panic!("expected diagnostics");
+    };
+    let mut r = Reporter::new();
+    r.add_file(FileId(0), "src/main.nepl", source);
+
+    let json = r.json_lines(&diags);
+    assert_eq!(json.lines().count(), 1, "{json}");
+    assert!(json.contains(r#""code":"E0001""#), "{json}");
+
+    let sarif = r.sarif(&diags);
+    assert_eq!(sarif.matches(r#""ruleId":"E0001""#).count(), 1, "{sarif}");
+}
@@ -0,0 +1,85 @@
+use nepl_core::span::{ColumnUnit, FileId, LineCol, LineIndex, SourceFile, SourceMap, Span};
+
+// "é" is 2 bytes / 1 UTF-16 unit, "𝒳" is 4 bytes / 2 UTF-16 units.
+const TEXT: &str = "let é = 1\r\n// 𝒳 x\nend";
+
+#[test]
+fn positions_in_every_unit() {
+    let index = LineIndex::new(TEXT);
+    assert_eq!(index.line_count(), 3);
+
+    // The `=` after "é".
+    let eq = TEXT.find('=').unwrap() as u32;
+    assert_eq!(index.position(eq, ColumnUnit::Utf8), LineCol::new(0, 7));
+    assert_eq!(index.position(eq, ColumnUnit::Utf16), LineCol::new(0, 6));
+    assert_eq!(index.position(eq, ColumnUnit::Char), LineCol::new(0, 6));
+
+    // The `x` after "𝒳".
+    let x = TEXT.rfind('x').unwrap() as u32;
+    assert_eq!(index.position(x, ColumnUnit::Utf8), LineCol::new(1, 8));
+    assert_eq!(index.position(x, ColumnUnit::Utf16), LineCol::new(1, 6));
+    assert_eq!(index.position(x, ColumnUnit::Char), LineCol::new(1, 5));
+
+    for unit in [ColumnUnit::Utf8, ColumnUnit::Utf16, ColumnUnit::Char] {
+        // Every offset but the `\n` of `\r\n`, which is not on any column.
+        for (offset, _) in TEXT.char_indices().filter(|(i, _)| *i != 11) {
+            let pos = index.position(offset as u32, unit);
+            assert_eq!(
+                index.offset(pos, unit),
+                Some(offset as u32),
+                "{unit:?} {pos:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn offsets_inside_characters_and_past_line_ends() {
+    let index = LineIndex::new(TEXT);
+    let wide = TEXT.find('𝒳').unwrap() as u32;
+    // Between the two halves of the surrogate pair.
+    assert_eq!(index.offset(LineCol::new(1, 4), ColumnUnit::Utf16), None);
+    // A byte in the middle of the character counts as its start.
+    assert_eq!(
+        index.position(wide + 2, ColumnUnit::Utf16),
+        LineCol::new(1, 3)
+    );
+
+    // Columns past the end of a line clamp to the line end, before `\r\n`.
+    assert_eq!(
+        index.offset(LineCol::new(0, 99), ColumnUnit::Utf16),
+        Some(10)
+    );
+    assert_eq!(index.offset(LineCol::new(3, 0), ColumnUnit::Utf8), None);
+    assert_eq!(index.position(999, ColumnUnit::Char), LineCol::new(2, 3));
+    assert_eq!(&TEXT[index.line_range(0)], "let é = 1");
+
+    assert_eq!(
+        index.convert_col(1, 6, ColumnUnit::Utf16, ColumnUnit::Char),
+        Some(5)
+    );
+}
+
+#[test]
+fn source_map_holds_files_by_id() {
+    let mut map = SourceMap::new();
+    let main = map.add("main.nepl", TEXT);
+    let lib = map.add("lib.nepl", "add 1 2");
+    assert_eq!((main, lib), (FileId(0), FileId(1)));
+    assert_eq!(map.name(lib), Some("lib.nepl"));
+    assert_eq!(map.snippet(Span::new(lib, 4, 5)), Some("1"));
+    assert_eq!(map.snippet(Span::new(FileId(7), 0, 1)), None);
+
+    let file = map.get(main).unwrap();
+    assert_eq!(file.line_text(1), "// 𝒳 x");
+    let span = Span::new(main, 15, 19);
+    assert_eq!(file.snippet(span), Some("𝒳"));
+    assert_eq!(
+        map.range(span, ColumnUnit::Utf16),
+        Some((LineCol::new(1, 3), LineCol::new(1, 5)))
+    );
+
+    map.insert(SourceFile::new(lib, "lib.nepl", "sub 1 2"));
+    assert_eq!(map.source(lib), Some("sub 1 2"));
+    assert_eq!(map.files().count(), 2);
+}
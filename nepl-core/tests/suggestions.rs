@@ -0,0 +1,88 @@
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::fix::apply_suggestions;
+use nepl_core::parser::parse_file;
+use nepl_core::render::Renderer;
+use nepl_core::span::{FileId, Span};
+use nepl_core::typecheck::typecheck_expr;
+
+fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let parsed = parse_file(FileId(0), source);
+    let mut diags = parsed.diagnostics;
+    if let Some(expr) = &parsed.expr {
+        diags.extend(typecheck_expr(expr).diagnostics);
+    }
+    diags
+}
+
+/// The source after applying every suggestion once.
+fn fixed(source: &str) -> String {
+    apply_suggestions(FileId(0), source, &diagnostics(source)).source
+}
+
+#[test]
+fn misspelled_names_suggest_a_close_match() {
+    assert_eq!(
+        fixed("{ let total = 1; add totla 2 }"),
+        "{ let total = 1; add total 2 }"
+    );
+    assert_eq!(fixed("{ let x = 1; ad x 2 }"), "{ let x = 1; add x 2 }");
+    let diags = diagnostics("{ let x = 1; add x y }");
+    assert!(diags[0].suggestions.is_empty(), "{diags:?}");
+}
+
+#[test]
+fn assignments_and_mut_arguments_suggest_mut() {
+    assert_eq!(
+        fixed("{ let n = 1; set n 2; n }"),
+        "{ let mut n = 1; set n 2; n }"
+    );
+
+    let source = "{ fn inc = |i32 mut x|->Unit set x add x 1; let n = 1; inc n; n }";
+    assert_eq!(
+        fixed(source),
+        "{ fn inc = |i32 mut x|->Unit set x add x 1; let mut n = 1; inc n; n }"
+    );
+
+    let source = "|i32 x|->i32 { set x 2; x }";
+    assert_eq!(fixed(source), "|i32 mut x|->i32 { set x 2; x }");
+}
+
+#[test]
+fn missing_match_arrow_is_inserted() {
+    let source = "match 1 { case 1 2; case _ => 0 }";
+    let diags = diagnostics(source);
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!(fixed(source), "match 1 { case 1 => 2; case _ => 0 }");
+}
+
+#[test]
+fn overlapping_suggestions_are_applied_once() {
+    let at = Span::empty(FileId(0), 2);
+    let d = Diagnostic::error("a", at).with_suggestion("first", at, "x");
+    let e = Diagnostic::error("b", at).with_suggestion("second", at, "y");
+    let result = apply_suggestions(FileId(0), "ab", &[d, e]);
+    assert_eq!(result.source, "abx");
+    assert_eq!(result.applied, 1);
+}
+
+#[test]
+fn suggestions_render_as_help_with_the_fixed_line() {
+    let source = "{ let n = 1; set n 2; n }";
+    let diags = diagnostics(source);
+    let mut renderer = Renderer::new();
+    renderer.add_file(FileId(0), "main.nepl", source);
+    assert_eq!(
+        renderer.render(&diags[0]),
+        "\
+error[E0404]: cannot assign to immutable variable `n`
+ --> main.nepl:1:18
+  |
+1 | { let n = 1; set n 2; n }
+  |                  ^
+help: consider making this binding mutable
+  |
+1 | { let mut n = 1; set n 2; n }
+  |       ++++
+"
+    );
+}
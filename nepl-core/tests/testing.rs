@@ -0,0 +1,133 @@
+use nepl_core::ast::ExprKind;
+use nepl_core::interp::CaptureHost;
+use nepl_core::lint::LintConfig;
+use nepl_core::parser::parse_file;
+use nepl_core::span::FileId;
+use nepl_core::{CompilationArtifact, CoreError, compile_tests, compile_wasm, interpret};
+use wasmi::{Caller, Engine, Linker, Module, Store};
+
+const SOURCE: &str = r#"{
+    fn double = |i32 n|*>i32 add n n;
+    test "double": assert eq double 2 4 "double 2 is 4";
+    test "broken": { print_i32 double 3; assert eq double 3 7 "double 3 is 7" };
+    double 21
+}"#;
+
+fn codes(e: CoreError) -> Vec<&'static str> {
+    let CoreError::Diagnostics(diags) = e else {
+        panic!("{e:?}");
+    };
+    diags.iter().filter_map(|d| d.code).collect()
+}
+
+/// Run the export `name` in a fresh instance; returns what it printed
+/// and the index of the failed assertion, if any.
+fn call(artifact: &CompilationArtifact, name: &str) -> (Vec<i32>, Option<i32>) {
+    let engine = Engine::default();
+    let module = Module::new(&engine, &artifact.wasm).unwrap();
+    let mut linker = Linker::new(&engine);
+    linker
+        .func_wrap(
+            "nepl",
+            "print_i32",
+            |mut caller: Caller<'_, (Vec<i32>, Option<i32>)>, v: i32| caller.data_mut().0.push(v),
+        )
+        .unwrap();
+    linker
+        .func_wrap(
+            "nepl",
+            "assert_failed",
+            |mut caller: Caller<'_, (Vec<i32>, Option<i32>)>, i: i32| caller.data_mut().1 = Some(i),
+        )
+        .unwrap();
+    let mut store = Store::new(&engine, (Vec::new(), None));
+    let instance = linker.instantiate_and_start(&mut store, &module).unwrap();
+    let func = instance.get_typed_func::<(), ()>(&store, name).unwrap();
+    let trapped = func.call(&mut store, ()).is_err();
+    let (printed, failed) = store.into_data();
+    assert_eq!(trapped, failed.is_some());
+    (printed, failed)
+}
+
+#[test]
+fn test_declarations_parse() {
+    let parsed = parse_file(FileId(0), r#"test "a \"b\"": { 1 }"#);
+    assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+    let ExprKind::Test(test) = &parsed.expr.unwrap().kind else {
+        panic!("expected a test");
+    };
+    assert_eq!(test.name, "a \"b\"");
+
+    let parsed = parse_file(FileId(0), "test 1");
+    assert!(parsed.diagnostics.iter().any(|d| d.code == Some("E0100")));
+}
+
+#[test]
+fn normal_builds_skip_tests() {
+    let artifact = compile_wasm(FileId(0), SOURCE).unwrap();
+    assert!(artifact.tests.is_empty());
+    assert!(artifact.assertions.is_empty());
+
+    // Test bodies are not even type-checked.
+    let source = r#"{ test "unknown": print_i32 missing; 0 }"#;
+    compile_wasm(FileId(0), source).unwrap();
+    let err = compile_tests(FileId(0), source, &LintConfig::default()).unwrap_err();
+    assert_eq!(codes(err), ["E0200"]);
+}
+
+#[test]
+fn tests_are_exported_and_report_failed_assertions() {
+    let artifact = compile_tests(FileId(0), SOURCE, &LintConfig::default()).unwrap();
+    let exports: Vec<_> = artifact
+        .tests
+        .iter()
+        .map(|t| (t.name.as_str(), t.export.as_str()))
+        .collect();
+    assert_eq!(
+        exports,
+        [("double", "test:double"), ("broken", "test:broken")]
+    );
+    let spans: Vec<_> = artifact
+        .tests
+        .iter()
+        .map(|t| &SOURCE[t.span.start as usize..t.span.end as usize])
+        .collect();
+    assert!(spans[0].starts_with(r#"test "double": assert"#));
+
+    assert_eq!(call(&artifact, "test:double"), (vec![], None));
+    let (printed, failed) = call(&artifact, "test:broken");
+    assert_eq!(printed, [6]);
+    let assertion = &artifact.assertions[failed.unwrap() as usize];
+    assert_eq!(assertion.message, "double 3 is 7");
+    assert_eq!(
+        &SOURCE[assertion.span.start as usize..assertion.span.end as usize],
+        r#"assert eq double 3 7 "double 3 is 7""#
+    );
+}
+
+#[test]
+fn test_errors() {
+    let duplicate = r#"{ test "a": assert true "x"; test "a": assert true "y"; 0 }"#;
+    let err = compile_tests(FileId(0), duplicate, &LintConfig::default()).unwrap_err();
+    assert_eq!(codes(err), ["E0204"]);
+
+    let capture = r#"{ let x = 1; test "a": assert eq x 1 "x"; x }"#;
+    let err = compile_tests(FileId(0), capture, &LintConfig::default()).unwrap_err();
+    assert_eq!(codes(err), ["E0503"]);
+}
+
+#[test]
+fn interpreter_reports_failed_assertions() {
+    let mut host = CaptureHost {
+        printed: Vec::new(),
+        random: 4,
+    };
+    let source = r#"{ print_i32 1; assert eq 1 2 "one is two"; 0 }"#;
+    let err = interpret(FileId(0), source, &mut host, None).unwrap_err();
+    let CoreError::Diagnostics(diags) = err else {
+        panic!("{err:?}");
+    };
+    assert_eq!(diags[0].code, Some("E1000"));
+    assert_eq!(diags[0].message, "assertion failed: one is two");
+    assert_eq!(host.printed, [1]);
+}
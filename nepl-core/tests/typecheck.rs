@@ -0,0 +1,140 @@
+mod common;
+
+use common::{check, messages};
+use nepl_core::hir::{HirExpr, HirExprKind};
+use nepl_core::types::Type;
+
+fn check_ok(source: &str) -> HirExpr {
+    let result = check(source);
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    result.expr.unwrap()
+}
+
+#[test]
+fn let_annotation_selects_overload() {
+    let hir = check_ok("{ let x: i64 = add 1 2; x }");
+    assert_eq!(hir.ty, Type::I64);
+
+    let HirExprKind::Block { expressions } = &hir.kind else {
+        panic!("expected block, got {:?}", hir.kind);
+    };
+    let HirExprKind::Let { init, .. } = &expressions[0].kind else {
+        panic!("expected let, got {:?}", expressions[0].kind);
+    };
+    assert_eq!(init.ty, Type::I64);
+    let HirExprKind::BuiltinCall { args, .. } = &init.kind else {
+        panic!("expected builtin call, got {:?}", init.kind);
+    };
+    assert!(args.iter().all(|a| matches!(a.kind, HirExprKind::I64(_))));
+}
+
+#[test]
+fn expression_annotation_types_literals() {
+    assert_eq!(check_ok("i64 add 1 2").ty, Type::I64);
+    assert_eq!(check_ok("f32 mul 1.5 2.0").ty, Type::F32);
+    assert_eq!(check_ok("add 1 2").ty, Type::I32);
+}
+
+#[test]
+fn function_literals_are_checked_against_annotations() {
+    let result = check("{ let f: (i32) -> i32 = |i32 x|->i32 add x 1; f 41 }");
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    assert_eq!(result.expr.unwrap().ty, Type::I32);
+    assert_eq!(result.functions.len(), 1);
+
+    let result = check("{ let f: (i64) -> i32 = |i32 x|->i32 x; 0 }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["mismatched types: expected `(i64) -> i32`, found `(i32) -> i32`"]
+    );
+}
+
+#[test]
+fn annotation_mismatch_is_reported() {
+    let result = check("{ let b: Bool = add 1 2; b }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["mismatched types: expected `Bool`, found `i32`"]
+    );
+}
+
+#[test]
+fn unresolved_types_cause_no_follow_up_errors() {
+    let result = check(
+        "{
+            enum Opt<T> { Some(T); None };
+            struct P { a: Foo; b: i32 };
+            fn id = <T>|T x|*>T x;
+            fn f = |Baz v|->i32 add v 1;
+            let x: Bar = 1;
+            let z: Opt<i32, i32> = None;
+            let w = match z { case None => 1; case Some(v) => v };
+            let s = match P 1 2 { case P { a: u, b: v } => add u v };
+            if true then id x else add w s;
+            add x f 3
+        }",
+    );
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec![
+            "unknown type `Foo`",
+            "unknown type `Baz`",
+            "unknown type `Bar`",
+            "type `Opt` expects 1 type argument(s) but 2 were given",
+        ]
+    );
+}
+
+#[test]
+fn numeric_literals_take_their_values_and_suffix_types() {
+    let literal = |source: &str| check_ok(source).kind;
+    assert_eq!(literal("0xff"), HirExprKind::I32(255));
+    assert_eq!(literal("0b1010"), HirExprKind::I32(10));
+    assert_eq!(literal("1_000_000"), HirExprKind::I32(1_000_000));
+    assert_eq!(literal("10i64"), HirExprKind::I64(10));
+    assert_eq!(literal("1.5f32"), HirExprKind::F32(1.5));
+    assert_eq!(literal("2e3"), HirExprKind::F64(2000.0));
+
+    // A suffix decides the overload, where a bare literal would default
+    // to `i32`.
+    assert_eq!(check_ok("add 1 2i64").ty, Type::I64);
+    let result = check("{ let x: i32 = 1i64; x }");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["mismatched types: expected `i32`, found `i64`"]
+    );
+}
+
+#[test]
+fn out_of_range_literals_are_reported() {
+    let result = check("add 2147483648 1");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["literal out of range for `i32`"]
+    );
+    let edit = &result.diagnostics[0].suggestions[0].edits[0];
+    assert_eq!((edit.span.start, edit.replacement.as_str()), (14, "i64"));
+
+    assert_eq!(check_ok("2147483647").kind, HirExprKind::I32(i32::MAX));
+    assert_eq!(check_ok("i64 add 2147483648 1").ty, Type::I64);
+
+    let result = check("18446744073709551616i64");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["literal out of range for `i64`"]
+    );
+    assert!(result.diagnostics[0].suggestions.is_empty());
+
+    let result = check("1e39f32");
+    assert_eq!(
+        messages(&result.diagnostics),
+        vec!["literal out of range for `f32`"]
+    );
+}
+
+#[test]
+fn let_outside_a_block_binds_nothing_after_it() {
+    let result = check("{ let mut n = 3; loop let mut c = 0; while ne n 1 { set c 1 }; n }");
+    let codes: Vec<_> = result.diagnostics.iter().filter_map(|d| d.code).collect();
+    assert!(codes.contains(&"E0200"), "{:?}", result.diagnostics);
+}
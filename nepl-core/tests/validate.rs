@@ -0,0 +1,96 @@
+//! The `validate` feature: invalid modules produced by the backend are
+//! reported as internal errors naming the HIR expression at fault.
+//!
+//! The HIR is built by hand, since the type checker never produces the
+//! ill-typed expressions needed to make the backend emit invalid code.
+#![cfg(feature = "validate")]
+
+use nepl_core::builtins::BuiltinKind;
+use nepl_core::codegen_wasm::generate_wasm;
+use nepl_core::hir::{HirExpr, HirExprKind, HirModule};
+use nepl_core::span::{FileId, Span};
+use nepl_core::types::Type;
+use nepl_core::{CoreError, compile_wasm};
+
+fn expr(kind: HirExprKind, ty: Type, start: u32, end: u32) -> HirExpr {
+    HirExpr {
+        kind,
+        ty,
+        span: Span::new(FileId(0), start, end),
+    }
+}
+
+#[test]
+fn valid_programs_pass_validation() {
+    let artifact = compile_wasm(FileId(0), "{ print_i32 add 1 2; 0 }").unwrap();
+    wasmparser::Validator::new()
+        .validate_all(&artifact.wasm)
+        .unwrap();
+    let err = compile_wasm(FileId(0), "{ print_i32 missing; 0 }").unwrap_err();
+    let CoreError::Diagnostics(diags) = err else {
+        panic!("{err:?}");
+    };
+    assert_eq!(diags[0].code, Some("E0200"));
+}
+
+#[test]
+fn invalid_code_names_the_expression_being_lowered() {
+    // `print_i32` applied to an `i64`: the call instruction is invalid.
+    let arg = expr(HirExprKind::I64(2), Type::I64, 10, 11);
+    let call = expr(
+        HirExprKind::BuiltinCall {
+            kind: BuiltinKind::WasiPrintI32,
+            args: vec![arg],
+        },
+        Type::Unit,
+        0,
+        11,
+    );
+    let entry = expr(
+        HirExprKind::Block {
+            expressions: vec![call, expr(HirExprKind::I32(0), Type::I32, 13, 14)],
+        },
+        Type::I32,
+        0,
+        16,
+    );
+    let module = HirModule {
+        functions: Vec::new(),
+    };
+    let diags = generate_wasm(&module, &entry).unwrap_err();
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].code, Some("E0900"));
+    assert_eq!(
+        (diags[0].primary.span.start, diags[0].primary.span.end),
+        (0, 11)
+    );
+    assert!(
+        diags[0].message.starts_with(
+            "internal codegen error: invalid wasm generated for call of builtin `print_i32` in `main`: "
+        ),
+        "{}",
+        diags[0].message
+    );
+}
+
+#[test]
+fn a_wrong_result_names_the_function_body() {
+    // `main` is declared to return `i32` but its body leaves an `i64`.
+    let entry = expr(HirExprKind::I64(1), Type::I32, 0, 1);
+    let module = HirModule {
+        functions: Vec::new(),
+    };
+    let diags = generate_wasm(&module, &entry).unwrap_err();
+    assert_eq!(diags[0].code, Some("E0900"));
+    assert_eq!(
+        (diags[0].primary.span.start, diags[0].primary.span.end),
+        (0, 1)
+    );
+    assert!(
+        diags[0].message.starts_with(
+            "internal codegen error: invalid wasm generated for `i64` literal in `main`: "
+        ),
+        "{}",
+        diags[0].message
+    );
+}
@@ -0,0 +1,423 @@
+//! Answers to language server queries about one document.
+//!
+//! An [`Analysis`] is computed from the text of a document and holds the
+//! results of every front-end pass the queries need. Positions are
+//! converted with the document's `LineIndex` in UTF-16, the LSP default.
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticRelatedInformation,
+    DiagnosticSeverity, DocumentSymbol, Hover, HoverContents, InlayHint, InlayHintLabel, Location,
+    MarkupContent, MarkupKind, NumberOrString, Position, Range, SemanticToken, SemanticTokenType,
+    SymbolKind, Uri,
+};
+use nepl_core::builtins;
+use nepl_core::cst::SyntaxKind;
+use nepl_core::diagnostic::{self, Severity};
+use nepl_core::hir::{HirExprKind, HirFunction, HirPattern};
+use nepl_core::lexer::{KEYWORDS, TokenKind, TriviaKind};
+use nepl_core::lint::LintConfig;
+use nepl_core::name_resolve::{self, NameResolveResult, Symbol, SymbolId};
+use nepl_core::parser::parse_cst;
+use nepl_core::span::{ColumnUnit, FileId, LineCol, LineIndex, Span};
+use nepl_core::typecheck::TypeCheckResult;
+use nepl_core::types::Type;
+
+/// Semantic token types, in the order of the legend sent to clients.
+pub const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::TYPE_PARAMETER,
+    SemanticTokenType::ENUM,
+    SemanticTokenType::STRUCT,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::NAMESPACE,
+];
+
+/// The front-end results for one version of a document.
+pub struct Analysis {
+    source: String,
+    index: LineIndex,
+    diagnostics: Vec<diagnostic::Diagnostic>,
+    names: NameResolveResult,
+    typed: Option<TypeCheckResult>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Analysis {
+        // Test bodies are checked too, so that hovers work in them.
+        let front_end = nepl_core::analyze(FileId(0), source, &LintConfig::default());
+        Analysis {
+            source: source.to_string(),
+            index: LineIndex::new(source),
+            diagnostics: front_end.diagnostics,
+            names: front_end.names,
+            typed: front_end.typed,
+        }
+    }
+
+    pub fn offset(&self, position: Position) -> Option<u32> {
+        self.index.offset(
+            LineCol::new(position.line, position.character),
+            ColumnUnit::Utf16,
+        )
+    }
+
+    pub fn position(&self, offset: u32) -> Position {
+        let pos = self.index.position(offset, ColumnUnit::Utf16);
+        Position::new(pos.line, pos.col)
+    }
+
+    pub fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    pub fn diagnostics(&self, uri: &Uri) -> Vec<Diagnostic> {
+        self.diagnostics
+            .iter()
+            .map(|d| Diagnostic {
+                range: self.range(d.primary.span),
+                severity: Some(match d.severity {
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                    Severity::Warning => DiagnosticSeverity::WARNING,
+                }),
+                code: d.code.map(|c| NumberOrString::String(c.to_string())),
+                source: Some("nepl".to_string()),
+                message: d.message.clone(),
+                related_information: Some(
+                    d.secondary
+                        .iter()
+                        .filter_map(|label| {
+                            Some(DiagnosticRelatedInformation {
+                                location: Location::new(uri.clone(), self.range(label.span)),
+                                message: label.message.clone()?,
+                            })
+                        })
+                        .collect(),
+                )
+                .filter(|related: &Vec<_>| !related.is_empty()),
+                ..Diagnostic::default()
+            })
+            .collect()
+    }
+
+    /// The inferred type of the name or expression at `offset`.
+    pub fn hover(&self, offset: u32) -> Option<Hover> {
+        let typed = self.typed.as_ref()?;
+        let covers = |span: Span| span.start <= offset && offset <= span.end;
+
+        let mut found: Option<(Span, String)> = None;
+        let mut consider = |span: Span, text: String| {
+            // The innermost name or expression wins.
+            if covers(span) && found.as_ref().is_none_or(|(s, _)| span.len() < s.len()) {
+                found = Some((span, text));
+            }
+        };
+        let roots = typed
+            .expr
+            .iter()
+            .chain(typed.functions.iter().map(|f| &f.body));
+        for root in roots {
+            root.walk(&mut |e| {
+                match &e.kind {
+                    HirExprKind::Var(name) => {
+                        consider(name.span, format!("{}: {}", name.name, e.ty))
+                    }
+                    HirExprKind::Let { name, init, .. } => {
+                        consider(name.span, format!("let {}: {}", name.name, init.ty))
+                    }
+                    HirExprKind::Call { callee, .. } => {
+                        if let Some(f) = typed.functions.iter().find(|f| f.name.name == callee.name)
+                        {
+                            consider(callee.span, signature(f));
+                        }
+                    }
+                    HirExprKind::Match { arms, .. } => {
+                        for arm in arms {
+                            pattern_bindings(&arm.pattern, &mut consider);
+                        }
+                    }
+                    _ => {}
+                }
+                consider(e.span, e.ty.to_string());
+            });
+        }
+        for f in &typed.functions {
+            consider(f.name.span, signature(f));
+            for p in &f.params {
+                consider(p.name.span, format!("{}: {}", p.name.name, p.ty));
+            }
+        }
+
+        let (span, text) = found?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```nepl\n{text}\n```"),
+            }),
+            range: Some(self.range(span)),
+        })
+    }
+
+    /// Where the name at `offset` is defined.
+    pub fn definition(&self, offset: u32) -> Option<Range> {
+        self.names
+            .definition_at(offset)
+            .map(|symbol| self.range(symbol.span))
+    }
+
+    /// Members of the namespace before `::`, or keywords, builtins and
+    /// the names in scope at `offset`.
+    pub fn completion(&self, offset: u32) -> Vec<CompletionItem> {
+        let line_start = self.source[..offset as usize]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let before = &self.source[line_start..offset as usize];
+        let partial = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        if let Some(path) = partial.strip_suffix("::") {
+            let namespace = path
+                .rsplit(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .next()
+                .unwrap_or("");
+            let ids = (0..self.names.symbols.len()).map(SymbolId);
+            return ids
+                .filter(|id| {
+                    let s = self.names.symbol(*id);
+                    s.kind == name_resolve::SymbolKind::Namespace && s.name == namespace
+                })
+                .flat_map(|id| self.names.members(id))
+                .map(symbol_completion)
+                .collect();
+        }
+
+        let keywords = KEYWORDS.iter().map(|(keyword, _)| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..CompletionItem::default()
+        });
+        let builtins = builtins::builtins().into_iter().map(|b| CompletionItem {
+            label: b.name.to_string(),
+            kind: Some(CompletionItemKind::FUNCTION),
+            detail: Some(b.ty.to_string()),
+            ..CompletionItem::default()
+        });
+        let mut items: Vec<CompletionItem> = Vec::new();
+        let candidates = self
+            .names
+            .visible_at(offset)
+            .into_iter()
+            .map(symbol_completion)
+            .chain(keywords)
+            .chain(builtins);
+        for item in candidates {
+            // Overloaded builtins are listed once.
+            if !items.iter().any(|i| i.label == item.label) {
+                items.push(item);
+            }
+        }
+        items
+    }
+
+    /// The parentheses P-style resolution implied in `range`, as
+    /// `(` and `)` hints around each grouped call.
+    pub fn inlay_hints(&self, range: Range) -> Vec<InlayHint> {
+        let Some(typed) = &self.typed else {
+            return Vec::new();
+        };
+        let mut hints = Vec::new();
+        for seq in &typed.seqs {
+            for span in seq.implicit_parens() {
+                for (offset, label) in [(span.start, "("), (span.end, ")")] {
+                    let position = self.position(offset);
+                    if range.start <= position && position <= range.end {
+                        hints.push(InlayHint {
+                            position,
+                            label: InlayHintLabel::String(label.to_string()),
+                            kind: None,
+                            text_edits: None,
+                            tooltip: None,
+                            padding_left: None,
+                            padding_right: None,
+                            data: None,
+                        });
+                    }
+                }
+            }
+        }
+        hints.sort_by_key(|h| h.position);
+        hints
+    }
+
+    /// Namespaces, functions, enums and structs, nested by container.
+    pub fn document_symbols(&self) -> Vec<DocumentSymbol> {
+        self.symbols_in(None)
+    }
+
+    fn symbols_in(&self, container: Option<SymbolId>) -> Vec<DocumentSymbol> {
+        use name_resolve::SymbolKind as K;
+        let names = &self.names;
+        (0..names.symbols.len())
+            .map(SymbolId)
+            .filter(|id| names.symbol(*id).container == container)
+            .filter_map(|id| {
+                let symbol = names.symbol(id);
+                let kind = match symbol.kind {
+                    K::Namespace => SymbolKind::NAMESPACE,
+                    K::Function => SymbolKind::FUNCTION,
+                    K::Enum => SymbolKind::ENUM,
+                    K::Struct => SymbolKind::STRUCT,
+                    K::EnumVariant => SymbolKind::ENUM_MEMBER,
+                    K::StructField => SymbolKind::FIELD,
+                    K::Variable | K::Parameter | K::TypeParam => return None,
+                };
+                let children = self.symbols_in(Some(id));
+                #[allow(deprecated)]
+                Some(DocumentSymbol {
+                    name: symbol.name.clone(),
+                    detail: None,
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    range: self.range(symbol.full_span),
+                    selection_range: self.range(symbol.span),
+                    children: (!children.is_empty()).then_some(children),
+                })
+            })
+            .collect()
+    }
+
+    /// Keywords, comments, literals and resolved names, encoded
+    /// relative to each other as LSP requires.
+    pub fn semantic_tokens(&self) -> Vec<SemanticToken> {
+        let tree = parse_cst(FileId(0), &self.source).tree;
+        let mut spans = Vec::new();
+        for token in tree.tokens() {
+            let token_type = match token.kind() {
+                SyntaxKind::Trivia(
+                    TriviaKind::LineComment | TriviaKind::DocComment | TriviaKind::BlockComment,
+                ) => SemanticTokenType::COMMENT,
+                SyntaxKind::Token(TokenKind::Ident) => {
+                    match self.names.definition_at(token.span().start) {
+                        Some(symbol) => symbol_token_type(symbol),
+                        None => continue,
+                    }
+                }
+                SyntaxKind::Token(TokenKind::IntLiteral | TokenKind::FloatLiteral) => {
+                    SemanticTokenType::NUMBER
+                }
+                SyntaxKind::Token(TokenKind::StringLiteral) => SemanticTokenType::STRING,
+                SyntaxKind::Token(kind) if KEYWORDS.iter().any(|(_, k)| *k == kind) => {
+                    SemanticTokenType::KEYWORD
+                }
+                _ => continue,
+            };
+            let index = TOKEN_TYPES.iter().position(|t| *t == token_type).unwrap();
+            spans.push((token.span(), index as u32));
+        }
+
+        let mut tokens = Vec::new();
+        let mut prev = Position::new(0, 0);
+        for (span, token_type) in spans {
+            // Tokens may not span lines; split comments and strings.
+            let (first, last) = (self.position(span.start), self.position(span.end));
+            for line in first.line..=last.line {
+                let start = if line == first.line {
+                    first.character
+                } else {
+                    0
+                };
+                let end = if line == last.line {
+                    last.character
+                } else {
+                    let range = self.index.line_range(line);
+                    self.position(range.end as u32).character
+                };
+                if end <= start {
+                    continue;
+                }
+                let delta_line = line - prev.line;
+                let delta_start = if delta_line == 0 {
+                    start - prev.character
+                } else {
+                    start
+                };
+                tokens.push(SemanticToken {
+                    delta_line,
+                    delta_start,
+                    length: end - start,
+                    token_type,
+                    token_modifiers_bitset: 0,
+                });
+                prev = Position::new(line, start);
+            }
+        }
+        tokens
+    }
+}
+
+/// `fn name<T>: (T) -> T`.
+fn signature(f: &HirFunction) -> String {
+    let ty = Type::Function {
+        params: f.params.iter().map(|p| p.ty.clone()).collect(),
+        result: Box::new(f.result.clone()),
+        arrow: f.arrow,
+    };
+    if f.type_params.is_empty() {
+        format!("fn {}: {ty}", f.name.name)
+    } else {
+        format!("fn {}<{}>: {ty}", f.name.name, f.type_params.join(", "))
+    }
+}
+
+fn pattern_bindings(pattern: &HirPattern, consider: &mut impl FnMut(Span, String)) {
+    match pattern {
+        HirPattern::Ident { name, ty } => consider(name.span, format!("{}: {ty}", name.name)),
+        HirPattern::EnumVariant { args, .. } => {
+            args.iter().for_each(|a| pattern_bindings(a, consider))
+        }
+        HirPattern::Struct { fields, .. } => fields
+            .iter()
+            .for_each(|f| pattern_bindings(&f.pattern, consider)),
+        _ => {}
+    }
+}
+
+fn symbol_completion(symbol: &Symbol) -> CompletionItem {
+    use name_resolve::SymbolKind as K;
+    let kind = match symbol.kind {
+        K::Variable => CompletionItemKind::VARIABLE,
+        K::Parameter => CompletionItemKind::VARIABLE,
+        K::Function => CompletionItemKind::FUNCTION,
+        K::TypeParam => CompletionItemKind::TYPE_PARAMETER,
+        K::Enum => CompletionItemKind::ENUM,
+        K::Struct => CompletionItemKind::STRUCT,
+        K::EnumVariant => CompletionItemKind::ENUM_MEMBER,
+        K::StructField => CompletionItemKind::FIELD,
+        K::Namespace => CompletionItemKind::MODULE,
+    };
+    CompletionItem {
+        label: symbol.name.clone(),
+        kind: Some(kind),
+        ..CompletionItem::default()
+    }
+}
+
+fn symbol_token_type(symbol: &Symbol) -> SemanticTokenType {
+    use name_resolve::SymbolKind as K;
+    match symbol.kind {
+        K::Variable => SemanticTokenType::VARIABLE,
+        K::Parameter => SemanticTokenType::PARAMETER,
+        K::Function => SemanticTokenType::FUNCTION,
+        K::TypeParam => SemanticTokenType::TYPE_PARAMETER,
+        K::Enum => SemanticTokenType::ENUM,
+        K::Struct => SemanticTokenType::STRUCT,
+        K::EnumVariant => SemanticTokenType::ENUM_MEMBER,
+        K::StructField => SemanticTokenType::PROPERTY,
+        K::Namespace => SemanticTokenType::NAMESPACE,
+    }
+}
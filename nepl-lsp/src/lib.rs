@@ -0,0 +1,247 @@
+//! Language server for NEPL.
+//!
+//! The server speaks LSP over any [`Connection`]; the `nepl-lsp` binary
+//! runs it over stdio. Documents are synchronised in full, and each
+//! change is analysed again by the `nepl-core` front end (see
+//! [`analysis`]), after which the server publishes its diagnostics.
+
+pub mod analysis;
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, LogMessage,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, InlayHintRequest,
+    Request as _, SemanticTokensFullRequest,
+};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, DocumentSymbolResponse, GotoDefinitionResponse,
+    HoverProviderCapability, InitializeResult, Location, LogMessageParams, MessageType, OneOf,
+    PublishDiagnosticsParams,
+    SemanticTokens, SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions,
+    SemanticTokensResult, SemanticTokensServerCapabilities, ServerCapabilities, ServerInfo,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use serde_json::Value;
+
+use analysis::{Analysis, TOKEN_TYPES};
+
+/// What this server supports.
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![":".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        inlay_hint_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: TOKEN_TYPES.to_vec(),
+                    token_modifiers: Vec::new(),
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..SemanticTokensOptions::default()
+            },
+        )),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Run the server on `connection` until the client shuts it down.
+pub fn run(connection: &Connection) -> Result<()> {
+    let (id, _params) = connection.initialize_start()?;
+    let result = InitializeResult {
+        capabilities: capabilities(),
+        server_info: Some(ServerInfo {
+            name: "nepl-lsp".to_string(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }),
+    };
+    connection.initialize_finish(id, serde_json::to_value(result)?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                connection.sender.send(server.request(request).into())?;
+            }
+            Message::Notification(notification) => {
+                for reply in server.notification(notification) {
+                    connection.sender.send(reply.into())?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Open documents and their analyses.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Uri, Analysis>,
+}
+
+impl Server {
+    fn request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        match self.answer(request) {
+            Ok(value) => Response::new_ok(id, value),
+            Err(response) => response,
+        }
+    }
+
+    fn answer(&mut self, request: Request) -> Result<Value, Response> {
+        let Request { id, method, params } = request;
+        let value = match method.as_str() {
+            HoverRequest::METHOD => {
+                let at = self.at(&id, params)?;
+                serde_json::to_value(at.and_then(|(doc, _, offset)| doc.hover(offset)))
+            }
+            GotoDefinition::METHOD => {
+                let location = self.at(&id, params)?.and_then(|(doc, uri, offset)| {
+                    let range = doc.definition(offset)?;
+                    Some(GotoDefinitionResponse::Scalar(Location::new(uri, range)))
+                });
+                serde_json::to_value(location)
+            }
+            Completion::METHOD => {
+                let items = self
+                    .at(&id, params)?
+                    .map(|(doc, _, offset)| CompletionResponse::Array(doc.completion(offset)));
+                serde_json::to_value(items)
+            }
+            DocumentSymbolRequest::METHOD => {
+                let params: lsp_types::DocumentSymbolParams = parse_params(&id, params)?;
+                let symbols = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .map(|doc| DocumentSymbolResponse::Nested(doc.document_symbols()));
+                serde_json::to_value(symbols)
+            }
+            InlayHintRequest::METHOD => {
+                let params: lsp_types::InlayHintParams = parse_params(&id, params)?;
+                let hints = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .map(|doc| doc.inlay_hints(params.range));
+                serde_json::to_value(hints)
+            }
+            SemanticTokensFullRequest::METHOD => {
+                let params: lsp_types::SemanticTokensParams = parse_params(&id, params)?;
+                let tokens = self.documents.get(&params.text_document.uri).map(|doc| {
+                    SemanticTokensResult::Tokens(SemanticTokens {
+                        result_id: None,
+                        data: doc.semantic_tokens(),
+                    })
+                });
+                serde_json::to_value(tokens)
+            }
+            _ => {
+                return Err(Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request `{method}`"),
+                ));
+            }
+        };
+        Ok(value.expect("LSP types serialize"))
+    }
+
+    /// The document and byte offset a positional request refers to, or
+    /// `None` for unknown documents and positions outside the text.
+    fn at(&self, id: &RequestId, params: Value) -> Result<Option<(&Analysis, Uri, u32)>, Response> {
+        let params: TextDocumentPositionParams = parse_params(id, params)?;
+        let uri = params.text_document.uri;
+        let Some(doc) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        Ok(doc.offset(params.position).map(|offset| (doc, uri, offset)))
+    }
+
+    /// The notifications to send in reply. A notification whose
+    /// parameters do not parse is ignored, with a message in the
+    /// client's log.
+    fn notification(&mut self, notification: Notification) -> Vec<Notification> {
+        self.notify(notification).unwrap_or_else(|log| vec![log])
+    }
+
+    fn notify(&mut self, notification: Notification) -> Result<Vec<Notification>, Notification> {
+        let Notification { method, params } = notification;
+        let uri = match method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    notification_params(&method, params)?;
+                let doc = params.text_document;
+                self.documents
+                    .insert(doc.uri.clone(), Analysis::new(&doc.text));
+                doc.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    notification_params(&method, params)?;
+                // Full synchronisation: the last change is the new text.
+                let Some(change) = params.content_changes.last() else {
+                    return Ok(Vec::new());
+                };
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), Analysis::new(&change.text));
+                uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    notification_params(&method, params)?;
+                self.documents.remove(&params.text_document.uri);
+                // Clear the diagnostics of the closed file.
+                let cleared =
+                    PublishDiagnosticsParams::new(params.text_document.uri, Vec::new(), None);
+                return Ok(vec![Notification::new(
+                    PublishDiagnostics::METHOD.to_string(),
+                    cleared,
+                )]);
+            }
+            _ => return Ok(Vec::new()),
+        };
+        let diagnostics = self.documents[&uri].diagnostics(&uri);
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        Ok(vec![Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )])
+    }
+}
+
+fn notification_params<T: serde::de::DeserializeOwned>(
+    method: &str,
+    params: Value,
+) -> Result<T, Notification> {
+    serde_json::from_value(params).map_err(|e| {
+        let params = LogMessageParams {
+            typ: MessageType::WARNING,
+            message: format!("ignored `{method}` notification: {e}"),
+        };
+        Notification::new(LogMessage::METHOD.to_string(), params)
+    })
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(
+    id: &RequestId,
+    params: Value,
+) -> Result<T, Response> {
+    serde_json::from_value(params)
+        .map_err(|e| Response::new_err(id.clone(), ErrorCode::InvalidParams as i32, e.to_string()))
+}
@@ -0,0 +1,11 @@
+//! `nepl-lsp`: the NEPL language server over stdio.
+
+use lsp_server::Connection;
+
+fn main() -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    nepl_lsp::run(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
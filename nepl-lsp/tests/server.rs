@@ -0,0 +1,379 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use serde_json::{Value, json};
+
+const URI: &str = "file:///main.nepl";
+
+/// A client that sends a fixed script of messages to a server running
+/// in a thread and reads its replies.
+struct Client {
+    connection: Connection,
+    server: Option<JoinHandle<()>>,
+    next_id: i32,
+}
+
+impl Client {
+    fn start() -> Client {
+        let (server, connection) = Connection::memory();
+        let server = std::thread::spawn(move || nepl_lsp::run(&server).unwrap());
+        let mut client = Client {
+            connection,
+            server: Some(server),
+            next_id: 0,
+        };
+        let result = client.request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(result["serverInfo"]["name"], "nepl-lsp");
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        let notification = Notification::new(method.to_string(), params);
+        self.connection.sender.send(notification.into()).unwrap();
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let request = Request::new(id.clone(), method.to_string(), params);
+        self.connection.sender.send(request.into()).unwrap();
+        match self.connection.receiver.recv().unwrap() {
+            Message::Response(Response {
+                id: reply,
+                result,
+                error,
+            }) => {
+                assert_eq!(reply, id);
+                assert!(error.is_none(), "{error:?}");
+                result.unwrap_or(Value::Null)
+            }
+            other => panic!("expected a response, got {other:?}"),
+        }
+    }
+
+    /// Open or replace the document and return its published diagnostics.
+    fn open(&self, text: &str) -> Vec<Value> {
+        self.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "nepl", "version": 1, "text": text } }),
+        );
+        self.diagnostics()
+    }
+
+    fn diagnostics(&self) -> Vec<Value> {
+        match self.connection.receiver.recv().unwrap() {
+            Message::Notification(n) if n.method == "textDocument/publishDiagnostics" => {
+                assert_eq!(n.params["uri"], URI);
+                n.params["diagnostics"].as_array().unwrap().clone()
+            }
+            other => panic!("expected diagnostics, got {other:?}"),
+        }
+    }
+
+    fn at(&mut self, method: &str, line: u32, character: u32) -> Value {
+        self.request(
+            method,
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+            }),
+        )
+    }
+
+    fn shutdown(mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        self.server.take().unwrap().join().unwrap();
+    }
+}
+
+#[test]
+fn diagnostics_follow_changes() {
+    let client = Client::start();
+    let diagnostics = client.open("{\n    let x = 1;\n    add x y\n}");
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    assert_eq!(diagnostics[0]["code"], "E0200");
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({ "start": { "line": 2, "character": 10 }, "end": { "line": 2, "character": 11 } })
+    );
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "{\n    let x = 1;\n    add x x\n}" }],
+        }),
+    );
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+
+    client.notify(
+        "textDocument/didClose",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+    client.shutdown();
+}
+
+#[test]
+fn hover_definition_and_completion() {
+    let mut client = Client::start();
+    let source = "\
+{
+    namespace math {
+        fn double = |i32 n|->i32 add n n;
+    };
+    use math::double;
+    fn twice = |i32 n|->i32 add n n;
+    let total = twice 21;
+    double total
+}";
+    client.open(source);
+
+    // Hover shows inferred types of names and signatures of functions.
+    let hover = client.at("textDocument/hover", 6, 9);
+    assert_eq!(hover["contents"]["value"], "```nepl\nlet total: i32\n```");
+    let hover = client.at("textDocument/hover", 5, 8);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```nepl\nfn twice: (i32) -> i32\n```"
+    );
+
+    // Go to definition follows `use` into the namespace.
+    let definition = client.at("textDocument/definition", 7, 5);
+    assert_eq!(definition["uri"], URI);
+    assert_eq!(
+        definition["range"],
+        json!({ "start": { "line": 2, "character": 11 }, "end": { "line": 2, "character": 17 } })
+    );
+    assert_eq!(client.at("textDocument/definition", 0, 0), Value::Null);
+
+    let labels = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["label"].as_str().unwrap().to_string())
+            .collect()
+    };
+    // Names in scope, keywords and builtins.
+    let items = labels(client.at("textDocument/completion", 7, 4));
+    for expected in ["total", "twice", "double", "math", "match", "let", "add"] {
+        assert!(items.iter().any(|i| i == expected), "{expected}: {items:?}");
+    }
+    // Namespace members after `::`.
+    let items = labels(client.at("textDocument/completion", 4, 14));
+    assert_eq!(items, vec!["double"]);
+
+    client.shutdown();
+}
+
+#[test]
+fn symbols_and_semantic_tokens() {
+    let mut client = Client::start();
+    client.open(
+        "\
+// shapes
+{
+    enum Shape { Circle(i32); Square(i32) };
+    struct Point { x: i32; y: i32 };
+    namespace geo {
+        fn area = |Shape s|->i32 match s { case Circle(r) => r; case Square(a) => mul a a };
+    };
+    0
+}",
+    );
+
+    let document = json!({ "textDocument": { "uri": URI } });
+    let symbols = client.request("textDocument/documentSymbol", document.clone());
+    let outline: Vec<(String, u64, usize)> = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| {
+            let children = s["children"].as_array().map_or(0, |c| c.len());
+            (
+                s["name"].as_str().unwrap().to_string(),
+                s["kind"].as_u64().unwrap(),
+                children,
+            )
+        })
+        .collect();
+    // Enum 10, struct 23, namespace 3.
+    assert_eq!(
+        outline,
+        vec![
+            ("Shape".to_string(), 10, 2),
+            ("Point".to_string(), 23, 2),
+            ("geo".to_string(), 3, 1),
+        ]
+    );
+    assert_eq!(symbols[2]["children"][0]["name"], "area");
+    assert_eq!(symbols[2]["children"][0]["kind"], 12);
+
+    let data = client.request("textDocument/semanticTokens/full", document);
+    let data: Vec<u64> = data["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_u64().unwrap())
+        .collect();
+    // Decode into (line, start, length, type).
+    let mut tokens = Vec::new();
+    let (mut line, mut start) = (0, 0);
+    for t in data.chunks(5) {
+        line += t[0];
+        start = if t[0] == 0 { start + t[1] } else { t[1] };
+        tokens.push((line, start, t[2], t[3]));
+    }
+    // comment, `enum`, `Shape` (enum), `Circle` (enum member).
+    assert_eq!(tokens[0], (0, 0, 9, 1));
+    assert_eq!(tokens[1], (2, 4, 4, 0));
+    assert_eq!(tokens[2], (2, 9, 5, 8));
+    assert_eq!(tokens[3], (2, 17, 6, 10));
+    // `area` is a function, `s` a parameter, `0` a number.
+    assert!(tokens.contains(&(5, 11, 4, 6)));
+    assert!(tokens.contains(&(5, 25, 1, 5)));
+    assert_eq!(*tokens.last().unwrap(), (7, 4, 1, 3));
+
+    client.shutdown();
+}
+
+#[test]
+fn inlay_hints_show_implied_parentheses() {
+    let mut client = Client::start();
+    client.open("{\n    let x = add 1 mul 2 3;\n    neg add x 1\n}");
+    let hints = client.request(
+        "textDocument/inlayHint",
+        json!({
+            "textDocument": { "uri": URI },
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 3, "character": 1 } },
+        }),
+    );
+    let hints: Vec<(u64, u64, &str)> = hints
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|h| {
+            let p = &h["position"];
+            (
+                p["line"].as_u64().unwrap(),
+                p["character"].as_u64().unwrap(),
+                h["label"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        hints,
+        vec![(1, 18, "("), (1, 25, ")"), (2, 8, "("), (2, 15, ")")]
+    );
+    client.shutdown();
+}
+
+#[test]
+fn unknown_requests_are_errors() {
+    let mut client = Client::start();
+    client.next_id += 1;
+    let id = RequestId::from(client.next_id);
+    let request = Request::new(id, "workspace/unknown".to_string(), json!({}));
+    client.connection.sender.send(request.into()).unwrap();
+    let Message::Response(response) = client.connection.receiver.recv().unwrap() else {
+        panic!("expected a response");
+    };
+    assert_eq!(response.error.unwrap().code, -32601);
+    client.shutdown();
+}
+
+#[test]
+fn malformed_notifications_are_logged_and_ignored() {
+    let client = Client::start();
+    client.notify("textDocument/didOpen", json!({ "textDocument": 1 }));
+    match client.connection.receiver.recv().unwrap() {
+        Message::Notification(n) if n.method == "window/logMessage" => {
+            assert_eq!(n.params["type"], 2);
+            let message = n.params["message"].as_str().unwrap();
+            assert!(message.starts_with("ignored `textDocument/didOpen`"), "{message}");
+        }
+        other => panic!("expected a log message, got {other:?}"),
+    }
+    // The server keeps running.
+    assert_eq!(client.open("add 1 2"), Vec::<Value>::new());
+    client.shutdown();
+}
+
+/// Write one LSP message with its `Content-Length` header.
+fn send(stdin: &mut impl Write, message: Value) {
+    let body = message.to_string();
+    write!(stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    stdin.flush().unwrap();
+}
+
+fn receive(stdout: &mut impl BufRead) -> Value {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        stdout.read_line(&mut header).unwrap();
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length: ") {
+            length = value.parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    stdout.read_exact(&mut body).unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[test]
+fn binary_speaks_lsp_over_stdio() {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_nepl-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = server.stdin.take().unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+
+    send(
+        &mut stdin,
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "capabilities": {} } }),
+    );
+    let reply = receive(&mut stdout);
+    assert_eq!(reply["id"], 1);
+    assert_eq!(reply["result"]["capabilities"]["hoverProvider"], true);
+    send(
+        &mut stdin,
+        json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+    );
+
+    send(
+        &mut stdin,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "languageId": "nepl", "version": 1, "text": "add 1 true" } },
+        }),
+    );
+    let published = receive(&mut stdout);
+    assert_eq!(published["method"], "textDocument/publishDiagnostics");
+    assert!(
+        !published["params"]["diagnostics"]
+            .as_array()
+            .unwrap()
+            .is_empty()
+    );
+
+    send(
+        &mut stdin,
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+    );
+    assert_eq!(receive(&mut stdout)["id"], 2);
+    send(&mut stdin, json!({ "jsonrpc": "2.0", "method": "exit" }));
+    assert!(server.wait().unwrap().success());
+}
@@ -1,3 +1,8 @@
+use nepl_core::diagnostic::Diagnostic;
+use nepl_core::messages::Locale;
+use nepl_core::report::Reporter;
+use nepl_core::span::{FileId, Span};
+use nepl_core::{CoreError, compile_wasm};
 use thiserror::Error;
 use wasmi::{
     Config, Engine, Error as WasmiError, Linker, Module, Store, TrapCode, TypedFunc,
@@ -97,6 +102,30 @@ impl FuelStepper {
     pub fn clear_pending(&mut self) {}
 }
 
+/// Display name of the editor buffer in diagnostics.
+pub const PLAYGROUND_FILE: &str = "main.nepl";
+
+/// Compile the editor contents and return its diagnostics as JSON lines,
+/// in the same format as `nepl --error-format=json`, with messages in the
+/// playground's language setting. Only warnings are reported when the
+/// program compiles.
+pub fn diagnostics_json(source: &str, locale: Locale) -> String {
+    let file_id = FileId(0);
+    let diagnostics = match compile_wasm(file_id, source) {
+        Ok(artifact) => artifact.warnings,
+        Err(CoreError::Diagnostics(diagnostics)) => diagnostics,
+        Err(error @ CoreError::Internal(_)) => {
+            vec![Diagnostic::error(
+                error.to_string(),
+                Span::empty(file_id, 0),
+            )]
+        }
+    };
+    let mut reporter = Reporter::new().with_locale(locale);
+    reporter.add_file(file_id, PLAYGROUND_FILE, source);
+    reporter.json_lines(&diagnostics)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,6 +188,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reports_diagnostics_as_json_lines() {
+        assert_eq!(diagnostics_json("add 1 2", Locale::En), "");
+
+        let json = diagnostics_json("{ let b: Bool = add 1 2; b }", Locale::En);
+        assert_eq!(json.lines().count(), 1);
+        assert!(json.starts_with(r#"{"severity":"error","#), "{json}");
+        assert!(json.contains(r#""file":"main.nepl""#), "{json}");
+
+        let json = diagnostics_json("{ let b: Bool = add 1 2; b }", Locale::Ja);
+        assert!(json.contains(r#""message":"型が一致しません"#), "{json}");
+
+        let json = diagnostics_json("{ let x = 1; 0 }", Locale::En);
+        assert!(json.starts_with(r#"{"severity":"warning","code":"W0001","#), "{json}");
+
+        let json = diagnostics_json("{ 1 $ }", Locale::En);
+        assert_eq!(json.lines().count(), 1, "{json}");
+    }
+
     fn spin_loop_module() -> Vec<u8> {
         let mut types = TypeSection::new();
         types.ty().function([], []);
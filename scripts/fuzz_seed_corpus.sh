@@ -0,0 +1,17 @@
+#!/usr/bin/env bash
+# Copy the examples and the standard library into the seed corpus of
+# every fuzz target under nepl-core/fuzz.
+set -euo pipefail
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+ROOT_DIR="$(cd "${SCRIPT_DIR}/.." && pwd)"
+FUZZ_DIR="${ROOT_DIR}/nepl-core/fuzz"
+
+for target in lex parse compile; do
+    CORPUS_DIR="${FUZZ_DIR}/corpus/${target}"
+    mkdir -p "${CORPUS_DIR}"
+    (cd "${ROOT_DIR}" && find examples stdlib -name '*.nepl' | sort) | while read -r file; do
+        # examples/shapes.nepl -> examples_shapes.nepl
+        cp "${ROOT_DIR}/${file}" "${CORPUS_DIR}/${file//\//_}"
+    done
+done